@@ -4,6 +4,7 @@ use super::message::{Request, Response};
 use super::node::Status;
 
 
+#[derive(Clone)]
 pub struct Client {
     request_tx: mpsc::UnboundedSender<(Request, oneshot::Sender<Result<Response>>)>
 }
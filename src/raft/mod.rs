@@ -1,4 +1,5 @@
 mod server;
+pub use server::Server;
 mod message;
 mod node;
 pub use node::Status;
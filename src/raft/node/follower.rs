@@ -93,7 +93,7 @@ impl RoleNode<Follower> {
                     if has_commited && commit_index > self.log.commited_index {
                         let old_commited_index = self.log.commited_index;
                         self.log.commited_index = commit_index;
-                        let mut scan = self.log.scan(old_commited_index + 1..commit_index);
+                        let mut scan = self.log.scan(old_commited_index + 1..=commit_index);
                         while let Some(entry) = scan.next().transpose()? {
                             self.state_tx.send(Instruction::Apply { entry })?;
                         }
@@ -170,4 +170,50 @@ impl RoleNode<Follower> {
         }
     }
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::raft::log::Log;
+    use crate::sql::LogStore;
+    use futures::FutureExt;
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+    use tokio::sync::mpsc;
+
+    #[test]
+    fn heartbeat_applies_commited() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("toydb-follower-{}", uuid::Uuid::new_v4()));
+        let mut log = Log::new(Box::new(LogStore::new(&dir, false)?))?;
+        log.append(1, Some(vec![0x01]))?;
+        log.append(1, Some(vec![0x02]))?;
+        let (node_tx, _node_rx) = mpsc::unbounded_channel();
+        let (state_tx, mut state_rx) = mpsc::unbounded_channel();
+        let node = RoleNode {
+            id: "a".into(),
+            peers: vec!["b".into()],
+            term: 1,
+            log,
+            node_tx,
+            state_tx,
+            queued_reqs: Vec::new(),
+            proxied_reqs: HashMap::new(),
+            role: Follower::new(Some("b"), None),
+        };
+
+        // A heartbeat commiting both entries applies both, including the last one.
+        node.step(Message {
+            term: 1,
+            from: Address::Peer("b".into()),
+            to: Address::Peer("a".into()),
+            event: Event::Heartbeat { commit_index: 2, commit_term: 1 },
+        })?;
+        let mut applied = Vec::new();
+        while let Ok(Instruction::Apply { entry }) = state_rx.recv().now_or_never().flatten().ok_or(()) {
+            applied.push(entry.index);
+        }
+        assert_eq!(applied, vec![1, 2]);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -158,7 +158,7 @@ impl Driver {
     fn query_vote(&mut self, term: u64, commit_index: u64, address: Address) {
         for (_, queries) in self.queries.range_mut(..=commit_index) {
             for (_, query) in queries.iter_mut() {
-                if term > query.term {
+                if term >= query.term {
                     query.votes.insert(address.clone());
                 }
             }
@@ -215,4 +215,56 @@ struct Query {
     command: Vec<u8>,
     quorum: u64,
     votes: HashSet<Address>,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::FutureExt;
+    use pretty_assertions::assert_eq;
+
+    /// A state machine which echoes queries.
+    struct Echo;
+
+    impl State for Echo {
+        fn applied_index(&self) -> u64 {
+            0
+        }
+
+        fn mutate(&mut self, _: u64, command: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(command)
+        }
+
+        fn query(&self, command: Vec<u8>) -> Result<Vec<u8>> {
+            Ok(command)
+        }
+    }
+
+    #[test]
+    fn query_quorum() -> Result<()> {
+        let (_state_tx, state_rx) = mpsc::unbounded_channel();
+        let (node_tx, mut node_rx) = mpsc::unbounded_channel();
+        let mut driver = Driver::new(UnboundedReceiverStream::new(state_rx), node_tx);
+        let mut state = Echo;
+        let mut execute = |i| futures::executor::block_on(driver.execute(&mut state, i));
+
+        // Votes from the query's own term count towards the quorum, including the leader's.
+        execute(Instruction::Query {
+            id: vec![0x01],
+            address: Address::Client,
+            command: vec![0xff],
+            term: 1,
+            index: 0,
+            quorum: 2,
+        })?;
+        execute(Instruction::Vote { term: 1, index: 0, address: Address::Local })?;
+        assert!(node_rx.recv().now_or_never().is_none());
+        execute(Instruction::Vote { term: 1, index: 0, address: Address::Peer("b".into()) })?;
+        match node_rx.recv().now_or_never().flatten().map(|msg| msg.event) {
+            Some(Event::ClientResponse { id, response: Ok(Response::State(result)) }) => {
+                assert_eq!((id, result), (vec![0x01], vec![0xff]));
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+        Ok(())
+    }
+}
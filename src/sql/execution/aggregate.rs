@@ -0,0 +1,134 @@
+use super::query::compare;
+use super::Output;
+use crate::error::{Error, Result};
+use crate::sql::plan::{label, Aggregate};
+use crate::sql::storage::{Expression, Row, Value};
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// Groups the rows by the group_by values in a hash table and computes the aggregates of each
+/// group, emitting groups in the order they were first seen. Without GROUP BY, a single row is
+/// emitted even if there are no input rows.
+pub fn aggregate(
+    source: Output,
+    group_by: Vec<Expression>,
+    aggregates: Vec<(Aggregate, Expression)>,
+) -> Result<Output> {
+    let mut columns: Vec<String> = group_by.iter().map(|e| label(e, &None)).collect();
+    columns.extend(aggregates.iter().map(|(a, e)| a.format(e)));
+
+    let mut groups: HashMap<Vec<Value>, usize> = HashMap::new();
+    let mut accumulators: Vec<(Vec<Value>, Vec<Accumulator>)> = Vec::new();
+    let new = || aggregates.iter().map(|(a, _)| Accumulator::new(*a)).collect::<Vec<_>>();
+    if group_by.is_empty() {
+        groups.insert(Vec::new(), 0);
+        accumulators.push((Vec::new(), new()));
+    }
+
+    for row in source.rows {
+        let row = row?;
+        let key = group_by.iter().map(|e| e.evaluate(Some(&row))).collect::<Result<Vec<_>>>()?;
+        let index = match groups.get(&key) {
+            Some(index) => *index,
+            None => {
+                groups.insert(key.clone(), accumulators.len());
+                accumulators.push((key, new()));
+                accumulators.len() - 1
+            }
+        };
+        for ((_, expr), accumulator) in aggregates.iter().zip(&mut accumulators[index].1) {
+            accumulator.add(expr.evaluate(Some(&row))?)?;
+        }
+    }
+
+    let rows = accumulators
+        .into_iter()
+        .map(|(mut key, accumulators)| {
+            key.extend(accumulators.into_iter().map(|a| a.value()));
+            Ok(key)
+        })
+        .collect::<Vec<Result<Row>>>();
+    Ok(Output { columns, rows: Box::new(rows.into_iter()) })
+}
+
+/// The running state of an aggregate function. NULL inputs are ignored.
+#[derive(Clone)]
+pub(super) enum Accumulator {
+    Average { sum: Value, count: u64 },
+    Count(u64),
+    CountDistinct(HashSet<Value>),
+    Max(Value),
+    Min(Value),
+    Sum(Value),
+}
+
+impl Accumulator {
+    pub(super) fn new(aggregate: Aggregate) -> Self {
+        match aggregate {
+            Aggregate::Average => Self::Average { sum: Value::Null, count: 0 },
+            Aggregate::Count { distinct: false } => Self::Count(0),
+            Aggregate::Count { distinct: true } => Self::CountDistinct(HashSet::new()),
+            Aggregate::Max => Self::Max(Value::Null),
+            Aggregate::Min => Self::Min(Value::Null),
+            Aggregate::Sum => Self::Sum(Value::Null),
+        }
+    }
+
+    pub(super) fn add(&mut self, value: Value) -> Result<()> {
+        if value == Value::Null {
+            return Ok(());
+        }
+        match self {
+            Self::Average { sum, count } => {
+                *sum = Self::sum("AVG", std::mem::replace(sum, Value::Null), value)?;
+                *count += 1;
+            }
+            Self::Count(count) => *count += 1,
+            Self::CountDistinct(seen) => {
+                seen.insert(value);
+            }
+            Self::Max(max) => {
+                if *max == Value::Null || compare(&value, max) == Ordering::Greater {
+                    *max = value
+                }
+            }
+            Self::Min(min) => {
+                if *min == Value::Null || compare(&value, min) == Ordering::Less {
+                    *min = value
+                }
+            }
+            Self::Sum(sum) => *sum = Self::sum("SUM", std::mem::replace(sum, Value::Null), value)?,
+        }
+        Ok(())
+    }
+
+    /// Adds a value to a running sum.
+    fn sum(name: &str, sum: Value, value: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match (sum, value) {
+            (Null, Integer(i)) => Integer(i),
+            (Null, Float(f)) => Float(f),
+            (Integer(s), Integer(i)) => {
+                Integer(s.checked_add(i).ok_or_else(|| Error::Value("Integer overflow".into()))?)
+            }
+            (Integer(s), Float(f)) => Float(s as f64 + f),
+            (Float(s), Integer(i)) => Float(s + i as f64),
+            (Float(s), Float(f)) => Float(s + f),
+            (_, value) => return Err(Error::Value(format!("Can't {} {}", name, value))),
+        })
+    }
+
+    pub(super) fn value(&self) -> Value {
+        match self {
+            Self::Average { sum: Value::Integer(s), count } => {
+                Value::Float(*s as f64 / *count as f64)
+            }
+            Self::Average { sum: Value::Float(s), count } => Value::Float(s / *count as f64),
+            Self::Average { .. } => Value::Null,
+            Self::Count(count) => Value::Integer(*count as i64),
+            Self::CountDistinct(seen) => Value::Integer(seen.len() as i64),
+            Self::Max(value) | Self::Min(value) | Self::Sum(value) => value.clone(),
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use super::{Output, ResultSet, Rows};
+use crate::error::Result;
+use crate::sql::plan::Node;
+use crate::sql::storage::{Row, Value};
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Emits the plan lines of a node tree as a query result, annotated with any statistics.
+pub fn explain(node: &Node, stats: Option<&Stats>) -> ResultSet {
+    let annotations = stats.map(|s| s.annotations()).unwrap_or_default();
+    let rows = node.explain(&annotations).into_iter().map(|line| Ok(vec![Value::String(line)]));
+    ResultSet::Query { columns: vec!["QUERY PLAN".into()], rows: Box::new(rows) }
+}
+
+/// Execution statistics for EXPLAIN ANALYZE: the number of rows emitted by each plan node and
+/// the time spent in it, including its sources. Nodes are registered in pre-order as they're
+/// executed, which matches the order in which Node::explain() displays them. Subquery plans
+/// are executed separately for each row, and are left without statistics.
+#[derive(Default)]
+pub struct Stats(Mutex<Vec<Option<(u64, Duration)>>>);
+
+impl Stats {
+    /// Registers a node, returning its ID.
+    pub fn register(&self) -> usize {
+        let mut stats = self.0.lock().unwrap();
+        stats.push(Some((0, Duration::ZERO)));
+        stats.len() - 1
+    }
+
+    /// Skips the given number of nodes, which won't be annotated.
+    pub fn skip(&self, nodes: usize) {
+        self.0.lock().unwrap().extend(std::iter::repeat_n(None, nodes));
+    }
+
+    /// Records emitted rows and time spent for a node.
+    pub fn record(&self, id: usize, rows: u64, time: Duration) {
+        let mut stats = self.0.lock().unwrap();
+        if let Some((r, t)) = &mut stats[id] {
+            *r += rows;
+            *t += time;
+        }
+    }
+
+    /// Formats the statistics of each node as plan annotations.
+    fn annotations(&self) -> Vec<String> {
+        let stats = self.0.lock().unwrap();
+        stats
+            .iter()
+            .map(|stats| match stats {
+                Some((rows, time)) => {
+                    format!("(rows={} time={:.3}ms)", rows, time.as_secs_f64() * 1000.0)
+                }
+                None => String::new(),
+            })
+            .collect()
+    }
+
+    /// Wraps a node's output, recording the rows it emits and the time spent emitting them.
+    pub fn instrument(self: &Arc<Self>, id: usize, output: Output) -> Output {
+        let rows = Instrumented { rows: output.rows, stats: self.clone(), id };
+        Output { columns: output.columns, rows: Box::new(rows) }
+    }
+}
+
+/// A row iterator that records statistics, see Stats::instrument().
+struct Instrumented {
+    rows: Rows,
+    stats: Arc<Stats>,
+    id: usize,
+}
+
+impl Iterator for Instrumented {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let next = self.rows.next();
+        self.stats.record(self.id, matches!(next, Some(Ok(_))) as u64, start.elapsed());
+        next
+    }
+}
@@ -0,0 +1,379 @@
+use super::query::compare;
+use super::{Output, Rows};
+use crate::error::{Error, Result};
+use crate::sql::plan::JoinType;
+use crate::sql::storage::{Datatype, Expression, Row, Transaction, Value};
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::iter::Peekable;
+use std::sync::{Arc, Mutex};
+
+/// Joins the rows by comparing every left row with every right row, buffering the right rows
+/// in memory. This handles arbitrary join predicates, and is used when there is no equality
+/// predicate between the inputs.
+pub fn nested_loop(
+    left: Output,
+    right: Output,
+    predicate: Option<Expression>,
+    r#type: JoinType,
+) -> Result<Output> {
+    let right_rows = right.rows.collect::<Result<Vec<_>>>()?;
+    let candidates = (0..right_rows.len()).collect::<Vec<_>>();
+    join(left, right.columns, right_rows, move |_| Ok(candidates.clone()), predicate, r#type)
+}
+
+/// Joins the rows on equal left_field and right_field values, by building a hash table of the
+/// right rows and probing it with each left row. NULL keys never match. Additional predicates
+/// are evaluated on the joined rows.
+pub fn hash(
+    left: Output,
+    left_field: usize,
+    right: Output,
+    right_field: usize,
+    predicate: Option<Expression>,
+    r#type: JoinType,
+) -> Result<Output> {
+    let right_rows = right.rows.collect::<Result<Vec<_>>>()?;
+    let mut table: HashMap<Value, Vec<usize>> = HashMap::new();
+    for (i, row) in right_rows.iter().enumerate() {
+        if let Some(key) = hash_key(&row[right_field]) {
+            table.entry(key).or_default().push(i)
+        }
+    }
+    let candidates = move |row: &Row| {
+        Ok(hash_key(&row[left_field]).and_then(|k| table.get(&k).cloned()).unwrap_or_default())
+    };
+    join(left, right.columns, right_rows, candidates, predicate, r#type)
+}
+
+/// Normalizes a join key for hashing, such that numerically equal integers and floats hash
+/// to the same value. Returns None for NULL, which never matches.
+fn hash_key(value: &Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        Value::Float(f) if f.fract() == 0.0 && f.abs() < i64::MAX as f64 => {
+            Some(Value::Integer(*f as i64))
+        }
+        value => Some(value.clone()),
+    }
+}
+
+/// Joins each left row with the table rows whose primary key, or indexed column if given,
+/// equals the left row's left_field value. The rows are joined eagerly, since the reads
+/// borrow the transaction.
+pub fn lookup<T: Transaction>(
+    txn: &T,
+    left: Output,
+    left_field: usize,
+    table: &str,
+    column: Option<String>,
+    predicate: Option<Expression>,
+    outer: bool,
+) -> Result<Output> {
+    let table = txn.must_read_table(table)?;
+    let datatype = match &column {
+        Some(column) => table.get_column(column)?.datatype.clone(),
+        None => table.get_primary_key()?.datatype.clone(),
+    };
+    let mut rows = Vec::new();
+    for row in left.rows {
+        let row = row?;
+        let key = match (&datatype, &row[left_field]) {
+            (_, Value::Null) => None,
+            (Datatype::Float, Value::Integer(i)) => Some(Value::Float(*i as f64)),
+            (Datatype::Integer, Value::Float(f)) if f.fract() == 0.0 => {
+                Some(Value::Integer(*f as i64))
+            }
+            (_, key) => Some(key.clone()),
+        };
+        let mut ids = match (key, &column) {
+            (None, _) => Vec::new(),
+            (Some(key), None) => vec![key],
+            (Some(key), Some(column)) => {
+                txn.read_index(&table.name, column, &key)?.into_iter().collect()
+            }
+        };
+        ids.sort_by(compare);
+        let mut matched = false;
+        for id in ids {
+            if let Some(right) = txn.read(&table.name, &id)? {
+                let mut joined = row.clone();
+                joined.extend(right);
+                if is_match(&predicate, &joined)? {
+                    rows.push(joined);
+                    matched = true;
+                }
+            }
+        }
+        if outer && !matched {
+            rows.push(pad_right(row, table.columns.len()));
+        }
+    }
+
+    let mut columns = left.columns;
+    columns.extend(table.columns.iter().map(|c| c.name.clone()));
+    Ok(Output { columns, rows: Box::new(rows.into_iter().map(Ok)) })
+}
+
+/// Joins the rows on equal left_field and right_field values, given inputs that are sorted
+/// by them (with NULLs first), by stepping through both inputs in lockstep. Only the rows
+/// of one key group are buffered at a time.
+pub fn merge(
+    left: Output,
+    left_field: usize,
+    right: Output,
+    right_field: usize,
+    predicate: Option<Expression>,
+    r#type: JoinType,
+) -> Result<Output> {
+    let (left_outer, right_outer) = outer(&r#type);
+    let left_only = matches!(r#type, JoinType::Semi | JoinType::Anti);
+    let rows = MergeJoin {
+        left: left.rows.peekable(),
+        left_field,
+        left_width: left.columns.len(),
+        right: right.rows.peekable(),
+        right_field,
+        right_width: right.columns.len(),
+        predicate,
+        left_outer,
+        right_outer,
+        r#type,
+        buffer: VecDeque::new(),
+    };
+    let mut columns = left.columns;
+    if !left_only {
+        columns.extend(right.columns);
+    }
+    Ok(Output { columns, rows: Box::new(rows) })
+}
+
+/// A streaming merge join, see merge().
+struct MergeJoin {
+    left: Peekable<Rows>,
+    left_field: usize,
+    left_width: usize,
+    right: Peekable<Rows>,
+    right_field: usize,
+    right_width: usize,
+    predicate: Option<Expression>,
+    left_outer: bool,
+    right_outer: bool,
+    r#type: JoinType,
+    buffer: VecDeque<Row>,
+}
+
+impl MergeJoin {
+    /// Joins the next key group, or the next row without a matching key, into the buffer.
+    /// Returns false when both inputs are exhausted.
+    fn fill(&mut self) -> Result<bool> {
+        let left_key = match self.left.peek() {
+            Some(Ok(row)) => Some(row[self.left_field].clone()),
+            Some(Err(_)) => return self.left.next().unwrap().map(|_| true),
+            None => None,
+        };
+        let right_key = match self.right.peek() {
+            Some(Ok(row)) => Some(row[self.right_field].clone()),
+            Some(Err(_)) => return self.right.next().unwrap().map(|_| true),
+            None => None,
+        };
+        let ordering = match (&left_key, &right_key) {
+            (None, None) => return Ok(false),
+            (Some(_), None) | (Some(Value::Null), _) => Ordering::Less,
+            (None, Some(_)) | (_, Some(Value::Null)) => Ordering::Greater,
+            (Some(l), Some(r)) => compare(l, r),
+        };
+        match ordering {
+            Ordering::Less => {
+                let row = self.left.next().unwrap()?;
+                if self.r#type == JoinType::Anti {
+                    self.buffer.push_back(row);
+                } else if self.left_outer {
+                    self.buffer.push_back(pad_right(row, self.right_width));
+                }
+            }
+            Ordering::Greater => {
+                let row = self.right.next().unwrap()?;
+                if self.right_outer {
+                    self.buffer.push_back(pad_left(self.left_width, row));
+                }
+            }
+            Ordering::Equal => {
+                let key = left_key.unwrap();
+                let lefts = Self::group(&mut self.left, self.left_field, &key)?;
+                let rights = Self::group(&mut self.right, self.right_field, &key)?;
+                let mut matched = vec![false; rights.len()];
+                for left in lefts {
+                    let mut any = false;
+                    for (i, right) in rights.iter().enumerate() {
+                        let mut row = left.clone();
+                        row.extend(right.iter().cloned());
+                        if is_match(&self.predicate, &row)? {
+                            any = true;
+                            if matches!(self.r#type, JoinType::Semi | JoinType::Anti) {
+                                break;
+                            }
+                            self.buffer.push_back(row);
+                            matched[i] = true;
+                        }
+                    }
+                    match self.r#type {
+                        JoinType::Semi if any => self.buffer.push_back(left),
+                        JoinType::Anti if !any => self.buffer.push_back(left),
+                        _ if self.left_outer && !any => {
+                            self.buffer.push_back(pad_right(left, self.right_width))
+                        }
+                        _ => {}
+                    }
+                }
+                if self.right_outer {
+                    for (right, _) in rights.into_iter().zip(matched).filter(|(_, m)| !m) {
+                        self.buffer.push_back(pad_left(self.left_width, right));
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Takes the leading rows with the given key from an input.
+    fn group(rows: &mut Peekable<Rows>, field: usize, key: &Value) -> Result<Vec<Row>> {
+        let mut group = Vec::new();
+        while let Some(row) = rows.next_if(|r| match r {
+            Ok(row) => compare(&row[field], key) == Ordering::Equal,
+            Err(_) => true,
+        }) {
+            group.push(row?);
+        }
+        Ok(group)
+    }
+}
+
+impl Iterator for MergeJoin {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.is_empty() {
+            match self.fill() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err)),
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+/// Joins the left rows with buffered right rows, given a function that returns the indexes
+/// of the right rows that may match a left row. Outer joins pad missing rows with NULLs:
+/// unmatched left rows are emitted as they are seen, and unmatched right rows after all left
+/// rows. Semi and anti joins emit each left row once if it has any or no matches.
+fn join<F>(
+    left: Output,
+    right_columns: Vec<String>,
+    right_rows: Vec<Row>,
+    candidates: F,
+    predicate: Option<Expression>,
+    r#type: JoinType,
+) -> Result<Output>
+where
+    F: Fn(&Row) -> Result<Vec<usize>> + Send + 'static,
+{
+    let (left_width, right_width) = (left.columns.len(), right_columns.len());
+    let (left_outer, right_outer) = outer(&r#type);
+    let (semi, anti) = (r#type == JoinType::Semi, r#type == JoinType::Anti);
+    let matched = Arc::new(Mutex::new(vec![false; right_rows.len()]));
+    let right_rows = Arc::new(right_rows);
+
+    let rows = {
+        let (right_rows, matched) = (right_rows.clone(), matched.clone());
+        left.rows.flat_map(move |r| -> Vec<Result<Row>> {
+            let left_row = match r {
+                Ok(row) => row,
+                Err(err) => return vec![Err(err)],
+            };
+            let candidates = match candidates(&left_row) {
+                Ok(candidates) => candidates,
+                Err(err) => return vec![Err(err)],
+            };
+            let mut joined = Vec::new();
+            for i in candidates {
+                let mut row = left_row.clone();
+                row.extend(right_rows[i].iter().cloned());
+                match is_match(&predicate, &row) {
+                    Ok(true) if semi => return vec![Ok(left_row)],
+                    Ok(true) if anti => return vec![],
+                    Ok(true) => {
+                        if right_outer {
+                            matched.lock().unwrap()[i] = true;
+                        }
+                        joined.push(Ok(row))
+                    }
+                    Ok(false) => {}
+                    Err(err) => return vec![Err(err)],
+                }
+            }
+            match joined.is_empty() {
+                true if anti => vec![Ok(left_row)],
+                true if left_outer => vec![Ok(pad_right(left_row, right_width))],
+                _ => joined,
+            }
+        })
+    };
+
+    // Unmatched right rows can only be determined once all left rows have been joined, so
+    // they're computed lazily when the iterator reaches them.
+    let unmatched = std::iter::once(()).flat_map(move |_| -> Rows {
+        if !right_outer {
+            return Box::new(std::iter::empty());
+        }
+        let matched = matched.lock().unwrap().clone();
+        let right_rows = right_rows.clone();
+        Box::new(
+            (0..right_rows.len())
+                .filter(move |i| !matched[*i])
+                .map(move |i| Ok(pad_left(left_width, right_rows[i].clone()))),
+        )
+    });
+
+    let mut columns = left.columns;
+    if !semi && !anti {
+        columns.extend(right_columns);
+    }
+    Ok(Output { columns, rows: Box::new(rows.chain(unmatched)) })
+}
+
+/// Returns whether unmatched left and right rows are emitted, respectively.
+fn outer(r#type: &JoinType) -> (bool, bool) {
+    match r#type {
+        JoinType::Cross | JoinType::Inner | JoinType::Semi | JoinType::Anti => (false, false),
+        JoinType::Left => (true, false),
+        JoinType::Right => (false, true),
+        JoinType::Full => (true, true),
+    }
+}
+
+/// Pads an unmatched left row with NULLs for the right columns.
+fn pad_right(mut row: Row, right_width: usize) -> Row {
+    row.extend(std::iter::repeat_n(Value::Null, right_width));
+    row
+}
+
+/// Pads an unmatched right row with NULLs for the left columns.
+fn pad_left(left_width: usize, right: Row) -> Row {
+    let mut row = vec![Value::Null; left_width];
+    row.extend(right);
+    row
+}
+
+/// Evaluates a join predicate for a joined row.
+fn is_match(predicate: &Option<Expression>, row: &Row) -> Result<bool> {
+    match predicate.as_ref().map(|p| p.evaluate(Some(row))).transpose()? {
+        None | Some(Value::Boolean(true)) => Ok(true),
+        Some(Value::Boolean(false)) | Some(Value::Null) => Ok(false),
+        Some(value) => {
+            Err(Error::Value(format!("Join predicate returned {}, expected boolean", value)))
+        }
+    }
+}
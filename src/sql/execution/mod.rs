@@ -0,0 +1,1882 @@
+mod aggregate;
+mod explain;
+mod join;
+mod mutation;
+mod query;
+mod recursive;
+mod set;
+mod sort;
+mod source;
+mod subquery;
+mod window;
+
+use super::plan::{Node, Plan};
+use super::storage::{Mode, Row, Table, TableKind, TableStats, Transaction};
+use crate::error::{Error, Result};
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A stream of result rows.
+pub type Rows = Box<dyn Iterator<Item = Result<Row>> + Send>;
+
+/// The result of executing a statement.
+pub enum ResultSet {
+    Begin { id: u64, mode: Mode },
+    Commit { id: u64 },
+    Rollback { id: u64 },
+    CreateTable { name: String },
+    DropTable { name: String },
+    CreateView { name: String },
+    DropView { name: String },
+    Refresh { name: String, count: u64 },
+    Analyze { tables: Vec<String> },
+    Create { count: u64 },
+    Update { count: u64 },
+    Delete { count: u64 },
+    Query { columns: Vec<String>, rows: Rows },
+    Prepare { name: String },
+    Deallocate { name: String },
+}
+
+impl ResultSet {
+    /// Collects the rows of a query result, erroring for other results.
+    pub fn into_rows(self) -> Result<Vec<Row>> {
+        match self {
+            Self::Query { rows, .. } => rows.collect(),
+            _ => Err(Error::Internal("Not a query result".into())),
+        }
+    }
+}
+
+/// The output of a query node: its column names and a stream of rows.
+pub struct Output {
+    pub columns: Vec<String>,
+    pub rows: Rows,
+}
+
+/// Executor configuration.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The approximate number of bytes of rows that a sort buffers in memory before spilling
+    /// sorted runs to temporary files.
+    pub sort_memory: usize,
+    /// The directory for temporary sort files.
+    pub temp_dir: PathBuf,
+    /// The maximum number of iterations of a WITH RECURSIVE query, beyond which it errors.
+    pub recursion_limit: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { sort_memory: 16 << 20, temp_dir: std::env::temp_dir(), recursion_limit: 1000 }
+    }
+}
+
+impl Plan {
+    /// Executes the plan in the given transaction, which may be a local or a Raft transaction.
+    pub fn execute<T: Transaction>(self, txn: &mut T) -> Result<ResultSet> {
+        self.execute_with(txn, &Config::default())
+    }
+
+    /// Executes the plan with the given executor configuration.
+    pub fn execute_with<T: Transaction>(self, txn: &mut T, config: &Config) -> Result<ResultSet> {
+        execute(self.0, txn, config)
+    }
+}
+
+/// Executes a plan node. Statements and mutations are run to completion, while queries
+/// return a lazy row stream.
+pub fn execute<T: Transaction>(node: Node, txn: &mut T, config: &Config) -> Result<ResultSet> {
+    execute_in(node, txn, &Context { config, stats: None })
+}
+
+/// Execution state shared by the nodes of a plan.
+struct Context<'a> {
+    config: &'a Config,
+    /// Per-node statistics, collected for EXPLAIN ANALYZE.
+    stats: Option<Arc<explain::Stats>>,
+}
+
+fn execute_in<T: Transaction>(node: Node, txn: &mut T, ctx: &Context) -> Result<ResultSet> {
+    // Query nodes are instrumented by query(), statements here.
+    let id = match &ctx.stats {
+        Some(stats) if !is_query(&node) && !matches!(node, Node::Explain { .. }) => {
+            Some(stats.register())
+        }
+        _ => None,
+    };
+    let start = Instant::now();
+    let result = match node {
+        Node::CreateTable { schema } => {
+            let name = schema.name.clone();
+            txn.create_table(schema)?;
+            ResultSet::CreateTable { name }
+        }
+        Node::DropTable { table } => {
+            txn.delete_table(&table)?;
+            ResultSet::DropTable { name: table }
+        }
+        Node::CreateView { name, query, references, source, materialized: false } => {
+            if let Some(stats) = &ctx.stats {
+                stats.skip(source.size());
+            }
+            txn.create_table(Table::view(name.clone(), query, references))?;
+            ResultSet::CreateView { name }
+        }
+        Node::CreateView { name, query: sql, references, source, materialized: true } => {
+            if txn.read_table(&name)?.is_some() {
+                return Err(Error::Value(format!("Table {} already exists", name)));
+            }
+            let datatypes = source.datatypes(txn)?;
+            let source = query(*source, txn, ctx)?;
+            let kind = TableKind::MaterializedView { query: sql, references };
+            mutation::materialize(txn, &name, kind, datatypes, source)?;
+            ResultSet::CreateView { name }
+        }
+        Node::DropView { name } => {
+            txn.delete_table(&name)?;
+            ResultSet::DropView { name }
+        }
+        Node::RefreshView { name, source } => {
+            let datatypes = source.datatypes(txn)?;
+            let source = query(*source, txn, ctx)?;
+            let count = mutation::refresh(txn, &name, datatypes, source)?;
+            ResultSet::Refresh { name, count }
+        }
+        Node::Analyze { tables } => {
+            for table in &tables {
+                let columns = txn.must_read_table(table)?.columns.len();
+                let stats = TableStats::compute(columns, txn.scan(table, None)?)?;
+                txn.write_stats(table, stats)?;
+            }
+            ResultSet::Analyze { tables }
+        }
+        Node::Explain { source, analyze: false } => explain::explain(&source, None),
+        Node::Explain { source, analyze: true } => {
+            let stats = Arc::new(explain::Stats::default());
+            let analyze = Context { config: ctx.config, stats: Some(stats.clone()) };
+            if let ResultSet::Query { rows, .. } = execute_in((*source).clone(), txn, &analyze)? {
+                for row in rows {
+                    row?;
+                }
+            }
+            explain::explain(&source, Some(&stats))
+        }
+        Node::Insert { table, columns, source, conflict } => {
+            let source = query(*source, txn, ctx)?;
+            ResultSet::Create { count: mutation::insert(txn, &table, columns, source, conflict)? }
+        }
+        Node::Update { table, source, expressions } => {
+            let source = query(*source, txn, ctx)?;
+            ResultSet::Update { count: mutation::update(txn, &table, source, expressions)? }
+        }
+        Node::Delete { table, source } => {
+            let source = query(*source, txn, ctx)?;
+            ResultSet::Delete { count: mutation::delete(txn, &table, source)? }
+        }
+        node => {
+            let Output { columns, rows } = query(node, txn, ctx)?;
+            ResultSet::Query { columns, rows }
+        }
+    };
+    if let (Some(stats), Some(id)) = (&ctx.stats, id) {
+        let rows = match &result {
+            ResultSet::Create { count }
+            | ResultSet::Update { count }
+            | ResultSet::Delete { count }
+            | ResultSet::Refresh { count, .. } => *count,
+            _ => 0,
+        };
+        stats.record(id, rows, start.elapsed());
+    }
+    Ok(result)
+}
+
+/// Returns true if the node is a query node, which emits rows via query().
+fn is_query(node: &Node) -> bool {
+    !matches!(
+        node,
+        Node::Analyze { .. }
+            | Node::CreateTable { .. }
+            | Node::CreateView { .. }
+            | Node::Delete { .. }
+            | Node::DropTable { .. }
+            | Node::DropView { .. }
+            | Node::Explain { .. }
+            | Node::Insert { .. }
+            | Node::RefreshView { .. }
+            | Node::Update { .. }
+    )
+}
+
+/// Executes a query node, recursively executing its sources. With statistics enabled, the
+/// node is registered before its sources, and its output is instrumented.
+fn query<T: Transaction>(node: Node, txn: &T, ctx: &Context) -> Result<Output> {
+    let Some(stats) = &ctx.stats else {
+        return query_node(node, txn, ctx);
+    };
+    let id = stats.register();
+    let start = Instant::now();
+    let output = query_node(node, txn, ctx)?;
+    stats.record(id, 0, start.elapsed());
+    Ok(stats.instrument(id, output))
+}
+
+fn query_node<T: Transaction>(node: Node, txn: &T, ctx: &Context) -> Result<Output> {
+    match node {
+        Node::Scan { table, alias: _, filter } => source::scan(txn, &table, filter),
+        Node::KeyLookup { table, alias: _, keys } => source::key_lookup(txn, &table, keys),
+        Node::IndexLookup { table, alias: _, column, values } => {
+            source::index_lookup(txn, &table, &column, values)
+        }
+        Node::Nothing => Ok(source::nothing()),
+        Node::Values { rows } => Ok(source::values(rows)),
+
+        Node::Filter { source, predicate } => {
+            Ok(query::filter(query(*source, txn, ctx)?, predicate))
+        }
+        Node::Projection { source, expressions } => {
+            Ok(query::projection(query(*source, txn, ctx)?, expressions))
+        }
+        Node::Order { source, orders, limit } => {
+            sort::order(query(*source, txn, ctx)?, orders, limit, ctx.config)
+        }
+        Node::Limit { source, offset, limit } => {
+            Ok(query::limit(query(*source, txn, ctx)?, offset, limit))
+        }
+
+        Node::Join { left, right, predicate, r#type } => {
+            let (left, right) = (query(*left, txn, ctx)?, query(*right, txn, ctx)?);
+            join::nested_loop(left, right, predicate, r#type)
+        }
+        Node::HashJoin { left, left_field, right, right_field, predicate, r#type } => {
+            let (left, right) = (query(*left, txn, ctx)?, query(*right, txn, ctx)?);
+            join::hash(left, left_field, right, right_field, predicate, r#type)
+        }
+        Node::LookupJoin { left, left_field, table, alias: _, column, predicate, outer } => {
+            let left = query(*left, txn, ctx)?;
+            join::lookup(txn, left, left_field, &table, column, predicate, outer)
+        }
+        Node::MergeJoin { left, left_field, right, right_field, predicate, r#type } => {
+            let (left, right) = (query(*left, txn, ctx)?, query(*right, txn, ctx)?);
+            join::merge(left, left_field, right, right_field, predicate, r#type)
+        }
+        Node::Aggregate { source, group_by, aggregates } => {
+            aggregate::aggregate(query(*source, txn, ctx)?, group_by, aggregates)
+        }
+        Node::SetOperation { left, right, operator, all, datatypes } => {
+            let (left, right) = (query(*left, txn, ctx)?, query(*right, txn, ctx)?);
+            set::set_operation(left, right, operator, all, datatypes)
+        }
+        Node::Recursive { name, anchor, recursive, all, datatypes } => {
+            let anchor = query(*anchor, txn, ctx)?;
+            if let Some(stats) = &ctx.stats {
+                stats.skip(recursive.size());
+            }
+            recursive::recursive(txn, anchor, &name, *recursive, all, datatypes, ctx.config)
+        }
+        Node::Subquery { source, subquery, kind } => {
+            let source = query(*source, txn, ctx)?;
+            if let Some(stats) = &ctx.stats {
+                stats.skip(subquery.size());
+            }
+            subquery::subquery(txn, source, *subquery, kind, ctx.config)
+        }
+        Node::Window { source, function, args, partition_by, order, frame } => {
+            let source = query(*source, txn, ctx)?;
+            window::window(source, function, args, partition_by, order, frame)
+        }
+
+        node @ (Node::Analyze { .. }
+        | Node::CreateTable { .. }
+        | Node::CreateView { .. }
+        | Node::DropTable { .. }
+        | Node::DropView { .. }
+        | Node::RefreshView { .. }
+        | Node::Explain { .. }
+        | Node::Insert { .. }
+        | Node::Update { .. }
+        | Node::Delete { .. }
+        | Node::WorkingTable { .. }) => {
+            Err(Error::Internal(format!("Can't query node {:?}", node)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::parser::Parser;
+    use super::super::storage::{information, Catalog, Datatype, Kv, Memory, Mode, Mvcc, Value};
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    use Value::{Boolean as Bool, Float, Integer, Null, String as Str};
+
+    fn setup() -> Result<Kv> {
+        let kv = Kv::new(Mvcc::new(Box::new(Memory::new())));
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        for query in [
+            "CREATE TABLE studios (id INTEGER PRIMARY KEY, name STRING NOT NULL)",
+            "CREATE TABLE movies (
+                id INTEGER PRIMARY KEY,
+                title STRING NOT NULL,
+                studio_id INTEGER REFERENCES studios INDEX,
+                rating FLOAT
+            )",
+            "INSERT INTO studios VALUES (1, 'Mosfilm'), (2, 'Lionsgate'), (3, 'Ghibli')",
+            "INSERT INTO movies VALUES
+                (1, 'Stalker', 1, 8.2),
+                (2, 'Sicario', 2, 7.6),
+                (3, 'Spirited Away', 3, 8.6),
+                (4, 'Solaris', 1, 8),
+                (5, 'Unknown', NULL, NULL)",
+        ] {
+            execute_sql(&mut txn, query)?;
+        }
+        txn.commit()?;
+        Ok(kv)
+    }
+
+    fn execute_sql<T: Transaction>(txn: &mut T, query: &str) -> Result<ResultSet> {
+        Plan::build(Parser::new(query).parse()?, txn)?.execute(txn)
+    }
+
+    /// Runs a query both with and without optimizations, asserting that the results match
+    /// and returning the optimized results. The optimized plan is also run with sorts spilling
+    /// every row to disk, which must give identical results.
+    fn query(kv: &Kv, query: &str) -> Result<(Vec<String>, Vec<Row>)> {
+        let mut txn = kv.begin(Mode::ReadOnly)?;
+        let plan = Plan::build(Parser::new(query).parse()?, &txn)?;
+        let optimized = plan.clone().optimize(&txn)?;
+        let spill = Config { sort_memory: 0, ..Config::default() };
+        let mut results = Vec::new();
+        for (plan, config) in
+            [(plan, Config::default()), (optimized.clone(), spill), (optimized, Config::default())]
+        {
+            match execute(plan.0, &mut txn, &config)? {
+                ResultSet::Query { columns, rows } => {
+                    results.push((columns, rows.collect::<Result<Vec<_>>>()?))
+                }
+                _ => panic!("Expected query result"),
+            }
+        }
+        txn.commit()?;
+        assert_eq!(results[1], results[2], "spilled results differ for {}", query);
+        // Without ORDER BY, the row order depends on the execution strategy.
+        let sorted = |rows: &[Row]| {
+            let mut rows = rows.to_vec();
+            rows.sort_by(|a, b| {
+                a.iter().zip(b).map(|(a, b)| query::compare(a, b)).find(|o| o.is_ne()).unwrap_or(
+                    std::cmp::Ordering::Equal,
+                )
+            });
+            rows
+        };
+        let (columns, rows) = results.pop().unwrap();
+        assert_eq!(columns, results[0].0, "optimized columns differ for {}", query);
+        assert_eq!(sorted(&rows), sorted(&results[0].1), "optimized rows differ for {}", query);
+        Ok((columns, rows))
+    }
+
+    #[test]
+    fn select() -> Result<()> {
+        let kv = setup()?;
+        assert_eq!(
+            query(&kv, "SELECT id, title AS name, rating * 10 FROM movies WHERE id < 3")?,
+            (
+                vec!["id".into(), "name".into(), "movies.rating * 10".into()],
+                vec![
+                    vec![Integer(1), Str("Stalker".into()), Float(82.0)],
+                    vec![Integer(2), Str("Sicario".into()), Float(76.0)],
+                ]
+            )
+        );
+        assert_eq!(
+            query(&kv, "SELECT title FROM movies ORDER BY rating DESC, id LIMIT 2 OFFSET 1")?.1,
+            vec![vec![Str("Stalker".into())], vec![Str("Solaris".into())]]
+        );
+        assert_eq!(
+            query(&kv, "SELECT id FROM movies WHERE rating > 8 ORDER BY title")?.1,
+            vec![vec![Integer(3)], vec![Integer(1)]]
+        );
+        assert_eq!(query(&kv, "SELECT 1 + 2 AS three")?.1, vec![vec![Integer(3)]]);
+        // Conditional results are cast to their common datatype, whichever branch is taken.
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT COALESCE(studio_id, rating) / 2 AS a, IF(id = 1, id, rating) AS b,
+                    CASE WHEN id = 1 THEN id ELSE 0.5 END AS c, COALESCE(NULL, 1, 2.0) AS d
+                FROM movies WHERE id = 1"
+            )?
+            .1,
+            vec![vec![Float(0.5), Float(1.0), Float(1.0), Float(1.0)]]
+        );
+        assert_eq!(
+            query(&kv, "SELECT id FROM movies WHERE studio_id IN (1, 3) AND id != 4")?.1,
+            vec![vec![Integer(1)], vec![Integer(3)]]
+        );
+        assert_eq!(
+            query(&kv, "SELECT id FROM movies WHERE id = 2.0 OR id = 5 OR id = 9")?.1,
+            vec![vec![Integer(2)], vec![Integer(5)]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn order() -> Result<()> {
+        let kv = setup()?;
+        let ids = |sql: &str| -> Result<Vec<Value>> {
+            Ok(query(&kv, sql)?.1.into_iter().map(|mut row| row.remove(0)).collect())
+        };
+        assert_eq!(
+            ids("SELECT id FROM movies ORDER BY rating NULLS LAST")?,
+            vec![Integer(2), Integer(4), Integer(1), Integer(3), Integer(5)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM movies ORDER BY rating DESC")?,
+            vec![Integer(3), Integer(1), Integer(4), Integer(2), Integer(5)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM movies ORDER BY rating DESC NULLS FIRST")?,
+            vec![Integer(5), Integer(3), Integer(1), Integer(4), Integer(2)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM movies ORDER BY studio_id DESC, title")?,
+            vec![Integer(3), Integer(2), Integer(4), Integer(1), Integer(5)]
+        );
+        // Sorting is stable.
+        assert_eq!(
+            ids("SELECT id FROM movies ORDER BY studio_id")?,
+            vec![Integer(5), Integer(1), Integer(4), Integer(2), Integer(3)]
+        );
+        // Top-N sorts.
+        assert_eq!(
+            ids("SELECT id FROM movies ORDER BY rating NULLS LAST LIMIT 2 OFFSET 3")?,
+            vec![Integer(3), Integer(5)]
+        );
+        assert_eq!(
+            ids("SELECT id FROM movies ORDER BY studio_id LIMIT 3")?,
+            vec![Integer(5), Integer(1), Integer(4)]
+        );
+        assert_eq!(ids("SELECT id FROM movies ORDER BY id LIMIT 0")?, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn external_sort() -> Result<()> {
+        let kv = Kv::new(Mvcc::new(Box::new(Memory::new())));
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        execute_sql(&mut txn, "CREATE TABLE t (id INTEGER PRIMARY KEY, v INTEGER)")?;
+        let values = (0..200).map(|i| format!("({}, {})", i, i * 37 % 101)).collect::<Vec<_>>();
+        execute_sql(&mut txn, &format!("INSERT INTO t VALUES {}", values.join(", ")))?;
+
+        let temp_dir = std::env::temp_dir().join(format!("sort-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&temp_dir)?;
+        let config = Config { sort_memory: 1000, temp_dir: temp_dir.clone(), ..Config::default() };
+        let plan = Plan::build(Parser::new("SELECT v, id FROM t ORDER BY v DESC").parse()?, &txn)?;
+        let rows = match plan.execute_with(&mut txn, &config)? {
+            ResultSet::Query { rows, .. } => {
+                assert!(std::fs::read_dir(&temp_dir)?.count() > 1, "expected spilled runs");
+                rows.collect::<Result<Vec<_>>>()?
+            }
+            _ => panic!("Expected query result"),
+        };
+        let mut expect =
+            (0..200).map(|i| vec![Integer(i * 37 % 101), Integer(i)]).collect::<Vec<_>>();
+        expect.sort_by(|a, b| query::compare(&b[0], &a[0]));
+        assert_eq!(rows, expect);
+        // The temporary files are removed once the rows have been consumed.
+        assert_eq!(std::fs::read_dir(&temp_dir)?.count(), 0);
+
+        // With a LIMIT, a top-N heap is used if it fits in memory, and the rows are spilled
+        // otherwise.
+        for (limit, spilled) in [(5, false), (150, true)] {
+            let query = format!("SELECT v, id FROM t ORDER BY v DESC LIMIT {}", limit);
+            let plan = Plan::build(Parser::new(&query).parse()?, &txn)?;
+            let rows = match plan.execute_with(&mut txn, &config)? {
+                ResultSet::Query { rows, .. } => {
+                    assert_eq!(std::fs::read_dir(&temp_dir)?.count() > 0, spilled, "{}", query);
+                    rows.collect::<Result<Vec<_>>>()?
+                }
+                _ => panic!("Expected query result"),
+            };
+            assert_eq!(rows, expect[..limit]);
+        }
+        std::fs::remove_dir(&temp_dir)?;
+        txn.commit()
+    }
+
+    #[test]
+    fn explain() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        let mut explain = |query: &str| -> Result<Vec<String>> {
+            let plan = Plan::build(Parser::new(query).parse()?, &txn)?.optimize(&txn)?;
+            Ok(plan
+                .execute(&mut txn)?
+                .into_rows()?
+                .into_iter()
+                .map(|row| row[0].to_string())
+                .collect())
+        };
+        let join = "SELECT m.title, s.name FROM movies m JOIN studios s ON m.studio_id = s.id \
+                    WHERE m.rating > 8 ORDER BY m.rating DESC";
+        assert_eq!(
+            explain(&format!("EXPLAIN {}", join))?,
+            vec![
+                "Projection: #0 AS title, #1 AS name",
+                "└─ Order: #2 DESC",
+                "   └─ Projection: m.title, s.name, m.rating",
+                "      └─ LookupJoin: inner studios as s using primary key = #2",
+                "         └─ Scan: movies as m where movies.rating > 8",
+            ]
+        );
+        // Timings vary, so they're masked.
+        let time = regex::Regex::new(r"time=\d+\.\d{3}ms").unwrap();
+        let mut analyze = |query: &str| -> Result<Vec<String>> {
+            Ok(explain(query)?.iter().map(|l| time.replace(l, "time=?").into_owned()).collect())
+        };
+        assert_eq!(
+            analyze(&format!("EXPLAIN ANALYZE {}", join))?,
+            vec![
+                "Projection: #0 AS title, #1 AS name (rows=2 time=?)",
+                "└─ Order: #2 DESC (rows=2 time=?)",
+                "   └─ Projection: m.title, s.name, m.rating (rows=2 time=?)",
+                "      └─ LookupJoin: inner studios as s using primary key = #2 (rows=2 time=?)",
+                "         └─ Scan: movies as m where movies.rating > 8 (rows=2 time=?)",
+            ]
+        );
+        assert_eq!(
+            analyze(
+                "EXPLAIN ANALYZE SELECT * FROM studios s FULL JOIN movies m ON s.id = m.id
+                 WHERE m.id > 1 OR m.id IS NULL"
+            )?,
+            vec![
+                "Projection: s.id, s.name, m.id, m.title, m.studio_id, m.rating (rows=4 time=?)",
+                "└─ Filter: m.id > 1 OR m.id IS NULL (rows=4 time=?)",
+                "   └─ MergeJoin: full on left #0 = right #0 (rows=5 time=?)",
+                "      ├─ Scan: studios as s (rows=3 time=?)",
+                "      └─ Scan: movies as m (rows=5 time=?)",
+            ]
+        );
+        // EXPLAIN ANALYZE executes mutations.
+        assert_eq!(
+            analyze("EXPLAIN ANALYZE DELETE FROM movies WHERE studio_id = 1")?,
+            vec![
+                "Delete: movies (rows=2 time=?)",
+                "└─ IndexLookup: movies using studio_id (1) (rows=2 time=?)",
+            ]
+        );
+        assert_eq!(
+            explain(
+                "EXPLAIN SELECT studio_id, COUNT(DISTINCT id) FROM movies WHERE id IN (1, 2)
+                 GROUP BY studio_id LIMIT 1"
+            )?,
+            vec![
+                "Limit: 1",
+                "└─ Projection: movies.studio_id, #1",
+                "   └─ Aggregate: COUNT(DISTINCT movies.id) group by movies.studio_id",
+                "      └─ KeyLookup: movies (1, 2)",
+            ]
+        );
+        txn.commit()
+    }
+
+    #[test]
+    fn join() -> Result<()> {
+        let kv = setup()?;
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT m.title, s.name FROM movies m JOIN studios s ON m.studio_id = s.id
+                 WHERE s.id = 1 ORDER BY m.id"
+            )?
+            .1,
+            vec![
+                vec![Str("Stalker".into()), Str("Mosfilm".into())],
+                vec![Str("Solaris".into()), Str("Mosfilm".into())],
+            ]
+        );
+
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        execute_sql(&mut txn, "INSERT INTO studios VALUES (4, 'Empty')")?;
+        txn.commit()?;
+        let sql = "SELECT m.id, s.id FROM movies m {} JOIN studios s ON m.studio_id = s.id";
+        let rows = |r#type: &str| -> Result<Vec<Row>> {
+            let mut rows = query(&kv, &sql.replace("{}", r#type))?.1;
+            rows.sort_by(|a, b| query::compare(&a[0], &b[0]).then(query::compare(&a[1], &b[1])));
+            Ok(rows)
+        };
+        let inner = vec![
+            vec![Integer(1), Integer(1)],
+            vec![Integer(2), Integer(2)],
+            vec![Integer(3), Integer(3)],
+            vec![Integer(4), Integer(1)],
+        ];
+        assert_eq!(rows("INNER")?, inner);
+        let mut left = inner.clone();
+        left.push(vec![Integer(5), Null]);
+        assert_eq!(rows("LEFT")?, left);
+        let mut right = inner.clone();
+        right.insert(0, vec![Null, Integer(4)]);
+        assert_eq!(rows("RIGHT")?, right);
+        let mut full = left.clone();
+        full.insert(0, vec![Null, Integer(4)]);
+        assert_eq!(rows("FULL")?, full);
+        assert_eq!(query(&kv, "SELECT * FROM movies, studios")?.1.len(), 20);
+        Ok(())
+    }
+
+    #[test]
+    fn join_algorithms() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        execute_sql(&mut txn, "INSERT INTO studios VALUES (4, 'Empty'), (5, 'Unknown')")?;
+        execute_sql(&mut txn, "INSERT INTO movies VALUES (6, 'Dune', 3, 7.9), (7, 'Ran', 5, 8)")?;
+        txn.commit()?;
+
+        // The optimized plans use merge, hash and lookup joins, which must match the results
+        // of the unoptimized nested loop joins, including NULL-padding for outer joins.
+        for on in [
+            "m.id = s.id",
+            "s.id = m.id AND m.rating > 8",
+            "m.studio_id = s.id",
+            "m.title = s.name",
+            "m.studio_id = s.id AND s.name != 'Ghibli'",
+            "m.id = s.id + 1",
+            "m.rating > s.id + 4",
+        ] {
+            for r#type in ["INNER", "LEFT", "RIGHT", "FULL"] {
+                query(&kv, &format!("SELECT * FROM movies m {} JOIN studios s ON {}", r#type, on))?;
+                query(&kv, &format!("SELECT * FROM studios s {} JOIN movies m ON {}", r#type, on))?;
+            }
+        }
+        let ordered = |r#type: &str| -> Result<Vec<Row>> {
+            let sql = "SELECT m.id, s.id FROM movies m {} JOIN studios s ON m.id = s.id";
+            let mut rows = query(&kv, &sql.replace("{}", r#type))?.1;
+            rows.retain(|r| r[0] == Null || r[1] == Null);
+            Ok(rows)
+        };
+        assert_eq!(ordered("INNER")?, Vec::<Row>::new());
+        assert_eq!(ordered("LEFT")?, vec![vec![Integer(6), Null], vec![Integer(7), Null]]);
+        assert_eq!(ordered("RIGHT")?, Vec::<Row>::new());
+        assert_eq!(ordered("FULL")?, vec![vec![Integer(6), Null], vec![Integer(7), Null]]);
+        assert_eq!(
+            query(&kv, "SELECT s.name FROM studios s JOIN movies m ON s.id = m.studio_id")?.1.len(),
+            6
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn aggregate() -> Result<()> {
+        let kv = setup()?;
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT studio_id, COUNT(*), COUNT(rating), MAX(rating), SUM(id)
+                 FROM movies GROUP BY studio_id ORDER BY studio_id"
+            )?
+            .1,
+            vec![
+                vec![Null, Integer(1), Integer(0), Null, Integer(5)],
+                vec![Integer(1), Integer(2), Integer(2), Float(8.2), Integer(5)],
+                vec![Integer(2), Integer(1), Integer(1), Float(7.6), Integer(2)],
+                vec![Integer(3), Integer(1), Integer(1), Float(8.6), Integer(3)],
+            ]
+        );
+        assert_eq!(
+            query(&kv, "SELECT MIN(title), MAX(title) FROM movies")?,
+            (
+                vec!["MIN(movies.title)".into(), "MAX(movies.title)".into()],
+                vec![vec![Str("Sicario".into()), Str("Unknown".into())]]
+            )
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT studio_id, AVG(rating) FROM movies GROUP BY studio_id HAVING COUNT(*) > 1"
+            )?
+            .1,
+            vec![vec![Integer(1), Float(8.1)]]
+        );
+        assert_eq!(
+            query(&kv, "SELECT COUNT(*), SUM(rating), AVG(id) FROM movies WHERE id > 10")?.1,
+            vec![vec![Integer(0), Null, Null]]
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT COUNT(DISTINCT studio_id), COUNT(DISTINCT rating > 8), COUNT(*)
+                 FROM movies"
+            )?,
+            (
+                vec![
+                    "COUNT(DISTINCT movies.studio_id)".into(),
+                    "COUNT(DISTINCT movies.rating > 8)".into(),
+                    "COUNT(*)".into(),
+                ],
+                vec![vec![Integer(3), Integer(2), Integer(5)]]
+            )
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT studio_id, COUNT(DISTINCT rating) FROM movies
+                 GROUP BY studio_id HAVING COUNT(DISTINCT rating) > 1 OR studio_id IS NULL
+                 ORDER BY studio_id"
+            )?
+            .1,
+            vec![vec![Null, Integer(0)], vec![Integer(1), Integer(2)]]
+        );
+        assert_eq!(
+            query(&kv, "SELECT SUM(title) FROM movies"),
+            Err(Error::Value("Can't SUM Stalker".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn subqueries() -> Result<()> {
+        let kv = setup()?;
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT title FROM movies WHERE studio_id IN \
+                 (SELECT id FROM studios WHERE name LIKE '%o%') ORDER BY id"
+            )?
+            .1,
+            vec![
+                vec![Str("Stalker".into())],
+                vec![Str("Sicario".into())],
+                vec![Str("Solaris".into())],
+            ]
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT name FROM studios s WHERE EXISTS \
+                 (SELECT * FROM movies m WHERE m.studio_id = s.id AND m.rating > 8.5)"
+            )?
+            .1,
+            vec![vec![Str("Ghibli".into())]]
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT name FROM studios s WHERE NOT EXISTS \
+                 (SELECT * FROM movies WHERE studio_id = s.id AND rating < 8) ORDER BY name"
+            )?
+            .1,
+            vec![vec![Str("Ghibli".into())], vec![Str("Mosfilm".into())]]
+        );
+        // NOT IN is NULL rather than true if the subquery returns a NULL.
+        assert_eq!(
+            query(&kv, "SELECT id FROM studios WHERE id NOT IN (SELECT studio_id FROM movies)")?.1,
+            Vec::<Row>::new()
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT id FROM studios WHERE id NOT IN \
+                 (SELECT studio_id FROM movies WHERE studio_id IS NOT NULL AND rating > 8)"
+            )?
+            .1,
+            vec![vec![Integer(2)]]
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT name, (SELECT MAX(rating) FROM movies WHERE studio_id = s.id) AS best \
+                 FROM studios s ORDER BY id"
+            )?,
+            (
+                vec!["name".into(), "best".into()],
+                vec![
+                    vec![Str("Mosfilm".into()), Float(8.2)],
+                    vec![Str("Lionsgate".into()), Float(7.6)],
+                    vec![Str("Ghibli".into()), Float(8.6)],
+                ]
+            )
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT title FROM movies WHERE rating > (SELECT AVG(rating) FROM movies) \
+                 ORDER BY id"
+            )?
+            .1,
+            vec![vec![Str("Stalker".into())], vec![Str("Spirited Away".into())]]
+        );
+        assert_eq!(
+            query(&kv, "SELECT (SELECT title FROM movies WHERE id = 9)")?,
+            (vec!["(subquery)".into()], vec![vec![Null]])
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT id, EXISTS (SELECT 1 FROM movies WHERE studio_id = studios.id \
+                 AND rating > 8) FROM studios ORDER BY id"
+            )?,
+            (
+                vec!["id".into(), "EXISTS (subquery)".into()],
+                vec![
+                    vec![Integer(1), Value::Boolean(true)],
+                    vec![Integer(2), Value::Boolean(false)],
+                    vec![Integer(3), Value::Boolean(true)],
+                ]
+            )
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT studio_id, COUNT(*) FROM movies GROUP BY studio_id \
+                 HAVING COUNT(*) > (SELECT COUNT(*) FROM studios WHERE id > 2)"
+            )?
+            .1,
+            vec![vec![Integer(1), Integer(2)]]
+        );
+        // The nested subquery prevents decorrelation into a semi join.
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT name FROM studios s WHERE EXISTS (SELECT 1 FROM movies m \
+                 WHERE m.studio_id = s.id AND m.rating = (SELECT MAX(rating) FROM movies))"
+            )?
+            .1,
+            vec![vec![Str("Ghibli".into())]]
+        );
+
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        assert!(matches!(
+            execute_sql(
+                &mut txn,
+                "DELETE FROM movies WHERE studio_id IN (SELECT id FROM studios WHERE id != 1)"
+            )?,
+            ResultSet::Delete { count: 2 }
+        ));
+        assert_eq!(
+            execute_sql(&mut txn, "SELECT (SELECT title FROM movies)").and_then(|r| r.into_rows()),
+            Err(Error::Value("Scalar subquery returned more than one row".into()))
+        );
+        assert_eq!(
+            execute_sql(&mut txn, "SELECT (SELECT id, name FROM studios)").err(),
+            Some(Error::Value("Subquery returns 2 columns, expected 1".into()))
+        );
+        assert_eq!(
+            execute_sql(&mut txn, "SELECT id FROM studios ORDER BY (SELECT 1)").err(),
+            Some(Error::Value("Subqueries are not allowed here".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn window() -> Result<()> {
+        let kv = setup()?;
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT title, ROW_NUMBER() OVER (ORDER BY rating DESC) AS n,
+                    RANK() OVER (ORDER BY studio_id), DENSE_RANK() OVER (ORDER BY studio_id)
+                 FROM movies ORDER BY id"
+            )?,
+            (
+                vec![
+                    "title".into(),
+                    "n".into(),
+                    "RANK() OVER (ORDER BY studio_id)".into(),
+                    "DENSE_RANK() OVER (ORDER BY studio_id)".into(),
+                ],
+                vec![
+                    vec![Str("Stalker".into()), Integer(2), Integer(2), Integer(2)],
+                    vec![Str("Sicario".into()), Integer(4), Integer(4), Integer(3)],
+                    vec![Str("Spirited Away".into()), Integer(1), Integer(5), Integer(4)],
+                    vec![Str("Solaris".into()), Integer(3), Integer(2), Integer(2)],
+                    vec![Str("Unknown".into()), Integer(5), Integer(1), Integer(1)],
+                ]
+            )
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT id, LAG(title) OVER (PARTITION BY studio_id ORDER BY id),
+                    LEAD(id, 2, 0) OVER (ORDER BY id)
+                 FROM movies ORDER BY id"
+            )?
+            .1,
+            vec![
+                vec![Integer(1), Null, Integer(3)],
+                vec![Integer(2), Null, Integer(4)],
+                vec![Integer(3), Null, Integer(5)],
+                vec![Integer(4), Str("Stalker".into()), Integer(0)],
+                vec![Integer(5), Null, Integer(0)],
+            ]
+        );
+        // Without a frame, aggregates run up to the row's last peer, or over the entire
+        // partition without ORDER BY.
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT id, MAX(rating) OVER (PARTITION BY studio_id ORDER BY id),
+                    SUM(id) OVER (ORDER BY id ROWS BETWEEN 1 PRECEDING AND 1 FOLLOWING),
+                    SUM(id) OVER (ORDER BY id ROWS 2 PRECEDING),
+                    COUNT(rating) OVER (ORDER BY studio_id), COUNT(*) OVER ()
+                 FROM movies ORDER BY id"
+            )?
+            .1,
+            vec![
+                vec![Integer(1), Float(8.2), Integer(3), Integer(1), Integer(2), Integer(5)],
+                vec![Integer(2), Float(7.6), Integer(6), Integer(3), Integer(3), Integer(5)],
+                vec![Integer(3), Float(8.6), Integer(9), Integer(6), Integer(4), Integer(5)],
+                vec![Integer(4), Float(8.2), Integer(12), Integer(9), Integer(2), Integer(5)],
+                vec![Integer(5), Null, Integer(9), Integer(12), Integer(0), Integer(5)],
+            ]
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT studio_id, COUNT(*), RANK() OVER (ORDER BY studio_id DESC)
+                 FROM movies GROUP BY studio_id ORDER BY studio_id"
+            )?
+            .1,
+            vec![
+                vec![Null, Integer(1), Integer(4)],
+                vec![Integer(1), Integer(2), Integer(3)],
+                vec![Integer(2), Integer(1), Integer(2)],
+                vec![Integer(3), Integer(1), Integer(1)],
+            ]
+        );
+        // Windows are evaluated after aggregation, and can use aggregates.
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT studio_id, SUM(SUM(id)) OVER (ORDER BY studio_id),
+                    RANK() OVER (ORDER BY COUNT(*) DESC)
+                 FROM movies GROUP BY studio_id ORDER BY studio_id"
+            )?
+            .1,
+            vec![
+                vec![Null, Integer(5), Integer(2)],
+                vec![Integer(1), Integer(10), Integer(1)],
+                vec![Integer(2), Integer(12), Integer(2)],
+                vec![Integer(3), Integer(15), Integer(2)],
+            ]
+        );
+        assert_eq!(
+            query(&kv, "SELECT SUM(COUNT(*)) OVER () FROM movies")?.1,
+            vec![vec![Integer(5)]]
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT title FROM movies ORDER BY ROW_NUMBER() OVER (ORDER BY rating) DESC \
+                 LIMIT 2"
+            )?
+            .1,
+            vec![vec![Str("Spirited Away".into())], vec![Str("Stalker".into())]]
+        );
+
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        let plan = Plan::build(
+            Parser::new(
+                "EXPLAIN SELECT SUM(rating) OVER (PARTITION BY studio_id ORDER BY id ROWS 1 \
+                 PRECEDING) FROM movies",
+            )
+            .parse()?,
+            &txn,
+        )?;
+        assert_eq!(
+            plan.execute(&mut txn)?
+                .into_rows()?
+                .into_iter()
+                .map(|row| row[0].to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Projection: #4 AS SUM(rating) OVER (PARTITION BY studio_id ORDER BY id ROWS \
+                 BETWEEN 1 PRECEDING AND CURRENT ROW)",
+                "└─ Window: SUM(movies.rating) partition by movies.studio_id order by movies.id \
+                 ASC rows between 1 preceding and current row",
+                "   └─ Order: movies.studio_id ASC, movies.id ASC",
+                "      └─ Scan: movies",
+            ]
+        );
+        for (query, error) in [
+            (
+                "SELECT id FROM movies WHERE RANK() OVER () > 1",
+                "Window functions are not allowed here",
+            ),
+            ("SELECT FOO() OVER () FROM movies", "Unknown window function FOO"),
+            (
+                "SELECT SUM(studio_id) OVER (ORDER BY title) FROM movies GROUP BY studio_id",
+                "Column title must appear in GROUP BY or be used in an aggregate function",
+            ),
+            (
+                "SELECT RANK(id) OVER () FROM movies",
+                "Window function RANK takes 0 arguments, got 1",
+            ),
+            (
+                "SELECT LAG() OVER () FROM movies",
+                "Window function LAG takes 1 to 3 arguments, got 0",
+            ),
+            (
+                "SELECT COUNT(DISTINCT id) OVER () FROM movies",
+                "DISTINCT is not supported for COUNT",
+            ),
+            (
+                "SELECT SUM(id) OVER (ROWS BETWEEN 1 FOLLOWING AND CURRENT ROW) FROM movies",
+                "Invalid window frame ROWS BETWEEN 1 FOLLOWING AND CURRENT ROW",
+            ),
+            (
+                "SELECT LAG(id, -1) OVER () FROM movies",
+                "LAG offset must be a non-negative integer, got -1",
+            ),
+        ] {
+            assert_eq!(
+                execute_sql(&mut txn, query).and_then(|r| r.into_rows()),
+                Err(Error::Value(error.into())),
+                "{}",
+                query
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn set_operations() -> Result<()> {
+        let kv = setup()?;
+        let column = |query: &str| -> Result<Vec<Value>> {
+            Ok(self::query(&kv, query)?.1.into_iter().map(|mut row| row.remove(0)).collect())
+        };
+        assert_eq!(
+            query(&kv, "SELECT studio_id FROM movies UNION SELECT id FROM studios ORDER BY 1")?,
+            (
+                vec!["studio_id".into()],
+                vec![vec![Null], vec![Integer(1)], vec![Integer(2)], vec![Integer(3)]]
+            )
+        );
+        assert_eq!(
+            column(
+                "SELECT studio_id FROM movies UNION ALL SELECT id FROM studios \
+                 ORDER BY studio_id DESC LIMIT 3"
+            )?,
+            vec![Integer(3), Integer(3), Integer(2)]
+        );
+        assert_eq!(
+            column(
+                "SELECT studio_id FROM movies INTERSECT SELECT id FROM studios WHERE id < 3 \
+                 ORDER BY 1"
+            )?,
+            vec![Integer(1), Integer(2)]
+        );
+        // NULLs are equal in set operations, and ALL keeps as many duplicates as match.
+        assert_eq!(
+            column(
+                "SELECT studio_id FROM movies INTERSECT ALL \
+                 SELECT studio_id FROM movies WHERE id > 2 ORDER BY 1"
+            )?,
+            vec![Null, Integer(1), Integer(3)]
+        );
+        assert_eq!(
+            column("SELECT studio_id FROM movies EXCEPT SELECT id FROM studios")?,
+            vec![Null]
+        );
+        assert_eq!(
+            column("SELECT studio_id FROM movies EXCEPT ALL SELECT id FROM studios ORDER BY 1")?,
+            vec![Null, Integer(1)]
+        );
+        // Integers and floats are coerced to floats, and compare equal.
+        assert_eq!(
+            query(&kv, "SELECT 8 UNION SELECT rating FROM movies WHERE id = 4")?,
+            (vec!["8".into()], vec![vec![Float(8.0)]])
+        );
+        // INTERSECT binds tighter than UNION.
+        assert_eq!(column("SELECT 1 UNION SELECT 2 INTERSECT SELECT 3")?, vec![Integer(1)]);
+        assert_eq!(
+            column(
+                "SELECT name FROM studios WHERE id IN \
+                 (SELECT studio_id FROM movies WHERE rating > 8.5 UNION SELECT 2) ORDER BY id"
+            )?,
+            vec![Str("Lionsgate".into()), Str("Ghibli".into())]
+        );
+
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        execute_sql(
+            &mut txn,
+            "CREATE VIEW names AS SELECT name FROM studios UNION ALL SELECT title FROM movies",
+        )?;
+        assert_eq!(
+            execute_sql(&mut txn, "SELECT COUNT(*) FROM names WHERE name LIKE 'S%'")?
+                .into_rows()?,
+            vec![vec![Integer(4)]]
+        );
+        let plan = Plan::build(
+            Parser::new("EXPLAIN SELECT id FROM studios EXCEPT ALL SELECT studio_id FROM movies")
+                .parse()?,
+            &txn,
+        )?;
+        assert_eq!(
+            plan.execute(&mut txn)?
+                .into_rows()?
+                .into_iter()
+                .map(|row| row[0].to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "SetOperation: except all",
+                "├─ Projection: studios.id",
+                "│  └─ Scan: studios",
+                "└─ Projection: movies.studio_id",
+                "   └─ Scan: movies",
+            ]
+        );
+        for (query, error) in [
+            (
+                "SELECT id, name FROM studios UNION SELECT id FROM movies",
+                "UNION queries must have the same number of columns, got 2 and 1",
+            ),
+            (
+                "SELECT id FROM studios EXCEPT SELECT title FROM movies",
+                "EXCEPT column 1 has incompatible types INTEGER and STRING",
+            ),
+            (
+                "SELECT id FROM studios UNION SELECT id FROM movies ORDER BY 2",
+                "ORDER BY position 2 is not in select list",
+            ),
+            (
+                "SELECT id FROM studios UNION SELECT id FROM movies ORDER BY title",
+                "Unknown column title",
+            ),
+        ] {
+            assert_eq!(
+                execute_sql(&mut txn, query).and_then(|r| r.into_rows()),
+                Err(Error::Value(error.into())),
+                "{}",
+                query
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn ctes() -> Result<()> {
+        let kv = setup()?;
+        let column = |query: &str| -> Result<Vec<Value>> {
+            Ok(self::query(&kv, query)?.1.into_iter().map(|mut row| row.remove(0)).collect())
+        };
+        // A CTE can be referenced several times, and by later CTEs.
+        assert_eq!(
+            query(
+                &kv,
+                "WITH good AS (SELECT title, studio_id FROM movies WHERE rating > 8), \
+                      mosfilm (title) AS (SELECT title FROM good WHERE studio_id = 1) \
+                 SELECT g.title, m.title FROM good g LEFT JOIN mosfilm m ON g.title = m.title \
+                 ORDER BY g.title"
+            )?,
+            (
+                vec!["title".into(), "title".into()],
+                vec![
+                    vec![Str("Spirited Away".into()), Null],
+                    vec![Str("Stalker".into()), Str("Stalker".into())],
+                ]
+            )
+        );
+        // CTEs shadow tables.
+        assert_eq!(
+            column("WITH movies AS (SELECT name FROM studios) SELECT COUNT(*) FROM movies")?,
+            vec![Integer(3)]
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "WITH RECURSIVE counter (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM counter \
+                 WHERE n < 5) SELECT n, n * n FROM counter"
+            )?,
+            (
+                vec!["n".into(), "counter.n * counter.n".into()],
+                (1..=5).map(|n| vec![Integer(n), Integer(n * n)]).collect()
+            )
+        );
+        // Without ALL, the recursion ends when no new rows are found.
+        assert_eq!(
+            column(
+                "WITH RECURSIVE cycle (n) AS (SELECT 0 UNION SELECT (n + 1) % 3 FROM cycle) \
+                 SELECT n FROM cycle ORDER BY n"
+            )?,
+            vec![Integer(0), Integer(1), Integer(2)]
+        );
+
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        for query in [
+            "CREATE TABLE employees (
+                id INTEGER PRIMARY KEY,
+                name STRING NOT NULL,
+                manager_id INTEGER REFERENCES employees
+            )",
+            "INSERT INTO employees VALUES
+                (1, 'Ada', NULL), (2, 'Bob', 1), (3, 'Cy', 1), (4, 'Di', 2), (5, 'Ed', 4),
+                (6, 'Flo', NULL)",
+        ] {
+            execute_sql(&mut txn, query)?;
+        }
+        let reports = "WITH RECURSIVE reports AS ( \
+                           SELECT id, name, 0 AS depth FROM employees WHERE id = 2 \
+                           UNION ALL \
+                           SELECT e.id, e.name, r.depth + 1 FROM employees e \
+                           JOIN reports r ON e.manager_id = r.id \
+                       ) SELECT name, depth FROM reports ORDER BY depth";
+        assert_eq!(
+            execute_sql(&mut txn, reports)?.into_rows()?,
+            vec![
+                vec![Str("Bob".into()), Integer(0)],
+                vec![Str("Di".into()), Integer(1)],
+                vec![Str("Ed".into()), Integer(2)],
+            ]
+        );
+        // Views can contain CTEs, and don't see the CTEs of the query referencing them.
+        execute_sql(&mut txn, &format!("CREATE VIEW reports AS {}", reports))?;
+        execute_sql(&mut txn, "CREATE VIEW ghibli AS SELECT name FROM studios WHERE id = 3")?;
+        assert_eq!(
+            execute_sql(
+                &mut txn,
+                "WITH studios AS (SELECT name FROM reports) SELECT * FROM studios, ghibli"
+            )?
+            .into_rows()?,
+            vec![
+                vec![Str("Bob".into()), Str("Ghibli".into())],
+                vec![Str("Di".into()), Str("Ghibli".into())],
+                vec![Str("Ed".into()), Str("Ghibli".into())],
+            ]
+        );
+        let plan = Plan::build(Parser::new(&format!("EXPLAIN {}", reports)).parse()?, &txn)?;
+        assert_eq!(
+            plan.execute(&mut txn)?
+                .into_rows()?
+                .into_iter()
+                .map(|row| row[0].to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "Order: #1 ASC",
+                "└─ Projection: reports.name, reports.depth",
+                "   └─ Recursive: reports all",
+                "      ├─ Projection: employees.id, employees.name, 0 AS depth",
+                "      │  └─ Filter: employees.id = 2",
+                "      │     └─ Scan: employees",
+                "      └─ Projection: e.id, e.name, r.depth + 1",
+                "         └─ NestedLoopJoin: inner on e.manager_id = r.id",
+                "            ├─ Scan: employees as e",
+                "            └─ WorkingTable: reports",
+            ]
+        );
+
+        // Runaway recursion errors once the iteration limit is exceeded.
+        let config = Config { recursion_limit: 10, ..Config::default() };
+        let plan = Plan::build(
+            Parser::new(
+                "WITH RECURSIVE r (n) AS (SELECT 1 UNION ALL SELECT n + 1 FROM r) SELECT * FROM r",
+            )
+            .parse()?,
+            &txn,
+        )?;
+        assert_eq!(
+            plan.execute_with(&mut txn, &config).and_then(|r| r.into_rows()),
+            Err(Error::Value("WITH RECURSIVE query r exceeded 10 iterations".into()))
+        );
+        for (query, error) in [
+            ("WITH a AS (SELECT 1), a AS (SELECT 2) SELECT * FROM a", "Duplicate CTE name a"),
+            (
+                "WITH a (x, y) AS (SELECT 1) SELECT * FROM a",
+                "CTE a has 2 columns, but its query returns 1",
+            ),
+            // Without RECURSIVE, a CTE can't refer to itself.
+            (
+                "WITH a AS (SELECT 1 UNION SELECT * FROM a) SELECT * FROM a",
+                "Table a does not exist",
+            ),
+            (
+                "WITH RECURSIVE a AS (SELECT 1 UNION SELECT name FROM a) SELECT * FROM a",
+                "Unknown column name",
+            ),
+            (
+                "WITH RECURSIVE a (n) AS (SELECT 1 UNION SELECT 'x' FROM a) SELECT * FROM a",
+                "UNION column 1 has incompatible types INTEGER and STRING",
+            ),
+            // CTEs are only in scope within their WITH query.
+            (
+                "SELECT * FROM studios WHERE id IN (WITH a AS (SELECT 1) SELECT * FROM a) \
+                 AND id IN (SELECT * FROM a)",
+                "Table a does not exist",
+            ),
+        ] {
+            assert_eq!(
+                execute_sql(&mut txn, query).and_then(|r| r.into_rows()),
+                Err(Error::Value(error.into())),
+                "{}",
+                query
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn mutations() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        assert!(matches!(
+            execute_sql(&mut txn, "INSERT INTO movies (id, title) VALUES (6, 'Nostalghia')")?,
+            ResultSet::Create { count: 1 }
+        ));
+        assert!(matches!(
+            execute_sql(&mut txn, "UPDATE movies SET rating = 9, title = 'x' WHERE studio_id = 1")?,
+            ResultSet::Update { count: 2 }
+        ));
+        assert!(matches!(
+            execute_sql(&mut txn, "DELETE FROM movies WHERE rating IS NULL")?,
+            ResultSet::Delete { count: 2 }
+        ));
+        txn.commit()?;
+        assert_eq!(
+            query(&kv, "SELECT * FROM movies WHERE studio_id = 1")?.1,
+            vec![
+                vec![Integer(1), Str("x".into()), Integer(1), Float(9.0)],
+                vec![Integer(4), Str("x".into()), Integer(1), Float(9.0)],
+            ]
+        );
+
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        assert!(matches!(execute_sql(&mut txn, "DROP TABLE movies")?, ResultSet::DropTable { .. }));
+        assert!(matches!(
+            execute_sql(&mut txn, "DELETE FROM studios")?,
+            ResultSet::Delete { count: 3 }
+        ));
+        txn.commit()?;
+        assert_eq!(query(&kv, "SELECT * FROM studios")?.1, Vec::<Row>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn upsert() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        assert_eq!(
+            execute_sql(&mut txn, "INSERT INTO studios VALUES (1, 'Mosfilm'), (4, 'Pixar')").err(),
+            Some(Error::Value("Primary key 1 already exists for table studios".into()))
+        );
+        assert!(matches!(
+            execute_sql(
+                &mut txn,
+                "INSERT INTO studios VALUES (1, 'Mosfilm'), (4, 'Pixar') ON CONFLICT DO NOTHING"
+            )?,
+            ResultSet::Create { count: 1 }
+        ));
+        assert!(matches!(
+            execute_sql(
+                &mut txn,
+                "INSERT INTO movies (id, title, rating) VALUES (1, 'Solaris', 9), (7, 'Up', 8)
+                 ON CONFLICT (id) DO UPDATE SET title = excluded.title, rating = rating + 1"
+            )?,
+            ResultSet::Create { count: 2 }
+        ));
+        txn.commit()?;
+        assert_eq!(
+            query(&kv, "SELECT id, title, studio_id, rating FROM movies WHERE id IN (1, 7)")?.1,
+            vec![
+                vec![Integer(1), Str("Solaris".into()), Integer(1), Float(9.2)],
+                vec![Integer(7), Str("Up".into()), Null, Float(8.0)],
+            ]
+        );
+        assert_eq!(
+            query(&kv, "SELECT * FROM studios WHERE id = 4")?.1,
+            vec![vec![Integer(4), Str("Pixar".into())]]
+        );
+
+        // INSERT SELECT copies rows, including from the same table.
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        execute_sql(&mut txn, "CREATE TABLE archive (id INTEGER PRIMARY KEY, title STRING)")?;
+        assert!(matches!(
+            execute_sql(&mut txn, "INSERT INTO archive SELECT id, title FROM movies")?,
+            ResultSet::Create { count: 6 }
+        ));
+        assert!(matches!(
+            execute_sql(
+                &mut txn,
+                "INSERT INTO archive (title, id) SELECT title, id + 10 FROM archive WHERE id < 3"
+            )?,
+            ResultSet::Create { count: 2 }
+        ));
+        assert!(matches!(
+            execute_sql(
+                &mut txn,
+                "INSERT INTO archive SELECT id, 'x' FROM movies
+                 ON CONFLICT (id) DO UPDATE SET title = CONCAT(excluded.title, title)"
+            )?,
+            ResultSet::Create { count: 6 }
+        ));
+        assert_eq!(
+            execute_sql(&mut txn, "INSERT INTO archive VALUES (1) ON CONFLICT (title) DO NOTHING")
+                .err(),
+            Some(Error::Value(
+                "ON CONFLICT column title is not the primary key of table archive".into()
+            ))
+        );
+        assert_eq!(
+            execute_sql(&mut txn, "INSERT INTO archive SELECT * FROM movies").err(),
+            Some(Error::Value("Expected 2 values for table archive, got 4".into()))
+        );
+        assert_eq!(
+            execute_sql(
+                &mut txn,
+                "INSERT INTO archive VALUES (1, 'a') ON CONFLICT (id) DO UPDATE SET title = title2"
+            )
+            .err(),
+            Some(Error::Value("Unknown column title2".into()))
+        );
+        txn.commit()?;
+        assert_eq!(
+            query(&kv, "SELECT * FROM archive WHERE id IN (1, 11, 12)")?.1,
+            vec![
+                vec![Integer(1), Str("xSolaris".into())],
+                vec![Integer(11), Str("Solaris".into())],
+                vec![Integer(12), Str("Sicario".into())],
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn views() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        for sql in [
+            "CREATE VIEW rated AS SELECT id, title, rating * 10 AS score FROM movies
+                WHERE rating IS NOT NULL",
+            "CREATE VIEW best AS SELECT title FROM rated WHERE score > 80",
+            "CREATE MATERIALIZED VIEW counts AS
+                SELECT s.name, COUNT(*) AS movies, AVG(m.rating) AS rating
+                FROM studios s JOIN movies m ON m.studio_id = s.id GROUP BY s.name",
+            "CREATE VIEW top AS SELECT name FROM counts WHERE rating > 8",
+        ] {
+            assert!(matches!(execute_sql(&mut txn, sql)?, ResultSet::CreateView { .. }));
+        }
+        txn.commit()?;
+
+        // Views are expanded on reference, and can be filtered, joined and nested.
+        assert_eq!(
+            query(&kv, "SELECT * FROM rated WHERE score > 85")?,
+            (
+                vec!["id".into(), "title".into(), "score".into()],
+                vec![vec![Integer(3), Str("Spirited Away".into()), Float(86.0)]]
+            )
+        );
+        assert_eq!(
+            query(&kv, "SELECT r.title, s.name FROM rated r JOIN movies m ON m.id = r.id
+                JOIN studios s ON s.id = m.studio_id WHERE r.score < 80")?
+                .1,
+            vec![vec![Str("Sicario".into()), Str("Lionsgate".into())]]
+        );
+        assert_eq!(
+            query(&kv, "SELECT title FROM best ORDER BY title")?.1,
+            vec![vec![Str("Spirited Away".into())], vec![Str("Stalker".into())]]
+        );
+
+        // Materialized views store their results, and hide their row ID column.
+        let counts = |kv: &Kv| query(kv, "SELECT * FROM counts ORDER BY name");
+        assert_eq!(
+            counts(&kv)?,
+            (
+                vec!["name".into(), "movies".into(), "rating".into()],
+                vec![
+                    vec![Str("Ghibli".into()), Integer(1), Float(8.6)],
+                    vec![Str("Lionsgate".into()), Integer(1), Float(7.6)],
+                    vec![Str("Mosfilm".into()), Integer(2), Float(8.1)],
+                ]
+            )
+        );
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        execute_sql(&mut txn, "INSERT INTO movies VALUES (6, 'Mirror', 1, NULL)")?;
+        execute_sql(&mut txn, "DELETE FROM movies WHERE studio_id = 2")?;
+        txn.commit()?;
+        assert_eq!(counts(&kv)?.1.len(), 3);
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        assert!(matches!(
+            execute_sql(&mut txn, "REFRESH MATERIALIZED VIEW counts")?,
+            ResultSet::Refresh { count: 2, .. }
+        ));
+        txn.commit()?;
+        assert_eq!(
+            counts(&kv)?.1,
+            vec![
+                vec![Str("Ghibli".into()), Integer(1), Float(8.6)],
+                vec![Str("Mosfilm".into()), Integer(3), Float(8.1)],
+            ]
+        );
+
+        // The catalog distinguishes tables, views and materialized views.
+        let txn = kv.begin(Mode::ReadOnly)?;
+        let mut kinds: Vec<_> = txn
+            .scan_tables()?
+            .map(|t| {
+                let kind = match t.kind {
+                    TableKind::Table => "table",
+                    TableKind::View { .. } => "view",
+                    TableKind::MaterializedView { .. } => "materialized view",
+                };
+                (t.name, kind)
+            })
+            .collect();
+        kinds.sort();
+        assert_eq!(
+            kinds,
+            vec![
+                ("best".into(), "view"),
+                ("counts".into(), "materialized view"),
+                ("movies".into(), "table"),
+                ("rated".into(), "view"),
+                ("studios".into(), "table"),
+                ("top".into(), "view"),
+            ]
+        );
+        txn.commit()?;
+
+        // Materialized view columns take the datatypes inferred from the query's plan, even
+        // without any rows.
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        execute_sql(
+            &mut txn,
+            "CREATE MATERIALIZED VIEW unrated AS SELECT id, rating FROM movies WHERE rating > 10",
+        )?;
+        let unrated = txn.must_read_table("unrated")?;
+        assert_eq!(
+            unrated.columns.into_iter().map(|c| c.datatype).collect::<Vec<_>>(),
+            vec![Datatype::Integer, Datatype::Float, Datatype::Integer]
+        );
+
+        let mut error = |sql| execute_sql(&mut txn, sql).err();
+        assert_eq!(
+            error("INSERT INTO rated VALUES (1, 'x', 1)"),
+            Some(Error::Value("Can't modify view rated".into()))
+        );
+        assert_eq!(
+            error("DELETE FROM counts"),
+            Some(Error::Value("Can't modify view counts".into()))
+        );
+        assert_eq!(
+            error("DROP TABLE best"),
+            Some(Error::Value("best is a view, not a table".into()))
+        );
+        assert_eq!(
+            error("DROP VIEW movies"),
+            Some(Error::Value("movies is a table, not a view".into()))
+        );
+        assert_eq!(
+            error("DROP TABLE movies"),
+            Some(Error::Value("Table movies is referenced by view counts".into()))
+        );
+        assert_eq!(
+            error("DROP VIEW rated"),
+            Some(Error::Value("View rated is referenced by view best".into()))
+        );
+        assert_eq!(
+            error("REFRESH MATERIALIZED VIEW best"),
+            Some(Error::Value("best is not a materialized view".into()))
+        );
+        assert_eq!(
+            error("CREATE VIEW best AS SELECT 1"),
+            Some(Error::Value("Table best already exists".into()))
+        );
+        assert_eq!(
+            error("CREATE VIEW v AS SELECT * FROM missing"),
+            Some(Error::Value("Table missing does not exist".into()))
+        );
+        assert_eq!(
+            error("CREATE VIEW v AS SELECT * FROM movies WHERE id = ?"),
+            Some(Error::Value("Views can't have parameters".into()))
+        );
+        assert_eq!(
+            error("CREATE MATERIALIZED VIEW v AS SELECT m.id, s.id FROM movies m, studios s"),
+            Some(Error::Value("Duplicate column id in materialized view v".into()))
+        );
+
+        assert!(matches!(execute_sql(&mut txn, "DROP VIEW best")?, ResultSet::DropView { .. }));
+        execute_sql(&mut txn, "DROP VIEW top")?;
+        assert!(matches!(
+            execute_sql(&mut txn, "DROP MATERIALIZED VIEW counts")?,
+            ResultSet::DropView { .. }
+        ));
+        assert_eq!(
+            execute_sql(&mut txn, "SELECT * FROM best").err(),
+            Some(Error::Value("Table best does not exist".into()))
+        );
+        assert_eq!(
+            execute_sql(&mut txn, "DROP VIEW best").err(),
+            Some(Error::Value("View best does not exist".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn analyze() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        execute_sql(&mut txn, "CREATE VIEW rated AS SELECT * FROM movies WHERE rating > 8")?;
+        assert_eq!(txn.read_stats("movies")?, None);
+        match execute_sql(&mut txn, "ANALYZE")? {
+            ResultSet::Analyze { mut tables } => {
+                tables.sort();
+                assert_eq!(tables, vec!["movies".to_string(), "studios".to_string()]);
+            }
+            _ => panic!("Expected analyze result"),
+        }
+        txn.commit()?;
+
+        // Statistics are stored transactionally, and aren't updated by writes.
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        let stats = txn.read_stats("movies")?.expect("no stats");
+        assert_eq!(stats.rows, 5);
+        assert_eq!(stats.columns.len(), 4);
+        assert_eq!(stats.columns[0].distinct, 5);
+        assert_eq!(stats.columns[2].distinct, 3);
+        assert_eq!(stats.columns[2].null_fraction, 0.2);
+        assert_eq!(
+            stats.columns[3].histogram,
+            vec![Float(7.6), Float(8.0), Float(8.2), Float(8.6)]
+        );
+        execute_sql(&mut txn, "INSERT INTO movies VALUES (6, 'Mirror', 1, NULL)")?;
+        assert_eq!(txn.read_stats("movies")?.map(|s| s.rows), Some(5));
+        assert!(matches!(
+            execute_sql(&mut txn, "ANALYZE movies")?,
+            ResultSet::Analyze { tables } if tables == vec!["movies".to_string()]
+        ));
+        assert_eq!(txn.read_stats("movies")?.map(|s| s.rows), Some(6));
+        txn.commit()?;
+
+        // Joins are reordered by the statistics, without changing the results.
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT m.title, s.name, o.title FROM movies m
+                 JOIN movies o ON o.studio_id = m.studio_id AND o.id != m.id
+                 JOIN studios s ON s.id = m.studio_id ORDER BY m.id, o.id"
+            )?,
+            (
+                vec!["title".into(), "name".into(), "title".into()],
+                vec![
+                    vec![Str("Stalker".into()), Str("Mosfilm".into()), Str("Solaris".into())],
+                    vec![Str("Stalker".into()), Str("Mosfilm".into()), Str("Mirror".into())],
+                    vec![Str("Solaris".into()), Str("Mosfilm".into()), Str("Stalker".into())],
+                    vec![Str("Solaris".into()), Str("Mosfilm".into()), Str("Mirror".into())],
+                    vec![Str("Mirror".into()), Str("Mosfilm".into()), Str("Stalker".into())],
+                    vec![Str("Mirror".into()), Str("Mosfilm".into()), Str("Solaris".into())],
+                ]
+            )
+        );
+
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        assert_eq!(
+            execute_sql(&mut txn, "ANALYZE rated").err(),
+            Some(Error::Value("Can't analyze view rated".into()))
+        );
+        assert_eq!(
+            execute_sql(&mut txn, "ANALYZE missing").err(),
+            Some(Error::Value("Table missing does not exist".into()))
+        );
+        execute_sql(&mut txn, "DROP VIEW rated")?;
+        execute_sql(&mut txn, "DROP TABLE movies")?;
+        assert_eq!(txn.read_stats("movies")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn information_schema() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        execute_sql(&mut txn, "CREATE VIEW rated AS SELECT * FROM movies WHERE rating > 8")?;
+        execute_sql(
+            &mut txn,
+            "CREATE TABLE \"Odd \"\"name\"\"\" (
+                \"select\" INTEGER PRIMARY KEY,
+                label STRING DEFAULT 'it''s' UNIQUE,
+                score FLOAT NOT NULL DEFAULT 1.0 INDEX
+            )",
+        )?;
+        txn.commit()?;
+
+        assert_eq!(
+            query(&kv, "SELECT * FROM information_schema.tables")?,
+            (
+                vec!["table_name".into(), "table_type".into()],
+                vec![
+                    vec![Str("Odd \"name\"".into()), Str("TABLE".into())],
+                    vec![Str("movies".into()), Str("TABLE".into())],
+                    vec![Str("rated".into()), Str("VIEW".into())],
+                    vec![Str("studios".into()), Str("TABLE".into())],
+                ]
+            )
+        );
+        assert_eq!(
+            query(&kv, "SELECT * FROM information_schema.tables WHERE table_name = 'movies'")?.1,
+            vec![vec![Str("movies".into()), Str("TABLE".into())]]
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT column_name, ordinal_position, data_type, is_nullable, column_default
+                 FROM information_schema.columns WHERE table_name = 'movies'"
+            )?
+            .1,
+            vec![
+                vec![Str("id".into()), Integer(1), Str("INTEGER".into()), Bool(false), Null],
+                vec![Str("title".into()), Integer(2), Str("STRING".into()), Bool(false), Null],
+                vec![
+                    Str("studio_id".into()),
+                    Integer(3),
+                    Str("INTEGER".into()),
+                    Bool(true),
+                    Str("NULL".into())
+                ],
+                vec![
+                    Str("rating".into()),
+                    Integer(4),
+                    Str("FLOAT".into()),
+                    Bool(true),
+                    Str("NULL".into())
+                ],
+            ]
+        );
+        assert_eq!(
+            query(&kv, "SELECT * FROM information_schema.indexes i")?,
+            (
+                vec![
+                    "table_name".into(),
+                    "column_name".into(),
+                    "is_primary".into(),
+                    "is_unique".into()
+                ],
+                vec![
+                    vec![Str("Odd \"name\"".into()), Str("select".into()), Bool(true), Bool(true)],
+                    vec![Str("Odd \"name\"".into()), Str("score".into()), Bool(false), Bool(false)],
+                    vec![Str("movies".into()), Str("id".into()), Bool(true), Bool(true)],
+                    vec![Str("movies".into()), Str("studio_id".into()), Bool(false), Bool(false)],
+                    vec![Str("studios".into()), Str("id".into()), Bool(true), Bool(true)],
+                ]
+            )
+        );
+        assert_eq!(
+            query(
+                &kv,
+                "SELECT column_name, constraint_type, referenced_table
+                 FROM information_schema.constraints WHERE table_name = 'movies'"
+            )?
+            .1,
+            vec![
+                vec![Str("id".into()), Str("PRIMARY KEY".into()), Null],
+                vec![Str("title".into()), Str("NOT NULL".into()), Null],
+                vec![Str("studio_id".into()), Str("FOREIGN KEY".into()), Str("studios".into())],
+            ]
+        );
+
+        // SHOW TABLES and SHOW TRANSACTIONS query the information schema.
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        assert_eq!(execute_sql(&mut txn, "SHOW TABLES")?.into_rows()?.len(), 4);
+        let other = kv.begin(Mode::ReadOnly)?;
+        let snapshot = kv.begin(Mode::Snapshot { version: 1 })?;
+        assert_eq!(
+            execute_sql(&mut txn, "SHOW TRANSACTIONS")?.into_rows()?,
+            vec![
+                vec![Integer(txn.id() as i64), Str("READ WRITE".into()), Null, Bool(true)],
+                vec![Integer(other.id() as i64), Str("READ ONLY".into()), Null, Bool(false)],
+                vec![
+                    Integer(snapshot.id() as i64),
+                    Str("SNAPSHOT".into()),
+                    Integer(1),
+                    Bool(false)
+                ],
+            ]
+        );
+        other.commit()?;
+        snapshot.rollback()?;
+        assert_eq!(execute_sql(&mut txn, "SHOW TRANSACTIONS")?.into_rows()?.len(), 1);
+
+        // SHOW CREATE TABLE renders a statement that recreates the table.
+        execute_sql(
+            &mut txn,
+            "CREATE TABLE extremes (
+                id INTEGER PRIMARY KEY DEFAULT 9223372036854775807,
+                min INTEGER DEFAULT (-9223372036854775807 - 1),
+                f FLOAT DEFAULT -INFINITY
+            )",
+        )?;
+        for (table, sql) in [
+            ("movies", "SHOW CREATE TABLE movies"),
+            ("extremes", "SHOW CREATE TABLE extremes"),
+            ("Odd \"name\"", "SHOW CREATE TABLE \"Odd \"\"name\"\"\""),
+            (information::COLUMNS, "SHOW CREATE TABLE information_schema.columns"),
+        ] {
+            let schema = txn.must_read_table(table)?;
+            let rows = execute_sql(&mut txn, sql)?.into_rows()?;
+            let Value::String(ddl) = &rows[0][0] else { panic!("Expected string") };
+            match Plan::build(Parser::new(ddl).parse()?, &txn)?.0 {
+                Node::CreateTable { schema: created } => assert_eq!(created, schema),
+                node => panic!("Unexpected node {:?}", node),
+            }
+        }
+        assert_eq!(
+            execute_sql(&mut txn, "SHOW CREATE TABLE movies")?.into_rows()?,
+            vec![vec![Str(
+                "CREATE TABLE movies (
+  id INTEGER PRIMARY KEY,
+  title STRING NOT NULL,
+  studio_id INTEGER REFERENCES studios INDEX,
+  rating FLOAT
+)"
+                .into()
+            )]]
+        );
+
+        let mut error = |sql| execute_sql(&mut txn, sql).err();
+        assert_eq!(
+            error("SHOW CREATE TABLE rated"),
+            Some(Error::Value("rated is a view, not a table".into()))
+        );
+        assert_eq!(
+            error("INSERT INTO information_schema.tables VALUES ('t', 'TABLE')"),
+            Some(Error::Value("Can't modify system table information_schema.tables".into()))
+        );
+        assert_eq!(
+            error("DELETE FROM information_schema.transactions"),
+            Some(Error::Value("Can't modify system table information_schema.transactions".into()))
+        );
+        assert_eq!(
+            error("DROP TABLE information_schema.columns"),
+            Some(Error::Value("Can't modify system table information_schema.columns".into()))
+        );
+        assert_eq!(
+            error("CREATE TABLE \"information_schema.t\" (id INTEGER PRIMARY KEY)"),
+            Some(Error::Value("Can't modify system table information_schema.t".into()))
+        );
+        assert_eq!(
+            error("CREATE VIEW \"information_schema.v\" AS SELECT 1"),
+            Some(Error::Value("Can't modify system table information_schema.v".into()))
+        );
+        assert_eq!(
+            error("SELECT * FROM information_schema.views"),
+            Some(Error::Value("Table information_schema.views does not exist".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        assert_eq!(
+            execute_sql(&mut txn, "INSERT INTO studios VALUES (1, 'Duplicate')").err(),
+            Some(Error::Value("Primary key 1 already exists for table studios".into()))
+        );
+        assert_eq!(
+            execute_sql(&mut txn, "SELECT * FROM movies WHERE title").and_then(|r| r.into_rows()),
+            Err(Error::Value("Filter returned Stalker, expected boolean".into()))
+        );
+        Ok(())
+    }
+}
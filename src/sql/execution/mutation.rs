@@ -0,0 +1,218 @@
+use super::Output;
+use crate::error::{Error, Result};
+use crate::sql::storage::{
+    Column, Conflict, Datatype, Expression, Row, Table, TableKind, Transaction, Value,
+};
+
+/// Inserts the source rows into a table, filling in column defaults for any columns that
+/// aren't given. The rows are collected before any writes, and inserted with a single upsert.
+/// Returns the number of inserted or updated rows.
+pub fn insert<T: Transaction>(
+    txn: &mut T,
+    table: &str,
+    columns: Vec<String>,
+    source: Output,
+    conflict: Conflict,
+) -> Result<u64> {
+    let table = txn.must_read_table(table)?;
+    let rows = source
+        .rows
+        .map(|values| match columns.is_empty() {
+            true => pad_row(&table, values?),
+            false => make_row(&table, &columns, values?),
+        })
+        .collect::<Result<_>>()?;
+    txn.upsert(&table.name, rows, conflict)
+}
+
+/// Updates the source rows by evaluating the expressions for each of them. The source rows are
+/// collected before any writes, such that updates don't affect the rows being scanned.
+pub fn update<T: Transaction>(
+    txn: &mut T,
+    table: &str,
+    source: Output,
+    expressions: Vec<(usize, String, Expression)>,
+) -> Result<u64> {
+    let table = txn.must_read_table(table)?;
+    let rows = source.rows.collect::<Result<Vec<_>>>()?;
+    let mut count = 0;
+    for row in rows {
+        let id = table.get_row_key(&row)?;
+        let mut new = row.clone();
+        for (index, _, expr) in &expressions {
+            new[*index] = coerce(&table.columns[*index], expr.evaluate(Some(&row))?);
+        }
+        txn.update(&table.name, &id, new)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Deletes the source rows, collecting them before any writes.
+pub fn delete<T: Transaction>(txn: &mut T, table: &str, source: Output) -> Result<u64> {
+    let table = txn.must_read_table(table)?;
+    let rows = source.rows.collect::<Result<Vec<_>>>()?;
+    let mut count = 0;
+    for row in rows {
+        txn.delete(&table.name, &table.get_row_key(&row)?)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Stores the source rows as a new materialized view of the given kind, returning the number
+/// of rows. The column datatypes are the given datatypes inferred from the query's plan.
+/// Where they can't be inferred, e.g. for scalar subqueries, the datatypes of the rows' values
+/// are used instead, with integers and floats combined as floats, and columns of only NULLs
+/// as strings. A trailing row ID column is added as the primary key.
+pub fn materialize<T: Transaction>(
+    txn: &mut T,
+    name: &str,
+    kind: TableKind,
+    datatypes: Vec<Option<Datatype>>,
+    source: Output,
+) -> Result<u64> {
+    let rows = source.rows.collect::<Result<Vec<_>>>()?;
+    let table = view_table(name, kind, source.columns, &datatypes, &rows)?;
+    txn.create_table(table.clone())?;
+    create_view_rows(txn, &table, rows)
+}
+
+/// Replaces the rows of a materialized view with the source rows, i.e. its query results,
+/// whose column datatypes are given as for materialize(). The view is recreated if the
+/// datatypes of its columns have changed, which fails if other views reference it.
+pub fn refresh<T: Transaction>(
+    txn: &mut T,
+    name: &str,
+    datatypes: Vec<Option<Datatype>>,
+    source: Output,
+) -> Result<u64> {
+    let view = txn.must_read_table(name)?;
+    if !matches!(view.kind, TableKind::MaterializedView { .. }) {
+        return Err(Error::Internal(format!("{} is not a materialized view", name)));
+    }
+    // Read the query results before removing the old rows.
+    let rows = source.rows.collect::<Result<Vec<_>>>()?;
+    let table = view_table(name, view.kind.clone(), source.columns, &datatypes, &rows)?;
+    if table.columns == view.columns {
+        let keys = txn
+            .scan(name, None)?
+            .map(|row| view.get_row_key(&row?))
+            .collect::<Result<Vec<_>>>()?;
+        for key in keys {
+            txn.delete(name, &key)?;
+        }
+    } else {
+        txn.delete_table(name)?;
+        txn.create_table(table.clone())?;
+    }
+    create_view_rows(txn, &table, rows)
+}
+
+/// Builds the schema of a materialized view with the given column labels, see materialize().
+fn view_table(
+    name: &str,
+    kind: TableKind,
+    labels: Vec<String>,
+    datatypes: &[Option<Datatype>],
+    rows: &[Row],
+) -> Result<Table> {
+    let mut columns = Vec::with_capacity(labels.len() + 1);
+    for (i, label) in labels.into_iter().enumerate() {
+        if columns.iter().any(|c: &Column| c.name == label) {
+            return Err(Error::Value(format!(
+                "Duplicate column {} in materialized view {}",
+                label, name
+            )));
+        }
+        let mut datatype = datatypes.get(i).cloned().flatten();
+        if datatype.is_none() {
+            for value in rows.iter().filter_map(|row| row[i].datatype()) {
+                datatype = match datatype {
+                    None => Some(value),
+                    Some(d) => Some(d.coerce(&value).ok_or_else(|| {
+                        Error::Value(format!(
+                            "Column {} in materialized view {} has values of type {} and {}",
+                            label, name, d, value
+                        ))
+                    })?),
+                };
+            }
+        }
+        columns.push(Column {
+            name: label,
+            datatype: datatype.unwrap_or(Datatype::String),
+            primary_key: false,
+            nullalbe: true,
+            default: Some(Value::Null),
+            unique: false,
+            reference: None,
+            index: false,
+        });
+    }
+    columns.push(Column {
+        name: "#rowid".into(),
+        datatype: Datatype::Integer,
+        primary_key: true,
+        nullalbe: false,
+        default: None,
+        unique: true,
+        reference: None,
+        index: false,
+    });
+    Ok(Table { name: name.into(), columns, kind })
+}
+
+/// Stores the rows of a materialized view, numbering their row IDs from 1. Returns the number
+/// of rows.
+fn create_view_rows<T: Transaction>(txn: &mut T, table: &Table, rows: Vec<Row>) -> Result<u64> {
+    let mut count = 0;
+    for row in rows {
+        let mut row: Row =
+            row.into_iter().zip(&table.columns).map(|(v, c)| coerce(c, v)).collect();
+        row.push(Value::Integer(count as i64 + 1));
+        txn.create(&table.name, row)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Builds a row from values given for the leading columns, padding it with column defaults.
+fn pad_row(table: &Table, values: Vec<Value>) -> Result<Row> {
+    let mut row = Vec::with_capacity(table.columns.len());
+    let mut values = values.into_iter();
+    for column in &table.columns {
+        row.push(match values.next() {
+            Some(value) => coerce(column, value),
+            None => default(table, column)?,
+        });
+    }
+    Ok(row)
+}
+
+/// Builds a row from values given for the named columns, using defaults for the others.
+fn make_row(table: &Table, columns: &[String], values: Vec<Value>) -> Result<Row> {
+    let mut row = Vec::with_capacity(table.columns.len());
+    for column in &table.columns {
+        row.push(match columns.iter().position(|c| c == &column.name) {
+            Some(i) => coerce(column, values[i].clone()),
+            None => default(table, column)?,
+        });
+    }
+    Ok(row)
+}
+
+/// Returns the default value of a column that wasn't given a value.
+fn default(table: &Table, column: &Column) -> Result<Value> {
+    column.default.clone().ok_or_else(|| {
+        Error::Value(format!("No value given for column {} in table {}", column.name, table.name))
+    })
+}
+
+/// Converts integers to floats for float columns, leaving other values as-is for validation.
+fn coerce(column: &Column, value: Value) -> Value {
+    match (&column.datatype, value) {
+        (Datatype::Float, Value::Integer(i)) => Value::Float(i as f64),
+        (_, value) => value,
+    }
+}
@@ -0,0 +1,60 @@
+use super::Output;
+use crate::error::Error;
+use crate::sql::plan::label;
+use crate::sql::storage::{Expression, Value};
+
+use std::cmp::Ordering;
+
+/// Emits the rows for which the predicate evaluates to true.
+pub fn filter(source: Output, predicate: Expression) -> Output {
+    let rows = source.rows.filter_map(move |r| {
+        r.and_then(|row| match predicate.evaluate(Some(&row))? {
+            Value::Boolean(true) => Ok(Some(row)),
+            Value::Boolean(false) | Value::Null => Ok(None),
+            value => Err(Error::Value(format!("Filter returned {}, expected boolean", value))),
+        })
+        .transpose()
+    });
+    Output { columns: source.columns, rows: Box::new(rows) }
+}
+
+/// Evaluates the expressions for each row. Unlabeled field references inherit the source
+/// column name, e.g. for aggregate results.
+pub fn projection(source: Output, expressions: Vec<(Expression, Option<String>)>) -> Output {
+    let columns = expressions
+        .iter()
+        .map(|(e, a)| match (e, a) {
+            (Expression::Field(i, None), None) => source.columns[*i].clone(),
+            (e, a) => label(e, a),
+        })
+        .collect();
+    let expressions: Vec<_> = expressions.into_iter().map(|(e, _)| e).collect();
+    let rows = source.rows.map(move |r| {
+        r.and_then(|row| expressions.iter().map(|e| e.evaluate(Some(&row))).collect())
+    });
+    Output { columns, rows: Box::new(rows) }
+}
+
+/// Skips offset rows, then emits at most limit rows.
+pub fn limit(source: Output, offset: usize, limit: Option<usize>) -> Output {
+    let rows = source.rows.skip(offset);
+    match limit {
+        Some(limit) => Output { columns: source.columns, rows: Box::new(rows.take(limit)) },
+        None => Output { columns: source.columns, rows: Box::new(rows) },
+    }
+}
+
+/// Compares two values for sorting, in a total order: NULL sorts first, integers and floats
+/// are compared numerically with NaN after all other numbers, and incomparable datatypes are
+/// ordered by datatype.
+pub fn compare(a: &Value, b: &Value) -> Ordering {
+    let rank = |v: &Value| match v {
+        Value::Null => 0,
+        Value::Boolean(_) => 1,
+        Value::Integer(_) => 2,
+        Value::Float(f) if f.is_nan() => 3,
+        Value::Float(_) => 2,
+        Value::String(_) => 4,
+    };
+    a.compare(b).unwrap_or_else(|| rank(a).cmp(&rank(b)))
+}
@@ -0,0 +1,77 @@
+use super::set::cast;
+use super::{query, Config, Context, Output};
+use crate::error::{Error, Result};
+use crate::sql::plan::Node;
+use crate::sql::storage::{Datatype, Expression, Row, Transaction};
+
+use std::collections::HashSet;
+
+/// Evaluates a recursive CTE to a fixpoint, emitting the anchor column names. The anchor rows
+/// form the initial working table, and each iteration runs the recursive term against the
+/// working table, whose rows are replaced by the iteration's new rows. Without all, rows that
+/// were already emitted are discarded, so the recursion ends once no new rows are found. It
+/// errors after the configured number of iterations, to prevent runaway recursion. The rows
+/// are processed eagerly, since the iterations borrow the transaction.
+pub fn recursive<T: Transaction>(
+    txn: &T,
+    anchor: Output,
+    name: &str,
+    recursive: Node,
+    all: bool,
+    datatypes: Vec<Option<Datatype>>,
+    config: &Config,
+) -> Result<Output> {
+    let ctx = Context { config, stats: None };
+    let mut seen = HashSet::new();
+    let mut rows = Vec::new();
+    let mut add = |new: &mut Vec<Row>, row: Result<Row>| -> Result<()> {
+        let row = cast(row?, &datatypes)?;
+        if all || seen.insert(row.clone()) {
+            new.push(row.clone());
+            rows.push(row);
+        }
+        Ok(())
+    };
+
+    let mut working = Vec::new();
+    for row in anchor.rows {
+        add(&mut working, row)?;
+    }
+    let mut iterations = 0;
+    while !working.is_empty() {
+        if iterations == config.recursion_limit {
+            return Err(Error::Value(format!(
+                "WITH RECURSIVE query {} exceeded {} iterations",
+                name, config.recursion_limit
+            )));
+        }
+        iterations += 1;
+        let node = substitute(recursive.clone(), name, &working)?;
+        let mut new = Vec::new();
+        for row in query(node, txn, &ctx)?.rows {
+            add(&mut new, row)?;
+        }
+        working = new;
+    }
+    Ok(Output { columns: anchor.columns, rows: Box::new(rows.into_iter().map(Ok)) })
+}
+
+/// Replaces references to the named working table with its rows, including in subquery plans.
+fn substitute(node: Node, name: &str, rows: &[Row]) -> Result<Node> {
+    node.transform(
+        &|node| match node {
+            Node::WorkingTable { name: n, .. } if n == name => Ok(Node::Values {
+                rows: rows
+                    .iter()
+                    .map(|row| row.iter().cloned().map(Expression::Constant).collect())
+                    .collect(),
+            }),
+            Node::Subquery { source, subquery, kind } => {
+                let subquery = Box::new(substitute(*subquery, name, rows)?);
+                Ok(Node::Subquery { source, subquery, kind })
+            }
+            node => Ok(node),
+        },
+        &Ok,
+    )
+}
@@ -0,0 +1,77 @@
+use super::{Output, Rows};
+use crate::error::Result;
+use crate::sql::plan::SetOperator;
+use crate::sql::storage::{Datatype, Row};
+
+use std::collections::{HashMap, HashSet};
+
+/// Combines the left and right rows by a set operation, emitting the left column names. The
+/// right rows are buffered in a hash table for INTERSECT and EXCEPT, while the left rows are
+/// streamed. Without all, duplicates are removed via a hash set of emitted rows. Rows are
+/// compared by value equality, so NULLs are equal to each other.
+pub fn set_operation(
+    left: Output,
+    right: Output,
+    operator: SetOperator,
+    all: bool,
+    datatypes: Vec<Option<Datatype>>,
+) -> Result<Output> {
+    let cast_rows = move |rows: Rows| -> Rows {
+        let datatypes = datatypes.clone();
+        Box::new(rows.map(move |row| cast(row?, &datatypes)))
+    };
+    let (left_rows, right_rows) = (cast_rows(left.rows), cast_rows(right.rows));
+
+    let rows: Rows = match operator {
+        SetOperator::Union => Box::new(left_rows.chain(right_rows)),
+        SetOperator::Intersect | SetOperator::Except => {
+            // Counts the right rows, which are consumed by matching left rows with ALL.
+            let mut counts: HashMap<Row, usize> = HashMap::new();
+            for row in right_rows {
+                *counts.entry(row?).or_default() += 1;
+            }
+            Box::new(left_rows.filter_map(move |row| {
+                let row = match row {
+                    Ok(row) => row,
+                    Err(err) => return Some(Err(err)),
+                };
+                let matched = match counts.get_mut(&row) {
+                    Some(count) if *count > 0 => {
+                        if all {
+                            *count -= 1;
+                        }
+                        true
+                    }
+                    _ => false,
+                };
+                let emit = match operator {
+                    SetOperator::Intersect => matched,
+                    _ => !matched,
+                };
+                emit.then_some(Ok(row))
+            }))
+        }
+    };
+    let rows = match all {
+        true => rows,
+        false => {
+            let mut seen = HashSet::new();
+            Box::new(rows.filter(move |row| match row {
+                Ok(row) => seen.insert(row.clone()),
+                Err(_) => true,
+            }))
+        }
+    };
+    Ok(Output { columns: left.columns, rows })
+}
+
+/// Casts a row's values to the given common column datatypes, where they differ.
+pub fn cast(row: Row, datatypes: &[Option<Datatype>]) -> Result<Row> {
+    row.into_iter()
+        .enumerate()
+        .map(|(i, value)| match (datatypes.get(i), value.datatype()) {
+            (Some(Some(datatype)), Some(d)) if d != *datatype => value.cast(datatype),
+            _ => Ok(value),
+        })
+        .collect()
+}
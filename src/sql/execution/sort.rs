@@ -0,0 +1,242 @@
+use super::query::compare;
+use super::{Config, Output, Rows};
+use crate::error::Result;
+use crate::sql::plan::{Direction, Nulls};
+use crate::sql::storage::{Expression, Row, Value};
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write as _};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Sorts the rows by the given expressions. With a limit, only the first limit rows are kept,
+/// using a bounded heap (top-N sort) as long as it fits in config.sort_memory bytes. Otherwise,
+/// rows are buffered in memory until they exceed config.sort_memory bytes, at which point the
+/// buffer is sorted and spilled to a temporary file as a run. The runs are then merged with a
+/// k-way merge as the output is read.
+pub fn order(
+    source: Output,
+    orders: Vec<(Expression, Direction, Nulls)>,
+    limit: Option<usize>,
+    config: &Config,
+) -> Result<Output> {
+    let (expressions, orders): (Vec<_>, Vec<_>) =
+        orders.into_iter().map(|(e, d, n)| (e, (d, n))).unzip();
+    let orders = Arc::new(orders);
+    let mut entries = source.rows.enumerate().map(|(seq, r)| {
+        let row = r?;
+        let keys = expressions.iter().map(|e| e.evaluate(Some(&row))).collect::<Result<_>>()?;
+        Ok(Entry { keys, row, seq: seq as u64, orders: orders.clone() })
+    });
+
+    let mut buffer = Vec::new();
+    if let Some(limit) = limit {
+        match top_n(&mut entries, limit, config.sort_memory)? {
+            TopN::Sorted(entries) => {
+                let rows = Box::new(entries.into_iter().map(|e| Ok(e.row)));
+                return Ok(Output { columns: source.columns, rows });
+            }
+            TopN::Overflow(entries) => buffer = entries,
+        }
+    }
+
+    let mut runs = Vec::new();
+    let mut size = buffer.iter().map(Entry::size).sum();
+    for entry in entries {
+        let entry = entry?;
+        size += entry.size();
+        buffer.push(entry);
+        if size > config.sort_memory {
+            runs.push(Run::spill(std::mem::take(&mut buffer), config)?);
+            size = 0;
+        }
+    }
+    buffer.sort();
+    let rows: Rows = if runs.is_empty() {
+        Box::new(buffer.into_iter().map(|e| Ok(e.row)))
+    } else {
+        runs.push(Run::Memory(buffer.into_iter()));
+        Box::new(Merge::new(runs, orders)?)
+    };
+    let rows = match limit {
+        Some(limit) => Box::new(rows.take(limit)),
+        None => rows,
+    };
+    Ok(Output { columns: source.columns, rows })
+}
+
+/// The result of a top-N sort.
+enum TopN {
+    /// The first limit entries, in sorted order.
+    Sorted(Vec<Entry>),
+    /// The buffered entries, unsorted, once they exceeded the memory budget. The remaining
+    /// entries haven't been consumed.
+    Overflow(Vec<Entry>),
+}
+
+/// Sorts the first limit entries, buffering at most limit + 1 entries in a max-heap whose
+/// largest entry is evicted when it's full. Stops if the heap exceeds memory bytes.
+fn top_n(
+    entries: &mut impl Iterator<Item = Result<Entry>>,
+    limit: usize,
+    memory: usize,
+) -> Result<TopN> {
+    if limit == 0 {
+        return Ok(TopN::Sorted(Vec::new()));
+    }
+    let mut heap = BinaryHeap::new();
+    let mut size = 0;
+    for entry in entries {
+        let entry = entry?;
+        size += entry.size();
+        heap.push(entry);
+        if heap.len() > limit {
+            size -= heap.pop().map_or(0, |e| e.size());
+        }
+        if size > memory {
+            return Ok(TopN::Overflow(heap.into_vec()));
+        }
+    }
+    Ok(TopN::Sorted(heap.into_sorted_vec()))
+}
+
+/// A row with its sort keys. The input sequence number breaks ties, which keeps the sort stable
+/// across top-N heaps and spilled runs.
+struct Entry {
+    keys: Vec<Value>,
+    row: Row,
+    seq: u64,
+    orders: Arc<Vec<(Direction, Nulls)>>,
+}
+
+impl Entry {
+    /// Returns the approximate in-memory size of the entry in bytes.
+    fn size(&self) -> usize {
+        self.keys
+            .iter()
+            .chain(&self.row)
+            .map(|v| match v {
+                Value::String(s) => std::mem::size_of::<Value>() + s.len(),
+                _ => std::mem::size_of::<Value>(),
+            })
+            .sum()
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for ((a, b), (direction, nulls)) in self.keys.iter().zip(&other.keys).zip(&*self.orders) {
+            let ordering = match (a, b, nulls) {
+                (Value::Null, Value::Null, _) => Ordering::Equal,
+                (Value::Null, _, Nulls::First) | (_, Value::Null, Nulls::Last) => Ordering::Less,
+                (Value::Null, _, Nulls::Last) | (_, Value::Null, Nulls::First) => {
+                    Ordering::Greater
+                }
+                (a, b, _) => match direction {
+                    Direction::Ascending => compare(a, b),
+                    Direction::Descending => compare(a, b).reverse(),
+                },
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        self.seq.cmp(&other.seq)
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+/// A sorted run of entries, either in memory or spilled to a temporary file.
+enum Run {
+    Memory(std::vec::IntoIter<Entry>),
+    File { reader: BufReader<File>, remaining: usize, _file: TempFile },
+}
+
+impl Run {
+    /// Sorts the entries and writes them to a temporary file as (seq, keys, row) tuples.
+    fn spill(mut entries: Vec<Entry>, config: &Config) -> Result<Self> {
+        entries.sort();
+        let file = TempFile(config.temp_dir.join(format!("sort-{}", uuid::Uuid::new_v4())));
+        let mut writer = BufWriter::new(File::create(&file.0)?);
+        for entry in &entries {
+            bincode::serialize_into(&mut writer, &(entry.seq, &entry.keys, &entry.row))?;
+        }
+        writer.flush()?;
+        let reader = BufReader::new(File::open(&file.0)?);
+        Ok(Self::File { reader, remaining: entries.len(), _file: file })
+    }
+
+    /// Returns the next entry of the run, if any.
+    fn next(&mut self, orders: &Arc<Vec<(Direction, Nulls)>>) -> Result<Option<Entry>> {
+        match self {
+            Self::Memory(entries) => Ok(entries.next()),
+            Self::File { remaining: 0, .. } => Ok(None),
+            Self::File { reader, remaining, .. } => {
+                let (seq, keys, row) = bincode::deserialize_from(reader)?;
+                *remaining -= 1;
+                Ok(Some(Entry { keys, row, seq, orders: orders.clone() }))
+            }
+        }
+    }
+}
+
+/// A temporary file, which is removed when dropped.
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+/// A k-way merge of sorted runs, using a min-heap of the next entry of each run.
+struct Merge {
+    runs: Vec<Run>,
+    heap: BinaryHeap<Reverse<(Entry, usize)>>,
+    orders: Arc<Vec<(Direction, Nulls)>>,
+}
+
+impl Merge {
+    fn new(mut runs: Vec<Run>, orders: Arc<Vec<(Direction, Nulls)>>) -> Result<Self> {
+        let mut heap = BinaryHeap::new();
+        for (i, run) in runs.iter_mut().enumerate() {
+            if let Some(entry) = run.next(&orders)? {
+                heap.push(Reverse((entry, i)));
+            }
+        }
+        Ok(Self { runs, heap, orders })
+    }
+
+    fn try_next(&mut self) -> Result<Option<Row>> {
+        let Some(Reverse((entry, i))) = self.heap.pop() else {
+            return Ok(None);
+        };
+        if let Some(next) = self.runs[i].next(&self.orders)? {
+            self.heap.push(Reverse((next, i)));
+        }
+        Ok(Some(entry.row))
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().transpose()
+    }
+}
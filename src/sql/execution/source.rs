@@ -0,0 +1,56 @@
+use super::Output;
+use crate::error::Result;
+use crate::sql::storage::{Expression, Table, Transaction, Value};
+
+fn columns(table: &Table) -> Vec<String> {
+    table.columns.iter().map(|c| c.name.clone()).collect()
+}
+
+/// Scans a table's rows, filtered by the storage engine.
+pub fn scan<T: Transaction>(txn: &T, table: &str, filter: Option<Expression>) -> Result<Output> {
+    let table = txn.must_read_table(table)?;
+    Ok(Output { columns: columns(&table), rows: Box::new(txn.scan(&table.name, filter)?) })
+}
+
+/// Reads the rows with the given primary keys, skipping missing ones.
+pub fn key_lookup<T: Transaction>(txn: &T, table: &str, keys: Vec<Value>) -> Result<Output> {
+    let table = txn.must_read_table(table)?;
+    let mut rows = Vec::new();
+    for key in keys {
+        if let Some(row) = txn.read(&table.name, &key)? {
+            rows.push(row);
+        }
+    }
+    Ok(Output { columns: columns(&table), rows: Box::new(rows.into_iter().map(Ok)) })
+}
+
+/// Reads the rows whose indexed column has one of the given values.
+pub fn index_lookup<T: Transaction>(
+    txn: &T,
+    table: &str,
+    column: &str,
+    values: Vec<Value>,
+) -> Result<Output> {
+    let table = txn.must_read_table(table)?;
+    let mut keys = Vec::new();
+    for value in values {
+        let mut ids: Vec<_> = txn.read_index(&table.name, column, &value)?.into_iter().collect();
+        // Index entries are unordered sets, so sort them for a deterministic output.
+        ids.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        keys.extend(ids);
+    }
+    key_lookup(txn, &table.name, keys)
+}
+
+/// Emits a single empty row.
+pub fn nothing() -> Output {
+    Output { columns: Vec::new(), rows: Box::new(std::iter::once(Ok(Vec::new()))) }
+}
+
+/// Emits rows of constant expressions, labelling the columns by position.
+pub fn values(rows: Vec<Vec<Expression>>) -> Output {
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let columns = (1..=width).map(|i| format!("column{}", i)).collect();
+    let rows = rows.into_iter().map(|row| row.into_iter().map(|e| e.evaluate(None)).collect());
+    Output { columns, rows: Box::new(rows) }
+}
@@ -0,0 +1,98 @@
+use super::{query, Config, Context, Output};
+use crate::error::{Error, Result};
+use crate::sql::plan::{Node, Plan, SubqueryKind};
+use crate::sql::storage::{Expression, Row, Transaction, Value};
+
+use std::cell::Cell;
+
+/// Evaluates a subquery for each source row, appending its result as a column. Uncorrelated
+/// subqueries are executed once, while correlated subqueries are executed for each row with
+/// their outer references replaced by the row's values. These are re-optimized first, such
+/// that the values can be used for key and index lookups. The rows are processed eagerly,
+/// since the subquery executions borrow the transaction.
+pub fn subquery<T: Transaction>(
+    txn: &T,
+    source: Output,
+    subquery: Node,
+    kind: SubqueryKind,
+    config: &Config,
+) -> Result<Output> {
+    let ctx = Context { config, stats: None };
+    let uncorrelated = match is_correlated(&subquery)? {
+        true => None,
+        false => Some(query(subquery.clone(), txn, &ctx)?.rows.collect::<Result<Vec<_>>>()?),
+    };
+    let mut rows = Vec::new();
+    for row in source.rows {
+        let mut row = row?;
+        let value = match &uncorrelated {
+            Some(results) => evaluate(&kind, &row, results)?,
+            None => {
+                let plan = Plan(substitute(subquery.clone(), &row)?).optimize(txn)?;
+                let results = query(plan.0, txn, &ctx)?.rows.collect::<Result<Vec<_>>>()?;
+                evaluate(&kind, &row, &results)?
+            }
+        };
+        row.push(value);
+        rows.push(row);
+    }
+
+    let mut columns = source.columns;
+    columns.push(
+        match kind {
+            SubqueryKind::Exists => "exists",
+            SubqueryKind::In(_) => "in",
+            SubqueryKind::Scalar => "subquery",
+        }
+        .into(),
+    );
+    Ok(Output { columns, rows: Box::new(rows.into_iter().map(Ok)) })
+}
+
+/// Computes the subquery result for a source row, given the subquery's rows.
+fn evaluate(kind: &SubqueryKind, row: &Row, results: &[Row]) -> Result<Value> {
+    Ok(match kind {
+        SubqueryKind::Exists => Value::Boolean(!results.is_empty()),
+        // Evaluated like an IN list of the subquery's values, with the same NULL semantics.
+        SubqueryKind::In(expr) => {
+            let value = Box::new(Expression::Constant(expr.evaluate(Some(row))?));
+            let list = results.iter().map(|r| Expression::Constant(r[0].clone())).collect();
+            Expression::In(value, list).evaluate(None)?
+        }
+        SubqueryKind::Scalar => match results {
+            [] => Value::Null,
+            [result] => result[0].clone(),
+            _ => return Err(Error::Value("Scalar subquery returned more than one row".into())),
+        },
+    })
+}
+
+/// Returns true if the subquery refers to the outer row. Outer references in nested
+/// subqueries refer to this subquery's rows instead, and aren't considered.
+fn is_correlated(subquery: &Node) -> Result<bool> {
+    let correlated = Cell::new(false);
+    subquery.clone().transform(&Ok, &|n| {
+        n.map_expressions(&|e| {
+            if e.contains(&|e| matches!(e, Expression::Outer(_, _))) {
+                correlated.set(true);
+            }
+            Ok(e)
+        })
+    })?;
+    Ok(correlated.get())
+}
+
+/// Replaces the subquery's outer references with the outer row's values.
+fn substitute(subquery: Node, row: &Row) -> Result<Node> {
+    subquery.transform(&Ok, &|n| {
+        n.map_expressions(&|e| {
+            e.transform(
+                &|e| match e {
+                    Expression::Outer(i, _) => Ok(Expression::Constant(row[i].clone())),
+                    e => Ok(e),
+                },
+                &Ok,
+            )
+        })
+    })
+}
@@ -0,0 +1,172 @@
+use super::aggregate::Accumulator;
+use super::query::compare;
+use super::Output;
+use crate::error::{Error, Result};
+use crate::sql::parser::ast::{Frame, FrameBound};
+use crate::sql::plan::{Direction, Nulls, WindowFunction};
+use crate::sql::storage::{Expression, Row, Value};
+
+use std::cmp::Ordering;
+use std::ops::Range;
+
+/// Evaluates a window function for each source row, appending its value as a column. The
+/// source rows must be sorted by the partition and order keys, and are buffered in memory such
+/// that each partition can be processed as a whole. Rows with equal order keys are peers.
+pub fn window(
+    source: Output,
+    function: WindowFunction,
+    args: Vec<Expression>,
+    partition_by: Vec<Expression>,
+    order: Vec<(Expression, Direction, Nulls)>,
+    frame: Option<Frame>,
+) -> Result<Output> {
+    let mut columns = source.columns;
+    columns.push(function.format(&args));
+
+    let rows = source.rows.collect::<Result<Vec<_>>>()?;
+    let keys = |exprs: Vec<&Expression>| {
+        rows.iter()
+            .map(|row| exprs.iter().map(|e| e.evaluate(Some(row))).collect())
+            .collect::<Result<Vec<Vec<_>>>>()
+    };
+    let partition_keys = keys(partition_by.iter().collect())?;
+    let order_keys = keys(order.iter().map(|(e, _, _)| e).collect())?;
+
+    let mut values = Vec::with_capacity(rows.len());
+    let mut start = 0;
+    while start < rows.len() {
+        let mut end = start + 1;
+        while end < rows.len() && equal(&partition_keys[start], &partition_keys[end]) {
+            end += 1;
+        }
+        let partition = Partition {
+            rows: &rows[start..end],
+            order_keys: &order_keys[start..end],
+            ordered: !order.is_empty(),
+        };
+        values.extend(partition.evaluate(function, &args, frame)?);
+        start = end;
+    }
+
+    let rows = rows
+        .into_iter()
+        .zip(values)
+        .map(|(mut row, value)| {
+            row.push(value);
+            row
+        })
+        .collect::<Vec<_>>();
+    Ok(Output { columns, rows: Box::new(rows.into_iter().map(Ok)) })
+}
+
+/// Whether two keys are equal, where NULLs are equal to each other.
+fn equal(a: &[Value], b: &[Value]) -> bool {
+    a.iter().zip(b).all(|(a, b)| compare(a, b) == Ordering::Equal)
+}
+
+/// A window partition, i.e. a contiguous run of rows with equal partition keys.
+struct Partition<'a> {
+    rows: &'a [Row],
+    order_keys: &'a [Vec<Value>],
+    ordered: bool,
+}
+
+impl Partition<'_> {
+    /// Evaluates the window function for each row in the partition.
+    fn evaluate(
+        &self,
+        function: WindowFunction,
+        args: &[Expression],
+        frame: Option<Frame>,
+    ) -> Result<Vec<Value>> {
+        let len = self.rows.len();
+        let mut values = Vec::with_capacity(len);
+        match function {
+            WindowFunction::RowNumber => {
+                values.extend((1..=len).map(|n| Value::Integer(n as i64)));
+            }
+            WindowFunction::Rank | WindowFunction::DenseRank => {
+                let (mut rank, mut dense_rank) = (0, 0);
+                for i in 0..len {
+                    if i == 0 || !equal(&self.order_keys[i - 1], &self.order_keys[i]) {
+                        (rank, dense_rank) = (i + 1, dense_rank + 1);
+                    }
+                    values.push(Value::Integer(match function {
+                        WindowFunction::Rank => rank as i64,
+                        _ => dense_rank as i64,
+                    }));
+                }
+            }
+            WindowFunction::Lag | WindowFunction::Lead => {
+                for (i, row) in self.rows.iter().enumerate() {
+                    let offset = match args.get(1).map(|e| e.evaluate(Some(row))).transpose()? {
+                        None => 1,
+                        Some(Value::Integer(offset)) if offset >= 0 => offset as usize,
+                        Some(value) => {
+                            return Err(Error::Value(format!(
+                                "{} offset must be a non-negative integer, got {}",
+                                function, value
+                            )))
+                        }
+                    };
+                    let target = match function {
+                        WindowFunction::Lag => i.checked_sub(offset),
+                        _ => i.checked_add(offset).filter(|j| *j < len),
+                    };
+                    values.push(match (target, args.get(2)) {
+                        (Some(j), _) => args[0].evaluate(Some(&self.rows[j]))?,
+                        (None, Some(default)) => default.evaluate(Some(row))?,
+                        (None, None) => Value::Null,
+                    });
+                }
+            }
+            WindowFunction::Aggregate(aggregate) => {
+                // The accumulator covers the rows in acc_range. As long as the frame start
+                // stays put, the frame only grows and rows are added incrementally, otherwise
+                // the accumulator is rebuilt for the frame.
+                let args = self
+                    .rows
+                    .iter()
+                    .map(|row| args[0].evaluate(Some(row)))
+                    .collect::<Result<Vec<_>>>()?;
+                let mut acc = Accumulator::new(aggregate);
+                let mut acc_range = 0..0;
+                for i in 0..len {
+                    let range = self.frame(i, frame);
+                    if range.start != acc_range.start || range.end < acc_range.end {
+                        acc = Accumulator::new(aggregate);
+                        acc_range = range.start..range.start;
+                    }
+                    for value in &args[acc_range.end..range.end] {
+                        acc.add(value.clone())?;
+                    }
+                    acc_range.end = range.end;
+                    values.push(acc.value());
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Returns the frame of the row at the given index, as a range of partition rows. Without
+    /// an explicit frame, this is the rows up to the row's last peer, or the entire partition
+    /// without an ORDER BY.
+    fn frame(&self, i: usize, frame: Option<Frame>) -> Range<usize> {
+        let len = self.rows.len();
+        let Some(Frame { start, end }) = frame else {
+            if !self.ordered {
+                return 0..len;
+            }
+            let mut end = i + 1;
+            while end < len && equal(&self.order_keys[i], &self.order_keys[end]) {
+                end += 1;
+            }
+            return 0..end;
+        };
+        let bound = |bound: FrameBound, add: i64| {
+            (i as i64).saturating_add(bound.offset()).saturating_add(add).clamp(0, len as i64)
+        };
+        let (start, end) = (bound(start, 0) as usize, bound(end, 1) as usize);
+        start..end.max(start)
+    }
+}
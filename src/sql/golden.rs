@@ -0,0 +1,216 @@
+use super::execution::{Config, ResultSet};
+use super::session::Session;
+use super::storage::schema::literal;
+use super::storage::{Engine, Kv, LogStore, Memory, Mode, Mvcc, Raft, RaftState};
+use crate::error::{Error, Result};
+use crate::raft::{log::Log, Client, Server};
+
+use pretty_assertions::assert_eq;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The directory of golden scripts, relative to the crate root. A script is a sequence of
+/// cases separated by blank lines, where a case is a SQL statement followed by a ---- line and
+/// the statement's expected output. Lines starting with # before a case are comments. A
+/// statement prefixed with [name] runs in the named session, e.g. to interleave concurrent
+/// transactions, and others run in the default session.
+const DIR: &str = "src/sql/golden";
+
+/// If set, the scripts are rewritten with the actual output rather than compared with it.
+const UPDATE: &str = "UPDATE_GOLDEN";
+
+/// A case in a golden script.
+struct Case {
+    comments: Vec<String>,
+    sql: String,
+}
+
+/// Parses a golden script into its cases. The expected output is skipped, since the scripts
+/// are compared with the actual output as a whole.
+fn parse(script: &str) -> Result<Vec<Case>> {
+    let mut cases = Vec::new();
+    let mut lines = script.lines().peekable();
+    loop {
+        while lines.next_if(|l| l.trim().is_empty()).is_some() {}
+        if lines.peek().is_none() {
+            return Ok(cases);
+        }
+        let mut case = Case { comments: Vec::new(), sql: String::new() };
+        while let Some(comment) = lines.next_if(|l| l.starts_with('#')) {
+            case.comments.push(comment.to_string());
+        }
+        let mut sql = Vec::new();
+        loop {
+            match lines.next() {
+                Some("----") => break,
+                Some(line) if !line.trim().is_empty() => sql.push(line),
+                _ => return Err(Error::Parse(format!("Missing ---- after {}", sql.join("\n")))),
+            }
+        }
+        case.sql = sql.join("\n");
+        while lines.next_if(|l| !l.trim().is_empty()).is_some() {}
+        cases.push(case);
+    }
+}
+
+/// Runs the cases against sessions created by the given function, returning the script with
+/// the actual output of each case.
+fn run<E: Engine>(cases: &[Case], mut engine: impl FnMut() -> E) -> String {
+    let mut sessions: HashMap<String, Session<E>> = HashMap::new();
+    let mut output = Vec::new();
+    for case in cases {
+        let (name, sql) = match case.sql.strip_prefix('[').and_then(|s| s.split_once(']')) {
+            Some((name, sql)) => (name.to_string(), sql.trim_start()),
+            None => (String::new(), case.sql.as_str()),
+        };
+        let session =
+            sessions.entry(name).or_insert_with(|| Session::new(engine(), Config::default()));
+        let result = match session.execute(sql) {
+            Ok(result) => format(result),
+            Err(err) => Err(err),
+        };
+        let mut lines = case.comments.clone();
+        lines.push(case.sql.clone());
+        lines.push("----".into());
+        match result {
+            Ok(result) => lines.extend(result),
+            Err(err) => lines.push(format!("Error: {}", err)),
+        }
+        output.push(lines.join("\n"));
+    }
+    output.join("\n\n") + "\n"
+}
+
+/// Formats a result set as output lines. Query results are a header of column names followed
+/// by the rows, with values rendered as SQL literals and separated by |, so that e.g. strings
+/// are distinguishable from NULL and an empty string doesn't end the case.
+fn format(result: ResultSet) -> Result<Vec<String>> {
+    Ok(vec![match result {
+        ResultSet::Begin { id, mode } => match mode {
+            Mode::ReadWrite => format!("BEGIN {} READ WRITE", id),
+            Mode::ReadOnly => format!("BEGIN {} READ ONLY", id),
+            Mode::Snapshot { version } => format!("BEGIN {} AS OF {}", id, version),
+        },
+        ResultSet::Commit { id } => format!("COMMIT {}", id),
+        ResultSet::Rollback { id } => format!("ROLLBACK {}", id),
+        ResultSet::CreateTable { name } => format!("CREATE TABLE {}", name),
+        ResultSet::DropTable { name } => format!("DROP TABLE {}", name),
+        ResultSet::CreateView { name } => format!("CREATE VIEW {}", name),
+        ResultSet::DropView { name } => format!("DROP VIEW {}", name),
+        ResultSet::Refresh { name, count } => format!("REFRESH {} {}", name, count),
+        ResultSet::Analyze { tables } => format!("ANALYZE {}", tables.join(", ")),
+        ResultSet::Create { count } => format!("INSERT {}", count),
+        ResultSet::Update { count } => format!("UPDATE {}", count),
+        ResultSet::Delete { count } => format!("DELETE {}", count),
+        ResultSet::Prepare { name } => format!("PREPARE {}", name),
+        ResultSet::Deallocate { name } => format!("DEALLOCATE {}", name),
+        ResultSet::Query { columns, rows } => {
+            let mut lines = vec![columns.join("|")];
+            for row in rows {
+                lines.push(row?.iter().map(literal).collect::<Vec<_>>().join("|"));
+            }
+            return Ok(lines);
+        }
+    }])
+}
+
+/// Returns the golden scripts, in name order.
+fn scripts() -> Result<Vec<PathBuf>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(DIR);
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "sql") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// An in-process Raft cluster, whose nodes listen on local ports and store their logs in
+/// temporary directories.
+struct Cluster {
+    runtime: tokio::runtime::Runtime,
+    clients: Vec<Client>,
+    dirs: Vec<PathBuf>,
+}
+
+impl Cluster {
+    fn new(size: usize) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        let ids: Vec<String> = (1..=size).map(|i| format!("node{}", i)).collect();
+        let mut listeners = Vec::new();
+        for _ in &ids {
+            listeners.push(runtime.block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))?);
+        }
+        let mut addrs = Vec::new();
+        for listener in &listeners {
+            addrs.push(listener.local_addr()?.to_string());
+        }
+        let mut cluster = Cluster { runtime, clients: Vec::new(), dirs: Vec::new() };
+        for (id, listener) in ids.iter().zip(listeners) {
+            let peers = ids
+                .iter()
+                .zip(&addrs)
+                .filter(|(peer, _)| *peer != id)
+                .map(|(peer, addr)| (peer.clone(), addr.clone()))
+                .collect();
+            let dir = std::env::temp_dir().join(format!("toydb-golden-{}", uuid::Uuid::new_v4()));
+            cluster.dirs.push(dir.clone());
+            let log = Log::new(Box::new(LogStore::new(&dir, false)?))?;
+            let state = Box::new(RaftState::new(Mvcc::new(Box::new(Memory::new())))?);
+            let (client_tx, client_rx) = tokio::sync::mpsc::unbounded_channel();
+            let server = cluster.runtime.block_on(Server::new(id, peers, log, state))?;
+            cluster.runtime.spawn(server.server(listener, client_rx));
+            cluster.clients.push(Client::new(client_tx));
+        }
+        Ok(cluster)
+    }
+}
+
+impl Drop for Cluster {
+    fn drop(&mut self) {
+        for dir in &self.dirs {
+            std::fs::remove_dir_all(dir).ok();
+        }
+    }
+}
+
+/// Runs the golden scripts against a Kv engine over Memory.
+#[test]
+fn golden() -> Result<()> {
+    for path in scripts()? {
+        let script = std::fs::read_to_string(&path)?;
+        let output = run(&parse(&script)?, memory());
+        if std::env::var_os(UPDATE).is_some() {
+            std::fs::write(&path, output)?;
+        } else {
+            assert_eq!(script, output, "{}", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Runs the golden scripts against a 3-node Raft cluster, with sessions spread across the
+/// nodes, and compares the output with that of the Kv engine.
+#[test]
+fn golden_raft() -> Result<()> {
+    for path in scripts()? {
+        let cases = parse(&std::fs::read_to_string(&path)?)?;
+        let cluster = Cluster::new(3)?;
+        let mut next = 0;
+        let output = run(&cases, || {
+            next += 1;
+            Raft::new(cluster.clients[next % cluster.clients.len()].clone())
+        });
+        assert_eq!(run(&cases, memory()), output, "{}", path.display());
+    }
+    Ok(())
+}
+
+/// Returns a function creating engines over a shared, fresh Memory store.
+fn memory() -> impl FnMut() -> Kv {
+    let kv = Kv::new(Mvcc::new(Box::new(Memory::new())));
+    move || kv.clone()
+}
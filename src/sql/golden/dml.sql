@@ -0,0 +1,58 @@
+# Inserting, updating and deleting rows.
+CREATE TABLE t (id INTEGER PRIMARY KEY, name STRING, score INTEGER DEFAULT 0)
+----
+CREATE TABLE t
+
+INSERT INTO t VALUES (1, 'a', 10), (2, 'b', 20)
+----
+INSERT 2
+
+INSERT INTO t (id, name) VALUES (3, 'c')
+----
+INSERT 1
+
+SELECT * FROM t
+----
+id|name|score
+1|'a'|10
+2|'b'|20
+3|'c'|0
+
+UPDATE t SET score = score + 1 WHERE id >= 2
+----
+UPDATE 2
+
+DELETE FROM t WHERE name = 'a'
+----
+DELETE 1
+
+SELECT * FROM t
+----
+id|name|score
+2|'b'|21
+3|'c'|1
+
+# Errors leave the table unchanged.
+INSERT INTO t VALUES (2, 'dup', 0)
+----
+Error: Primary key 2 already exists for table t
+
+INSERT INTO t VALUES ('x', 'y', 0)
+----
+Error: Invalid datatype STRING for INTEGER column id
+
+UPDATE t SET missing = 1
+----
+Error: Unknown column missing in table t
+
+SELECT * FROM t
+----
+id|name|score
+2|'b'|21
+3|'c'|1
+
+# Empty strings and NULLs render distinctly.
+SELECT '' AS empty, NULL AS none, 'it''s' AS quoted
+----
+empty|none|quoted
+''|NULL|'it''s'
@@ -0,0 +1,83 @@
+# Filters, joins, aggregates and ordering.
+CREATE TABLE genres (id INTEGER PRIMARY KEY, name STRING NOT NULL)
+----
+CREATE TABLE genres
+
+CREATE TABLE movies (
+    id INTEGER PRIMARY KEY,
+    title STRING NOT NULL,
+    genre_id INTEGER REFERENCES genres,
+    rating FLOAT
+)
+----
+CREATE TABLE movies
+
+INSERT INTO genres VALUES (1, 'Science Fiction'), (2, 'Action'), (3, 'Comedy')
+----
+INSERT 3
+
+INSERT INTO movies VALUES
+    (1, 'Stalker', 1, 8.2),
+    (2, 'Sicario', 2, 7.6),
+    (3, 'Primer', 1, 6.9),
+    (4, 'Heat', 2, 8.3),
+    (5, 'Solaris', 1, 7.7),
+    (6, 'Untitled', NULL, NULL)
+----
+INSERT 6
+
+SELECT title, rating FROM movies WHERE rating > 7.5 ORDER BY rating DESC
+----
+title|rating
+'Heat'|8.3
+'Stalker'|8.2
+'Solaris'|7.7
+'Sicario'|7.6
+
+SELECT m.title, g.name FROM movies m JOIN genres g ON m.genre_id = g.id ORDER BY m.id
+----
+title|name
+'Stalker'|'Science Fiction'
+'Sicario'|'Action'
+'Primer'|'Science Fiction'
+'Heat'|'Action'
+'Solaris'|'Science Fiction'
+
+SELECT m.title, g.name FROM movies m LEFT JOIN genres g ON m.genre_id = g.id
+WHERE g.id IS NULL
+----
+title|name
+'Untitled'|NULL
+
+SELECT g.name, COUNT(*), MAX(m.rating) FROM genres g JOIN movies m ON m.genre_id = g.id
+GROUP BY g.name ORDER BY g.name
+----
+name|COUNT(*)|MAX(m.rating)
+'Action'|2|8.3
+'Science Fiction'|3|8.2
+
+SELECT title FROM movies ORDER BY title LIMIT 2 OFFSET 1
+----
+title
+'Primer'
+'Sicario'
+
+SELECT 1 + 2 * 3 AS a, 7 / 2 AS b, NULL IS NULL AS c
+----
+a|b|c
+7|3|TRUE
+
+EXPLAIN SELECT title FROM movies WHERE id = 3
+----
+QUERY PLAN
+'Projection: movies.title'
+'└─ KeyLookup: movies (3)'
+
+SELECT missing FROM movies
+----
+Error: Unknown column missing
+
+# Negating the minimum integer overflows rather than wrapping.
+SELECT -(-9223372036854775807 - 1)
+----
+Error: Integer overflow
@@ -0,0 +1,65 @@
+# Creating, inspecting and dropping tables.
+CREATE TABLE genres (id INTEGER PRIMARY KEY, name STRING NOT NULL UNIQUE)
+----
+CREATE TABLE genres
+
+CREATE TABLE movies (
+    id INTEGER PRIMARY KEY,
+    title STRING NOT NULL,
+    genre_id INTEGER REFERENCES genres INDEX,
+    rating FLOAT DEFAULT 0.0
+)
+----
+CREATE TABLE movies
+
+SHOW TABLES
+----
+table_name|table_type
+'genres'|'TABLE'
+'movies'|'TABLE'
+
+SHOW CREATE TABLE movies
+----
+create_table
+'CREATE TABLE movies (
+  id INTEGER PRIMARY KEY,
+  title STRING NOT NULL,
+  genre_id INTEGER REFERENCES genres INDEX,
+  rating FLOAT DEFAULT 0.0
+)'
+
+SELECT * FROM information_schema.constraints
+----
+table_name|column_name|constraint_type|referenced_table
+'genres'|'id'|'PRIMARY KEY'|NULL
+'genres'|'name'|'NOT NULL'|NULL
+'genres'|'name'|'UNIQUE'|NULL
+'movies'|'id'|'PRIMARY KEY'|NULL
+'movies'|'title'|'NOT NULL'|NULL
+'movies'|'genre_id'|'FOREIGN KEY'|'genres'
+
+# Errors.
+CREATE TABLE genres (id INTEGER PRIMARY KEY)
+----
+Error: Table genres already exists
+
+CREATE TABLE nokey (id INTEGER)
+----
+Error: No primary key in table nokey
+
+DROP TABLE genres
+----
+Error: Table genres is referenced by table movies column genre_id
+
+DROP TABLE movies
+----
+DROP TABLE movies
+
+DROP TABLE missing
+----
+Error: Table missing does not exist
+
+SHOW TABLES
+----
+table_name|table_type
+'genres'|'TABLE'
@@ -0,0 +1,129 @@
+# Explicit and concurrent transactions. Statements prefixed with [b] run in a second session.
+CREATE TABLE t (id INTEGER PRIMARY KEY, value STRING)
+----
+CREATE TABLE t
+
+INSERT INTO t VALUES (1, 'a')
+----
+INSERT 1
+
+BEGIN
+----
+BEGIN 3 READ WRITE
+
+INSERT INTO t VALUES (2, 'b')
+----
+INSERT 1
+
+[b] BEGIN
+----
+BEGIN 4 READ WRITE
+
+[b] SELECT * FROM t
+----
+id|value
+1|'a'
+
+SHOW TRANSACTIONS
+----
+id|mode|version|current
+3|'READ WRITE'|NULL|TRUE
+4|'READ WRITE'|NULL|FALSE
+
+COMMIT
+----
+COMMIT 3
+
+[b] SELECT * FROM t
+----
+id|value
+1|'a'
+
+[b] ROLLBACK
+----
+ROLLBACK 4
+
+[b] SELECT * FROM t
+----
+id|value
+1|'a'
+2|'b'
+
+# Rolled back writes are discarded.
+BEGIN
+----
+BEGIN 6 READ WRITE
+
+DELETE FROM t
+----
+DELETE 2
+
+ROLLBACK
+----
+ROLLBACK 6
+
+SELECT * FROM t
+----
+id|value
+1|'a'
+2|'b'
+
+# Conflicting writes.
+BEGIN
+----
+BEGIN 8 READ WRITE
+
+[b] BEGIN
+----
+BEGIN 9 READ WRITE
+
+UPDATE t SET value = 'x' WHERE id = 1
+----
+UPDATE 1
+
+# The error rolls back the second transaction.
+[b] UPDATE t SET value = 'y' WHERE id = 1
+----
+Error: serialization failure, retry transaction
+
+[b] SHOW TRANSACTIONS
+----
+id|mode|version|current
+8|'READ WRITE'|NULL|FALSE
+10|'READ WRITE'|NULL|TRUE
+
+COMMIT
+----
+COMMIT 8
+
+SELECT * FROM t
+----
+id|value
+1|'x'
+2|'b'
+
+# Read-only and snapshot transactions. Errors roll back explicit transactions too.
+BEGIN READ ONLY
+----
+BEGIN 12 READ ONLY
+
+INSERT INTO t VALUES (3, 'c')
+----
+Error: Read-Only transaction
+
+BEGIN READ ONLY AS OF SYSTEM TIME 2
+----
+BEGIN 13 AS OF 2
+
+SELECT * FROM t
+----
+id|value
+1|'a'
+
+COMMIT
+----
+COMMIT 13
+
+COMMIT
+----
+Error: Not in a transaction
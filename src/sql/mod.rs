@@ -1,3 +1,9 @@
 mod storage;
 mod parser;
-pub use storage::{Store, Range, State};
\ No newline at end of file
+mod plan;
+mod execution;
+mod prepared;
+mod session;
+#[cfg(test)]
+mod golden;
+pub use storage::{Store, Range, State, LogStore};
\ No newline at end of file
@@ -0,0 +1,588 @@
+use super::super::storage::{Datatype, Value};
+use crate::error::Result;
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::mem::replace;
+
+/// A parsed SQL statement.
+#[derive(Clone, Debug, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum Statement {
+    /// Begins an explicit transaction, which is read-only if read_only is set or as_of
+    /// gives a historical version to read.
+    Begin {
+        read_only: bool,
+        as_of: Option<u64>,
+    },
+    Commit,
+    Rollback,
+    /// Shows the statement's query plan, or executes it and annotates the plan with execution
+    /// statistics for EXPLAIN ANALYZE.
+    Explain {
+        statement: Box<Statement>,
+        analyze: bool,
+    },
+    /// Prepares a statement with parameter placeholders under a name, for later execution.
+    Prepare {
+        name: String,
+        statement: Box<Statement>,
+    },
+    /// Executes a prepared statement with the given parameter values.
+    Execute {
+        name: String,
+        parameters: Vec<Expression>,
+    },
+    Deallocate(String),
+
+    CreateTable {
+        name: String,
+        columns: Vec<Column>,
+    },
+    DropTable(String),
+    /// Creates a view of a SELECT query, or a materialized view which stores its results.
+    /// The query's SQL text is stored in the catalog, and parsed again when the view is used.
+    CreateView {
+        name: String,
+        query: Box<Statement>,
+        sql: String,
+        materialized: bool,
+    },
+    DropView(String),
+    /// Recomputes the stored results of a materialized view.
+    RefreshView(String),
+    /// Computes statistics for the planner's cost model, for the given table or all tables.
+    Analyze(Option<String>),
+    /// Lists the tables and views, via the information schema.
+    ShowTables,
+    /// Shows the CREATE TABLE statement of a table.
+    ShowCreateTable(String),
+    /// Lists the active transactions, via the information schema.
+    ShowTransactions,
+
+    Delete {
+        table: String,
+        r#where: Option<Expression>,
+    },
+    Insert {
+        table: String,
+        /// The target columns, or None for all columns in table order.
+        columns: Option<Vec<String>>,
+        source: InsertSource,
+        /// How to handle rows whose primary key already exists, or None to error.
+        on_conflict: Option<OnConflict>,
+    },
+    Update {
+        table: String,
+        set: BTreeMap<String, Expression>,
+        r#where: Option<Expression>,
+    },
+
+    Select {
+        /// The selected expressions with optional aliases. Empty for SELECT *.
+        select: Vec<(Expression, Option<String>)>,
+        from: Vec<FromItem>,
+        r#where: Option<Expression>,
+        group_by: Vec<Expression>,
+        having: Option<Expression>,
+        order: Vec<(Expression, Order, Nulls)>,
+        limit: Option<Expression>,
+        offset: Option<Expression>,
+    },
+    /// Combines the rows of two queries, which are SELECTs or set operations without ORDER BY,
+    /// LIMIT and OFFSET. These apply to the combined rows instead.
+    SetOperation {
+        operator: SetOperator,
+        /// Whether to keep duplicate rows, i.e. ALL.
+        all: bool,
+        left: Box<Statement>,
+        right: Box<Statement>,
+        order: Vec<(Expression, Order, Nulls)>,
+        limit: Option<Expression>,
+        offset: Option<Expression>,
+    },
+    /// A query with common table expressions, which it can refer to by name like tables.
+    With {
+        /// With RECURSIVE, CTEs can refer to themselves. CTEs can always refer to earlier ones.
+        recursive: bool,
+        ctes: Vec<Cte>,
+        statement: Box<Statement>,
+    },
+}
+
+/// A parsed expression. Column references are unbound, and are resolved to column indexes by
+/// the planner, which builds subqueries and window functions into plan nodes and the rest into
+/// a storage Expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    // Values
+    Constant(Value),
+    /// A column reference, optionally qualified by a table name or alias.
+    Column(Option<String>, String),
+    /// A column bound to a field index by the planner, e.g. an aggregate or subquery result.
+    Field(usize, Option<(Option<String>, String)>),
+    /// A prepared statement parameter, by 0-based position.
+    Parameter(usize),
+    /// A * function argument, e.g. COUNT(*). The planner binds it to TRUE, so COUNT(*) counts
+    /// all rows like COUNT(TRUE), but it keeps its * label.
+    All,
+
+    // Logical operations
+    And(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+
+    // Comparisons operations (GTE, LTE, and NEQ are composite operations)
+    Between(Box<Expression>, Box<Expression>, Box<Expression>),
+    Equal(Box<Expression>, Box<Expression>),
+    GreaterThan(Box<Expression>, Box<Expression>),
+    In(Box<Expression>, Vec<Expression>),
+    IsDistinctFrom(Box<Expression>, Box<Expression>),
+    IsNull(Box<Expression>),
+    LessThan(Box<Expression>, Box<Expression>),
+
+    // Mathematical operations
+    Add(Box<Expression>, Box<Expression>),
+    Assert(Box<Expression>),
+    Cast(Box<Expression>, Datatype),
+    Divide(Box<Expression>, Box<Expression>),
+    Exponentiate(Box<Expression>, Box<Expression>),
+    Factorial(Box<Expression>),
+    Modulo(Box<Expression>, Box<Expression>),
+    Multiply(Box<Expression>, Box<Expression>),
+    Negate(Box<Expression>),
+    Subtract(Box<Expression>, Box<Expression>),
+
+    // String operations
+    ILike(Box<Expression>, Box<Expression>),
+    Like(Box<Expression>, Box<Expression>),
+    Regexp(Box<Expression>, Box<Expression>),
+
+    // Function calls and conditionals
+    /// A function call. The flag is set for DISTINCT aggregate calls, e.g. COUNT(DISTINCT a).
+    Function(String, Vec<Expression>, bool),
+    Case(Option<Box<Expression>>, Vec<(Expression, Expression)>, Option<Box<Expression>>),
+
+    // Subqueries, which the planner builds into Subquery nodes or semi joins
+    Exists(Box<Statement>),
+    InSubquery(Box<Expression>, Box<Statement>),
+    Subquery(Box<Statement>),
+
+    /// A window function call over a window, which the planner builds into a Window node.
+    /// Like subqueries, its function call and window aren't descended into.
+    Over(Box<Expression>, Box<Window>),
+}
+
+impl Expression {
+    /// Replaces the expression with result of the closure. Helper function for transform().
+    fn replace_with<F: Fn(Self) -> Result<Self>>(&mut self, f: F) -> Result<()> {
+        let expr = replace(self, Expression::Constant(Value::Null));
+        *self = f(expr)?;
+        Ok(())
+    }
+
+    /// Transforms the expression tree by applying a closure before and after descending.
+    pub fn transform<B, A>(mut self, before: &B, after: &A) -> Result<Self>
+    where
+        B: Fn(Self) -> Result<Self>,
+        A: Fn(Self) -> Result<Self>,
+    {
+        self = before(self)?;
+        match &mut self {
+            Self::Add(lhs, rhs)
+            | Self::And(lhs, rhs)
+            | Self::Divide(lhs, rhs)
+            | Self::Equal(lhs, rhs)
+            | Self::Exponentiate(lhs, rhs)
+            | Self::GreaterThan(lhs, rhs)
+            | Self::ILike(lhs, rhs)
+            | Self::IsDistinctFrom(lhs, rhs)
+            | Self::LessThan(lhs, rhs)
+            | Self::Like(lhs, rhs)
+            | Self::Modulo(lhs, rhs)
+            | Self::Multiply(lhs, rhs)
+            | Self::Or(lhs, rhs)
+            | Self::Regexp(lhs, rhs)
+            | Self::Subtract(lhs, rhs) => {
+                Self::replace_with(lhs, |e| e.transform(before, after))?;
+                Self::replace_with(rhs, |e| e.transform(before, after))?;
+            }
+
+            Self::Between(expr, low, high) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                Self::replace_with(low, |e| e.transform(before, after))?;
+                Self::replace_with(high, |e| e.transform(before, after))?;
+            }
+            Self::In(expr, list) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                for item in list {
+                    Self::replace_with(item, |e| e.transform(before, after))?;
+                }
+            }
+            Self::InSubquery(expr, _) => Self::replace_with(expr, |e| e.transform(before, after))?,
+
+            Self::Assert(expr)
+            | Self::Cast(expr, _)
+            | Self::Factorial(expr)
+            | Self::IsNull(expr)
+            | Self::Negate(expr)
+            | Self::Not(expr) => Self::replace_with(expr, |e| e.transform(before, after))?,
+
+            Self::Function(_, args, _) => {
+                for arg in args {
+                    Self::replace_with(arg, |e| e.transform(before, after))?;
+                }
+            }
+            Self::Case(operand, whens, default) => {
+                if let Some(operand) = operand {
+                    Self::replace_with(operand, |e| e.transform(before, after))?;
+                }
+                for (when, then) in whens {
+                    Self::replace_with(when, |e| e.transform(before, after))?;
+                    Self::replace_with(then, |e| e.transform(before, after))?;
+                }
+                if let Some(default) = default {
+                    Self::replace_with(default, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::Constant(_)
+            | Self::Column(_, _)
+            | Self::Field(_, _)
+            | Self::Parameter(_)
+            | Self::All
+            | Self::Exists(_)
+            | Self::Subquery(_)
+            | Self::Over(_, _) => {}
+        };
+        after(self)
+    }
+
+    /// Walks the expression tree, calling a closure for every node. Halts if closure returns false.
+    pub fn walk<F: Fn(&Expression) -> bool>(&self, visitor: &F) -> bool {
+        visitor(self)
+            && match self {
+                Self::Add(lhs, rhs)
+                | Self::And(lhs, rhs)
+                | Self::Divide(lhs, rhs)
+                | Self::Equal(lhs, rhs)
+                | Self::Exponentiate(lhs, rhs)
+                | Self::GreaterThan(lhs, rhs)
+                | Self::ILike(lhs, rhs)
+                | Self::IsDistinctFrom(lhs, rhs)
+                | Self::LessThan(lhs, rhs)
+                | Self::Like(lhs, rhs)
+                | Self::Modulo(lhs, rhs)
+                | Self::Multiply(lhs, rhs)
+                | Self::Or(lhs, rhs)
+                | Self::Regexp(lhs, rhs)
+                | Self::Subtract(lhs, rhs) => lhs.walk(visitor) && rhs.walk(visitor),
+
+                Self::Between(expr, low, high) => {
+                    expr.walk(visitor) && low.walk(visitor) && high.walk(visitor)
+                }
+                Self::In(expr, list) => expr.walk(visitor) && list.iter().all(|i| i.walk(visitor)),
+                Self::InSubquery(expr, _) => expr.walk(visitor),
+
+                Self::Assert(expr)
+                | Self::Cast(expr, _)
+                | Self::Factorial(expr)
+                | Self::IsNull(expr)
+                | Self::Negate(expr)
+                | Self::Not(expr) => expr.walk(visitor),
+
+                Self::Function(_, args, _) => args.iter().all(|arg| arg.walk(visitor)),
+                Self::Case(operand, whens, default) => {
+                    operand.as_ref().is_none_or(|o| o.walk(visitor))
+                        && whens.iter().all(|(w, t)| w.walk(visitor) && t.walk(visitor))
+                        && default.as_ref().is_none_or(|d| d.walk(visitor))
+                }
+
+                Self::Constant(_)
+                | Self::Column(_, _)
+                | Self::Field(_, _)
+                | Self::Parameter(_)
+                | Self::All
+                | Self::Exists(_)
+                | Self::Subquery(_)
+                | Self::Over(_, _) => true,
+            }
+    }
+
+    /// Walks the expression tree while calling a closure. Returns true as soon as the closure
+    /// returns true. This is the inverse of walk().
+    pub fn contains<F: Fn(&Expression) -> bool>(&self, visitor: &F) -> bool {
+        !self.walk(&|e| !visitor(e))
+    }
+}
+
+/// A column definition in CREATE TABLE.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub datatype: Datatype,
+    pub primary_key: bool,
+    /// Explicitly given as NULL or NOT NULL, otherwise None.
+    pub nullable: Option<bool>,
+    pub default: Option<Expression>,
+    pub unique: bool,
+    pub index: bool,
+    pub references: Option<String>,
+}
+
+/// The rows inserted by INSERT.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InsertSource {
+    Values(Vec<Vec<Expression>>),
+    Select(Box<Statement>),
+}
+
+/// The ON CONFLICT clause of INSERT, for rows whose primary key already exists.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnConflict {
+    /// The conflicting column, which must be the primary key. Required for DO UPDATE.
+    pub column: Option<String>,
+    /// The DO UPDATE assignments, or None for DO NOTHING. The expressions can refer to the
+    /// existing row's columns, and to the inserted row's columns as EXCLUDED.column.
+    pub update: Option<BTreeMap<String, Expression>>,
+}
+
+/// A common table expression, i.e. a named query in a WITH clause.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cte {
+    pub name: String,
+    /// Column names, overriding the query's column names. Empty to use the query's.
+    pub columns: Vec<String>,
+    pub query: Statement,
+}
+
+/// An item in the FROM clause.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FromItem {
+    Table {
+        name: String,
+        alias: Option<String>,
+    },
+    Join {
+        left: Box<FromItem>,
+        right: Box<FromItem>,
+        r#type: JoinType,
+        /// The ON predicate, which is None for cross joins.
+        predicate: Option<Expression>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JoinType {
+    Cross,
+    Inner,
+    Left,
+    Right,
+    Full,
+    /// Emits the left rows that match any right row, with the left columns only. Only planned
+    /// for EXISTS and IN subqueries.
+    Semi,
+    /// Emits the left rows that match no right row, with the left columns only. Only planned
+    /// for NOT EXISTS subqueries.
+    Anti,
+}
+
+/// A set operation between two queries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SetOperator {
+    /// The rows of either query.
+    Union,
+    /// The rows of the left query that are also in the right query.
+    Intersect,
+    /// The rows of the left query that aren't in the right query.
+    Except,
+}
+
+impl SetOperator {
+    /// Returns the operator's precedence: INTERSECT binds tighter than UNION and EXCEPT.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Self::Union | Self::Except => 1,
+            Self::Intersect => 2,
+        }
+    }
+}
+
+impl Display for SetOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Union => write!(f, "UNION"),
+            Self::Intersect => write!(f, "INTERSECT"),
+            Self::Except => write!(f, "EXCEPT"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order {
+    Ascending,
+    Descending,
+}
+
+/// Whether NULLs sort before or after other values. Defaults to first for ascending orders and
+/// last for descending orders, i.e. NULL sorts as the lowest value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Nulls {
+    First,
+    Last,
+}
+
+/// The window of a window function call, i.e. OVER (PARTITION BY ... ORDER BY ... ROWS ...).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Window {
+    pub partition_by: Vec<Expression>,
+    pub order: Vec<(Expression, Order, Nulls)>,
+    pub frame: Option<Frame>,
+}
+
+/// A ROWS window frame: the rows of a partition, relative to the current row, that an
+/// aggregate window function is computed over. Without a frame, it's computed over the rows
+/// up to the current row and its peers, or over the whole partition without ORDER BY.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frame {
+    pub start: FrameBound,
+    pub end: FrameBound,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameBound {
+    UnboundedPreceding,
+    Preceding(u64),
+    CurrentRow,
+    Following(u64),
+    UnboundedFollowing,
+}
+
+impl FrameBound {
+    /// Returns the bound's row offset from the current row, with unbounded bounds as the
+    /// minimum and maximum offsets.
+    pub fn offset(&self) -> i64 {
+        match self {
+            Self::UnboundedPreceding => i64::MIN,
+            Self::Preceding(n) => -(i64::try_from(*n).unwrap_or(i64::MAX)),
+            Self::CurrentRow => 0,
+            Self::Following(n) => i64::try_from(*n).unwrap_or(i64::MAX),
+            Self::UnboundedFollowing => i64::MAX,
+        }
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Constant(v) => v.to_string(),
+            Self::Column(None, name) | Self::Field(_, Some((None, name))) => name.to_string(),
+            Self::Column(Some(table), name) | Self::Field(_, Some((Some(table), name))) => {
+                format!("{}.{}", table, name)
+            }
+            Self::Field(i, None) => format!("#{}", i),
+            Self::Parameter(i) => format!("${}", i + 1),
+            Self::All => "*".into(),
+
+            Self::And(lhs, rhs) => format!("{} AND {}", lhs, rhs),
+            Self::Or(lhs, rhs) => format!("{} OR {}", lhs, rhs),
+            Self::Not(expr) => format!("NOT {}", expr),
+
+            Self::Between(expr, low, high) => format!("{} BETWEEN {} AND {}", expr, low, high),
+            Self::Equal(lhs, rhs) => format!("{} = {}", lhs, rhs),
+            Self::GreaterThan(lhs, rhs) => format!("{} > {}", lhs, rhs),
+            Self::In(expr, list) => format!(
+                "{} IN ({})",
+                expr,
+                list.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+            Self::IsDistinctFrom(lhs, rhs) => format!("{} IS DISTINCT FROM {}", lhs, rhs),
+            Self::LessThan(lhs, rhs) => format!("{} < {}", lhs, rhs),
+            Self::IsNull(expr) => format!("{} IS NULL", expr),
+
+            Self::Add(lhs, rhs) => format!("{} + {}", lhs, rhs),
+            Self::Assert(expr) => expr.to_string(),
+            Self::Cast(expr, datatype) => format!("CAST({} AS {})", expr, datatype),
+            Self::Divide(lhs, rhs) => format!("{} / {}", lhs, rhs),
+            Self::Exponentiate(lhs, rhs) => format!("{} ^ {}", lhs, rhs),
+            Self::Factorial(expr) => format!("!{}", expr),
+            Self::Modulo(lhs, rhs) => format!("{} % {}", lhs, rhs),
+            Self::Multiply(lhs, rhs) => format!("{} * {}", lhs, rhs),
+            Self::Negate(expr) => format!("-{}", expr),
+            Self::Subtract(lhs, rhs) => format!("{} - {}", lhs, rhs),
+
+            Self::ILike(lhs, rhs) => format!("{} ILIKE {}", lhs, rhs),
+            Self::Like(lhs, rhs) => format!("{} LIKE {}", lhs, rhs),
+            Self::Regexp(lhs, rhs) => format!("{} REGEXP {}", lhs, rhs),
+
+            Self::Function(name, args, distinct) => format!(
+                "{}({}{})",
+                name.to_uppercase(),
+                if *distinct { "DISTINCT " } else { "" },
+                args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+            Self::Case(operand, whens, default) => {
+                let mut s = "CASE".to_string();
+                if let Some(operand) = operand {
+                    s += &format!(" {}", operand);
+                }
+                for (when, then) in whens {
+                    s += &format!(" WHEN {} THEN {}", when, then);
+                }
+                if let Some(default) = default {
+                    s += &format!(" ELSE {}", default);
+                }
+                s + " END"
+            }
+
+            Self::Exists(_) => "EXISTS (subquery)".to_string(),
+            Self::InSubquery(expr, _) => format!("{} IN (subquery)", expr),
+            Self::Subquery(_) => "(subquery)".to_string(),
+            Self::Over(function, window) => format!("{} OVER ({})", function, window),
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut clauses = Vec::new();
+        if !self.partition_by.is_empty() {
+            let exprs: Vec<_> = self.partition_by.iter().map(|e| e.to_string()).collect();
+            clauses.push(format!("PARTITION BY {}", exprs.join(", ")));
+        }
+        if !self.order.is_empty() {
+            let orders: Vec<_> = self
+                .order
+                .iter()
+                .map(|(e, order, _)| match order {
+                    Order::Ascending => e.to_string(),
+                    Order::Descending => format!("{} DESC", e),
+                })
+                .collect();
+            clauses.push(format!("ORDER BY {}", orders.join(", ")));
+        }
+        if let Some(frame) = &self.frame {
+            clauses.push(frame.to_string());
+        }
+        write!(f, "{}", clauses.join(" "))
+    }
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ROWS BETWEEN {} AND {}", self.start, self.end)
+    }
+}
+
+impl Display for FrameBound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnboundedPreceding => write!(f, "UNBOUNDED PRECEDING"),
+            Self::Preceding(n) => write!(f, "{} PRECEDING", n),
+            Self::CurrentRow => write!(f, "CURRENT ROW"),
+            Self::Following(n) => write!(f, "{} FOLLOWING", n),
+            Self::UnboundedFollowing => write!(f, "UNBOUNDED FOLLOWING"),
+        }
+    }
+}
@@ -1,18 +1,447 @@
-use std::{iter::Peekable, str::Chars};
+use std::fmt::Display;
+use std::str::Chars;
 
+use crate::error::{Error, Result};
 
-struct Lexer<'a> {
-    lexer: Peekable<Chars<'a>>,
+/// A lexical token.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    /// An integer or float literal, parsed by the parser.
+    Number(String),
+    /// A string literal, with escapes resolved.
+    String(String),
+    /// An identifier. Unquoted identifiers are lowercased, quoted ones are kept as is.
+    Ident(String),
+    /// A numbered parameter placeholder $n, as opposed to a positional ? placeholder.
+    Parameter(String),
+    Keyword(Keyword),
+    Period,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+    LessThan,
+    LessThanOrEqual,
+    LessOrGreaterThan,
+    Plus,
+    Minus,
+    Asterisk,
+    Slash,
+    Caret,
+    Percent,
+    Exclamation,
+    Question,
+    OpenParen,
+    CloseParen,
+    Comma,
+    Semicolon,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::String(s) => write!(f, "'{}'", s.replace('\'', "''")),
+            Self::Ident(s) => write!(f, "{}", s),
+            Self::Parameter(n) => write!(f, "${}", n),
+            Self::Keyword(k) => write!(f, "{}", k),
+            Self::Period => write!(f, "."),
+            Self::Equal => write!(f, "="),
+            Self::NotEqual => write!(f, "!="),
+            Self::GreaterThan => write!(f, ">"),
+            Self::GreaterThanOrEqual => write!(f, ">="),
+            Self::LessThan => write!(f, "<"),
+            Self::LessThanOrEqual => write!(f, "<="),
+            Self::LessOrGreaterThan => write!(f, "<>"),
+            Self::Plus => write!(f, "+"),
+            Self::Minus => write!(f, "-"),
+            Self::Asterisk => write!(f, "*"),
+            Self::Slash => write!(f, "/"),
+            Self::Caret => write!(f, "^"),
+            Self::Percent => write!(f, "%"),
+            Self::Exclamation => write!(f, "!"),
+            Self::Question => write!(f, "?"),
+            Self::OpenParen => write!(f, "("),
+            Self::CloseParen => write!(f, ")"),
+            Self::Comma => write!(f, ","),
+            Self::Semicolon => write!(f, ";"),
+        }
+    }
+}
+
+/// A reserved SQL keyword. Keywords are case-insensitive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Keyword {
+    All,
+    Analyze,
+    And,
+    As,
+    Asc,
+    Begin,
+    Between,
+    Bool,
+    Boolean,
+    By,
+    Case,
+    Cast,
+    Char,
+    Commit,
+    Conflict,
+    Create,
+    Cross,
+    Deallocate,
+    Default,
+    Delete,
+    Desc,
+    Distinct,
+    Do,
+    Double,
+    Drop,
+    Else,
+    End,
+    Except,
+    Execute,
+    Exists,
+    Explain,
+    False,
+    Float,
+    From,
+    Full,
+    Group,
+    Having,
+    ILike,
+    In,
+    Index,
+    Infinity,
+    Inner,
+    Insert,
+    Int,
+    Integer,
+    Intersect,
+    Into,
+    Is,
+    Join,
+    Key,
+    Left,
+    Like,
+    Limit,
+    Materialized,
+    NaN,
+    Not,
+    Nothing,
+    Null,
+    Of,
+    Offset,
+    On,
+    Only,
+    Or,
+    Order,
+    Outer,
+    Over,
+    Prepare,
+    Primary,
+    Read,
+    Recursive,
+    References,
+    Refresh,
+    Regexp,
+    Right,
+    Rollback,
+    Select,
+    Set,
+    Show,
+    String,
+    System,
+    Table,
+    Text,
+    Then,
+    Time,
+    Transaction,
+    True,
+    Union,
+    Unique,
+    Update,
+    Values,
+    Varchar,
+    View,
+    When,
+    Where,
+    With,
+    Write,
+}
+
+impl Keyword {
+    /// Looks up a keyword by name, case-insensitively.
+    pub fn lookup(name: &str) -> Option<Self> {
+        Some(match name.to_uppercase().as_str() {
+            "ALL" => Self::All,
+            "ANALYZE" => Self::Analyze,
+            "AND" => Self::And,
+            "AS" => Self::As,
+            "ASC" => Self::Asc,
+            "BEGIN" => Self::Begin,
+            "BETWEEN" => Self::Between,
+            "BOOL" => Self::Bool,
+            "BOOLEAN" => Self::Boolean,
+            "BY" => Self::By,
+            "CASE" => Self::Case,
+            "CAST" => Self::Cast,
+            "CHAR" => Self::Char,
+            "COMMIT" => Self::Commit,
+            "CONFLICT" => Self::Conflict,
+            "CREATE" => Self::Create,
+            "CROSS" => Self::Cross,
+            "DEALLOCATE" => Self::Deallocate,
+            "DEFAULT" => Self::Default,
+            "DELETE" => Self::Delete,
+            "DESC" => Self::Desc,
+            "DISTINCT" => Self::Distinct,
+            "DO" => Self::Do,
+            "DOUBLE" => Self::Double,
+            "DROP" => Self::Drop,
+            "ELSE" => Self::Else,
+            "END" => Self::End,
+            "EXCEPT" => Self::Except,
+            "EXECUTE" => Self::Execute,
+            "EXISTS" => Self::Exists,
+            "EXPLAIN" => Self::Explain,
+            "FALSE" => Self::False,
+            "FLOAT" => Self::Float,
+            "FROM" => Self::From,
+            "FULL" => Self::Full,
+            "GROUP" => Self::Group,
+            "HAVING" => Self::Having,
+            "ILIKE" => Self::ILike,
+            "IN" => Self::In,
+            "INDEX" => Self::Index,
+            "INFINITY" => Self::Infinity,
+            "INNER" => Self::Inner,
+            "INSERT" => Self::Insert,
+            "INT" => Self::Int,
+            "INTEGER" => Self::Integer,
+            "INTERSECT" => Self::Intersect,
+            "INTO" => Self::Into,
+            "IS" => Self::Is,
+            "JOIN" => Self::Join,
+            "KEY" => Self::Key,
+            "LEFT" => Self::Left,
+            "LIKE" => Self::Like,
+            "LIMIT" => Self::Limit,
+            "MATERIALIZED" => Self::Materialized,
+            "NAN" => Self::NaN,
+            "NOT" => Self::Not,
+            "NOTHING" => Self::Nothing,
+            "NULL" => Self::Null,
+            "OF" => Self::Of,
+            "OFFSET" => Self::Offset,
+            "ON" => Self::On,
+            "ONLY" => Self::Only,
+            "OR" => Self::Or,
+            "ORDER" => Self::Order,
+            "OUTER" => Self::Outer,
+            "OVER" => Self::Over,
+            "PREPARE" => Self::Prepare,
+            "PRIMARY" => Self::Primary,
+            "READ" => Self::Read,
+            "RECURSIVE" => Self::Recursive,
+            "REFERENCES" => Self::References,
+            "REFRESH" => Self::Refresh,
+            "REGEXP" => Self::Regexp,
+            "RIGHT" => Self::Right,
+            "ROLLBACK" => Self::Rollback,
+            "SELECT" => Self::Select,
+            "SET" => Self::Set,
+            "SHOW" => Self::Show,
+            "STRING" => Self::String,
+            "SYSTEM" => Self::System,
+            "TABLE" => Self::Table,
+            "TEXT" => Self::Text,
+            "THEN" => Self::Then,
+            "TIME" => Self::Time,
+            "TRANSACTION" => Self::Transaction,
+            "TRUE" => Self::True,
+            "UNION" => Self::Union,
+            "UNIQUE" => Self::Unique,
+            "UPDATE" => Self::Update,
+            "VALUES" => Self::Values,
+            "VARCHAR" => Self::Varchar,
+            "VIEW" => Self::View,
+            "WHEN" => Self::When,
+            "WHERE" => Self::Where,
+            "WITH" => Self::With,
+            "WRITE" => Self::Write,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::All => "ALL",
+            Self::Analyze => "ANALYZE",
+            Self::And => "AND",
+            Self::As => "AS",
+            Self::Asc => "ASC",
+            Self::Begin => "BEGIN",
+            Self::Between => "BETWEEN",
+            Self::Bool => "BOOL",
+            Self::Boolean => "BOOLEAN",
+            Self::By => "BY",
+            Self::Case => "CASE",
+            Self::Cast => "CAST",
+            Self::Char => "CHAR",
+            Self::Commit => "COMMIT",
+            Self::Conflict => "CONFLICT",
+            Self::Create => "CREATE",
+            Self::Cross => "CROSS",
+            Self::Deallocate => "DEALLOCATE",
+            Self::Default => "DEFAULT",
+            Self::Delete => "DELETE",
+            Self::Desc => "DESC",
+            Self::Distinct => "DISTINCT",
+            Self::Do => "DO",
+            Self::Double => "DOUBLE",
+            Self::Drop => "DROP",
+            Self::Else => "ELSE",
+            Self::End => "END",
+            Self::Except => "EXCEPT",
+            Self::Execute => "EXECUTE",
+            Self::Exists => "EXISTS",
+            Self::Explain => "EXPLAIN",
+            Self::False => "FALSE",
+            Self::Float => "FLOAT",
+            Self::From => "FROM",
+            Self::Full => "FULL",
+            Self::Group => "GROUP",
+            Self::Having => "HAVING",
+            Self::ILike => "ILIKE",
+            Self::In => "IN",
+            Self::Index => "INDEX",
+            Self::Infinity => "INFINITY",
+            Self::Inner => "INNER",
+            Self::Insert => "INSERT",
+            Self::Int => "INT",
+            Self::Integer => "INTEGER",
+            Self::Intersect => "INTERSECT",
+            Self::Into => "INTO",
+            Self::Is => "IS",
+            Self::Join => "JOIN",
+            Self::Key => "KEY",
+            Self::Left => "LEFT",
+            Self::Like => "LIKE",
+            Self::Limit => "LIMIT",
+            Self::Materialized => "MATERIALIZED",
+            Self::NaN => "NAN",
+            Self::Not => "NOT",
+            Self::Nothing => "NOTHING",
+            Self::Null => "NULL",
+            Self::Of => "OF",
+            Self::Offset => "OFFSET",
+            Self::On => "ON",
+            Self::Only => "ONLY",
+            Self::Or => "OR",
+            Self::Order => "ORDER",
+            Self::Outer => "OUTER",
+            Self::Over => "OVER",
+            Self::Prepare => "PREPARE",
+            Self::Primary => "PRIMARY",
+            Self::Read => "READ",
+            Self::Recursive => "RECURSIVE",
+            Self::References => "REFERENCES",
+            Self::Refresh => "REFRESH",
+            Self::Regexp => "REGEXP",
+            Self::Right => "RIGHT",
+            Self::Rollback => "ROLLBACK",
+            Self::Select => "SELECT",
+            Self::Set => "SET",
+            Self::Show => "SHOW",
+            Self::String => "STRING",
+            Self::System => "SYSTEM",
+            Self::Table => "TABLE",
+            Self::Text => "TEXT",
+            Self::Then => "THEN",
+            Self::Time => "TIME",
+            Self::Transaction => "TRANSACTION",
+            Self::True => "TRUE",
+            Self::Union => "UNION",
+            Self::Unique => "UNIQUE",
+            Self::Update => "UPDATE",
+            Self::Values => "VALUES",
+            Self::Varchar => "VARCHAR",
+            Self::View => "VIEW",
+            Self::When => "WHEN",
+            Self::Where => "WHERE",
+            Self::With => "WITH",
+            Self::Write => "WRITE",
+        }
+    }
+}
+
+impl Display for Keyword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A SQL lexer, which splits a command into a stream of tokens. It yields an Error::Parse with
+/// the line and column of the offending input on invalid input, after which it should not be
+/// polled further.
+pub struct Lexer<'a> {
+    chars: Chars<'a>,
+    /// The line of the next character, from 1.
+    line: usize,
+    /// The column of the next character, from 1.
+    column: usize,
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Result<Token>> {
+        self.scan().transpose()
+    }
 }
 
 impl<'a> Lexer<'a> {
-    fn new(command: &'a str) -> Self {
-        Lexer { lexer: command.chars().peekable() }
+    pub fn new(command: &'a str) -> Self {
+        Lexer { chars: command.chars(), line: 1, column: 1 }
+    }
+
+    /// Returns the line and column of the next character.
+    pub fn location(&self) -> (usize, usize) {
+        (self.line, self.column)
+    }
+
+    /// Returns the input that hasn't been lexed yet.
+    pub fn remainder(&self) -> &'a str {
+        self.chars.as_str()
+    }
+
+    fn error(&self, (line, column): (usize, usize), message: String) -> Error {
+        Error::Parse(format!("{} at line {} column {}", message, line, column))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.clone().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        self.chars.clone().nth(1)
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
     }
 
     fn next_if<F: Fn(char) -> bool>(&mut self, predicate: F) -> Option<char> {
-        self.lexer.peek().filter(|&&c| predicate(c))?;
-        self.lexer.next()
+        self.peek().filter(|&c| predicate(c))?;
+        self.next_char()
     }
 
     fn next_while<F: Fn(char) -> bool>(&mut self, predicate: F) -> Option<String> {
@@ -26,20 +455,357 @@ impl<'a> Lexer<'a> {
     fn consume_whitespace(&mut self) -> Option<String> {
         self.next_while(|c| c.is_whitespace())
     }
-}
 
+    /// Skips whitespace and comments, i.e. -- until the end of the line and /* until */.
+    fn consume_whitespace_and_comments(&mut self) -> Result<()> {
+        loop {
+            self.consume_whitespace();
+            match (self.peek(), self.peek_second()) {
+                (Some('-'), Some('-')) => {
+                    self.next_while(|c| c != '\n');
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.location();
+                    self.next_char();
+                    self.next_char();
+                    loop {
+                        match self.next_char() {
+                            Some('*') if self.next_if(|c| c == '/').is_some() => break,
+                            Some(_) => {}
+                            None => {
+                                return Err(self.error(start, "Unterminated comment".into()))
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Scans the next token, if any.
+    fn scan(&mut self) -> Result<Option<Token>> {
+        self.consume_whitespace_and_comments()?;
+        match self.peek() {
+            Some('\'') => self.scan_string().map(Some),
+            Some('"') => self.scan_quoted_ident().map(Some),
+            Some(c) if c.is_ascii_digit() => self.scan_number().map(Some),
+            Some(c) if c.is_alphabetic() || c == '_' => Ok(Some(self.scan_ident())),
+            Some(_) => self.scan_symbol().map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Scans an unquoted identifier or keyword.
+    fn scan_ident(&mut self) -> Token {
+        let name = self.next_while(|c| c.is_alphanumeric() || c == '_').unwrap_or_default();
+        match Keyword::lookup(&name) {
+            Some(keyword) => Token::Keyword(keyword),
+            None => Token::Ident(name.to_lowercase()),
+        }
+    }
+
+    /// Scans a double-quoted identifier, where "" is an escaped ".
+    fn scan_quoted_ident(&mut self) -> Result<Token> {
+        let start = self.location();
+        self.next_char();
+        let mut name = String::new();
+        loop {
+            match self.next_char() {
+                Some('"') if self.next_if(|c| c == '"').is_some() => name.push('"'),
+                Some('"') => break,
+                Some(c) => name.push(c),
+                None => return Err(self.error(start, "Unterminated quoted identifier".into())),
+            }
+        }
+        if name.is_empty() {
+            return Err(self.error(start, "Empty quoted identifier".into()));
+        }
+        Ok(Token::Ident(name))
+    }
+
+    /// Scans a single-quoted string literal, where '' is an escaped ' and backslash escapes
+    /// \', \", \\, \n, \r, \t and \0 are resolved.
+    fn scan_string(&mut self) -> Result<Token> {
+        let start = self.location();
+        self.next_char();
+        let mut s = String::new();
+        loop {
+            let position = self.location();
+            match self.next_char() {
+                Some('\'') if self.next_if(|c| c == '\'').is_some() => s.push('\''),
+                Some('\'') => break,
+                Some('\\') => match self.next_char() {
+                    Some(c @ ('\'' | '"' | '\\')) => s.push(c),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('0') => s.push('\0'),
+                    Some(c) => {
+                        return Err(self.error(position, format!("Invalid escape sequence \\{}", c)))
+                    }
+                    None => return Err(self.error(start, "Unterminated string".into())),
+                },
+                Some(c) => s.push(c),
+                None => return Err(self.error(start, "Unterminated string".into())),
+            }
+        }
+        Ok(Token::String(s))
+    }
+
+    /// Scans a number: digits, optionally followed by a fraction and an exponent.
+    fn scan_number(&mut self) -> Result<Token> {
+        let start = self.location();
+        let mut number = self.next_while(|c| c.is_ascii_digit()).unwrap_or_default();
+        if let Some(sep) = self.next_if(|c| c == '.') {
+            number.push(sep);
+            number.extend(self.next_while(|c| c.is_ascii_digit()));
+        }
+        if let Some(exp) = self.next_if(|c| c == 'e' || c == 'E') {
+            number.push(exp);
+            number.extend(self.next_if(|c| c == '+' || c == '-'));
+            match self.next_while(|c| c.is_ascii_digit()) {
+                Some(digits) => number.push_str(&digits),
+                None => return Err(self.error(start, format!("Invalid number {}", number))),
+            }
+        }
+        Ok(Token::Number(number))
+    }
+
+    /// Scans an operator or punctuation symbol.
+    fn scan_symbol(&mut self) -> Result<Token> {
+        let start = self.location();
+        let token = match self.next_char() {
+            Some('.') => Token::Period,
+            Some('=') => Token::Equal,
+            Some('>') if self.next_if(|c| c == '=').is_some() => Token::GreaterThanOrEqual,
+            Some('>') => Token::GreaterThan,
+            Some('<') if self.next_if(|c| c == '=').is_some() => Token::LessThanOrEqual,
+            Some('<') if self.next_if(|c| c == '>').is_some() => Token::LessOrGreaterThan,
+            Some('<') => Token::LessThan,
+            Some('!') if self.next_if(|c| c == '=').is_some() => Token::NotEqual,
+            Some('!') => Token::Exclamation,
+            Some('+') => Token::Plus,
+            Some('-') => Token::Minus,
+            Some('*') => Token::Asterisk,
+            Some('/') => Token::Slash,
+            Some('^') => Token::Caret,
+            Some('%') => Token::Percent,
+            Some('?') => Token::Question,
+            Some('$') => match self.next_while(|c| c.is_ascii_digit()) {
+                Some(n) => Token::Parameter(n),
+                None => return Err(self.error(start, "Expected parameter number after $".into())),
+            },
+            Some('(') => Token::OpenParen,
+            Some(')') => Token::CloseParen,
+            Some(',') => Token::Comma,
+            Some(';') => Token::Semicolon,
+            Some(c) => return Err(self.error(start, format!("Unexpected character {}", c))),
+            None => return Err(self.error(start, "Unexpected end of input".into())),
+        };
+        Ok(token)
+    }
+}
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn lex(command: &str) -> Result<Vec<Token>> {
+        Lexer::new(command).collect()
+    }
 
-    //#[test]
-    fn lexer_test() {
-        let temp = "hellow world haha";
-        let mut lexer = Lexer::new(temp);
-        let result = lexer.consume_whitespace();
-        assert_eq!(result, None);
-        assert_eq!(temp, "hellowworldhaha");
+    fn error(message: &str) -> Result<Vec<Token>> {
+        Err(Error::Parse(message.into()))
     }
-    
-}
\ No newline at end of file
+
+    #[test]
+    fn next_while() {
+        let mut lexer = Lexer::new("  \n hello world");
+        assert_eq!(lexer.consume_whitespace(), Some("  \n ".into()));
+        assert_eq!(lexer.consume_whitespace(), None);
+        assert_eq!(lexer.next_while(|c| c.is_alphabetic()), Some("hello".into()));
+        assert_eq!(lexer.location(), (2, 7));
+    }
+
+    #[test]
+    fn select() -> Result<()> {
+        use super::Keyword::{From, Select, Where};
+        use Token::*;
+        assert_eq!(
+            lex("SELECT id, t.Name FROM t WHERE id >= 3.14;")?,
+            vec![
+                Keyword(Select),
+                Ident("id".into()),
+                Comma,
+                Ident("t".into()),
+                Period,
+                Ident("name".into()),
+                Keyword(From),
+                Ident("t".into()),
+                Keyword(Where),
+                Ident("id".into()),
+                GreaterThanOrEqual,
+                Number("3.14".into()),
+                Semicolon,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn keywords() -> Result<()> {
+        assert_eq!(
+            lex("select Select sElEcT selects")?,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Keyword(Keyword::Select),
+                Token::Keyword(Keyword::Select),
+                Token::Ident("selects".into()),
+            ]
+        );
+        assert_eq!(Keyword::lookup("ilike"), Some(Keyword::ILike));
+        assert_eq!(Keyword::lookup("foo"), None);
+        assert_eq!(Keyword::NaN.to_string(), "NAN");
+        Ok(())
+    }
+
+    #[test]
+    fn idents() -> Result<()> {
+        use Token::*;
+        assert_eq!(
+            lex(r#"_a1 Foo_Bar "Foo Bar" "a""b" "select" über"#)?,
+            vec![
+                Ident("_a1".into()),
+                Ident("foo_bar".into()),
+                Ident("Foo Bar".into()),
+                Ident("a\"b".into()),
+                Ident("select".into()),
+                Ident("über".into()),
+            ]
+        );
+        assert_eq!(lex(r#"a "b"#), error("Unterminated quoted identifier at line 1 column 3"));
+        assert_eq!(lex(r#"a """#), error("Empty quoted identifier at line 1 column 3"));
+        Ok(())
+    }
+
+    #[test]
+    fn strings() -> Result<()> {
+        use Token::*;
+        assert_eq!(
+            lex(r#"'' 'abc' 'it''s' 'a\'b\"c\\d' 'x\ny\tz\r\0' 'multi
+line' '-- /* */'"#)?,
+            vec![
+                String("".into()),
+                String("abc".into()),
+                String("it's".into()),
+                String("a'b\"c\\d".into()),
+                String("x\ny\tz\r\0".into()),
+                String("multi\nline".into()),
+                String("-- /* */".into()),
+            ]
+        );
+        assert_eq!(lex("'abc"), error("Unterminated string at line 1 column 1"));
+        assert_eq!(lex("'abc\\"), error("Unterminated string at line 1 column 1"));
+        assert_eq!(lex("a\n  'a\\qb'"), error("Invalid escape sequence \\q at line 2 column 5"));
+        Ok(())
+    }
+
+    #[test]
+    fn numbers() -> Result<()> {
+        let numbers = |s| -> Result<Vec<std::string::String>> {
+            lex(s)?
+                .into_iter()
+                .map(|t| match t {
+                    Token::Number(n) => Ok(n),
+                    t => Err(Error::Parse(format!("Unexpected token {}", t))),
+                })
+                .collect()
+        };
+        assert_eq!(
+            numbers("0 42 3.14 1. 1e3 1.5E-3 2e+10 007")?,
+            vec!["0", "42", "3.14", "1.", "1e3", "1.5E-3", "2e+10", "007"]
+        );
+        assert_eq!(
+            lex("1.2.3")?,
+            vec![Token::Number("1.2".into()), Token::Period, Token::Number("3".into())]
+        );
+        assert_eq!(lex("1 + 1e"), error("Invalid number 1e at line 1 column 5"));
+        assert_eq!(lex("1e-"), error("Invalid number 1e- at line 1 column 1"));
+        Ok(())
+    }
+
+    #[test]
+    fn symbols() -> Result<()> {
+        use Token::*;
+        assert_eq!(
+            lex(". = != <> < <= > >= + - * / ^ % ! ? ( ) , ; >=<=")?,
+            vec![
+                Period,
+                Equal,
+                NotEqual,
+                LessOrGreaterThan,
+                LessThan,
+                LessThanOrEqual,
+                GreaterThan,
+                GreaterThanOrEqual,
+                Plus,
+                Minus,
+                Asterisk,
+                Slash,
+                Caret,
+                Percent,
+                Exclamation,
+                Question,
+                OpenParen,
+                CloseParen,
+                Comma,
+                Semicolon,
+                GreaterThanOrEqual,
+                LessThanOrEqual,
+            ]
+        );
+        assert_eq!(lex("1-1")?, vec![Number("1".into()), Minus, Number("1".into())]);
+        assert_eq!(lex("3!")?, vec![Number("3".into()), Exclamation]);
+        assert_eq!(lex("$1 $12")?, vec![Parameter("1".into()), Parameter("12".into())]);
+        assert_eq!(lex("a = $b"), error("Expected parameter number after $ at line 1 column 5"));
+        assert_eq!(lex("a = @b"), error("Unexpected character @ at line 1 column 5"));
+        assert_eq!(lex("a\n\n  #"), error("Unexpected character # at line 3 column 3"));
+        Ok(())
+    }
+
+    #[test]
+    fn comments() -> Result<()> {
+        use Token::*;
+        assert_eq!(
+            lex("-- leading\nSELECT /* inline\n comment */ 1 -- trailing\n/**/- -1/2 --")?,
+            vec![
+                Keyword(self::Keyword::Select),
+                Number("1".into()),
+                Minus,
+                Minus,
+                Number("1".into()),
+                Slash,
+                Number("2".into()),
+            ]
+        );
+        assert_eq!(lex("")?, vec![]);
+        assert_eq!(lex(" -- only a comment")?, vec![]);
+        assert_eq!(lex("1 /* a\n * b"), error("Unterminated comment at line 1 column 3"));
+        Ok(())
+    }
+
+    #[test]
+    fn display() -> Result<()> {
+        let command = "SELECT a, 'it''s' FROM t WHERE x <> 1.5 AND y != ? OR z = $2 ;";
+        let tokens = lex(command)?;
+        let display: Vec<_> = tokens.iter().map(|t| t.to_string()).collect();
+        assert_eq!(
+            display.join(" "),
+            "SELECT a , 'it''s' FROM t WHERE x <> 1.5 AND y != ? OR z = $2 ;"
+        );
+        assert_eq!(lex(&display.join(" "))?, tokens);
+        Ok(())
+    }
+}
@@ -1 +1,1815 @@
-mod lexer;
\ No newline at end of file
+pub mod ast;
+pub mod lexer;
+pub use lexer::{Keyword, Lexer, Token};
+
+use super::storage::{Datatype, Value};
+use crate::error::{Error, Result};
+use ast::Expression;
+
+use std::collections::BTreeMap;
+
+/// A recursive-descent SQL parser, which parses a single statement into an AST. Operator
+/// expressions are parsed via precedence climbing.
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    /// The peeked next token, if any, along with the input remaining before it.
+    peeked: Option<(&'a str, Option<Result<Token>>)>,
+    /// The number of positional ? parameters parsed so far.
+    parameters: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(query: &'a str) -> Self {
+        Parser { lexer: Lexer::new(query), peeked: None, parameters: 0 }
+    }
+
+    /// Parses the input as a single statement, optionally terminated by a semicolon.
+    pub fn parse(&mut self) -> Result<ast::Statement> {
+        let statement = self.parse_statement()?;
+        self.next_is(Token::Semicolon);
+        match self.peek()? {
+            Some(token) => Err(Self::unexpected(token)),
+            None => Ok(statement),
+        }
+    }
+
+    fn unexpected(token: Token) -> Error {
+        Error::Parse(format!("Unexpected token {}", token))
+    }
+
+    /// Returns the next token, erroring at the end of the input.
+    fn next(&mut self) -> Result<Token> {
+        match self.peeked.take() {
+            Some((_, token)) => token,
+            None => self.lexer.next(),
+        }
+        .unwrap_or_else(|| Err(Error::Parse("Unexpected end of input".into())))
+    }
+
+    /// Peeks at the next token, if any.
+    fn peek(&mut self) -> Result<Option<Token>> {
+        if self.peeked.is_none() {
+            self.peeked = Some((self.lexer.remainder(), self.lexer.next()));
+        }
+        self.peeked.as_ref().and_then(|(_, token)| token.clone()).transpose()
+    }
+
+    /// Returns the input that hasn't been parsed yet, including any peeked token.
+    fn remainder(&self) -> &'a str {
+        match &self.peeked {
+            Some((remainder, _)) => remainder,
+            None => self.lexer.remainder(),
+        }
+    }
+
+    /// Returns the next token if it satisfies the predicate.
+    fn next_if<F: Fn(&Token) -> bool>(&mut self, predicate: F) -> Option<Token> {
+        self.peek().ok()?.filter(|t| predicate(t))?;
+        self.next().ok()
+    }
+
+    /// Consumes the next token if it is the given token.
+    fn next_is(&mut self, token: Token) -> bool {
+        self.next_if(|t| *t == token).is_some()
+    }
+
+    /// Consumes the next token if it is the given keyword.
+    fn next_is_keyword(&mut self, keyword: Keyword) -> bool {
+        self.next_is(Token::Keyword(keyword))
+    }
+
+    /// Consumes the next token, erroring if it isn't the expected token.
+    fn expect(&mut self, expect: Token) -> Result<()> {
+        match self.next()? {
+            token if token == expect => Ok(()),
+            token => Err(Error::Parse(format!("Expected token {}, found {}", expect, token))),
+        }
+    }
+
+    /// Consumes the next token, erroring if it isn't an identifier.
+    fn next_ident(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::Ident(ident) => Ok(ident),
+            token => Err(Error::Parse(format!("Expected identifier, found {}", token))),
+        }
+    }
+
+    /// Parses a table name, which can be qualified by a schema, e.g. information_schema.tables.
+    fn next_table_name(&mut self) -> Result<String> {
+        let name = self.next_ident()?;
+        match self.next_is(Token::Period) {
+            true => Ok(format!("{}.{}", name, self.next_ident()?)),
+            false => Ok(name),
+        }
+    }
+
+    /// Parses an optional alias, with or without AS.
+    fn parse_alias(&mut self) -> Result<Option<String>> {
+        if self.next_is_keyword(Keyword::As) {
+            return Ok(Some(self.next_ident()?));
+        }
+        Ok(self.next_if(|t| matches!(t, Token::Ident(_))).map(|t| t.to_string()))
+    }
+
+    fn parse_statement(&mut self) -> Result<ast::Statement> {
+        match self.peek()? {
+            Some(Token::Keyword(Keyword::Begin)) => {
+                self.next()?;
+                self.next_is_keyword(Keyword::Transaction);
+                let (mut read_only, mut read_write) = (false, false);
+                if self.next_is_keyword(Keyword::Read) {
+                    match self.next()? {
+                        Token::Keyword(Keyword::Only) => read_only = true,
+                        Token::Keyword(Keyword::Write) => read_write = true,
+                        token => return Err(Self::unexpected(token)),
+                    }
+                }
+                let mut as_of = None;
+                if self.next_is_keyword(Keyword::As) {
+                    self.expect(Token::Keyword(Keyword::Of))?;
+                    self.expect(Token::Keyword(Keyword::System))?;
+                    self.expect(Token::Keyword(Keyword::Time))?;
+                    as_of = match self.next()? {
+                        Token::Number(n) => Some(
+                            n.parse()
+                                .map_err(|_| Error::Parse(format!("Invalid version {}", n)))?,
+                        ),
+                        token => return Err(Self::unexpected(token)),
+                    };
+                    if read_write {
+                        return Err(Error::Parse("Can't write AS OF SYSTEM TIME".into()));
+                    }
+                }
+                Ok(ast::Statement::Begin { read_only, as_of })
+            }
+            Some(Token::Keyword(Keyword::Commit)) => {
+                self.next()?;
+                Ok(ast::Statement::Commit)
+            }
+            Some(Token::Keyword(Keyword::Rollback)) => {
+                self.next()?;
+                Ok(ast::Statement::Rollback)
+            }
+            Some(Token::Keyword(Keyword::Explain)) => {
+                self.next()?;
+                let analyze = self.next_is_keyword(Keyword::Analyze);
+                let statement = Box::new(self.parse_statement()?);
+                if let ast::Statement::Explain { .. } = *statement {
+                    return Err(Error::Parse("Can't EXPLAIN an EXPLAIN statement".into()));
+                }
+                Ok(ast::Statement::Explain { statement, analyze })
+            }
+            Some(Token::Keyword(Keyword::Prepare)) => {
+                self.next()?;
+                let name = self.next_ident()?;
+                self.expect(Token::Keyword(Keyword::As))?;
+                Ok(ast::Statement::Prepare { name, statement: Box::new(self.parse_statement()?) })
+            }
+            Some(Token::Keyword(Keyword::Execute)) => {
+                self.next()?;
+                let name = self.next_ident()?;
+                let mut parameters = Vec::new();
+                if self.next_is(Token::OpenParen) {
+                    parameters = self.parse_expressions()?;
+                    self.expect(Token::CloseParen)?;
+                }
+                Ok(ast::Statement::Execute { name, parameters })
+            }
+            Some(Token::Keyword(Keyword::Deallocate)) => {
+                self.next()?;
+                self.next_is_keyword(Keyword::Prepare);
+                Ok(ast::Statement::Deallocate(self.next_ident()?))
+            }
+            Some(Token::Keyword(Keyword::Create)) => self.parse_create(),
+            Some(Token::Keyword(Keyword::Drop)) => self.parse_drop(),
+            Some(Token::Keyword(Keyword::Refresh)) => {
+                self.next()?;
+                self.expect(Token::Keyword(Keyword::Materialized))?;
+                self.expect(Token::Keyword(Keyword::View))?;
+                Ok(ast::Statement::RefreshView(self.next_ident()?))
+            }
+            Some(Token::Keyword(Keyword::Analyze)) => {
+                self.next()?;
+                let table = match self.peek()? {
+                    Some(Token::Ident(_)) => Some(self.next_table_name()?),
+                    _ => None,
+                };
+                Ok(ast::Statement::Analyze(table))
+            }
+            // TABLES and TRANSACTIONS aren't keywords, to allow them as identifiers.
+            Some(Token::Keyword(Keyword::Show)) => {
+                self.next()?;
+                match self.next()? {
+                    Token::Keyword(Keyword::Create) => {
+                        self.expect(Token::Keyword(Keyword::Table))?;
+                        Ok(ast::Statement::ShowCreateTable(self.next_table_name()?))
+                    }
+                    Token::Ident(name) if name == "tables" => Ok(ast::Statement::ShowTables),
+                    Token::Ident(name) if name == "transactions" => {
+                        Ok(ast::Statement::ShowTransactions)
+                    }
+                    token => Err(Self::unexpected(token)),
+                }
+            }
+            Some(Token::Keyword(Keyword::Delete)) => self.parse_delete(),
+            Some(Token::Keyword(Keyword::Insert)) => self.parse_insert(),
+            Some(Token::Keyword(Keyword::Update)) => self.parse_update(),
+            Some(Token::Keyword(Keyword::Select | Keyword::With)) => self.parse_select(),
+            Some(token) => Err(Self::unexpected(token)),
+            None => Err(Error::Parse("Unexpected end of input".into())),
+        }
+    }
+
+    /// Parses a CREATE TABLE, VIEW or MATERIALIZED VIEW statement.
+    fn parse_create(&mut self) -> Result<ast::Statement> {
+        self.expect(Token::Keyword(Keyword::Create))?;
+        match self.next()? {
+            Token::Keyword(Keyword::Table) => self.parse_create_table(),
+            Token::Keyword(Keyword::View) => self.parse_create_view(false),
+            Token::Keyword(Keyword::Materialized) => {
+                self.expect(Token::Keyword(Keyword::View))?;
+                self.parse_create_view(true)
+            }
+            token => Err(Error::Parse(format!("Expected token TABLE, found {}", token))),
+        }
+    }
+
+    /// Parses the remainder of a CREATE VIEW statement.
+    fn parse_create_view(&mut self, materialized: bool) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        self.expect(Token::Keyword(Keyword::As))?;
+        let start = self.remainder();
+        let query = Box::new(self.parse_select()?);
+        let sql = start[..start.len() - self.remainder().len()].trim().to_string();
+        Ok(ast::Statement::CreateView { name, query, sql, materialized })
+    }
+
+    /// Parses the remainder of a CREATE TABLE statement.
+    fn parse_create_table(&mut self) -> Result<ast::Statement> {
+        let name = self.next_ident()?;
+        self.expect(Token::OpenParen)?;
+        let mut columns = Vec::new();
+        loop {
+            columns.push(self.parse_column()?);
+            if !self.next_is(Token::Comma) {
+                break;
+            }
+        }
+        self.expect(Token::CloseParen)?;
+        Ok(ast::Statement::CreateTable { name, columns })
+    }
+
+    /// Parses a column definition in CREATE TABLE.
+    fn parse_column(&mut self) -> Result<ast::Column> {
+        let mut column = ast::Column {
+            name: self.next_ident()?,
+            datatype: self.parse_datatype()?,
+            primary_key: false,
+            nullable: None,
+            default: None,
+            unique: false,
+            index: false,
+            references: None,
+        };
+        while let Some(Token::Keyword(keyword)) = self.next_if(|t| matches!(t, Token::Keyword(_)))
+        {
+            match keyword {
+                Keyword::Primary => {
+                    self.expect(Token::Keyword(Keyword::Key))?;
+                    column.primary_key = true;
+                }
+                Keyword::Null | Keyword::Not => {
+                    let nullable = keyword == Keyword::Null;
+                    if !nullable {
+                        self.expect(Token::Keyword(Keyword::Null))?;
+                    }
+                    if column.nullable.is_some_and(|n| n != nullable) {
+                        return Err(Error::Parse(format!(
+                            "Column {} can't be both NULL and NOT NULL",
+                            column.name
+                        )));
+                    }
+                    column.nullable = Some(nullable);
+                }
+                Keyword::Default => column.default = Some(self.parse_expression()?),
+                Keyword::Unique => column.unique = true,
+                Keyword::Index => column.index = true,
+                Keyword::References => column.references = Some(self.next_ident()?),
+                keyword => return Err(Self::unexpected(Token::Keyword(keyword))),
+            }
+        }
+        Ok(column)
+    }
+
+    fn parse_datatype(&mut self) -> Result<Datatype> {
+        Ok(match self.next()? {
+            Token::Keyword(Keyword::Bool) | Token::Keyword(Keyword::Boolean) => Datatype::Boolean,
+            Token::Keyword(Keyword::Int) | Token::Keyword(Keyword::Integer) => Datatype::Integer,
+            Token::Keyword(Keyword::Float) | Token::Keyword(Keyword::Double) => Datatype::Float,
+            Token::Keyword(Keyword::String)
+            | Token::Keyword(Keyword::Text)
+            | Token::Keyword(Keyword::Varchar)
+            | Token::Keyword(Keyword::Char) => Datatype::String,
+            token => return Err(Error::Parse(format!("Expected datatype, found {}", token))),
+        })
+    }
+
+    /// Parses a DROP TABLE, VIEW or MATERIALIZED VIEW statement.
+    fn parse_drop(&mut self) -> Result<ast::Statement> {
+        self.expect(Token::Keyword(Keyword::Drop))?;
+        match self.next()? {
+            Token::Keyword(Keyword::Table) => {
+                Ok(ast::Statement::DropTable(self.next_table_name()?))
+            }
+            Token::Keyword(Keyword::Materialized) => {
+                self.expect(Token::Keyword(Keyword::View))?;
+                Ok(ast::Statement::DropView(self.next_ident()?))
+            }
+            Token::Keyword(Keyword::View) => Ok(ast::Statement::DropView(self.next_ident()?)),
+            token => Err(Error::Parse(format!("Expected token TABLE, found {}", token))),
+        }
+    }
+
+    /// Parses a DELETE statement.
+    fn parse_delete(&mut self) -> Result<ast::Statement> {
+        self.expect(Token::Keyword(Keyword::Delete))?;
+        self.expect(Token::Keyword(Keyword::From))?;
+        let table = self.next_table_name()?;
+        Ok(ast::Statement::Delete { table, r#where: self.parse_where()? })
+    }
+
+    /// Parses an INSERT statement.
+    fn parse_insert(&mut self) -> Result<ast::Statement> {
+        self.expect(Token::Keyword(Keyword::Insert))?;
+        self.expect(Token::Keyword(Keyword::Into))?;
+        let table = self.next_table_name()?;
+
+        let mut columns = None;
+        if self.next_is(Token::OpenParen) {
+            let mut names = Vec::new();
+            loop {
+                names.push(self.next_ident()?);
+                if !self.next_is(Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(Token::CloseParen)?;
+            columns = Some(names);
+        }
+
+        let source = match self.peek()? {
+            Some(Token::Keyword(Keyword::Select | Keyword::With)) => {
+                ast::InsertSource::Select(Box::new(self.parse_select()?))
+            }
+            _ => {
+                self.expect(Token::Keyword(Keyword::Values))?;
+                let mut values = Vec::new();
+                loop {
+                    self.expect(Token::OpenParen)?;
+                    values.push(self.parse_expressions()?);
+                    self.expect(Token::CloseParen)?;
+                    if !self.next_is(Token::Comma) {
+                        break;
+                    }
+                }
+                ast::InsertSource::Values(values)
+            }
+        };
+
+        // ON CONFLICT [(column)] DO NOTHING | DO UPDATE SET column = expr, ...
+        let mut on_conflict = None;
+        if self.next_is_keyword(Keyword::On) {
+            self.expect(Token::Keyword(Keyword::Conflict))?;
+            let mut column = None;
+            if self.next_is(Token::OpenParen) {
+                column = Some(self.next_ident()?);
+                self.expect(Token::CloseParen)?;
+            }
+            self.expect(Token::Keyword(Keyword::Do))?;
+            let update = match self.next()? {
+                Token::Keyword(Keyword::Nothing) => None,
+                Token::Keyword(Keyword::Update) if column.is_some() => {
+                    self.expect(Token::Keyword(Keyword::Set))?;
+                    Some(self.parse_set()?)
+                }
+                Token::Keyword(Keyword::Update) => {
+                    return Err(Error::Parse("ON CONFLICT DO UPDATE requires a column".into()))
+                }
+                token => {
+                    return Err(Error::Parse(format!(
+                        "Expected NOTHING or UPDATE, found {}",
+                        token
+                    )))
+                }
+            };
+            on_conflict = Some(ast::OnConflict { column, update });
+        }
+        Ok(ast::Statement::Insert { table, columns, source, on_conflict })
+    }
+
+    /// Parses an UPDATE statement.
+    fn parse_update(&mut self) -> Result<ast::Statement> {
+        self.expect(Token::Keyword(Keyword::Update))?;
+        let table = self.next_table_name()?;
+        self.expect(Token::Keyword(Keyword::Set))?;
+        let set = self.parse_set()?;
+        Ok(ast::Statement::Update { table, set, r#where: self.parse_where()? })
+    }
+
+    /// Parses the column assignments of a SET clause.
+    fn parse_set(&mut self) -> Result<BTreeMap<String, Expression>> {
+        let mut set = BTreeMap::new();
+        loop {
+            let column = self.next_ident()?;
+            self.expect(Token::Equal)?;
+            let expr = self.parse_expression()?;
+            if set.contains_key(&column) {
+                return Err(Error::Parse(format!("Column {} set multiple times", column)));
+            }
+            set.insert(column, expr);
+            if !self.next_is(Token::Comma) {
+                break;
+            }
+        }
+        Ok(set)
+    }
+
+    /// Parses a SELECT statement, possibly combined with other SELECT statements by set
+    /// operations. ORDER BY, LIMIT and OFFSET apply to the combined result.
+    fn parse_select(&mut self) -> Result<ast::Statement> {
+        if self.next_is_keyword(Keyword::With) {
+            return self.parse_with();
+        }
+        let mut statement = self.parse_set_operation(0)?;
+        let (ast::Statement::Select { order, limit, offset, .. }
+        | ast::Statement::SetOperation { order, limit, offset, .. }) = &mut statement
+        else {
+            return Err(Error::Internal("Expected SELECT or set operation".into()));
+        };
+        if self.next_is_keyword(Keyword::Order) {
+            *order = self.parse_order_by()?;
+        }
+        if self.next_is_keyword(Keyword::Limit) {
+            *limit = Some(self.parse_expression()?);
+        }
+        if self.next_is_keyword(Keyword::Offset) {
+            *offset = Some(self.parse_expression()?);
+        }
+        Ok(statement)
+    }
+
+    /// Parses the remainder of a WITH query, after WITH.
+    fn parse_with(&mut self) -> Result<ast::Statement> {
+        let recursive = self.next_is_keyword(Keyword::Recursive);
+        let mut ctes = Vec::new();
+        loop {
+            let name = self.next_ident()?;
+            let mut columns = Vec::new();
+            if self.next_is(Token::OpenParen) {
+                loop {
+                    columns.push(self.next_ident()?);
+                    if !self.next_is(Token::Comma) {
+                        break;
+                    }
+                }
+                self.expect(Token::CloseParen)?;
+            }
+            self.expect(Token::Keyword(Keyword::As))?;
+            self.expect(Token::OpenParen)?;
+            let query = self.parse_select()?;
+            self.expect(Token::CloseParen)?;
+            ctes.push(ast::Cte { name, columns, query });
+            if !self.next_is(Token::Comma) {
+                break;
+            }
+        }
+        let statement = Box::new(self.parse_select()?);
+        Ok(ast::Statement::With { recursive, ctes, statement })
+    }
+
+    /// Parses SELECT statements combined by set operators of at least the given precedence,
+    /// using precedence climbing like parse_expression_at().
+    fn parse_set_operation(&mut self, min_precedence: u8) -> Result<ast::Statement> {
+        let mut left = self.parse_select_core()?;
+        loop {
+            let operator = match self.peek()? {
+                Some(Token::Keyword(Keyword::Union)) => ast::SetOperator::Union,
+                Some(Token::Keyword(Keyword::Intersect)) => ast::SetOperator::Intersect,
+                Some(Token::Keyword(Keyword::Except)) => ast::SetOperator::Except,
+                _ => break,
+            };
+            if operator.precedence() < min_precedence {
+                break;
+            }
+            self.next()?;
+            let all = self.next_is_keyword(Keyword::All);
+            if !all {
+                // DISTINCT is the default, but may be given explicitly.
+                self.next_is_keyword(Keyword::Distinct);
+            }
+            let right = self.parse_set_operation(operator.precedence() + 1)?;
+            left = ast::Statement::SetOperation {
+                operator,
+                all,
+                left: Box::new(left),
+                right: Box::new(right),
+                order: Vec::new(),
+                limit: None,
+                offset: None,
+            };
+        }
+        Ok(left)
+    }
+
+    /// Parses a single SELECT statement, without ORDER BY, LIMIT and OFFSET.
+    fn parse_select_core(&mut self) -> Result<ast::Statement> {
+        self.expect(Token::Keyword(Keyword::Select))?;
+        let mut select = Vec::new();
+        if !self.next_is(Token::Asterisk) {
+            loop {
+                let expr = self.parse_expression()?;
+                select.push((expr, self.parse_alias()?));
+                if !self.next_is(Token::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let mut from = Vec::new();
+        if self.next_is_keyword(Keyword::From) {
+            loop {
+                from.push(self.parse_from_item()?);
+                if !self.next_is(Token::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let r#where = self.parse_where()?;
+
+        let mut group_by = Vec::new();
+        if self.next_is_keyword(Keyword::Group) {
+            self.expect(Token::Keyword(Keyword::By))?;
+            group_by = self.parse_expressions()?;
+        }
+
+        let having = match self.next_is_keyword(Keyword::Having) {
+            true => Some(self.parse_expression()?),
+            false => None,
+        };
+
+        Ok(ast::Statement::Select {
+            select,
+            from,
+            r#where,
+            group_by,
+            having,
+            order: Vec::new(),
+            limit: None,
+            offset: None,
+        })
+    }
+
+    /// Parses a FROM item: a table, followed by any number of joins.
+    fn parse_from_item(&mut self) -> Result<ast::FromItem> {
+        let mut item = self.parse_from_table()?;
+        while let Some(r#type) = self.parse_join_type()? {
+            let right = Box::new(self.parse_from_table()?);
+            let predicate = match r#type {
+                ast::JoinType::Cross => None,
+                _ => {
+                    self.expect(Token::Keyword(Keyword::On))?;
+                    Some(self.parse_expression()?)
+                }
+            };
+            item = ast::FromItem::Join { left: Box::new(item), right, r#type, predicate };
+        }
+        Ok(item)
+    }
+
+    fn parse_from_table(&mut self) -> Result<ast::FromItem> {
+        let name = self.next_table_name()?;
+        Ok(ast::FromItem::Table { name, alias: self.parse_alias()? })
+    }
+
+    /// Parses a join keyword sequence, e.g. LEFT OUTER JOIN, if any.
+    fn parse_join_type(&mut self) -> Result<Option<ast::JoinType>> {
+        let r#type = match self.peek()? {
+            Some(Token::Keyword(Keyword::Join)) => ast::JoinType::Inner,
+            Some(Token::Keyword(Keyword::Inner)) => ast::JoinType::Inner,
+            Some(Token::Keyword(Keyword::Cross)) => ast::JoinType::Cross,
+            Some(Token::Keyword(Keyword::Left)) => ast::JoinType::Left,
+            Some(Token::Keyword(Keyword::Right)) => ast::JoinType::Right,
+            Some(Token::Keyword(Keyword::Full)) => ast::JoinType::Full,
+            _ => return Ok(None),
+        };
+        if !self.next_is_keyword(Keyword::Join) {
+            self.next()?;
+            if matches!(r#type, ast::JoinType::Left | ast::JoinType::Right | ast::JoinType::Full) {
+                self.next_is_keyword(Keyword::Outer);
+            }
+            self.expect(Token::Keyword(Keyword::Join))?;
+        }
+        Ok(Some(r#type))
+    }
+
+    /// Parses an optional WHERE clause.
+    fn parse_where(&mut self) -> Result<Option<Expression>> {
+        match self.next_is_keyword(Keyword::Where) {
+            true => Ok(Some(self.parse_expression()?)),
+            false => Ok(None),
+        }
+    }
+
+    /// Parses a comma-separated list of expressions.
+    fn parse_expressions(&mut self) -> Result<Vec<Expression>> {
+        let mut exprs = Vec::new();
+        loop {
+            exprs.push(self.parse_expression()?);
+            if !self.next_is(Token::Comma) {
+                break;
+            }
+        }
+        Ok(exprs)
+    }
+
+    pub fn parse_expression(&mut self) -> Result<Expression> {
+        self.parse_expression_at(0)
+    }
+
+    /// Parses an expression via precedence climbing, consuming infix and postfix operators
+    /// with a precedence of at least min_precedence. Precedences, from lowest:
+    ///
+    /// 1. OR
+    /// 2. AND
+    /// 3. NOT
+    /// 4. =, !=, <>, <, <=, >, >=, IS, [NOT] IN, BETWEEN, LIKE, ILIKE, REGEXP
+    /// 5. +, - (binary)
+    /// 6. *, /, %
+    /// 7. ^ (right-associative)
+    /// 8. +, - (unary)
+    /// 9. ! (factorial)
+    fn parse_expression_at(&mut self, min_precedence: u8) -> Result<Expression> {
+        let mut lhs = if self.next_is_keyword(Keyword::Not) {
+            Expression::Not(self.parse_expression_at(3)?.into())
+        } else if self.next_is(Token::Minus) {
+            Expression::Negate(self.parse_expression_at(8)?.into())
+        } else if self.next_is(Token::Plus) {
+            Expression::Assert(self.parse_expression_at(8)?.into())
+        } else {
+            self.parse_expression_atom()?
+        };
+
+        while let Some(token) = self.peek()? {
+            let (precedence, right_associative) = match &token {
+                Token::Keyword(Keyword::Or) => (1, false),
+                Token::Keyword(Keyword::And) => (2, false),
+                Token::Equal
+                | Token::NotEqual
+                | Token::LessOrGreaterThan
+                | Token::LessThan
+                | Token::LessThanOrEqual
+                | Token::GreaterThan
+                | Token::GreaterThanOrEqual
+                | Token::Keyword(Keyword::Is)
+                | Token::Keyword(Keyword::Not)
+                | Token::Keyword(Keyword::In)
+                | Token::Keyword(Keyword::Between)
+                | Token::Keyword(Keyword::Like)
+                | Token::Keyword(Keyword::ILike)
+                | Token::Keyword(Keyword::Regexp) => (4, false),
+                Token::Plus | Token::Minus => (5, false),
+                Token::Asterisk | Token::Slash | Token::Percent => (6, false),
+                Token::Caret => (7, true),
+                Token::Exclamation => (9, false),
+                _ => break,
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            self.next()?;
+            let next_precedence = if right_associative { precedence } else { precedence + 1 };
+
+            lhs = match token {
+                Token::Exclamation => Expression::Factorial(lhs.into()),
+                Token::Keyword(Keyword::Is) => self.parse_is(lhs)?,
+                Token::Keyword(Keyword::Not) => {
+                    let token = self.next()?;
+                    Expression::Not(self.parse_predicate(lhs, token)?.into())
+                }
+                Token::Keyword(Keyword::In | Keyword::Between)
+                | Token::Keyword(Keyword::Like | Keyword::ILike | Keyword::Regexp) => {
+                    self.parse_predicate(lhs, token)?
+                }
+                token => {
+                    let rhs = self.parse_expression_at(next_precedence)?;
+                    Self::build_infix(token, lhs, rhs)
+                }
+            };
+        }
+        Ok(lhs)
+    }
+
+    /// Builds a binary operator expression. GTE, LTE and NEQ are composite operations.
+    fn build_infix(token: Token, lhs: Expression, rhs: Expression) -> Expression {
+        use Expression::*;
+        let (lhs, rhs) = (Box::new(lhs), Box::new(rhs));
+        match token {
+            Token::Keyword(Keyword::Or) => Or(lhs, rhs),
+            Token::Keyword(Keyword::And) => And(lhs, rhs),
+            Token::Equal => Equal(lhs, rhs),
+            Token::NotEqual | Token::LessOrGreaterThan => Not(Equal(lhs, rhs).into()),
+            Token::LessThan => LessThan(lhs, rhs),
+            Token::LessThanOrEqual => {
+                Or(LessThan(lhs.clone(), rhs.clone()).into(), Equal(lhs, rhs).into())
+            }
+            Token::GreaterThan => GreaterThan(lhs, rhs),
+            Token::GreaterThanOrEqual => {
+                Or(GreaterThan(lhs.clone(), rhs.clone()).into(), Equal(lhs, rhs).into())
+            }
+            Token::Plus => Add(lhs, rhs),
+            Token::Minus => Subtract(lhs, rhs),
+            Token::Asterisk => Multiply(lhs, rhs),
+            Token::Slash => Divide(lhs, rhs),
+            Token::Percent => Modulo(lhs, rhs),
+            Token::Caret => Exponentiate(lhs, rhs),
+            token => panic!("Unexpected infix operator {}", token),
+        }
+    }
+
+    /// Parses the remainder of IS [NOT] NULL or IS [NOT] DISTINCT FROM.
+    fn parse_is(&mut self, lhs: Expression) -> Result<Expression> {
+        let not = self.next_is_keyword(Keyword::Not);
+        let expr = match self.next()? {
+            Token::Keyword(Keyword::Null) => Expression::IsNull(lhs.into()),
+            Token::Keyword(Keyword::Distinct) => {
+                self.expect(Token::Keyword(Keyword::From))?;
+                let rhs = self.parse_expression_at(5)?;
+                Expression::IsDistinctFrom(lhs.into(), rhs.into())
+            }
+            token => {
+                return Err(Error::Parse(format!(
+                    "Expected NULL or DISTINCT FROM after IS, found {}",
+                    token
+                )))
+            }
+        };
+        Ok(if not { Expression::Not(expr.into()) } else { expr })
+    }
+
+    /// Parses the remainder of a predicate which may be negated by a preceding NOT, i.e. IN,
+    /// BETWEEN, LIKE, ILIKE and REGEXP.
+    fn parse_predicate(&mut self, lhs: Expression, token: Token) -> Result<Expression> {
+        let lhs = Box::new(lhs);
+        Ok(match token {
+            Token::Keyword(Keyword::In) => {
+                self.expect(Token::OpenParen)?;
+                if let Some(Token::Keyword(Keyword::Select | Keyword::With)) = self.peek()? {
+                    let select = self.parse_select()?;
+                    self.expect(Token::CloseParen)?;
+                    return Ok(Expression::InSubquery(lhs, select.into()));
+                }
+                let list = self.parse_expressions()?;
+                self.expect(Token::CloseParen)?;
+                Expression::In(lhs, list)
+            }
+            Token::Keyword(Keyword::Between) => {
+                let low = self.parse_expression_at(5)?;
+                self.expect(Token::Keyword(Keyword::And))?;
+                let high = self.parse_expression_at(5)?;
+                Expression::Between(lhs, low.into(), high.into())
+            }
+            Token::Keyword(Keyword::Like) => {
+                Expression::Like(lhs, self.parse_expression_at(5)?.into())
+            }
+            Token::Keyword(Keyword::ILike) => {
+                Expression::ILike(lhs, self.parse_expression_at(5)?.into())
+            }
+            Token::Keyword(Keyword::Regexp) => {
+                Expression::Regexp(lhs, self.parse_expression_at(5)?.into())
+            }
+            token => return Err(Self::unexpected(token)),
+        })
+    }
+
+    /// Parses an expression atom: a literal, column reference, function call, CAST, CASE,
+    /// EXISTS, scalar subquery or parenthesized expression.
+    fn parse_expression_atom(&mut self) -> Result<Expression> {
+        Ok(match self.next()? {
+            Token::Number(n) if n.contains(['.', 'e', 'E']) => Expression::Constant(
+                Value::Float(n.parse().map_err(|_| Error::Parse(format!("Invalid float {}", n)))?),
+            ),
+            Token::Number(n) => Expression::Constant(Value::Integer(
+                n.parse().map_err(|_| Error::Parse(format!("Invalid integer {}", n)))?,
+            )),
+            Token::String(s) => Expression::Constant(Value::String(s)),
+            Token::Keyword(Keyword::True) => Expression::Constant(Value::Boolean(true)),
+            Token::Keyword(Keyword::False) => Expression::Constant(Value::Boolean(false)),
+            Token::Keyword(Keyword::Null) => Expression::Constant(Value::Null),
+            Token::Keyword(Keyword::Infinity) => Expression::Constant(Value::Float(f64::INFINITY)),
+            Token::Keyword(Keyword::NaN) => Expression::Constant(Value::Float(f64::NAN)),
+            Token::Keyword(Keyword::Cast) => {
+                self.expect(Token::OpenParen)?;
+                let expr = self.parse_expression()?;
+                self.expect(Token::Keyword(Keyword::As))?;
+                let datatype = self.parse_datatype()?;
+                self.expect(Token::CloseParen)?;
+                Expression::Cast(expr.into(), datatype)
+            }
+            Token::Keyword(Keyword::Case) => self.parse_case()?,
+            Token::Question => {
+                self.parameters += 1;
+                Expression::Parameter(self.parameters - 1)
+            }
+            Token::Parameter(n) => match n.parse::<usize>() {
+                Ok(n) if n > 0 => Expression::Parameter(n - 1),
+                _ => return Err(Error::Parse(format!("Invalid parameter ${}", n))),
+            },
+            Token::Keyword(Keyword::Exists) => {
+                self.expect(Token::OpenParen)?;
+                let select = self.parse_select()?;
+                self.expect(Token::CloseParen)?;
+                Expression::Exists(select.into())
+            }
+            Token::OpenParen
+                if matches!(self.peek()?, Some(Token::Keyword(Keyword::Select | Keyword::With))) =>
+            {
+                let select = self.parse_select()?;
+                self.expect(Token::CloseParen)?;
+                Expression::Subquery(select.into())
+            }
+            Token::OpenParen => {
+                let expr = self.parse_expression()?;
+                self.expect(Token::CloseParen)?;
+                expr
+            }
+            Token::Ident(name) if self.next_is(Token::OpenParen) => {
+                let mut args = Vec::new();
+                let mut distinct = false;
+                if self.next_is(Token::Asterisk) {
+                    args.push(Expression::All);
+                } else if self.next_is_keyword(Keyword::Distinct) {
+                    args.push(self.parse_expression()?);
+                    distinct = true;
+                } else if self.peek()? != Some(Token::CloseParen) {
+                    args = self.parse_expressions()?;
+                }
+                self.expect(Token::CloseParen)?;
+                let function = Expression::Function(name, args, distinct);
+                match self.next_is_keyword(Keyword::Over) {
+                    true => Expression::Over(function.into(), self.parse_window()?.into()),
+                    false => function,
+                }
+            }
+            Token::Ident(table) if self.next_is(Token::Period) => {
+                Expression::Column(Some(table), self.next_ident()?)
+            }
+            Token::Ident(name) => Expression::Column(None, name),
+            token => return Err(Self::unexpected(token)),
+        })
+    }
+
+    /// Parses the items of an ORDER BY clause, after ORDER.
+    fn parse_order_by(&mut self) -> Result<Vec<(Expression, ast::Order, ast::Nulls)>> {
+        self.expect(Token::Keyword(Keyword::By))?;
+        let mut order = Vec::new();
+        loop {
+            let expr = self.parse_expression()?;
+            let direction = match self.next_if(|t| {
+                matches!(t, Token::Keyword(Keyword::Asc) | Token::Keyword(Keyword::Desc))
+            }) {
+                Some(Token::Keyword(Keyword::Desc)) => ast::Order::Descending,
+                _ => ast::Order::Ascending,
+            };
+            // NULLS FIRST/LAST aren't reserved keywords, since first and last are common
+            // column names.
+            let nulls = match self.next_is(Token::Ident("nulls".into())) {
+                true => match self.next()? {
+                    Token::Ident(s) if s == "first" => ast::Nulls::First,
+                    Token::Ident(s) if s == "last" => ast::Nulls::Last,
+                    token => return Err(Self::unexpected(token)),
+                },
+                false if direction == ast::Order::Descending => ast::Nulls::Last,
+                false => ast::Nulls::First,
+            };
+            order.push((expr, direction, nulls));
+            if !self.next_is(Token::Comma) {
+                break;
+            }
+        }
+        Ok(order)
+    }
+
+    /// Parses the window of a window function call, after OVER. PARTITION, ROWS and the frame
+    /// bound words aren't reserved keywords, since they're common column names.
+    fn parse_window(&mut self) -> Result<ast::Window> {
+        self.expect(Token::OpenParen)?;
+        let mut partition_by = Vec::new();
+        if self.next_is(Token::Ident("partition".into())) {
+            self.expect(Token::Keyword(Keyword::By))?;
+            partition_by = self.parse_expressions()?;
+        }
+        let mut order = Vec::new();
+        if self.next_is_keyword(Keyword::Order) {
+            order = self.parse_order_by()?;
+        }
+        let mut frame = None;
+        if self.next_is(Token::Ident("rows".into())) {
+            frame = Some(match self.next_is_keyword(Keyword::Between) {
+                true => {
+                    let start = self.parse_frame_bound()?;
+                    self.expect(Token::Keyword(Keyword::And))?;
+                    ast::Frame { start, end: self.parse_frame_bound()? }
+                }
+                false => ast::Frame {
+                    start: self.parse_frame_bound()?,
+                    end: ast::FrameBound::CurrentRow,
+                },
+            });
+        }
+        self.expect(Token::CloseParen)?;
+        Ok(ast::Window { partition_by, order, frame })
+    }
+
+    /// Parses a window frame bound.
+    fn parse_frame_bound(&mut self) -> Result<ast::FrameBound> {
+        let ident = |token: Token| match token {
+            Token::Ident(s) => Ok(s),
+            token => Err(Self::unexpected(token)),
+        };
+        Ok(match self.next()? {
+            Token::Ident(s) if s == "unbounded" => match ident(self.next()?)?.as_str() {
+                "preceding" => ast::FrameBound::UnboundedPreceding,
+                "following" => ast::FrameBound::UnboundedFollowing,
+                s => return Err(Self::unexpected(Token::Ident(s.into()))),
+            },
+            Token::Ident(s) if s == "current" => {
+                self.expect(Token::Ident("row".into()))?;
+                ast::FrameBound::CurrentRow
+            }
+            Token::Number(n) => {
+                let offset = n
+                    .parse()
+                    .map_err(|_| Error::Parse(format!("Invalid frame offset {}", n)))?;
+                match ident(self.next()?)?.as_str() {
+                    "preceding" => ast::FrameBound::Preceding(offset),
+                    "following" => ast::FrameBound::Following(offset),
+                    s => return Err(Self::unexpected(Token::Ident(s.into()))),
+                }
+            }
+            token => return Err(Self::unexpected(token)),
+        })
+    }
+
+    /// Parses the remainder of a CASE expression.
+    fn parse_case(&mut self) -> Result<Expression> {
+        let mut operand = None;
+        if self.peek()? != Some(Token::Keyword(Keyword::When)) {
+            operand = Some(Box::new(self.parse_expression()?));
+        }
+        let mut whens = Vec::new();
+        while self.next_is_keyword(Keyword::When) {
+            let when = self.parse_expression()?;
+            self.expect(Token::Keyword(Keyword::Then))?;
+            whens.push((when, self.parse_expression()?));
+        }
+        if whens.is_empty() {
+            return Err(Error::Parse("Expected WHEN in CASE expression".into()));
+        }
+        let mut default = None;
+        if self.next_is_keyword(Keyword::Else) {
+            default = Some(Box::new(self.parse_expression()?));
+        }
+        self.expect(Token::Keyword(Keyword::End))?;
+        Ok(Expression::Case(operand, whens, default))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ast::*;
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn parse(query: &str) -> Result<Statement> {
+        Parser::new(query).parse()
+    }
+
+    fn expr(expr: &str) -> Result<Expression> {
+        let mut parser = Parser::new(expr);
+        let expr = parser.parse_expression()?;
+        match parser.peek()? {
+            Some(token) => Err(Parser::unexpected(token)),
+            None => Ok(expr),
+        }
+    }
+
+    fn field(name: &str) -> Box<Expression> {
+        Box::new(Expression::Column(None, name.into()))
+    }
+
+    fn int(i: i64) -> Box<Expression> {
+        Box::new(Expression::Constant(Value::Integer(i)))
+    }
+
+    #[test]
+    fn precedence() -> Result<()> {
+        use Expression::*;
+        assert_eq!(expr("1 + 2 * 3")?, Add(int(1), Multiply(int(2), int(3)).into()));
+        assert_eq!(expr("(1 + 2) * 3")?, Multiply(Add(int(1), int(2)).into(), int(3)));
+        assert_eq!(expr("1 - 2 - 3")?, Subtract(Subtract(int(1), int(2)).into(), int(3)));
+        assert_eq!(
+            expr("2 ^ 3 ^ 2")?,
+            Exponentiate(int(2), Exponentiate(int(3), int(2)).into())
+        );
+        assert_eq!(expr("-2 ^ 2")?, Exponentiate(Negate(int(2)).into(), int(2)));
+        assert_eq!(expr("-3!")?, Negate(Factorial(int(3)).into()));
+        assert_eq!(expr("+a % 2")?, Modulo(Assert(field("a")).into(), int(2)));
+        assert_eq!(
+            expr("a = 1 OR b = 2 AND NOT c")?,
+            Or(
+                Equal(field("a"), int(1)).into(),
+                And(Equal(field("b"), int(2)).into(), Not(field("c")).into()).into()
+            )
+        );
+        assert_eq!(expr("NOT a = 1")?, Not(Equal(field("a"), int(1)).into()));
+        assert_eq!(
+            expr("a + 1 > b * 2")?,
+            GreaterThan(Add(field("a"), int(1)).into(), Multiply(field("b"), int(2)).into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn comparisons() -> Result<()> {
+        use Expression::*;
+        assert_eq!(expr("a != 1")?, Not(Equal(field("a"), int(1)).into()));
+        assert_eq!(expr("a <> 1")?, Not(Equal(field("a"), int(1)).into()));
+        assert_eq!(
+            expr("a >= 1")?,
+            Or(GreaterThan(field("a"), int(1)).into(), Equal(field("a"), int(1)).into())
+        );
+        assert_eq!(
+            expr("a <= 1")?,
+            Or(LessThan(field("a"), int(1)).into(), Equal(field("a"), int(1)).into())
+        );
+        assert_eq!(expr("a IS NULL")?, IsNull(field("a")));
+        assert_eq!(expr("a IS NOT NULL")?, Not(IsNull(field("a")).into()));
+        assert_eq!(expr("a IS DISTINCT FROM b")?, IsDistinctFrom(field("a"), field("b")));
+        assert_eq!(
+            expr("a IS NOT DISTINCT FROM 1 + 1")?,
+            Not(IsDistinctFrom(field("a"), Add(int(1), int(1)).into()).into())
+        );
+        assert_eq!(expr("a IN (1, 2)")?, In(field("a"), vec![*int(1), *int(2)]));
+        assert_eq!(expr("a NOT IN (1)")?, Not(In(field("a"), vec![*int(1)]).into()));
+        assert_eq!(
+            expr("a BETWEEN 1 AND 2 + 1 AND b")?,
+            And(
+                Between(field("a"), int(1), Add(int(2), int(1)).into()).into(),
+                field("b")
+            )
+        );
+        assert_eq!(
+            expr("a NOT BETWEEN 1 AND 2")?,
+            Not(Between(field("a"), int(1), int(2)).into())
+        );
+        let s = |s: &str| Box::new(Expression::Constant(Value::String(s.into())));
+        assert_eq!(expr("a LIKE 'x%'")?, Like(field("a"), s("x%")));
+        assert_eq!(
+            expr("a NOT ILIKE 'x%'")?,
+            Not(ILike(field("a"), s("x%")).into())
+        );
+        assert_eq!(expr("a REGEXP '^x'")?, Regexp(field("a"), s("^x")));
+        assert!(expr("a IS 1").is_err());
+        assert!(expr("a NOT 1").is_err());
+        assert!(expr("a IN ()").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn atoms() -> Result<()> {
+        use Expression::*;
+        use Value::*;
+        assert_eq!(expr("1")?, Constant(Integer(1)));
+        assert_eq!(expr("3.5e1")?, Constant(Float(35.0)));
+        assert_eq!(expr("1.")?, Constant(Float(1.0)));
+        assert_eq!(expr("'a'")?, Constant(String("a".into())));
+        assert_eq!(expr("TRUE")?, Constant(Boolean(true)));
+        assert_eq!(expr("false")?, Constant(Boolean(false)));
+        assert_eq!(expr("NULL")?, Constant(Null));
+        assert_eq!(expr("INFINITY")?, Constant(Float(f64::INFINITY)));
+        assert!(matches!(expr("NAN")?, Constant(Float(f)) if f.is_nan()));
+        assert_eq!(expr("t.a")?, Column(Some("t".into()), "a".into()));
+        assert_eq!(expr("\"T\".\"A\"")?, Column(Some("T".into()), "A".into()));
+        assert_eq!(expr("now()")?, Function("now".into(), vec![], false));
+        assert_eq!(
+            expr("count(*)")?,
+            Function("count".into(), vec![All], false)
+        );
+        assert_eq!(
+            expr("count(DISTINCT a)")?,
+            Function("count".into(), vec![*field("a")], true)
+        );
+        assert_eq!(
+            expr("UPPER(a, 1 + 1)")?,
+            Function("upper".into(), vec![*field("a"), Add(int(1), int(1))], false)
+        );
+        assert_eq!(expr("CAST(a AS TEXT)")?, Cast(field("a"), Datatype::String));
+        assert_eq!(
+            expr("CASE WHEN a THEN 1 WHEN b THEN 2 END")?,
+            Case(None, vec![(*field("a"), *int(1)), (*field("b"), *int(2))], None)
+        );
+        assert_eq!(
+            expr("CASE a WHEN 1 THEN 2 ELSE 3 END")?,
+            Case(Some(field("a")), vec![(*int(1), *int(2))], Some(int(3)))
+        );
+        assert_eq!(
+            expr("99999999999999999999"),
+            Err(Error::Parse("Invalid integer 99999999999999999999".into()))
+        );
+        assert!(expr("CASE END").is_err());
+        assert!(expr("CAST(a AS BLOB)").is_err());
+        assert!(expr("(1").is_err());
+        assert!(expr("1 +").is_err());
+        assert!(expr("t.").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn select() -> Result<()> {
+        use Expression::*;
+        assert_eq!(
+            parse("SELECT * FROM t")?,
+            Statement::Select {
+                select: vec![],
+                from: vec![FromItem::Table { name: "t".into(), alias: None }],
+                r#where: None,
+                group_by: vec![],
+                having: None,
+                order: vec![],
+                limit: None,
+                offset: None,
+            }
+        );
+        assert_eq!(
+            parse(
+                "SELECT a AS x, b y, count(*) FROM t WHERE a > 1 GROUP BY a, b \
+                 HAVING count(*) > 2 ORDER BY x DESC, b ASC, 1 LIMIT 10 OFFSET 5;"
+            )?,
+            Statement::Select {
+                select: vec![
+                    (*field("a"), Some("x".into())),
+                    (*field("b"), Some("y".into())),
+                    (Function("count".into(), vec![All], false), None),
+                ],
+                from: vec![FromItem::Table { name: "t".into(), alias: None }],
+                r#where: Some(GreaterThan(field("a"), int(1))),
+                group_by: vec![*field("a"), *field("b")],
+                having: Some(GreaterThan(
+                    Function("count".into(), vec![All], false).into(),
+                    int(2)
+                )),
+                order: vec![
+                    (*field("x"), Order::Descending, Nulls::Last),
+                    (*field("b"), Order::Ascending, Nulls::First),
+                    (*int(1), Order::Ascending, Nulls::First),
+                ],
+                limit: Some(*int(10)),
+                offset: Some(*int(5)),
+            }
+        );
+        assert_eq!(
+            parse("SELECT 1 + 1")?,
+            Statement::Select {
+                select: vec![(Add(int(1), int(1)), None)],
+                from: vec![],
+                r#where: None,
+                group_by: vec![],
+                having: None,
+                order: vec![],
+                limit: None,
+                offset: None,
+            }
+        );
+        let Statement::Select { order, .. } =
+            parse("SELECT * FROM t ORDER BY a NULLS LAST, b DESC NULLS FIRST, first")?
+        else {
+            panic!("expected SELECT")
+        };
+        assert_eq!(
+            order,
+            vec![
+                (*field("a"), Order::Ascending, Nulls::Last),
+                (*field("b"), Order::Descending, Nulls::First),
+                (*field("first"), Order::Ascending, Nulls::First),
+            ]
+        );
+        assert_eq!(
+            parse("SELECT * FROM t ORDER BY a NULLS middle"),
+            Err(Error::Parse("Unexpected token middle".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn set_operations() -> Result<()> {
+        let select = |table: &str| Statement::Select {
+            select: vec![],
+            from: vec![FromItem::Table { name: table.into(), alias: None }],
+            r#where: None,
+            group_by: vec![],
+            having: None,
+            order: vec![],
+            limit: None,
+            offset: None,
+        };
+        let set = |operator, all, left, right| Statement::SetOperation {
+            operator,
+            all,
+            left: Box::new(left),
+            right: Box::new(right),
+            order: vec![],
+            limit: None,
+            offset: None,
+        };
+        use SetOperator::*;
+        assert_eq!(
+            parse("SELECT * FROM a UNION SELECT * FROM b")?,
+            set(Union, false, select("a"), select("b"))
+        );
+        // INTERSECT binds tighter than UNION and EXCEPT, which are left-associative.
+        assert_eq!(
+            parse(
+                "SELECT * FROM a EXCEPT ALL SELECT * FROM b UNION DISTINCT SELECT * FROM c \
+                 INTERSECT SELECT * FROM d"
+            )?,
+            set(
+                Union,
+                false,
+                set(Except, true, select("a"), select("b")),
+                set(Intersect, false, select("c"), select("d"))
+            )
+        );
+        // ORDER BY, LIMIT and OFFSET apply to the combined result.
+        assert_eq!(
+            parse("SELECT * FROM a UNION ALL SELECT * FROM b ORDER BY 1 DESC LIMIT 2 OFFSET 1")?,
+            Statement::SetOperation {
+                operator: Union,
+                all: true,
+                left: Box::new(select("a")),
+                right: Box::new(select("b")),
+                order: vec![(*int(1), Order::Descending, Nulls::Last)],
+                limit: Some(*int(2)),
+                offset: Some(*int(1)),
+            }
+        );
+        assert_eq!(
+            parse("SELECT * FROM a ORDER BY 1 UNION SELECT * FROM b"),
+            Err(Error::Parse("Unexpected token UNION".into()))
+        );
+        assert_eq!(
+            parse("SELECT * FROM a UNION ALL"),
+            Err(Error::Parse("Unexpected end of input".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ctes() -> Result<()> {
+        let select = |table: &str| Statement::Select {
+            select: vec![],
+            from: vec![FromItem::Table { name: table.into(), alias: None }],
+            r#where: None,
+            group_by: vec![],
+            having: None,
+            order: vec![],
+            limit: None,
+            offset: None,
+        };
+        let cte = |name: &str, columns: &[&str], query| Cte {
+            name: name.into(),
+            columns: columns.iter().map(|c| c.to_string()).collect(),
+            query,
+        };
+        assert_eq!(
+            parse("WITH a AS (SELECT * FROM t), b (x, y) AS (SELECT * FROM a) SELECT * FROM b")?,
+            Statement::With {
+                recursive: false,
+                ctes: vec![cte("a", &[], select("t")), cte("b", &["x", "y"], select("a"))],
+                statement: Box::new(select("b")),
+            }
+        );
+        assert_eq!(
+            parse(
+                "WITH RECURSIVE r AS (SELECT * FROM t UNION ALL SELECT * FROM r) SELECT * FROM r"
+            )?,
+            Statement::With {
+                recursive: true,
+                ctes: vec![cte(
+                    "r",
+                    &[],
+                    Statement::SetOperation {
+                        operator: SetOperator::Union,
+                        all: true,
+                        left: Box::new(select("t")),
+                        right: Box::new(select("r")),
+                        order: vec![],
+                        limit: None,
+                        offset: None,
+                    }
+                )],
+                statement: Box::new(select("r")),
+            }
+        );
+        assert_eq!(
+            parse("WITH a AS SELECT * FROM t SELECT * FROM a"),
+            Err(Error::Parse("Expected token (, found SELECT".into()))
+        );
+        assert_eq!(
+            parse("WITH a AS (SELECT * FROM t)"),
+            Err(Error::Parse("Unexpected end of input".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn joins() -> Result<()> {
+        let table = |name: &str, alias: Option<&str>| FromItem::Table {
+            name: name.into(),
+            alias: alias.map(|a| a.into()),
+        };
+        let on = |l: &str, r: &str| {
+            Expression::Equal(
+                Expression::Column(Some(l.into()), "id".into()).into(),
+                Expression::Column(Some(r.into()), "id".into()).into(),
+            )
+        };
+        let Statement::Select { from, .. } = parse(
+            "SELECT * FROM a JOIN b ON a.id = b.id LEFT OUTER JOIN c AS x ON b.id = x.id \
+             CROSS JOIN d, e f RIGHT JOIN g ON f.id = g.id FULL JOIN h ON g.id = h.id",
+        )?
+        else {
+            panic!("expected SELECT")
+        };
+        let join = |left, right, r#type, predicate| FromItem::Join {
+            left: Box::new(left),
+            right: Box::new(right),
+            r#type,
+            predicate,
+        };
+        let ab = join(table("a", None), table("b", None), JoinType::Inner, Some(on("a", "b")));
+        let fg = join(table("e", Some("f")), table("g", None), JoinType::Right, Some(on("f", "g")));
+        assert_eq!(
+            from,
+            vec![
+                join(
+                    join(
+                        ab,
+                        table("c", Some("x")),
+                        JoinType::Left,
+                        Some(on("b", "x")),
+                    ),
+                    table("d", None),
+                    JoinType::Cross,
+                    None,
+                ),
+                join(
+                    fg,
+                    table("h", None),
+                    JoinType::Full,
+                    Some(on("g", "h")),
+                ),
+            ]
+        );
+        assert!(parse("SELECT * FROM a JOIN b").is_err());
+        assert!(parse("SELECT * FROM a INNER b ON 1").is_err());
+        assert!(parse("SELECT * FROM a CROSS JOIN b ON 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn subqueries() -> Result<()> {
+        use Expression::*;
+        let select = |from: &str, r#where: Option<Expression>| {
+            Box::new(Statement::Select {
+                select: vec![(*field("a"), None)],
+                from: vec![FromItem::Table { name: from.into(), alias: None }],
+                r#where,
+                group_by: vec![],
+                having: None,
+                order: vec![],
+                limit: None,
+                offset: None,
+            })
+        };
+        assert_eq!(expr("(SELECT a FROM t)")?, Subquery(select("t", None)));
+        assert_eq!(
+            expr("(SELECT a FROM t) + 1")?,
+            Add(Subquery(select("t", None)).into(), int(1))
+        );
+        assert_eq!(
+            expr("EXISTS (SELECT a FROM t WHERE a = u.b)")?,
+            Exists(select(
+                "t",
+                Some(Equal(
+                    field("a"),
+                    Column(Some("u".into()), "b".into()).into()
+                ))
+            ))
+        );
+        assert_eq!(
+            expr("NOT EXISTS (SELECT a FROM t)")?,
+            Not(Exists(select("t", None)).into())
+        );
+        assert_eq!(expr("b IN (SELECT a FROM t)")?, InSubquery(field("b"), select("t", None)));
+        assert_eq!(
+            expr("b NOT IN (SELECT a FROM t)")?,
+            Not(InSubquery(field("b"), select("t", None)).into())
+        );
+        assert_eq!(
+            expr("b IN ((SELECT a FROM t))")?,
+            In(field("b"), vec![Subquery(select("t", None))])
+        );
+        assert!(expr("EXISTS a").is_err());
+        assert!(expr("EXISTS (SELECT a FROM t").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn insert_update_delete() -> Result<()> {
+        use Expression::*;
+        assert_eq!(
+            parse("INSERT INTO t VALUES (1, 'a'), (2, NULL)")?,
+            Statement::Insert {
+                table: "t".into(),
+                columns: None,
+                source: InsertSource::Values(vec![
+                    vec![*int(1), Constant(Value::String("a".into()))],
+                    vec![*int(2), Constant(Value::Null)],
+                ]),
+                on_conflict: None,
+            }
+        );
+        assert_eq!(
+            parse("INSERT INTO t (b, a) VALUES (1, 2)")?,
+            Statement::Insert {
+                table: "t".into(),
+                columns: Some(vec!["b".into(), "a".into()]),
+                source: InsertSource::Values(vec![vec![*int(1), *int(2)]]),
+                on_conflict: None,
+            }
+        );
+        assert_eq!(
+            parse("INSERT INTO t SELECT a FROM u ON CONFLICT DO NOTHING")?,
+            Statement::Insert {
+                table: "t".into(),
+                columns: None,
+                source: InsertSource::Select(Box::new(Statement::Select {
+                    select: vec![(*field("a"), None)],
+                    from: vec![FromItem::Table { name: "u".into(), alias: None }],
+                    r#where: None,
+                    group_by: vec![],
+                    having: None,
+                    order: vec![],
+                    limit: None,
+                    offset: None,
+                })),
+                on_conflict: Some(OnConflict { column: None, update: None }),
+            }
+        );
+        assert_eq!(
+            parse("INSERT INTO t VALUES (1, 2) ON CONFLICT (id) DO UPDATE SET a = excluded.a")?,
+            Statement::Insert {
+                table: "t".into(),
+                columns: None,
+                source: InsertSource::Values(vec![vec![*int(1), *int(2)]]),
+                on_conflict: Some(OnConflict {
+                    column: Some("id".into()),
+                    update: Some(
+                        vec![("a".into(), Column(Some("excluded".into()), "a".into()))]
+                            .into_iter()
+                            .collect()
+                    ),
+                }),
+            }
+        );
+        assert_eq!(
+            parse("INSERT INTO t VALUES (1) ON CONFLICT DO UPDATE SET a = 1"),
+            Err(Error::Parse("ON CONFLICT DO UPDATE requires a column".into()))
+        );
+        assert_eq!(
+            parse("INSERT INTO t VALUES (1) ON CONFLICT DO SELECT"),
+            Err(Error::Parse("Expected NOTHING or UPDATE, found SELECT".into()))
+        );
+        assert_eq!(
+            parse("UPDATE t SET a = a + 1, b = 2 WHERE id = 1")?,
+            Statement::Update {
+                table: "t".into(),
+                set: vec![("a".into(), Add(field("a"), int(1))), ("b".into(), *int(2))]
+                    .into_iter()
+                    .collect(),
+                r#where: Some(Equal(field("id"), int(1))),
+            }
+        );
+        assert_eq!(
+            parse("UPDATE t SET a = 1, a = 2"),
+            Err(Error::Parse("Column a set multiple times".into()))
+        );
+        assert_eq!(
+            parse("DELETE FROM t WHERE a IS NULL")?,
+            Statement::Delete { table: "t".into(), r#where: Some(IsNull(field("a"))) }
+        );
+        assert_eq!(parse("DELETE FROM t")?, Statement::Delete { table: "t".into(), r#where: None });
+        assert!(parse("INSERT INTO t VALUES ()").is_err());
+        assert!(parse("INSERT INTO t").is_err());
+        assert!(parse("UPDATE t WHERE a = 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn ddl() -> Result<()> {
+        assert_eq!(
+            parse(
+                "CREATE TABLE movies (
+                    id INTEGER PRIMARY KEY,
+                    title VARCHAR NOT NULL UNIQUE,
+                    rating FLOAT DEFAULT 0.0 NULL INDEX,
+                    studio_id INT REFERENCES studios,
+                    released BOOLEAN
+                )"
+            )?,
+            Statement::CreateTable {
+                name: "movies".into(),
+                columns: vec![
+                    Column {
+                        name: "id".into(),
+                        datatype: Datatype::Integer,
+                        primary_key: true,
+                        nullable: None,
+                        default: None,
+                        unique: false,
+                        index: false,
+                        references: None,
+                    },
+                    Column {
+                        name: "title".into(),
+                        datatype: Datatype::String,
+                        primary_key: false,
+                        nullable: Some(false),
+                        default: None,
+                        unique: true,
+                        index: false,
+                        references: None,
+                    },
+                    Column {
+                        name: "rating".into(),
+                        datatype: Datatype::Float,
+                        primary_key: false,
+                        nullable: Some(true),
+                        default: Some(Expression::Constant(Value::Float(0.0))),
+                        unique: false,
+                        index: true,
+                        references: None,
+                    },
+                    Column {
+                        name: "studio_id".into(),
+                        datatype: Datatype::Integer,
+                        primary_key: false,
+                        nullable: None,
+                        default: None,
+                        unique: false,
+                        index: false,
+                        references: Some("studios".into()),
+                    },
+                    Column {
+                        name: "released".into(),
+                        datatype: Datatype::Boolean,
+                        primary_key: false,
+                        nullable: None,
+                        default: None,
+                        unique: false,
+                        index: false,
+                        references: None,
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            parse("CREATE TABLE t (a INT NULL NOT NULL)"),
+            Err(Error::Parse("Column a can't be both NULL and NOT NULL".into()))
+        );
+        assert!(parse("CREATE TABLE t ()").is_err());
+        assert!(parse("CREATE TABLE t (a)").is_err());
+        assert!(parse("CREATE TABLE t (a INT PRIMARY)").is_err());
+        assert_eq!(parse("DROP TABLE t;")?, Statement::DropTable("t".into()));
+
+        let query = Box::new(Statement::Select {
+            select: vec![],
+            from: vec![FromItem::Table { name: "t".into(), alias: None }],
+            r#where: None,
+            group_by: vec![],
+            having: None,
+            order: vec![],
+            limit: None,
+            offset: None,
+        });
+        assert_eq!(
+            parse("CREATE VIEW v AS SELECT * FROM t")?,
+            Statement::CreateView {
+                name: "v".into(),
+                query: query.clone(),
+                sql: "SELECT * FROM t".into(),
+                materialized: false
+            }
+        );
+        // The SQL text excludes surrounding whitespace and the terminating semicolon.
+        assert_eq!(
+            parse("CREATE MATERIALIZED VIEW v AS\n  SELECT *\n  FROM t ;")?,
+            Statement::CreateView {
+                name: "v".into(),
+                query,
+                sql: "SELECT *\n  FROM t".into(),
+                materialized: true
+            }
+        );
+        assert_eq!(parse("DROP VIEW v")?, Statement::DropView("v".into()));
+        assert_eq!(parse("DROP MATERIALIZED VIEW v")?, Statement::DropView("v".into()));
+        assert_eq!(parse("REFRESH MATERIALIZED VIEW v")?, Statement::RefreshView("v".into()));
+        assert_eq!(parse("ANALYZE")?, Statement::Analyze(None));
+        assert_eq!(parse("ANALYZE t;")?, Statement::Analyze(Some("t".into())));
+        assert_eq!(parse("SHOW TABLES")?, Statement::ShowTables);
+        assert_eq!(parse("show transactions;")?, Statement::ShowTransactions);
+        assert_eq!(
+            parse("SHOW CREATE TABLE information_schema.tables")?,
+            Statement::ShowCreateTable("information_schema.tables".into())
+        );
+        assert_eq!(
+            parse("SHOW VIEWS"),
+            Err(Error::Parse("Unexpected token views".into()))
+        );
+        assert_eq!(
+            parse("CREATE VIEW v AS DELETE FROM t"),
+            Err(Error::Parse("Expected token SELECT, found DELETE".into()))
+        );
+        assert_eq!(
+            parse("REFRESH VIEW v"),
+            Err(Error::Parse("Expected token MATERIALIZED, found VIEW".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn transactions() -> Result<()> {
+        let begin = |read_only, as_of| Statement::Begin { read_only, as_of };
+        assert_eq!(parse("BEGIN")?, begin(false, None));
+        assert_eq!(parse("begin transaction;")?, begin(false, None));
+        assert_eq!(parse("BEGIN READ WRITE")?, begin(false, None));
+        assert_eq!(parse("BEGIN TRANSACTION READ ONLY")?, begin(true, None));
+        assert_eq!(parse("BEGIN AS OF SYSTEM TIME 7")?, begin(false, Some(7)));
+        assert_eq!(parse("BEGIN READ ONLY AS OF SYSTEM TIME 7")?, begin(true, Some(7)));
+        assert_eq!(
+            parse("BEGIN READ WRITE AS OF SYSTEM TIME 7"),
+            Err(Error::Parse("Can't write AS OF SYSTEM TIME".into()))
+        );
+        assert_eq!(
+            parse("BEGIN AS OF SYSTEM TIME 1.5"),
+            Err(Error::Parse("Invalid version 1.5".into()))
+        );
+        assert_eq!(parse("BEGIN READ"), Err(Error::Parse("Unexpected end of input".into())));
+        assert_eq!(parse("COMMIT")?, Statement::Commit);
+        assert_eq!(parse("ROLLBACK;")?, Statement::Rollback);
+        Ok(())
+    }
+
+    #[test]
+    fn explain() -> Result<()> {
+        assert_eq!(
+            parse("EXPLAIN DELETE FROM t")?,
+            Statement::Explain {
+                statement: Box::new(Statement::Delete { table: "t".into(), r#where: None }),
+                analyze: false,
+            }
+        );
+        assert_eq!(
+            parse("explain analyze DELETE FROM t;")?,
+            Statement::Explain {
+                statement: Box::new(Statement::Delete { table: "t".into(), r#where: None }),
+                analyze: true,
+            }
+        );
+        assert_eq!(
+            parse("EXPLAIN EXPLAIN DELETE FROM t"),
+            Err(Error::Parse("Can't EXPLAIN an EXPLAIN statement".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn prepared() -> Result<()> {
+        use Expression::*;
+        assert_eq!(
+            expr("a = ? OR b BETWEEN ? AND $1")?,
+            Or(
+                Equal(field("a"), Parameter(0).into()).into(),
+                Between(field("b"), Parameter(1).into(), Parameter(0).into()).into(),
+            )
+        );
+        assert_eq!(expr("$0"), Err(Error::Parse("Invalid parameter $0".into())));
+        assert_eq!(
+            parse("PREPARE q AS DELETE FROM t WHERE id = ?")?,
+            Statement::Prepare {
+                name: "q".into(),
+                statement: Box::new(Statement::Delete {
+                    table: "t".into(),
+                    r#where: Some(Equal(field("id"), Parameter(0).into())),
+                }),
+            }
+        );
+        assert_eq!(
+            parse("EXECUTE q (1, 'a')")?,
+            Statement::Execute {
+                name: "q".into(),
+                parameters: vec![*int(1), Constant(Value::String("a".into()))],
+            }
+        );
+        assert_eq!(
+            parse("EXECUTE q")?,
+            Statement::Execute { name: "q".into(), parameters: vec![] }
+        );
+        assert_eq!(parse("DEALLOCATE q")?, Statement::Deallocate("q".into()));
+        assert_eq!(parse("DEALLOCATE PREPARE q")?, Statement::Deallocate("q".into()));
+        assert_eq!(
+            parse("PREPARE q SELECT 1"),
+            Err(Error::Parse("Expected token AS, found SELECT".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn windows() -> Result<()> {
+        use Expression::*;
+        let over =
+            |window: Window| Over(Function("rank".into(), vec![], false).into(), window.into());
+        assert_eq!(
+            expr("rank() OVER ()")?,
+            over(Window { partition_by: vec![], order: vec![], frame: None })
+        );
+        assert_eq!(
+            expr("RANK() OVER (PARTITION BY a, b ORDER BY c DESC)")?,
+            over(Window {
+                partition_by: vec![*field("a"), *field("b")],
+                order: vec![(*field("c"), Order::Descending, Nulls::Last)],
+                frame: None,
+            })
+        );
+        let frame = |start, end| {
+            over(Window { partition_by: vec![], order: vec![], frame: Some(Frame { start, end }) })
+        };
+        assert_eq!(
+            expr("rank() OVER (ROWS 2 PRECEDING)")?,
+            frame(FrameBound::Preceding(2), FrameBound::CurrentRow)
+        );
+        assert_eq!(
+            expr("rank() OVER (ROWS BETWEEN UNBOUNDED PRECEDING AND 1 FOLLOWING)")?,
+            frame(FrameBound::UnboundedPreceding, FrameBound::Following(1))
+        );
+        assert_eq!(
+            expr("rank() OVER (ROWS BETWEEN CURRENT ROW AND UNBOUNDED FOLLOWING)")?,
+            frame(FrameBound::CurrentRow, FrameBound::UnboundedFollowing)
+        );
+        assert_eq!(
+            expr("rank() OVER (ROWS 1.5 PRECEDING)"),
+            Err(Error::Parse("Invalid frame offset 1.5".into()))
+        );
+        assert_eq!(
+            expr("rank() OVER (ROWS UNBOUNDED CURRENT)"),
+            Err(Error::Parse("Unexpected token current".into()))
+        );
+        assert!(expr("rank() OVER a").is_err());
+        assert!(expr("rank() OVER (PARTITION a)").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(""), Err(Error::Parse("Unexpected end of input".into())));
+        assert_eq!(parse("FOO"), Err(Error::Parse("Unexpected token foo".into())));
+        assert_eq!(parse("COMMIT; COMMIT"), Err(Error::Parse("Unexpected token COMMIT".into())));
+        assert_eq!(
+            parse("SELECT 1 FROM"),
+            Err(Error::Parse("Unexpected end of input".into()))
+        );
+        assert_eq!(
+            parse("DROP t"),
+            Err(Error::Parse("Expected token TABLE, found t".into()))
+        );
+        assert_eq!(
+            parse("SELECT 'a"),
+            Err(Error::Parse("Unterminated string at line 1 column 8".into()))
+        );
+        assert_eq!(
+            parse("SELECT a,\n  #"),
+            Err(Error::Parse("Unexpected character # at line 2 column 3".into()))
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use super::{JoinType, Node, SetOperator};
+use crate::error::Result;
+use crate::sql::storage::{Catalog, ColumnStats, Expression, Value};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The cost of reading a row sequentially, e.g. in a table scan.
+pub const SEQUENTIAL_COST: f64 = 1.0;
+/// The cost of reading a row or index entry by key, e.g. in a key or index lookup.
+pub const RANDOM_COST: f64 = 4.0;
+
+/// The selectivity of equality predicates without column statistics.
+const EQUAL_SELECTIVITY: f64 = 0.1;
+/// The selectivity of range predicates without column statistics.
+const RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+/// The selectivity of other predicates, e.g. LIKE.
+const DEFAULT_SELECTIVITY: f64 = 0.5;
+
+/// The estimated output of a plan node: its number of rows, and the statistics of each column
+/// that's passed through from an analyzed table.
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    pub rows: f64,
+    pub columns: Vec<Option<Rc<ColumnStats>>>,
+}
+
+/// Estimates the output of plan nodes from the table statistics computed by ANALYZE. Only
+/// nodes whose tables have all been analyzed can be estimated. Predicates are assumed to be
+/// independent, and the values of join columns to overlap.
+pub struct Estimator<'a, C: Catalog> {
+    catalog: &'a C,
+    /// Table estimates by table name, cached since they're read repeatedly while optimizing.
+    tables: RefCell<HashMap<String, Option<Estimate>>>,
+}
+
+impl<'a, C: Catalog> Estimator<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog, tables: RefCell::new(HashMap::new()) }
+    }
+
+    /// Returns the estimate of a table's rows, or None if it hasn't been analyzed.
+    pub fn table(&self, table: &str) -> Result<Option<Estimate>> {
+        if let Some(estimate) = self.tables.borrow().get(table) {
+            return Ok(estimate.clone());
+        }
+        let estimate = self.catalog.read_stats(table)?.map(|stats| Estimate {
+            rows: stats.rows as f64,
+            columns: stats.columns.into_iter().map(|c| Some(Rc::new(c))).collect(),
+        });
+        self.tables.borrow_mut().insert(table.to_string(), estimate.clone());
+        Ok(estimate)
+    }
+
+    /// Estimates a node's output, or returns None if it can't be estimated, e.g. because it
+    /// reads a table that hasn't been analyzed.
+    pub fn estimate(&self, node: &Node) -> Result<Option<Estimate>> {
+        macro_rules! estimate {
+            ($node:expr) => {
+                match self.estimate($node)? {
+                    Some(estimate) => estimate,
+                    None => return Ok(None),
+                }
+            };
+        }
+        macro_rules! table {
+            ($table:expr) => {
+                match self.table($table)? {
+                    Some(estimate) => estimate,
+                    None => return Ok(None),
+                }
+            };
+        }
+        Ok(Some(match node {
+            Node::Scan { table, filter, .. } => Self::filter(table!(table), filter.as_ref()),
+            Node::KeyLookup { table, keys, .. } => {
+                let mut estimate = table!(table);
+                estimate.rows = estimate.rows.min(keys.len() as f64);
+                estimate
+            }
+            Node::IndexLookup { table, column, values, .. } => {
+                let mut estimate = table!(table);
+                let index = self.catalog.must_read_table(table)?.get_column_index(column)?;
+                estimate.rows = Self::lookup_rows(&estimate, index, values);
+                estimate
+            }
+            Node::Filter { source, predicate } => Self::filter(estimate!(source), Some(predicate)),
+            Node::Projection { source, expressions } => {
+                let source = estimate!(source);
+                let columns = expressions
+                    .iter()
+                    .map(|(e, _)| match e {
+                        Expression::Field(i, _) => source.columns.get(*i).cloned().flatten(),
+                        _ => None,
+                    })
+                    .collect();
+                Estimate { rows: source.rows, columns }
+            }
+            Node::Order { source, limit, .. } => {
+                let mut estimate = estimate!(source);
+                if let Some(limit) = limit {
+                    estimate.rows = estimate.rows.min(*limit as f64);
+                }
+                estimate
+            }
+            Node::Limit { source, offset, limit } => {
+                let mut estimate = estimate!(source);
+                estimate.rows = (estimate.rows - *offset as f64).max(0.0);
+                if let Some(limit) = limit {
+                    estimate.rows = estimate.rows.min(*limit as f64);
+                }
+                estimate
+            }
+            // Groups are limited by the product of the group columns' distinct values.
+            Node::Aggregate { source, group_by, aggregates } => {
+                let source = estimate!(source);
+                let groups: f64 = group_by
+                    .iter()
+                    .map(|expr| match expr {
+                        Expression::Field(i, _) => match source.columns.get(*i).cloned().flatten() {
+                            // NULLs form a group of their own.
+                            Some(column) => column.distinct as f64 + column.null_fraction.ceil(),
+                            None => source.rows,
+                        },
+                        _ => source.rows,
+                    })
+                    .product();
+                let rows = if group_by.is_empty() { 1.0 } else { groups.min(source.rows) };
+                Estimate { rows, columns: vec![None; group_by.len() + aggregates.len()] }
+            }
+            Node::Join { left, right, predicate, r#type } => {
+                let (left, right) = (estimate!(left), estimate!(right));
+                let columns: Vec<_> = left.columns.iter().chain(&right.columns).cloned().collect();
+                let selectivity =
+                    predicate.as_ref().map_or(1.0, |p| Self::selectivity(p, &columns));
+                Self::join(left, right, r#type, selectivity)
+            }
+            Node::HashJoin { left, left_field, right, right_field, predicate, r#type }
+            | Node::MergeJoin { left, left_field, right, right_field, predicate, r#type } => {
+                let (left, right) = (estimate!(left), estimate!(right));
+                let columns: Vec<_> = left.columns.iter().chain(&right.columns).cloned().collect();
+                let selectivity = Self::equal_columns(
+                    columns.get(*left_field).cloned().flatten(),
+                    columns.get(left.columns.len() + right_field).cloned().flatten(),
+                ) * predicate.as_ref().map_or(1.0, |p| Self::selectivity(p, &columns));
+                Self::join(left, right, r#type, selectivity)
+            }
+            Node::LookupJoin { left, left_field, table, column, predicate, outer, .. } => {
+                let (left, right) = (estimate!(left), table!(table));
+                let schema = self.catalog.must_read_table(table)?;
+                let right_field = match column {
+                    Some(column) => schema.get_column_index(column)?,
+                    None => schema.columns.iter().position(|c| c.primary_key).unwrap_or(0),
+                };
+                let columns: Vec<_> = left.columns.iter().chain(&right.columns).cloned().collect();
+                let selectivity = Self::equal_columns(
+                    columns.get(*left_field).cloned().flatten(),
+                    right.columns.get(right_field).cloned().flatten(),
+                ) * predicate.as_ref().map_or(1.0, |p| Self::selectivity(p, &columns));
+                let r#type = if *outer { JoinType::Left } else { JoinType::Inner };
+                Self::join(left, right, &r#type, selectivity)
+            }
+            Node::Nothing => Estimate { rows: 1.0, columns: Vec::new() },
+            Node::Values { rows } => {
+                let width = rows.first().map_or(0, |row| row.len());
+                Estimate { rows: rows.len() as f64, columns: vec![None; width] }
+            }
+            Node::SetOperation { left, right, operator, .. } => {
+                let (left, right) = (estimate!(left), estimate!(right));
+                let rows = match operator {
+                    SetOperator::Union => left.rows + right.rows,
+                    SetOperator::Intersect => left.rows.min(right.rows),
+                    SetOperator::Except => left.rows,
+                };
+                Estimate { rows, columns: vec![None; left.columns.len()] }
+            }
+            Node::Subquery { source, .. } | Node::Window { source, .. } => {
+                let mut estimate = estimate!(source);
+                estimate.columns.push(None);
+                estimate
+            }
+            Node::Analyze { .. }
+            | Node::CreateTable { .. }
+            | Node::CreateView { .. }
+            | Node::Delete { .. }
+            | Node::DropTable { .. }
+            | Node::DropView { .. }
+            | Node::Explain { .. }
+            | Node::Insert { .. }
+            | Node::Recursive { .. }
+            | Node::RefreshView { .. }
+            | Node::Update { .. }
+            | Node::WorkingTable { .. } => return Ok(None),
+        }))
+    }
+
+    /// Estimates the rows of a table whose column has one of the given values, as looked up
+    /// via an index.
+    pub fn lookup_rows(table: &Estimate, column: usize, values: &[Value]) -> f64 {
+        let rows: f64 = values
+            .iter()
+            .map(|value| match (table.columns.get(column).cloned().flatten(), value) {
+                (Some(stats), Value::Null) => stats.null_fraction * table.rows,
+                (Some(stats), value) => {
+                    (1.0 - stats.null_fraction) * stats.equal_fraction(value) * table.rows
+                }
+                (None, _) => EQUAL_SELECTIVITY * table.rows,
+            })
+            .sum();
+        rows.min(table.rows)
+    }
+
+    /// Applies a filter predicate to an estimate.
+    fn filter(mut estimate: Estimate, predicate: Option<&Expression>) -> Estimate {
+        if let Some(predicate) = predicate {
+            estimate.rows *= Self::selectivity(predicate, &estimate.columns);
+        }
+        estimate
+    }
+
+    /// Estimates a join's output, given the selectivity of its predicate over the joined rows.
+    fn join(left: Estimate, right: Estimate, r#type: &JoinType, selectivity: f64) -> Estimate {
+        let (l, r) = (left.rows, right.rows);
+        let inner = l * r * selectivity;
+        let rows = match r#type {
+            JoinType::Cross | JoinType::Inner => inner,
+            JoinType::Left => inner.max(l),
+            JoinType::Right => inner.max(r),
+            JoinType::Full => inner.max(l).max(r),
+            JoinType::Semi => inner.min(l),
+            JoinType::Anti => l - inner.min(l),
+        };
+        let columns = match r#type {
+            JoinType::Semi | JoinType::Anti => left.columns,
+            _ => left.columns.into_iter().chain(right.columns).collect(),
+        };
+        Estimate { rows, columns }
+    }
+
+    /// Estimates the fraction of rows for which a predicate is true, given the statistics of
+    /// the columns its fields refer to.
+    pub fn selectivity(predicate: &Expression, columns: &[Option<Rc<ColumnStats>>]) -> f64 {
+        use Expression::*;
+        let column = |expr: &Expression| match expr {
+            Field(i, _) => columns.get(*i).cloned().flatten(),
+            _ => None,
+        };
+        let equal = |lhs: &Expression, rhs: &Expression| match (lhs, rhs) {
+            (Field(..), Constant(value)) | (Constant(value), Field(..)) => {
+                let field = if let Field(..) = lhs { lhs } else { rhs };
+                match column(field) {
+                    Some(stats) => (1.0 - stats.null_fraction) * stats.equal_fraction(value),
+                    None => EQUAL_SELECTIVITY,
+                }
+            }
+            (Field(..), Field(..)) => Self::equal_columns(column(lhs), column(rhs)),
+            _ => EQUAL_SELECTIVITY,
+        };
+        // The fraction of rows whose field is less than (or greater than) a constant.
+        let range = |field: &Expression, value: &Expression, less: bool| {
+            let (Some(stats), Constant(value)) = (column(field), value) else {
+                return RANGE_SELECTIVITY;
+            };
+            let Some(below) = stats.less_fraction(value) else {
+                return RANGE_SELECTIVITY;
+            };
+            let fraction = match less {
+                true => below,
+                false => (1.0 - below - stats.equal_fraction(value)).max(0.0),
+            };
+            (1.0 - stats.null_fraction) * fraction
+        };
+        match predicate {
+            Constant(Value::Boolean(true)) => 1.0,
+            Constant(_) => 0.0,
+            And(lhs, rhs) => Self::selectivity(lhs, columns) * Self::selectivity(rhs, columns),
+            Or(lhs, rhs) => {
+                let (l, r) = (Self::selectivity(lhs, columns), Self::selectivity(rhs, columns));
+                l + r - l * r
+            }
+            Not(expr) => 1.0 - Self::selectivity(expr, columns),
+            Equal(lhs, rhs) => equal(lhs, rhs),
+            In(expr, list) => list.iter().map(|item| equal(expr, item)).sum::<f64>().min(1.0),
+            IsNull(expr) => column(expr).map_or(EQUAL_SELECTIVITY, |stats| stats.null_fraction),
+            LessThan(lhs, rhs) => match (&**lhs, &**rhs) {
+                (Field(..), _) => range(lhs, rhs, true),
+                (_, Field(..)) => range(rhs, lhs, false),
+                _ => RANGE_SELECTIVITY,
+            },
+            GreaterThan(lhs, rhs) => match (&**lhs, &**rhs) {
+                (Field(..), _) => range(lhs, rhs, false),
+                (_, Field(..)) => range(rhs, lhs, true),
+                _ => RANGE_SELECTIVITY,
+            },
+            Between(expr, low, high) => {
+                let below = range(expr, low, true);
+                let above = range(expr, high, false);
+                let nulls = column(expr).map_or(0.0, |stats| stats.null_fraction);
+                (1.0 - nulls - below - above).clamp(0.0, 1.0)
+            }
+            _ => DEFAULT_SELECTIVITY,
+        }
+    }
+
+    /// Estimates the selectivity of an equality predicate between two columns, assuming that
+    /// the values of the column with fewer distinct values all occur in the other column.
+    fn equal_columns(lhs: Option<Rc<ColumnStats>>, rhs: Option<Rc<ColumnStats>>) -> f64 {
+        match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => {
+                let distinct = lhs.distinct.max(rhs.distinct).max(1) as f64;
+                (1.0 - lhs.null_fraction) * (1.0 - rhs.null_fraction) / distinct
+            }
+            _ => EQUAL_SELECTIVITY,
+        }
+    }
+}
@@ -0,0 +1,1025 @@
+mod cost;
+mod optimizer;
+mod planner;
+
+use optimizer::Optimizer as _;
+
+use super::parser::ast;
+use super::storage::{Catalog, Conflict, Datatype, Expression, Table, Value};
+use crate::error::Result;
+
+pub use super::parser::ast::{JoinType, Nulls, Order as Direction, SetOperator};
+
+use std::fmt::{self, Display};
+
+/// A query plan, i.e. a tree of plan nodes which the executor runs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan(pub Node);
+
+impl Plan {
+    /// Builds a plan for a statement, resolving names against the catalog.
+    pub fn build<C: Catalog>(statement: ast::Statement, catalog: &C) -> Result<Self> {
+        let plan = planner::Planner::new(catalog).build(statement)?;
+        Ok(Self(plan.0.coerce_branches(catalog)?))
+    }
+
+    /// Optimizes the plan by applying a sequence of rewrite rules, each of which must produce
+    /// the same rows as its input.
+    pub fn optimize<C: Catalog>(self, catalog: &C) -> Result<Self> {
+        // Subquery plans are optimized separately, since transform() doesn't descend into them.
+        let mut node = self.0.transform(&Ok, &|n| match n {
+            Node::Subquery { source, subquery, kind } => {
+                let subquery = Box::new(Plan(*subquery).optimize(catalog)?.0);
+                Ok(Node::Subquery { source, subquery, kind })
+            }
+            n => Ok(n),
+        })?;
+        node = optimizer::ConstantFolder.optimize(node)?;
+        node = optimizer::FilterPushdown::new(catalog).optimize(node)?;
+        node = optimizer::NoopCleaner.optimize(node)?;
+        node = optimizer::JoinOrder::new(catalog).optimize(node)?;
+        node = optimizer::JoinStrategy::new(catalog).optimize(node)?;
+        node = optimizer::IndexLookup::new(catalog).optimize(node)?;
+        Ok(Self(node))
+    }
+}
+
+/// Evaluates a constant expression, e.g. an EXECUTE parameter value.
+pub fn evaluate_constant<C: Catalog>(expr: ast::Expression, catalog: &C) -> Result<Value> {
+    planner::Planner::new(catalog).evaluate_constant(expr)
+}
+
+/// Returns the output column label for a projected expression: its alias, its column name
+/// for fields, or the expression itself.
+pub fn label(expr: &Expression, alias: &Option<String>) -> String {
+    match (alias, expr) {
+        (Some(alias), _) => alias.clone(),
+        (None, Expression::Field(_, Some((_, name)))) => name.clone(),
+        (None, expr) => expr.to_string(),
+    }
+}
+
+/// A plan node. Nodes that produce rows take their input from a source node, and their
+/// expressions refer to the source's columns via bound Expression::Field indexes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    /// Groups the source rows by the group_by expressions and computes the aggregates for
+    /// each group. Emits the group_by values followed by the aggregate values.
+    Aggregate {
+        source: Box<Node>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<(Aggregate, Expression)>,
+    },
+    /// Computes and stores the statistics of the given tables.
+    Analyze {
+        tables: Vec<String>,
+    },
+    CreateTable {
+        schema: Table,
+    },
+    /// Creates a view of the query, given as SQL text, whose plan is the source. References are
+    /// the tables and views the query reads. The source rows of materialized views are stored as
+    /// the view's rows, while plain views only check that the query plans.
+    CreateView {
+        name: String,
+        query: String,
+        references: Vec<String>,
+        source: Box<Node>,
+        materialized: bool,
+    },
+    /// Deletes the source rows from the table.
+    Delete {
+        table: String,
+        source: Box<Node>,
+    },
+    DropTable {
+        table: String,
+    },
+    DropView {
+        name: String,
+    },
+    /// Emits the source's plan as rows of text. With analyze, the source is executed and its
+    /// nodes are annotated with the rows they emitted and the time spent in them.
+    Explain {
+        source: Box<Node>,
+        analyze: bool,
+    },
+    /// Emits the source rows for which the predicate is true.
+    Filter {
+        source: Box<Node>,
+        predicate: Expression,
+    },
+    /// Emits the rows whose indexed column has one of the given values.
+    IndexLookup {
+        table: String,
+        alias: Option<String>,
+        column: String,
+        values: Vec<Value>,
+    },
+    /// Inserts the source rows. The columns are the target columns in order of the source
+    /// columns, or empty for all columns in table order. Rows whose primary key already exists
+    /// are handled by the conflict action.
+    Insert {
+        table: String,
+        columns: Vec<String>,
+        source: Box<Node>,
+        conflict: Conflict,
+    },
+    /// Joins the left and right rows on equal left_field and right_field values (indexes into
+    /// the left and right rows respectively) using a hash table of the right rows. Otherwise
+    /// like Join.
+    HashJoin {
+        left: Box<Node>,
+        left_field: usize,
+        right: Box<Node>,
+        right_field: usize,
+        predicate: Option<Expression>,
+        r#type: JoinType,
+    },
+    /// Joins the left and right rows using a nested loop, emitting left columns followed by
+    /// right columns. The predicate refers to the combined row, and outer joins pad missing
+    /// rows with NULLs.
+    Join {
+        left: Box<Node>,
+        right: Box<Node>,
+        predicate: Option<Expression>,
+        r#type: JoinType,
+    },
+    /// Emits the rows with the given primary keys.
+    KeyLookup {
+        table: String,
+        alias: Option<String>,
+        keys: Vec<Value>,
+    },
+    /// Joins each left row with the table rows whose primary key, or the given indexed column,
+    /// equals the left row's left_field value, emitting left columns followed by the table's
+    /// columns. The predicate is evaluated on the joined row, and outer joins pad missing rows
+    /// with NULLs.
+    LookupJoin {
+        left: Box<Node>,
+        left_field: usize,
+        table: String,
+        alias: Option<String>,
+        column: Option<String>,
+        predicate: Option<Expression>,
+        outer: bool,
+    },
+    /// Skips the first offset rows, and emits at most limit rows after that.
+    Limit {
+        source: Box<Node>,
+        offset: usize,
+        limit: Option<usize>,
+    },
+    /// Like HashJoin, but for inputs that are sorted by left_field and right_field, which are
+    /// joined by stepping through both in lockstep.
+    MergeJoin {
+        left: Box<Node>,
+        left_field: usize,
+        right: Box<Node>,
+        right_field: usize,
+        predicate: Option<Expression>,
+        r#type: JoinType,
+    },
+    /// Emits a single empty row, e.g. for SELECT without FROM.
+    Nothing,
+    /// Sorts the source rows by the given expressions. If limit is given, only the first limit
+    /// rows are emitted, which allows a top-N sort that only buffers those rows.
+    Order {
+        source: Box<Node>,
+        orders: Vec<(Expression, Direction, Nulls)>,
+        limit: Option<usize>,
+    },
+    /// Evaluates the expressions for each source row, with optional column labels.
+    Projection {
+        source: Box<Node>,
+        expressions: Vec<(Expression, Option<String>)>,
+    },
+    /// Evaluates a recursive CTE by iterating to a fixpoint: the anchor rows seed the working
+    /// table, and each iteration evaluates the recursive term with the working table's
+    /// WorkingTable node replaced by the previous iteration's rows, until no new rows are
+    /// produced. Emits the anchor column names. Values are cast to the common datatypes, and
+    /// without all, duplicate rows are removed.
+    Recursive {
+        name: String,
+        anchor: Box<Node>,
+        recursive: Box<Node>,
+        all: bool,
+        datatypes: Vec<Option<Datatype>>,
+    },
+    /// Replaces the rows of a materialized view with the source rows, i.e. its query results.
+    RefreshView {
+        name: String,
+        source: Box<Node>,
+    },
+    /// Scans the table's rows, with an optional filter evaluated by the storage engine.
+    Scan {
+        table: String,
+        alias: Option<String>,
+        filter: Option<Expression>,
+    },
+    /// Combines the left and right rows by a set operation, emitting the left column names.
+    /// Values are cast to the columns' common datatypes, such that e.g. integers and floats
+    /// compare equal. Without all, duplicate rows are removed by hashing.
+    SetOperation {
+        left: Box<Node>,
+        right: Box<Node>,
+        operator: SetOperator,
+        all: bool,
+        datatypes: Vec<Option<Datatype>>,
+    },
+    /// Emits rows of constant expressions, e.g. for INSERT VALUES. Rows may have different
+    /// lengths.
+    Values {
+        rows: Vec<Vec<Expression>>,
+    },
+    /// Evaluates a subquery for each source row, emitting the source columns followed by the
+    /// subquery result. The subquery is a separate plan, which may refer to the source row via
+    /// Expression::Outer.
+    Subquery {
+        source: Box<Node>,
+        subquery: Box<Node>,
+        kind: SubqueryKind,
+    },
+    /// Updates the source rows of the table, setting each column index to the expression's
+    /// value for the row.
+    Update {
+        table: String,
+        source: Box<Node>,
+        expressions: Vec<(usize, String, Expression)>,
+    },
+    /// Evaluates a window function for each source row, emitting the source columns followed
+    /// by the function's value. The source must be sorted by the partition_by expressions
+    /// followed by the order expressions, such that partitions are contiguous.
+    Window {
+        source: Box<Node>,
+        function: WindowFunction,
+        args: Vec<Expression>,
+        partition_by: Vec<Expression>,
+        order: Vec<(Expression, Direction, Nulls)>,
+        frame: Option<ast::Frame>,
+    },
+    /// The working table of a recursive CTE, referenced from its recursive term. Replaced by the
+    /// working table's rows during execution, and can't be executed itself.
+    WorkingTable {
+        name: String,
+        datatypes: Vec<Option<Datatype>>,
+    },
+}
+
+impl Node {
+    /// Recursively transforms the node tree by applying closures to each node before and after
+    /// descending into its sources. Subquery plans are not sources, and are left as is.
+    pub fn transform<B, A>(mut self, before: &B, after: &A) -> Result<Self>
+    where
+        B: Fn(Self) -> Result<Self>,
+        A: Fn(Self) -> Result<Self>,
+    {
+        let xform = |n: Box<Node>| n.transform(before, after).map(Box::new);
+        self = before(self)?;
+        self = match self {
+            Self::Aggregate { source, group_by, aggregates } => {
+                Self::Aggregate { source: xform(source)?, group_by, aggregates }
+            }
+            Self::CreateView { name, query, references, source, materialized } => {
+                let source = xform(source)?;
+                Self::CreateView { name, query, references, source, materialized }
+            }
+            Self::Delete { table, source } => Self::Delete { table, source: xform(source)? },
+            Self::Explain { source, analyze } => Self::Explain { source: xform(source)?, analyze },
+            Self::Filter { source, predicate } => {
+                Self::Filter { source: xform(source)?, predicate }
+            }
+            Self::Insert { table, columns, source, conflict } => {
+                Self::Insert { table, columns, source: xform(source)?, conflict }
+            }
+            Self::Join { left, right, predicate, r#type } => {
+                Self::Join { left: xform(left)?, right: xform(right)?, predicate, r#type }
+            }
+            Self::Limit { source, offset, limit } => {
+                Self::Limit { source: xform(source)?, offset, limit }
+            }
+            Self::HashJoin { left, left_field, right, right_field, predicate, r#type } => {
+                let (left, right) = (xform(left)?, xform(right)?);
+                Self::HashJoin { left, left_field, right, right_field, predicate, r#type }
+            }
+            Self::LookupJoin { left, left_field, table, alias, column, predicate, outer } => {
+                let left = xform(left)?;
+                Self::LookupJoin { left, left_field, table, alias, column, predicate, outer }
+            }
+            Self::MergeJoin { left, left_field, right, right_field, predicate, r#type } => {
+                let (left, right) = (xform(left)?, xform(right)?);
+                Self::MergeJoin { left, left_field, right, right_field, predicate, r#type }
+            }
+            Self::Order { source, orders, limit } => {
+                Self::Order { source: xform(source)?, orders, limit }
+            }
+            Self::Projection { source, expressions } => {
+                Self::Projection { source: xform(source)?, expressions }
+            }
+            Self::Recursive { name, anchor, recursive, all, datatypes } => {
+                let (anchor, recursive) = (xform(anchor)?, xform(recursive)?);
+                Self::Recursive { name, anchor, recursive, all, datatypes }
+            }
+            Self::RefreshView { name, source } => {
+                Self::RefreshView { name, source: xform(source)? }
+            }
+            Self::SetOperation { left, right, operator, all, datatypes } => {
+                let (left, right) = (xform(left)?, xform(right)?);
+                Self::SetOperation { left, right, operator, all, datatypes }
+            }
+            Self::Subquery { source, subquery, kind } => {
+                Self::Subquery { source: xform(source)?, subquery, kind }
+            }
+            Self::Update { table, source, expressions } => {
+                Self::Update { table, source: xform(source)?, expressions }
+            }
+            Self::Window { source, function, args, partition_by, order, frame } => {
+                let source = xform(source)?;
+                Self::Window { source, function, args, partition_by, order, frame }
+            }
+            node @ (Self::Analyze { .. }
+            | Self::CreateTable { .. }
+            | Self::DropTable { .. }
+            | Self::DropView { .. }
+            | Self::IndexLookup { .. }
+            | Self::KeyLookup { .. }
+            | Self::Nothing
+            | Self::Scan { .. }
+            | Self::Values { .. }
+            | Self::WorkingTable { .. }) => node,
+        };
+        after(self)
+    }
+
+    /// Maps all expressions in the node (but not in its sources) with the given closure.
+    pub fn map_expressions<F>(self, f: &F) -> Result<Self>
+    where
+        F: Fn(Expression) -> Result<Expression>,
+    {
+        let map_opt = |e: Option<Expression>| e.map(f).transpose();
+        Ok(match self {
+            Self::Aggregate { source, group_by, aggregates } => Self::Aggregate {
+                source,
+                group_by: group_by.into_iter().map(f).collect::<Result<_>>()?,
+                aggregates: aggregates
+                    .into_iter()
+                    .map(|(a, e)| Ok((a, f(e)?)))
+                    .collect::<Result<_>>()?,
+            },
+            Self::Filter { source, predicate } => Self::Filter { source, predicate: f(predicate)? },
+            Self::Insert { table, columns, source, conflict: Conflict::Update(set) } => {
+                let set = set.into_iter().map(|(i, e)| Ok((i, f(e)?))).collect::<Result<_>>()?;
+                Self::Insert { table, columns, source, conflict: Conflict::Update(set) }
+            }
+            Self::Join { left, right, predicate, r#type } => {
+                Self::Join { left, right, predicate: map_opt(predicate)?, r#type }
+            }
+            Self::HashJoin { left, left_field, right, right_field, predicate, r#type } => {
+                let predicate = map_opt(predicate)?;
+                Self::HashJoin { left, left_field, right, right_field, predicate, r#type }
+            }
+            Self::LookupJoin { left, left_field, table, alias, column, predicate, outer } => {
+                let predicate = map_opt(predicate)?;
+                Self::LookupJoin { left, left_field, table, alias, column, predicate, outer }
+            }
+            Self::MergeJoin { left, left_field, right, right_field, predicate, r#type } => {
+                let predicate = map_opt(predicate)?;
+                Self::MergeJoin { left, left_field, right, right_field, predicate, r#type }
+            }
+            Self::Order { source, orders, limit } => Self::Order {
+                source,
+                orders: orders
+                    .into_iter()
+                    .map(|(e, d, n)| Ok((f(e)?, d, n)))
+                    .collect::<Result<_>>()?,
+                limit,
+            },
+            Self::Projection { source, expressions } => Self::Projection {
+                source,
+                expressions: expressions
+                    .into_iter()
+                    .map(|(e, l)| Ok((f(e)?, l)))
+                    .collect::<Result<_>>()?,
+            },
+            Self::Scan { table, alias, filter } => {
+                Self::Scan { table, alias, filter: map_opt(filter)? }
+            }
+            Self::Subquery { source, subquery, kind: SubqueryKind::In(expr) } => {
+                Self::Subquery { source, subquery, kind: SubqueryKind::In(f(expr)?) }
+            }
+            Self::Update { table, source, expressions } => Self::Update {
+                table,
+                source,
+                expressions: expressions
+                    .into_iter()
+                    .map(|(i, c, e)| Ok((i, c, f(e)?)))
+                    .collect::<Result<_>>()?,
+            },
+            Self::Values { rows } => Self::Values {
+                rows: rows
+                    .into_iter()
+                    .map(|exprs| exprs.into_iter().map(f).collect())
+                    .collect::<Result<_>>()?,
+            },
+            Self::Window { source, function, args, partition_by, order, frame } => Self::Window {
+                source,
+                function,
+                args: args.into_iter().map(f).collect::<Result<_>>()?,
+                partition_by: partition_by.into_iter().map(f).collect::<Result<_>>()?,
+                order: order
+                    .into_iter()
+                    .map(|(e, d, n)| Ok((f(e)?, d, n)))
+                    .collect::<Result<_>>()?,
+                frame,
+            },
+            node @ (Self::CreateTable { .. }
+            | Self::Analyze { .. }
+            | Self::CreateView { .. }
+            | Self::Delete { .. }
+            | Self::DropTable { .. }
+            | Self::DropView { .. }
+            | Self::Explain { .. }
+            | Self::IndexLookup { .. }
+            | Self::Insert { .. }
+            | Self::KeyLookup { .. }
+            | Self::Limit { .. }
+            | Self::Nothing
+            | Self::Recursive { .. }
+            | Self::RefreshView { .. }
+            | Self::SetOperation { .. }
+            | Self::Subquery { .. }
+            | Self::WorkingTable { .. }) => node,
+        })
+    }
+
+    /// Returns all expressions in the node (but not in its sources), like map_expressions().
+    pub fn expressions(&self) -> Vec<&Expression> {
+        match self {
+            Self::Aggregate { group_by, aggregates, .. } => {
+                group_by.iter().chain(aggregates.iter().map(|(_, e)| e)).collect()
+            }
+            Self::Filter { predicate, .. } => vec![predicate],
+            Self::Insert { conflict: Conflict::Update(set), .. } => {
+                set.iter().map(|(_, e)| e).collect()
+            }
+            Self::Join { predicate, .. }
+            | Self::HashJoin { predicate, .. }
+            | Self::LookupJoin { predicate, .. }
+            | Self::MergeJoin { predicate, .. }
+            | Self::Scan { filter: predicate, .. } => predicate.iter().collect(),
+            Self::Order { orders, .. } => orders.iter().map(|(e, _, _)| e).collect(),
+            Self::Projection { expressions, .. } => expressions.iter().map(|(e, _)| e).collect(),
+            Self::Subquery { kind: SubqueryKind::In(expr), .. } => vec![expr],
+            Self::Update { expressions, .. } => expressions.iter().map(|(_, _, e)| e).collect(),
+            Self::Values { rows } => rows.iter().flatten().collect(),
+            Self::Window { args, partition_by, order, .. } => args
+                .iter()
+                .chain(partition_by)
+                .chain(order.iter().map(|(e, _, _)| e))
+                .collect(),
+            Self::CreateTable { .. }
+            | Self::Analyze { .. }
+            | Self::CreateView { .. }
+            | Self::Delete { .. }
+            | Self::DropTable { .. }
+            | Self::DropView { .. }
+            | Self::Explain { .. }
+            | Self::IndexLookup { .. }
+            | Self::Insert { .. }
+            | Self::KeyLookup { .. }
+            | Self::Limit { .. }
+            | Self::Nothing
+            | Self::Recursive { .. }
+            | Self::RefreshView { .. }
+            | Self::SetOperation { .. }
+            | Self::Subquery { .. }
+            | Self::WorkingTable { .. } => Vec::new(),
+        }
+    }
+
+    /// Returns the column datatypes of the rows that the node's expressions are evaluated on:
+    /// the joined rows for joins, the existing row followed by the inserted row for insert
+    /// conflict updates, no columns for values, and the source rows otherwise.
+    pub fn input_datatypes<C: Catalog>(&self, catalog: &C) -> Result<Vec<Option<Datatype>>> {
+        let table = |table: &str| -> Result<Vec<Option<Datatype>>> {
+            let table = catalog.must_read_table(table)?;
+            Ok(table.columns.into_iter().map(|c| Some(c.datatype)).collect())
+        };
+        Ok(match self {
+            Self::Insert { table: t, .. } => {
+                let columns = table(t)?;
+                columns.iter().chain(&columns).cloned().collect()
+            }
+            Self::Scan { table: t, .. } => table(t)?,
+            Self::LookupJoin { left, table: t, .. } => {
+                left.datatypes(catalog)?.into_iter().chain(table(t)?).collect()
+            }
+            Self::HashJoin { left, right, .. }
+            | Self::Join { left, right, .. }
+            | Self::MergeJoin { left, right, .. } => {
+                left.datatypes(catalog)?.into_iter().chain(right.datatypes(catalog)?).collect()
+            }
+            _ => match self.sources().first() {
+                Some(source) => source.datatypes(catalog)?,
+                None => Vec::new(),
+            },
+        })
+    }
+}
+
+impl Node {
+    /// Formats the node tree for EXPLAIN, one node per line. The given annotations, if any, are
+    /// appended to the nodes in pre-order, i.e. in the order they're displayed. Empty
+    /// annotations are skipped.
+    pub fn explain(&self, annotations: &[String]) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.explain_into(&mut lines, "", "", &mut annotations.iter());
+        lines
+    }
+
+    fn explain_into<'a>(
+        &self,
+        lines: &mut Vec<String>,
+        prefix: &str,
+        indent: &str,
+        annotations: &mut impl Iterator<Item = &'a String>,
+    ) {
+        let mut line = format!("{}{}", prefix, self.describe());
+        if let Some(annotation) = annotations.next().filter(|a| !a.is_empty()) {
+            line = format!("{} {}", line, annotation);
+        }
+        lines.push(line);
+        let sources = self.sources();
+        for (i, source) in sources.iter().enumerate() {
+            let (prefix, next) = match i == sources.len() - 1 {
+                true => (format!("{}└─ ", indent), format!("{}   ", indent)),
+                false => (format!("{}├─ ", indent), format!("{}│  ", indent)),
+            };
+            source.explain_into(lines, &prefix, &next, annotations);
+        }
+    }
+
+    /// Returns the node's source nodes, in execution order, followed by its subquery plan.
+    pub fn sources(&self) -> Vec<&Node> {
+        match self {
+            Self::Aggregate { source, .. }
+            | Self::CreateView { source, .. }
+            | Self::Delete { source, .. }
+            | Self::Explain { source, .. }
+            | Self::Filter { source, .. }
+            | Self::Insert { source, .. }
+            | Self::Limit { source, .. }
+            | Self::LookupJoin { left: source, .. }
+            | Self::Order { source, .. }
+            | Self::Projection { source, .. }
+            | Self::RefreshView { source, .. }
+            | Self::Update { source, .. }
+            | Self::Window { source, .. } => vec![source],
+            Self::HashJoin { left, right, .. }
+            | Self::Join { left, right, .. }
+            | Self::MergeJoin { left, right, .. }
+            | Self::Recursive { anchor: left, recursive: right, .. }
+            | Self::SetOperation { left, right, .. } => vec![left, right],
+            Self::Subquery { source, subquery, .. } => vec![source, subquery],
+            Self::Analyze { .. }
+            | Self::CreateTable { .. }
+            | Self::DropTable { .. }
+            | Self::DropView { .. }
+            | Self::IndexLookup { .. }
+            | Self::KeyLookup { .. }
+            | Self::Nothing
+            | Self::Scan { .. }
+            | Self::Values { .. }
+            | Self::WorkingTable { .. } => vec![],
+        }
+    }
+
+    /// Describes the node itself, without its sources.
+    fn describe(&self) -> String {
+        let list = |items: Vec<String>| items.join(", ");
+        let values = |values: &[Value]| list(values.iter().map(|v| v.to_string()).collect());
+        let table = |table: &str, alias: &Option<String>| match alias {
+            Some(alias) => format!("{} as {}", table, alias),
+            None => table.to_string(),
+        };
+        let and = |predicate: &Option<Expression>| match predicate {
+            Some(predicate) => format!(" and {}", predicate),
+            None => String::new(),
+        };
+        let join_type = |r#type: &JoinType| format!("{:?}", r#type).to_lowercase();
+        let orders = |orders: &[(Expression, Direction, Nulls)]| {
+            list(
+                orders
+                    .iter()
+                    .map(|(e, direction, nulls)| match (direction, nulls) {
+                        (Direction::Ascending, Nulls::First) => format!("{} ASC", e),
+                        (Direction::Ascending, Nulls::Last) => format!("{} ASC NULLS LAST", e),
+                        (Direction::Descending, Nulls::First) => {
+                            format!("{} DESC NULLS FIRST", e)
+                        }
+                        (Direction::Descending, Nulls::Last) => format!("{} DESC", e),
+                    })
+                    .collect(),
+            )
+        };
+        match self {
+            Self::Aggregate { group_by, aggregates, .. } => {
+                let mut s = format!(
+                    "Aggregate: {}",
+                    list(aggregates.iter().map(|(a, e)| a.format(e)).collect())
+                );
+                if !group_by.is_empty() {
+                    let group_by = group_by.iter().map(|e| e.to_string()).collect();
+                    s += &format!(" group by {}", list(group_by));
+                }
+                s
+            }
+            Self::Analyze { tables } => format!("Analyze: {}", tables.join(", ")),
+            Self::CreateTable { schema } => format!("CreateTable: {}", schema.name),
+            Self::CreateView { name, materialized: false, .. } => format!("CreateView: {}", name),
+            Self::CreateView { name, materialized: true, .. } => {
+                format!("CreateView: {} materialized", name)
+            }
+            Self::Delete { table, .. } => format!("Delete: {}", table),
+            Self::DropTable { table } => format!("DropTable: {}", table),
+            Self::DropView { name } => format!("DropView: {}", name),
+            Self::Explain { analyze: false, .. } => "Explain".to_string(),
+            Self::Explain { analyze: true, .. } => "Explain analyze".to_string(),
+            Self::Filter { predicate, .. } => format!("Filter: {}", predicate),
+            Self::HashJoin { left_field, right_field, predicate, r#type, .. } => format!(
+                "HashJoin: {} on left #{} = right #{}{}",
+                join_type(r#type),
+                left_field,
+                right_field,
+                and(predicate)
+            ),
+            Self::IndexLookup { table: t, alias, column, values: v } => {
+                format!("IndexLookup: {} using {} ({})", table(t, alias), column, values(v))
+            }
+            Self::Insert { table, conflict, .. } => match conflict {
+                Conflict::Error => format!("Insert: {}", table),
+                Conflict::Nothing => format!("Insert: {} on conflict do nothing", table),
+                Conflict::Update(set) => format!(
+                    "Insert: {} on conflict update {}",
+                    table,
+                    list(set.iter().map(|(i, e)| format!("#{} = {}", i, e)).collect())
+                ),
+            },
+            Self::Join { predicate, r#type, .. } => {
+                let mut s = format!("NestedLoopJoin: {}", join_type(r#type));
+                if let Some(predicate) = predicate {
+                    s += &format!(" on {}", predicate);
+                }
+                s
+            }
+            Self::KeyLookup { table: t, alias, keys } => {
+                format!("KeyLookup: {} ({})", table(t, alias), values(keys))
+            }
+            Self::Limit { offset, limit, .. } => match (limit, offset) {
+                (Some(limit), 0) => format!("Limit: {}", limit),
+                (Some(limit), offset) => format!("Limit: {} offset {}", limit, offset),
+                (None, offset) => format!("Limit: offset {}", offset),
+            },
+            Self::LookupJoin { left_field, table: t, alias, column, predicate, outer, .. } => {
+                format!(
+                    "LookupJoin: {} {} using {} = #{}{}",
+                    if *outer { "left" } else { "inner" },
+                    table(t, alias),
+                    column.as_deref().unwrap_or("primary key"),
+                    left_field,
+                    and(predicate)
+                )
+            }
+            Self::MergeJoin { left_field, right_field, predicate, r#type, .. } => format!(
+                "MergeJoin: {} on left #{} = right #{}{}",
+                join_type(r#type),
+                left_field,
+                right_field,
+                and(predicate)
+            ),
+            Self::Nothing => "Nothing".to_string(),
+            Self::Order { orders: o, limit, .. } => {
+                let mut s = format!("Order: {}", orders(o));
+                if let Some(limit) = limit {
+                    s += &format!(" top {}", limit);
+                }
+                s
+            }
+            Self::Projection { expressions, .. } => format!(
+                "Projection: {}",
+                list(
+                    expressions
+                        .iter()
+                        .map(|(e, alias)| match alias {
+                            Some(alias) => format!("{} AS {}", e, alias),
+                            None => e.to_string(),
+                        })
+                        .collect()
+                )
+            ),
+            Self::Recursive { name, all: false, .. } => format!("Recursive: {}", name),
+            Self::Recursive { name, all: true, .. } => format!("Recursive: {} all", name),
+            Self::RefreshView { name, .. } => format!("RefreshView: {}", name),
+            Self::Scan { table: t, alias, filter } => match filter {
+                Some(filter) => format!("Scan: {} where {}", table(t, alias), filter),
+                None => format!("Scan: {}", table(t, alias)),
+            },
+            Self::SetOperation { operator, all, .. } => {
+                let operator = operator.to_string().to_lowercase();
+                match all {
+                    true => format!("SetOperation: {} all", operator),
+                    false => format!("SetOperation: {}", operator),
+                }
+            }
+            Self::Subquery { kind: SubqueryKind::Exists, .. } => "Subquery: exists".to_string(),
+            Self::Subquery { kind: SubqueryKind::In(expr), .. } => {
+                format!("Subquery: {} in", expr)
+            }
+            Self::Subquery { kind: SubqueryKind::Scalar, .. } => "Subquery: scalar".to_string(),
+            Self::Values { rows } => format!("Values: {} rows", rows.len()),
+            Self::Update { table, expressions, .. } => format!(
+                "Update: {} set {}",
+                table,
+                list(expressions.iter().map(|(_, c, e)| format!("{} = {}", c, e)).collect())
+            ),
+            Self::WorkingTable { name, .. } => format!("WorkingTable: {}", name),
+            Self::Window { function, args, partition_by, order, frame, .. } => {
+                let mut s = format!("Window: {}", function.format(args));
+                if !partition_by.is_empty() {
+                    let partition_by = partition_by.iter().map(|e| e.to_string()).collect();
+                    s += &format!(" partition by {}", list(partition_by));
+                }
+                if !order.is_empty() {
+                    s += &format!(" order by {}", orders(order));
+                }
+                if let Some(frame) = frame {
+                    s += &format!(" {}", frame.to_string().to_lowercase());
+                }
+                s
+            }
+        }
+    }
+}
+
+impl Node {
+    /// Returns the number of nodes in the tree, including subquery plans.
+    pub fn size(&self) -> usize {
+        1 + self.sources().iter().map(|n| n.size()).sum::<usize>()
+    }
+}
+
+impl Node {
+    /// Infers the datatypes of the node's output columns. None is the datatype of columns that
+    /// are always NULL, or whose datatype isn't known until execution, e.g. scalar subqueries.
+    /// Non-query nodes have no columns.
+    pub fn datatypes<C: Catalog>(&self, catalog: &C) -> Result<Vec<Option<Datatype>>> {
+        let table = |table: &str| -> Result<Vec<Option<Datatype>>> {
+            let table = catalog.must_read_table(table)?;
+            Ok(table.columns.into_iter().map(|c| Some(c.datatype)).collect())
+        };
+        Ok(match self {
+            Self::Aggregate { source, group_by, aggregates } => {
+                let source = source.datatypes(catalog)?;
+                let mut datatypes = group_by
+                    .iter()
+                    .map(|e| e.row_datatype(&source))
+                    .collect::<Result<Vec<_>>>()?;
+                for (aggregate, expr) in aggregates {
+                    datatypes.push(aggregate.datatype(expr.row_datatype(&source)?));
+                }
+                datatypes
+            }
+            Self::Filter { source, .. }
+            | Self::Limit { source, .. }
+            | Self::Order { source, .. } => source.datatypes(catalog)?,
+            Self::HashJoin { left, r#type: JoinType::Semi | JoinType::Anti, .. }
+            | Self::Join { left, r#type: JoinType::Semi | JoinType::Anti, .. }
+            | Self::MergeJoin { left, r#type: JoinType::Semi | JoinType::Anti, .. } => {
+                left.datatypes(catalog)?
+            }
+            Self::HashJoin { left, right, .. }
+            | Self::Join { left, right, .. }
+            | Self::MergeJoin { left, right, .. } => {
+                let mut datatypes = left.datatypes(catalog)?;
+                datatypes.extend(right.datatypes(catalog)?);
+                datatypes
+            }
+            Self::IndexLookup { table: t, .. }
+            | Self::KeyLookup { table: t, .. }
+            | Self::Scan { table: t, .. } => table(t)?,
+            Self::LookupJoin { left, table: t, .. } => {
+                let mut datatypes = left.datatypes(catalog)?;
+                datatypes.extend(table(t)?);
+                datatypes
+            }
+            Self::Projection { source, expressions } => {
+                let source = source.datatypes(catalog)?;
+                expressions.iter().map(|(e, _)| e.row_datatype(&source)).collect::<Result<_>>()?
+            }
+            Self::Recursive { datatypes, .. }
+            | Self::SetOperation { datatypes, .. }
+            | Self::WorkingTable { datatypes, .. } => datatypes.clone(),
+            Self::Subquery { source, kind, .. } => {
+                let mut datatypes = source.datatypes(catalog)?;
+                datatypes.push(match kind {
+                    SubqueryKind::Exists | SubqueryKind::In(_) => Some(Datatype::Boolean),
+                    SubqueryKind::Scalar => None,
+                });
+                datatypes
+            }
+            // Rows may have different lengths, and only values common to all rows count.
+            Self::Values { rows } => {
+                let width = rows.iter().map(|row| row.len()).min().unwrap_or(0);
+                let mut datatypes = vec![None::<Datatype>; width];
+                for row in rows {
+                    for (datatype, expr) in datatypes.iter_mut().zip(row) {
+                        *datatype = match (datatype.take(), expr.row_datatype(&[])?) {
+                            (Some(l), Some(r)) => l.coerce(&r),
+                            (l, r) => l.or(r),
+                        };
+                    }
+                }
+                datatypes
+            }
+            Self::Window { source, function, args, .. } => {
+                let mut datatypes = source.datatypes(catalog)?;
+                let arg = args.first().map(|e| e.row_datatype(&datatypes)).transpose()?.flatten();
+                datatypes.push(match function {
+                    WindowFunction::Aggregate(aggregate) => aggregate.datatype(arg),
+                    WindowFunction::Lag | WindowFunction::Lead => arg,
+                    WindowFunction::DenseRank
+                    | WindowFunction::Rank
+                    | WindowFunction::RowNumber => Some(Datatype::Integer),
+                });
+                datatypes
+            }
+            Self::Explain { .. } => vec![Some(Datatype::String)],
+            Self::Nothing => Vec::new(),
+            Self::Analyze { .. }
+            | Self::CreateTable { .. }
+            | Self::CreateView { .. }
+            | Self::Delete { .. }
+            | Self::DropTable { .. }
+            | Self::DropView { .. }
+            | Self::Insert { .. }
+            | Self::RefreshView { .. }
+            | Self::Update { .. } => Vec::new(),
+        })
+    }
+}
+
+impl Node {
+    /// Casts conditional results to their inferred datatype in the expressions of the tree,
+    /// including subquery plans, see Expression::coerce_branches(). Insert and update
+    /// expressions are left as is, since their values are cast to the column datatype anyway.
+    fn coerce_branches<C: Catalog>(self, catalog: &C) -> Result<Self> {
+        self.transform(&Ok, &|n| {
+            let n = match n {
+                Self::Subquery { source, subquery, kind } => {
+                    let subquery = Box::new(subquery.coerce_branches(catalog)?);
+                    Self::Subquery { source, subquery, kind }
+                }
+                n => n,
+            };
+            if matches!(n, Self::Insert { .. } | Self::Update { .. } | Self::Values { .. }) {
+                return Ok(n);
+            }
+            // Columns whose datatypes can't be inferred are left to execution.
+            match n.input_datatypes(catalog) {
+                Ok(columns) => n.map_expressions(&|e| e.coerce_branches(&columns)),
+                Err(_) => Ok(n),
+            }
+        })
+    }
+}
+
+impl Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.explain(&[]).join("\n"))
+    }
+}
+
+/// The result of a Subquery node.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubqueryKind {
+    /// Whether the subquery returns any rows.
+    Exists,
+    /// Whether the expression's value is among the values of the subquery's single column,
+    /// using the NULL semantics of IN lists.
+    In(Expression),
+    /// The single value returned by the subquery, or NULL if it returns no rows. Errors if it
+    /// returns more than one row.
+    Scalar,
+}
+
+/// An aggregate function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregate {
+    Average,
+    /// Counts non-NULL values, or distinct non-NULL values for COUNT(DISTINCT).
+    Count { distinct: bool },
+    Max,
+    Min,
+    Sum,
+}
+
+impl Aggregate {
+    /// Looks up an aggregate function by name, case-insensitively.
+    pub fn lookup(name: &str) -> Option<Self> {
+        Some(match name.to_uppercase().as_str() {
+            "AVG" => Self::Average,
+            "COUNT" => Self::Count { distinct: false },
+            "MAX" => Self::Max,
+            "MIN" => Self::Min,
+            "SUM" => Self::Sum,
+            _ => return None,
+        })
+    }
+}
+
+impl Aggregate {
+    /// Returns the aggregate's result datatype, given its argument's datatype.
+    pub fn datatype(&self, arg: Option<Datatype>) -> Option<Datatype> {
+        match self {
+            Self::Average => Some(Datatype::Float),
+            Self::Count { .. } => Some(Datatype::Integer),
+            Self::Max | Self::Min | Self::Sum => arg,
+        }
+    }
+
+    /// Formats the aggregate function call for the given argument.
+    pub fn format(&self, arg: &Expression) -> String {
+        match self {
+            Self::Count { distinct: true } => format!("COUNT(DISTINCT {})", arg),
+            aggregate => format!("{}({})", aggregate, arg),
+        }
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Average => write!(f, "AVG"),
+            Self::Count { .. } => write!(f, "COUNT"),
+            Self::Max => write!(f, "MAX"),
+            Self::Min => write!(f, "MIN"),
+            Self::Sum => write!(f, "SUM"),
+        }
+    }
+}
+
+/// A window function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowFunction {
+    /// An aggregate function over the rows of the window frame.
+    Aggregate(Aggregate),
+    /// The peer group number of the row within its partition, without gaps.
+    DenseRank,
+    /// The value of the row the given offset before the current row in the partition.
+    Lag,
+    /// The value of the row the given offset after the current row in the partition.
+    Lead,
+    /// The peer group's first row number within the partition, with gaps.
+    Rank,
+    /// The row's number within its partition, starting at 1.
+    RowNumber,
+}
+
+impl WindowFunction {
+    /// Looks up a window function by name, case-insensitively.
+    pub fn lookup(name: &str) -> Option<Self> {
+        Some(match name.to_uppercase().as_str() {
+            "DENSE_RANK" => Self::DenseRank,
+            "LAG" => Self::Lag,
+            "LEAD" => Self::Lead,
+            "RANK" => Self::Rank,
+            "ROW_NUMBER" => Self::RowNumber,
+            name => Self::Aggregate(Aggregate::lookup(name)?),
+        })
+    }
+
+    /// Formats the window function call for the given arguments.
+    pub fn format(&self, args: &[Expression]) -> String {
+        match (self, args) {
+            (Self::Aggregate(aggregate), [arg]) => aggregate.format(arg),
+            (function, args) => format!(
+                "{}({})",
+                function,
+                args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+}
+
+impl Display for WindowFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Aggregate(aggregate) => aggregate.fmt(f),
+            Self::DenseRank => write!(f, "DENSE_RANK"),
+            Self::Lag => write!(f, "LAG"),
+            Self::Lead => write!(f, "LEAD"),
+            Self::Rank => write!(f, "RANK"),
+            Self::RowNumber => write!(f, "ROW_NUMBER"),
+        }
+    }
+}
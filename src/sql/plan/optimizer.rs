@@ -0,0 +1,941 @@
+use super::cost::{Estimator, RANDOM_COST, SEQUENTIAL_COST};
+use super::{Direction, JoinType, Node, Nulls};
+use crate::error::Result;
+use crate::sql::storage::{Catalog, Column, Datatype, Expression, Value};
+
+use std::collections::HashSet;
+
+/// A plan optimizer, which rewrites a node tree into one that produces the same rows.
+pub trait Optimizer {
+    fn optimize(&self, node: Node) -> Result<Node>;
+}
+
+/// Folds constant subexpressions into constant values, e.g. 1 + 2 into 3, and simplifies
+/// logical operations with constant operands, e.g. x AND TRUE into x.
+pub struct ConstantFolder;
+
+impl Optimizer for ConstantFolder {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| n.map_expressions(&|e| e.fold()), &Ok)
+    }
+}
+
+/// Pushes filter predicates down the tree, as close to the data source as possible: into
+/// table scans, where the storage engine can evaluate them, and through joins into the join
+/// inputs. Predicates that refer to both join inputs are moved into the join predicate.
+pub struct FilterPushdown<'a, C: Catalog> {
+    catalog: &'a C,
+}
+
+impl<'a, C: Catalog> FilterPushdown<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog }
+    }
+
+    fn push_down(&self, node: Node) -> Result<Node> {
+        match node {
+            Node::Filter { source, predicate } => self.push_filter(*source, predicate),
+            Node::Join { left, right, predicate, r#type } => {
+                self.push_join(*left, *right, predicate, r#type)
+            }
+            node => Ok(node),
+        }
+    }
+
+    /// Pushes a filter predicate into its source node, if possible.
+    fn push_filter(&self, source: Node, predicate: Expression) -> Result<Node> {
+        Ok(match source {
+            Node::Scan { table, alias, filter } => {
+                // The storage engine resolves field labels against the table, not the alias.
+                let predicate = predicate.transform(
+                    &|e| match e {
+                        Expression::Field(i, Some((_, name))) => {
+                            Ok(Expression::Field(i, Some((Some(table.clone()), name))))
+                        }
+                        e => Ok(e),
+                    },
+                    &Ok,
+                )?;
+                let filter = match filter {
+                    Some(filter) => Expression::And(filter.into(), predicate.into()),
+                    None => predicate,
+                };
+                Node::Scan { table, alias, filter: Some(filter) }
+            }
+            Node::Filter { source, predicate: inner } => {
+                self.push_filter(*source, Expression::And(inner.into(), predicate.into()))?
+            }
+            Node::Join { left, right, predicate: on, r#type } => {
+                // Filters on inner joins are equivalent to join predicates, but for outer joins
+                // they can only be pushed into the outer side, since they also apply to the
+                // NULL-padded rows of the inner side. Semi and anti joins only emit left rows.
+                let width = width(self.catalog, &left)?;
+                let (mut lefts, mut rights, mut ons, mut keep) = (vec![], vec![], vec![], vec![]);
+                for expr in predicate.into_cnf_vec() {
+                    match (&r#type, side(&expr, width)) {
+                        (JoinType::Cross | JoinType::Inner, _) => ons.push(expr),
+                        (JoinType::Semi | JoinType::Anti, _) => lefts.push(expr),
+                        (JoinType::Left, Some(Side::Left)) => lefts.push(expr),
+                        (JoinType::Right, Some(Side::Right)) => {
+                            rights.push(shift(expr, |i| i - width)?)
+                        }
+                        (_, _) => keep.push(expr),
+                    }
+                }
+                let r#type = match r#type {
+                    JoinType::Cross if !ons.is_empty() => JoinType::Inner,
+                    r#type => r#type,
+                };
+                let on = Expression::from_cnf_vec(on.into_iter().chain(ons).collect());
+                let (left, right) = (filter(*left, lefts), filter(*right, rights));
+                filter(self.push_join(left, right, on, r#type)?, keep)
+            }
+            source => Node::Filter { source: source.into(), predicate },
+        })
+    }
+
+    /// Pushes parts of a join predicate that only refer to one input into that input. For
+    /// outer joins, only predicates on the inner side can be pushed down, since outer rows are
+    /// emitted regardless of the predicate. The same goes for the left side of anti joins.
+    fn push_join(
+        &self,
+        left: Node,
+        right: Node,
+        predicate: Option<Expression>,
+        r#type: JoinType,
+    ) -> Result<Node> {
+        let Some(predicate) = predicate else {
+            return Ok(Node::Join { left: left.into(), right: right.into(), predicate, r#type });
+        };
+        let width = width(self.catalog, &left)?;
+        let (mut lefts, mut rights, mut keep) = (vec![], vec![], vec![]);
+        for expr in predicate.into_cnf_vec() {
+            match (&r#type, side(&expr, width)) {
+                (
+                    JoinType::Cross | JoinType::Inner | JoinType::Right | JoinType::Semi,
+                    Some(Side::Left),
+                ) => lefts.push(expr),
+                (
+                    JoinType::Cross
+                    | JoinType::Inner
+                    | JoinType::Left
+                    | JoinType::Semi
+                    | JoinType::Anti,
+                    Some(Side::Right),
+                ) => rights.push(shift(expr, |i| i - width)?),
+                (_, _) => keep.push(expr),
+            }
+        }
+        Ok(Node::Join {
+            left: filter(left, lefts).into(),
+            right: filter(right, rights).into(),
+            predicate: Expression::from_cnf_vec(keep),
+            r#type,
+        })
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for FilterPushdown<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| self.push_down(n), &Ok)
+    }
+}
+
+/// Reorders trees of inner and cross joins using the cost model, if all of the joined inputs
+/// can be estimated, i.e. their tables have been analyzed. The inputs are joined left-deep,
+/// starting with the smallest and then greedily adding the input that gives the smallest
+/// intermediate result, preferring inputs that are connected by a join predicate over cross
+/// joins. Predicates are placed at the first join where all of their inputs are available, and
+/// a projection restores the original column order if it changed.
+pub struct JoinOrder<'a, C: Catalog> {
+    catalog: &'a C,
+    estimator: Estimator<'a, C>,
+}
+
+impl<'a, C: Catalog> JoinOrder<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog, estimator: Estimator::new(catalog) }
+    }
+
+    fn reorder(&self, node: Node) -> Result<Node> {
+        if !matches!(node, Node::Join { r#type: JoinType::Inner | JoinType::Cross, .. })
+            || self.estimator.estimate(&node)?.is_none()
+        {
+            return Ok(node);
+        }
+        let (mut inputs, mut predicates) = (Vec::new(), Vec::new());
+        self.flatten(node, &mut inputs, &mut predicates)?;
+
+        // The input estimates, and the offset of each input's columns in the joined row.
+        let mut estimates = Vec::with_capacity(inputs.len());
+        let mut offsets = Vec::with_capacity(inputs.len());
+        let mut columns = Vec::new();
+        for (input, _) in &inputs {
+            let Some(estimate) = self.estimator.estimate(input)? else {
+                unreachable!("join input can't be estimated");
+            };
+            offsets.push(columns.len());
+            columns.extend(estimate.columns);
+            estimates.push(estimate.rows);
+        }
+        let input = |field: usize| offsets.partition_point(|offset| *offset <= field) - 1;
+        let predicates: Vec<_> = predicates
+            .into_iter()
+            .map(|expr| {
+                let refs = (0..offsets.len())
+                    .filter(|j| {
+                        expr.contains(&|e| matches!(e, Expression::Field(i, _) if input(*i) == *j))
+                    })
+                    .collect::<HashSet<_>>();
+                let selectivity = Estimator::<C>::selectivity(&expr, &columns);
+                (expr, refs, selectivity)
+            })
+            .collect();
+
+        // The estimated rows of joining the given inputs.
+        let rows = |joined: &HashSet<usize>| -> f64 {
+            let rows: f64 = joined.iter().map(|i| estimates[*i]).product();
+            predicates
+                .iter()
+                .filter(|(_, refs, _)| refs.is_subset(joined))
+                .map(|(_, _, selectivity)| selectivity)
+                .product::<f64>()
+                * rows
+        };
+        let min = |candidates: &mut dyn Iterator<Item = (usize, f64)>| {
+            candidates.fold(None, |min: Option<(usize, f64)>, (i, rows)| match min {
+                Some((_, r)) if r <= rows => min,
+                _ => Some((i, rows)),
+            })
+        };
+        let mut order = vec![min(&mut estimates.iter().copied().enumerate()).unwrap().0];
+        let mut joined: HashSet<usize> = order.iter().copied().collect();
+        while order.len() < inputs.len() {
+            let remaining: Vec<_> = (0..inputs.len()).filter(|i| !joined.contains(i)).collect();
+            let connected: Vec<_> = remaining
+                .iter()
+                .copied()
+                .filter(|i| {
+                    predicates.iter().any(|(_, refs, _)| {
+                        refs.contains(i) && refs.iter().any(|r| joined.contains(r))
+                    })
+                })
+                .collect();
+            let candidates = if connected.is_empty() { remaining } else { connected };
+            let (next, _) = min(&mut candidates.into_iter().map(|i| {
+                let mut joined = joined.clone();
+                joined.insert(i);
+                (i, rows(&joined))
+            }))
+            .unwrap();
+            order.push(next);
+            joined.insert(next);
+        }
+
+        // Build the left-deep join tree, remapping fields to the new column order.
+        let mut new_offsets = vec![0; inputs.len()];
+        let mut width = 0;
+        for i in &order {
+            new_offsets[*i] = width;
+            width += inputs[*i].1;
+        }
+        let remap = |field: usize| new_offsets[input(field)] + field - offsets[input(field)];
+        let mut inputs: Vec<_> = inputs.into_iter().map(|(input, _)| Some(input)).collect();
+        let mut predicates: Vec<_> =
+            predicates.into_iter().map(|(e, refs, _)| (Some(e), refs)).collect();
+        let mut node = inputs[order[0]].take().unwrap();
+        let mut joined = HashSet::from([order[0]]);
+        for i in &order[1..] {
+            joined.insert(*i);
+            let mut placed = Vec::new();
+            for (expr, refs) in &mut predicates {
+                if expr.is_some() && refs.is_subset(&joined) {
+                    placed.push(shift(expr.take().unwrap(), remap)?);
+                }
+            }
+            let predicate = Expression::from_cnf_vec(placed);
+            let r#type = if predicate.is_some() { JoinType::Inner } else { JoinType::Cross };
+            let right = Box::new(inputs[*i].take().unwrap());
+            node = Node::Join { left: Box::new(node), right, predicate, r#type };
+        }
+        if order.windows(2).all(|w| w[0] < w[1]) {
+            return Ok(node);
+        }
+        let expressions = (0..width).map(|i| (Expression::Field(remap(i), None), None)).collect();
+        Ok(Node::Projection { source: Box::new(node), expressions })
+    }
+
+    /// Flattens a tree of inner and cross joins into its inputs, with their widths, and its
+    /// CNF predicates, whose fields refer to the inputs' concatenated columns.
+    fn flatten(
+        &self,
+        node: Node,
+        inputs: &mut Vec<(Node, usize)>,
+        predicates: &mut Vec<Expression>,
+    ) -> Result<()> {
+        let offset = inputs.iter().map(|(_, width)| width).sum::<usize>();
+        match node {
+            Node::Join { left, right, predicate, r#type: JoinType::Inner | JoinType::Cross } => {
+                self.flatten(*left, inputs, predicates)?;
+                self.flatten(*right, inputs, predicates)?;
+                for expr in predicate.map(|p| p.into_cnf_vec()).unwrap_or_default() {
+                    predicates.push(shift(expr, |i| i + offset)?);
+                }
+            }
+            node => {
+                let width = width(self.catalog, &node)?;
+                inputs.push((node, width));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for JoinOrder<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&|n| self.reorder(n), &Ok)
+    }
+}
+
+/// Chooses the join algorithm for joins with an equality predicate between fields of the two
+/// inputs, which otherwise use a nested loop join. In order of preference:
+///
+/// * Merge join, if both inputs are sorted by the join fields, e.g. primary key scans.
+/// * Lookup join, for inner and left joins where the right input is a table scan and the
+///   right join field is its primary key or an indexed column. If the inputs can be estimated,
+///   the lookups must be cheaper than scanning the table for a hash join.
+/// * Hash join, otherwise.
+pub struct JoinStrategy<'a, C: Catalog> {
+    catalog: &'a C,
+    estimator: Estimator<'a, C>,
+}
+
+impl<'a, C: Catalog> JoinStrategy<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog, estimator: Estimator::new(catalog) }
+    }
+
+    fn choose(&self, node: Node) -> Result<Node> {
+        let Node::Join { left, right, predicate: Some(predicate), r#type } = node else {
+            return Ok(node);
+        };
+        let width = width(self.catalog, &left)?;
+        let mut cnf = predicate.into_cnf_vec();
+        let keys: Vec<_> = cnf
+            .iter()
+            .enumerate()
+            .filter_map(|(i, expr)| equi_join(expr, width).map(|(l, r)| (i, l, r)))
+            .collect();
+        let Some(&(index, left_field, right_field)) = keys.first() else {
+            let predicate = Expression::from_cnf_vec(cnf);
+            return Ok(Node::Join { left, right, predicate, r#type });
+        };
+
+        let (left_order, right_order) = (self.ordering(&left)?, self.ordering(&right)?);
+        if let Some(&(index, left_field, right_field)) =
+            keys.iter().find(|(_, l, r)| Some(*l) == left_order && Some(*r) == right_order)
+        {
+            cnf.remove(index);
+            let predicate = Expression::from_cnf_vec(cnf);
+            return Ok(Node::MergeJoin { left, left_field, right, right_field, predicate, r#type });
+        }
+
+        if let (Node::Scan { table, .. }, JoinType::Inner | JoinType::Left) = (&*right, &r#type) {
+            let schema = self.catalog.must_read_table(table)?;
+            let columns = &schema.columns;
+            let lookup = keys
+                .iter()
+                .find(|(_, _, r)| columns[*r].primary_key)
+                .or_else(|| keys.iter().find(|(_, _, r)| columns[*r].index))
+                .copied();
+            let lookup = match lookup {
+                Some((_, _, r)) if self.prefer_lookup(&left, table, r)? == Some(false) => None,
+                lookup => lookup,
+            };
+            if let Some((index, left_field, right_field)) = lookup {
+                let Node::Scan { table, alias, filter } = *right else { unreachable!() };
+                let column = &columns[right_field];
+                let column = (!column.primary_key).then(|| column.name.clone());
+                cnf.remove(index);
+                // The scan filter is applied to the joined row instead. For left joins, this is
+                // equivalent to filtering the right input, since it pads rows either way.
+                if let Some(filter) = filter {
+                    cnf.push(shift(filter, |i| i + width)?);
+                }
+                return Ok(Node::LookupJoin {
+                    left,
+                    left_field,
+                    table,
+                    alias,
+                    column,
+                    predicate: Expression::from_cnf_vec(cnf),
+                    outer: matches!(r#type, JoinType::Left),
+                });
+            }
+        }
+
+        cnf.remove(index);
+        let predicate = Expression::from_cnf_vec(cnf);
+        Ok(Node::HashJoin { left, left_field, right, right_field, predicate, r#type })
+    }
+
+    /// Returns whether a lookup join on the given table column is estimated to be cheaper than
+    /// a hash join that scans the table, or None if the inputs can't be estimated. Each left
+    /// row looks up the index entry, unless the column is the primary key, and its matching
+    /// rows.
+    fn prefer_lookup(&self, left: &Node, table: &str, column: usize) -> Result<Option<bool>> {
+        let left = self.estimator.estimate(left)?;
+        let (Some(left), Some(right)) = (left, self.estimator.table(table)?) else {
+            return Ok(None);
+        };
+        let per_row = match self.catalog.must_read_table(table)?.columns[column].primary_key {
+            true => 1.0,
+            false => match &right.columns[column] {
+                Some(stats) => 1.0 + right.rows * (1.0 - stats.null_fraction)
+                    / stats.distinct.max(1) as f64,
+                None => 1.0 + right.rows,
+            },
+        };
+        let lookup = left.rows * per_row * RANDOM_COST;
+        let hash = (left.rows + right.rows) * SEQUENTIAL_COST;
+        Ok(Some(lookup <= hash))
+    }
+
+    /// Returns the field by which a node's output is sorted in ascending order, if known.
+    fn ordering(&self, node: &Node) -> Result<Option<usize>> {
+        Ok(match node {
+            Node::Filter { source, .. } => self.ordering(source)?,
+            Node::LookupJoin { left, .. } => self.ordering(left)?,
+            Node::Order { orders, .. } => match orders.first() {
+                Some((Expression::Field(i, _), Direction::Ascending, Nulls::First)) => Some(*i),
+                _ => None,
+            },
+            // The storage engine stores rows in primary key order.
+            Node::Scan { table, .. } => {
+                self.catalog.must_read_table(table)?.columns.iter().position(|c| c.primary_key)
+            }
+            _ => None,
+        })
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for JoinStrategy<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&Ok, &|n| self.choose(n))
+    }
+}
+
+/// Rewrites table scans whose filter looks up constant values of the primary key or an
+/// indexed column into key or index lookups, keeping the rest of the filter as a filter
+/// node. If the table has been analyzed, the cheapest of the lookups and the full scan is
+/// chosen by the cost model, otherwise primary key lookups are preferred over index lookups.
+pub struct IndexLookup<'a, C: Catalog> {
+    catalog: &'a C,
+    estimator: Estimator<'a, C>,
+}
+
+impl<'a, C: Catalog> IndexLookup<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Self { catalog, estimator: Estimator::new(catalog) }
+    }
+
+    fn index_lookup(&self, node: Node) -> Result<Node> {
+        let Node::Scan { table, alias, filter: Some(predicate) } = node else {
+            return Ok(node);
+        };
+        let schema = self.catalog.must_read_table(&table)?;
+        let mut cnf = predicate.into_cnf_vec();
+        let columns = schema.columns.iter().enumerate();
+        let lookups = columns
+            .clone()
+            .filter(|(_, c)| c.primary_key)
+            .chain(columns.filter(|(_, c)| c.index))
+            .filter_map(|(i, column)| {
+                cnf.iter().enumerate().find_map(|(j, expr)| {
+                    expr.as_lookup(i)
+                        .and_then(|values| lookup_values(column, values))
+                        .map(|values| (j, i, column, values))
+                })
+            });
+        // A key lookup reads each key's row, while an index lookup reads each value's index
+        // entry and then the matching rows.
+        let lookup = match self.estimator.table(&table)? {
+            None => lookups.map(|(j, _, column, values)| (j, column, values)).next(),
+            Some(estimate) => lookups
+                .map(|(j, i, column, values)| {
+                    let rows = Estimator::<C>::lookup_rows(&estimate, i, &values);
+                    let cost = match column.primary_key {
+                        true => rows.min(values.len() as f64) * RANDOM_COST,
+                        false => (values.len() as f64 + rows) * RANDOM_COST,
+                    };
+                    (j, column, values, cost)
+                })
+                .filter(|(_, _, _, cost)| *cost < estimate.rows * SEQUENTIAL_COST)
+                .fold(None, |min: Option<(usize, &Column, Vec<Value>, f64)>, lookup| match min {
+                    Some(min) if min.3 <= lookup.3 => Some(min),
+                    _ => Some(lookup),
+                })
+                .map(|(j, column, values, _)| (j, column, values)),
+        };
+        let Some((index, column, values)) = lookup else {
+            return Ok(Node::Scan { table, alias, filter: Expression::from_cnf_vec(cnf) });
+        };
+        cnf.remove(index);
+        let node = if column.primary_key {
+            Node::KeyLookup { table, alias, keys: values }
+        } else {
+            Node::IndexLookup { table, alias, column: column.name.clone(), values }
+        };
+        Ok(filter(node, cnf))
+    }
+}
+
+impl<'a, C: Catalog> Optimizer for IndexLookup<'a, C> {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        node.transform(&Ok, &|n| self.index_lookup(n))
+    }
+}
+
+/// Removes predicates that are always true, e.g. after constant folding, and filter nodes
+/// that are left without a predicate.
+pub struct NoopCleaner;
+
+impl Optimizer for NoopCleaner {
+    fn optimize(&self, node: Node) -> Result<Node> {
+        let is_true = |e: &Option<Expression>| {
+            matches!(e, Some(Expression::Constant(Value::Boolean(true))))
+        };
+        node.transform(&Ok, &|n| {
+            Ok(match n {
+                Node::Filter { source, predicate: Expression::Constant(Value::Boolean(true)) } => {
+                    *source
+                }
+                Node::Join { left, right, predicate, r#type } if is_true(&predicate) => {
+                    Node::Join { left, right, predicate: None, r#type }
+                }
+                Node::Scan { table, alias, filter } if is_true(&filter) => {
+                    Node::Scan { table, alias, filter: None }
+                }
+                n => n,
+            })
+        })
+    }
+}
+
+/// Returns the number of columns a node emits.
+fn width<C: Catalog>(catalog: &C, node: &Node) -> Result<usize> {
+    Ok(match node {
+        Node::Aggregate { group_by, aggregates, .. } => group_by.len() + aggregates.len(),
+        Node::Filter { source, .. } | Node::Limit { source, .. } | Node::Order { source, .. } => {
+            width(catalog, source)?
+        }
+        Node::IndexLookup { table, .. }
+        | Node::KeyLookup { table, .. }
+        | Node::Scan { table, .. } => catalog.must_read_table(table)?.columns.len(),
+        Node::HashJoin { left, r#type: JoinType::Semi | JoinType::Anti, .. }
+        | Node::Join { left, r#type: JoinType::Semi | JoinType::Anti, .. }
+        | Node::MergeJoin { left, r#type: JoinType::Semi | JoinType::Anti, .. } => {
+            width(catalog, left)?
+        }
+        Node::HashJoin { left, right, .. }
+        | Node::Join { left, right, .. }
+        | Node::MergeJoin { left, right, .. } => width(catalog, left)? + width(catalog, right)?,
+        Node::LookupJoin { left, table, .. } => {
+            width(catalog, left)? + catalog.must_read_table(table)?.columns.len()
+        }
+        Node::Projection { expressions, .. } => expressions.len(),
+        Node::Values { rows } => rows.first().map_or(0, |row| row.len()),
+        Node::Recursive { anchor: left, .. } | Node::SetOperation { left, .. } => {
+            width(catalog, left)?
+        }
+        Node::WorkingTable { datatypes, .. } => datatypes.len(),
+        Node::Subquery { source, .. } | Node::Window { source, .. } => {
+            width(catalog, source)? + 1
+        }
+        Node::Explain { .. } => 1,
+        Node::Analyze { .. }
+        | Node::CreateTable { .. }
+        | Node::CreateView { .. }
+        | Node::Delete { .. }
+        | Node::DropTable { .. }
+        | Node::DropView { .. }
+        | Node::Insert { .. }
+        | Node::Nothing
+        | Node::RefreshView { .. }
+        | Node::Update { .. } => 0,
+    })
+}
+
+/// Returns the left and right field indexes of an equality predicate between fields of the
+/// left and right join inputs, given the width of the left input.
+fn equi_join(expr: &Expression, width: usize) -> Option<(usize, usize)> {
+    match expr {
+        Expression::Equal(lhs, rhs) => match (&**lhs, &**rhs) {
+            (Expression::Field(l, _), Expression::Field(r, _))
+            | (Expression::Field(r, _), Expression::Field(l, _))
+                if *l < width && *r >= width =>
+            {
+                Some((*l, *r - width))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The join input that an expression refers to.
+enum Side {
+    Left,
+    Right,
+}
+
+/// Returns the join input whose fields the expression refers to, given the width of the left
+/// input, or None if it refers to both inputs or to no fields at all.
+fn side(expr: &Expression, width: usize) -> Option<Side> {
+    let left = expr.contains(&|e| matches!(e, Expression::Field(i, _) if *i < width));
+    let right = expr.contains(&|e| matches!(e, Expression::Field(i, _) if *i >= width));
+    match (left, right) {
+        (true, false) => Some(Side::Left),
+        (false, true) => Some(Side::Right),
+        (_, _) => None,
+    }
+}
+
+/// Remaps the field indexes of an expression, e.g. when moving it across a join.
+fn shift<F: Fn(usize) -> usize>(expr: Expression, f: F) -> Result<Expression> {
+    expr.transform(
+        &|e| match e {
+            Expression::Field(i, label) => Ok(Expression::Field(f(i), label)),
+            e => Ok(e),
+        },
+        &Ok,
+    )
+}
+
+/// Wraps a node in a filter node for the given CNF predicates, if any.
+fn filter(source: Node, predicates: Vec<Expression>) -> Node {
+    match Expression::from_cnf_vec(predicates) {
+        Some(predicate) => Node::Filter { source: source.into(), predicate },
+        None => source,
+    }
+}
+
+/// Converts lookup values to the datatype of the column's keys and removes duplicates.
+/// Returns None if a value has a datatype that can't be looked up in the column.
+fn lookup_values(column: &Column, values: Vec<Value>) -> Option<Vec<Value>> {
+    let mut lookup = Vec::new();
+    for value in values {
+        let value = match (&column.datatype, value) {
+            (Datatype::Float, Value::Integer(i)) => Value::Float(i as f64),
+            (_, Value::Null) => Value::Null,
+            (datatype, value) if value.datatype().as_ref() == Some(datatype) => value,
+            (_, _) => return None,
+        };
+        if !lookup.contains(&value) {
+            lookup.push(value);
+        }
+    }
+    Some(lookup)
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::parser::Parser;
+    use super::super::super::storage::{Kv, Memory, Mode, Mvcc, Transaction};
+    use super::super::Plan;
+    use super::*;
+    use crate::sql::storage::TableStats;
+    use pretty_assertions::assert_eq;
+
+    fn setup() -> Result<Kv> {
+        let kv = Kv::new(Mvcc::new(Box::new(Memory::new())));
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        for query in [
+            "CREATE TABLE studios (id INTEGER PRIMARY KEY, name STRING NOT NULL)",
+            "CREATE TABLE movies (
+                id INTEGER PRIMARY KEY,
+                title STRING NOT NULL,
+                studio_id INTEGER REFERENCES studios INDEX,
+                rating FLOAT
+            )",
+        ] {
+            match Plan::build(Parser::new(query).parse()?, &txn)?.0 {
+                Node::CreateTable { schema } => txn.create_table(schema)?,
+                node => panic!("Unexpected node {:?}", node),
+            }
+        }
+        txn.commit()?;
+        Ok(kv)
+    }
+
+    /// Optimizes a SELECT query, returning the source of its final projection.
+    fn optimize(kv: &Kv, query: &str) -> Result<Node> {
+        let txn = kv.begin(Mode::ReadOnly)?;
+        match Plan::build(Parser::new(query).parse()?, &txn)?.optimize(&txn)?.0 {
+            Node::Projection { source, .. } => Ok(*source),
+            node => panic!("Unexpected node {:?}", node),
+        }
+    }
+
+    fn field(index: usize, table: &str, name: &str) -> Box<Expression> {
+        Box::new(Expression::Field(index, Some((Some(table.into()), name.into()))))
+    }
+
+    fn constant(value: Value) -> Box<Expression> {
+        Box::new(Expression::Constant(value))
+    }
+
+    fn scan(table: &str, alias: Option<&str>, filter: Option<Expression>) -> Box<Node> {
+        Box::new(Node::Scan { table: table.into(), alias: alias.map(|a| a.into()), filter })
+    }
+
+    #[test]
+    fn filters() -> Result<()> {
+        let kv = setup()?;
+        assert_eq!(
+            optimize(&kv, "SELECT * FROM movies WHERE rating > 8 AND 1 + 1 = 2")?,
+            *scan(
+                "movies",
+                None,
+                Some(Expression::GreaterThan(
+                    field(3, "movies", "rating"),
+                    constant(Value::Integer(8))
+                ))
+            ),
+        );
+        assert_eq!(
+            optimize(&kv, "SELECT * FROM movies WHERE TRUE OR id = 1")?,
+            *scan("movies", None, None)
+        );
+        assert_eq!(
+            optimize(&kv, "SELECT * FROM movies WHERE 1 = 2")?,
+            *scan("movies", None, Some(Expression::Constant(Value::Boolean(false)))),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn lookups() -> Result<()> {
+        let kv = setup()?;
+        assert_eq!(
+            optimize(&kv, "SELECT * FROM movies WHERE id = 1 AND rating > 8")?,
+            Node::Filter {
+                source: Box::new(Node::KeyLookup {
+                    table: "movies".into(),
+                    alias: None,
+                    keys: vec![Value::Integer(1)],
+                }),
+                predicate: Expression::GreaterThan(
+                    field(3, "movies", "rating"),
+                    constant(Value::Integer(8))
+                ),
+            }
+        );
+        assert_eq!(
+            optimize(&kv, "SELECT * FROM movies WHERE studio_id IN (1, 2, NULL) OR studio_id = 1")?,
+            Node::IndexLookup {
+                table: "movies".into(),
+                alias: None,
+                column: "studio_id".into(),
+                values: vec![Value::Integer(1), Value::Integer(2)],
+            }
+        );
+        assert_eq!(
+            optimize(&kv, "SELECT * FROM movies WHERE studio_id = 1 AND (id = 1 OR id = 3)")?,
+            Node::Filter {
+                source: Box::new(Node::KeyLookup {
+                    table: "movies".into(),
+                    alias: None,
+                    keys: vec![Value::Integer(1), Value::Integer(3)],
+                }),
+                predicate: Expression::Equal(
+                    field(2, "movies", "studio_id"),
+                    constant(Value::Integer(1))
+                ),
+            }
+        );
+        // Non-indexed columns and datatype mismatches can't use lookups.
+        for query in [
+            "SELECT * FROM movies WHERE rating = 8",
+            "SELECT * FROM movies WHERE id = 1 OR rating = 8",
+            "SELECT * FROM movies WHERE id = 'a'",
+        ] {
+            assert!(matches!(optimize(&kv, query)?, Node::Scan { .. }), "{}", query);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn joins() -> Result<()> {
+        let kv = setup()?;
+        assert_eq!(
+            optimize(
+                &kv,
+                "SELECT * FROM movies m, studios s
+                 WHERE m.studio_id = s.id AND s.name = 'x' AND m.rating > 8"
+            )?,
+            Node::LookupJoin {
+                left: scan(
+                    "movies",
+                    Some("m"),
+                    Some(Expression::GreaterThan(
+                        field(3, "movies", "rating"),
+                        constant(Value::Integer(8))
+                    ))
+                ),
+                left_field: 2,
+                table: "studios".into(),
+                alias: Some("s".into()),
+                column: None,
+                predicate: Some(Expression::Equal(
+                    field(5, "studios", "name"),
+                    constant(Value::String("x".into()))
+                )),
+                outer: false,
+            }
+        );
+
+        // Filters on the inner side of an outer join can't be pushed down, and join predicates
+        // on the outer side can't either. Indexed columns can be used for lookup joins.
+        assert_eq!(
+            optimize(
+                &kv,
+                "SELECT * FROM studios s LEFT JOIN movies m ON s.id = m.studio_id AND s.id > 1
+                 WHERE m.rating > 8 AND s.name = 'x'"
+            )?,
+            Node::Filter {
+                source: Box::new(Node::LookupJoin {
+                    left: scan(
+                        "studios",
+                        Some("s"),
+                        Some(Expression::Equal(
+                            field(1, "studios", "name"),
+                            constant(Value::String("x".into()))
+                        ))
+                    ),
+                    left_field: 0,
+                    table: "movies".into(),
+                    alias: Some("m".into()),
+                    column: Some("studio_id".into()),
+                    predicate: Some(Expression::GreaterThan(
+                        field(0, "s", "id"),
+                        constant(Value::Integer(1))
+                    )),
+                    outer: true,
+                }),
+                predicate: Expression::GreaterThan(
+                    field(5, "m", "rating"),
+                    constant(Value::Integer(8))
+                ),
+            }
+        );
+
+        // Joins on the primary keys of both inputs use merge joins, since scans are sorted.
+        assert_eq!(
+            optimize(&kv, "SELECT * FROM movies m FULL JOIN studios s ON s.id = m.id")?,
+            Node::MergeJoin {
+                left: scan("movies", Some("m"), None),
+                left_field: 0,
+                right: scan("studios", Some("s"), None),
+                right_field: 0,
+                predicate: None,
+                r#type: JoinType::Full,
+            }
+        );
+
+        // Other equi-joins use hash joins, and joins without equality predicates use nested
+        // loop joins.
+        assert_eq!(
+            optimize(
+                &kv,
+                "SELECT * FROM movies a RIGHT JOIN movies b
+                 ON a.studio_id = b.studio_id AND a.rating < b.rating"
+            )?,
+            Node::HashJoin {
+                left: scan("movies", Some("a"), None),
+                left_field: 2,
+                right: scan("movies", Some("b"), None),
+                right_field: 2,
+                predicate: Some(Expression::LessThan(
+                    field(3, "a", "rating"),
+                    field(7, "b", "rating")
+                )),
+                r#type: JoinType::Right,
+            }
+        );
+        assert!(matches!(
+            optimize(&kv, "SELECT * FROM movies a JOIN movies b ON a.rating < b.rating")?,
+            Node::Join { .. }
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn costs() -> Result<()> {
+        let kv = setup()?;
+        // Statistics as if there were 100 studios and 1000 movies, all from the first 3 studios,
+        // where a third of the movies have no rating.
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        let studios = (0..100).map(|i| Ok(vec![Value::Integer(i), Value::String(i.to_string())]));
+        txn.write_stats("studios", TableStats::compute(2, studios)?)?;
+        let movies = (0..1000).map(|i| {
+            Ok(vec![
+                Value::Integer(i),
+                Value::String(i.to_string()),
+                Value::Integer(i % 3),
+                if i % 3 == 0 { Value::Null } else { Value::Float(i as f64 / 100.0) },
+            ])
+        });
+        txn.write_stats("movies", TableStats::compute(4, movies)?)?;
+        txn.commit()?;
+
+        // Index lookups that match a large fraction of the table are cheaper as scans, but
+        // primary key lookups are still used.
+        assert_eq!(
+            optimize(&kv, "SELECT * FROM movies WHERE studio_id = 1")?,
+            *scan(
+                "movies",
+                None,
+                Some(Expression::Equal(
+                    field(2, "movies", "studio_id"),
+                    constant(Value::Integer(1))
+                ))
+            ),
+        );
+        assert!(matches!(
+            optimize(&kv, "SELECT * FROM movies WHERE id = 1 AND studio_id = 1")?,
+            Node::Filter { source, .. } if matches!(*source, Node::KeyLookup { .. })
+        ));
+
+        // The smaller studios table is joined first, with a projection restoring the column
+        // order. Hashing the movies is cheaper than looking up a third of them per studio.
+        assert_eq!(
+            optimize(&kv, "SELECT * FROM movies m, studios s WHERE m.studio_id = s.id")?,
+            Node::Projection {
+                source: Box::new(Node::HashJoin {
+                    left: scan("studios", Some("s"), None),
+                    left_field: 0,
+                    right: scan("movies", Some("m"), None),
+                    right_field: 2,
+                    predicate: None,
+                    r#type: JoinType::Inner,
+                }),
+                expressions: [2, 3, 4, 5, 0, 1]
+                    .into_iter()
+                    .map(|i| (Expression::Field(i, None), None))
+                    .collect(),
+            }
+        );
+
+        // A selective filter on movies makes it the smaller input instead, which then looks
+        // up studios by primary key.
+        assert!(matches!(
+            optimize(
+                &kv,
+                "SELECT * FROM studios s, movies m WHERE m.studio_id = s.id AND m.id < 2"
+            )?,
+            Node::Projection { source, .. } if matches!(*source, Node::LookupJoin { .. })
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,2136 @@
+use super::super::parser::{ast, Parser};
+use super::super::storage::types::Function;
+use super::super::storage::{
+    information, Catalog, Column, Conflict, Datatype, Expression, Table, TableKind, Value,
+};
+use super::{
+    label, Aggregate, Direction, JoinType, Node, Nulls, Plan, SetOperator, SubqueryKind,
+    WindowFunction,
+};
+use crate::error::{Error, Result};
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+
+/// A query planner, which builds a plan node tree from a statement's AST.
+pub struct Planner<'a, C: Catalog> {
+    catalog: &'a C,
+    /// The CTEs in scope, innermost last, which shadow tables and earlier CTEs of the same name.
+    ctes: RefCell<Vec<Cte>>,
+    /// The tables and views referenced by the statement so far, excluding those referenced
+    /// via views. Recorded for views, which depend on them.
+    references: RefCell<Vec<String>>,
+}
+
+/// A CTE in scope.
+enum Cte {
+    /// A CTE query, which is planned at each reference.
+    Query { name: String, columns: Vec<String>, query: Box<ast::Statement>, recursive: bool },
+    /// A recursive CTE referenced from its own recursive term, which reads the working table.
+    WorkingTable { name: String, columns: Vec<Option<String>>, datatypes: Vec<Option<Datatype>> },
+}
+
+impl Cte {
+    fn name(&self) -> &str {
+        match self {
+            Self::Query { name, .. } | Self::WorkingTable { name, .. } => name,
+        }
+    }
+}
+
+impl<'a, C: Catalog> Planner<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Planner { catalog, ctes: RefCell::default(), references: RefCell::default() }
+    }
+
+    pub fn build(&mut self, statement: ast::Statement) -> Result<Plan> {
+        Ok(Plan(self.build_statement(statement)?))
+    }
+
+    fn build_statement(&self, statement: ast::Statement) -> Result<Node> {
+        Ok(match statement {
+            ast::Statement::Begin { .. } | ast::Statement::Commit | ast::Statement::Rollback => {
+                return Err(Error::Internal(format!(
+                    "Unexpected transaction statement {:?}",
+                    statement
+                )))
+            }
+            ast::Statement::Prepare { .. }
+            | ast::Statement::Execute { .. }
+            | ast::Statement::Deallocate(_) => {
+                return Err(Error::Internal(format!(
+                    "Unexpected prepared statement command {:?}",
+                    statement
+                )))
+            }
+
+            ast::Statement::Explain { statement, analyze } => match *statement {
+                ast::Statement::Begin { .. }
+                | ast::Statement::Commit
+                | ast::Statement::Rollback => {
+                    return Err(Error::Value("Can't EXPLAIN transaction statements".into()))
+                }
+                ast::Statement::Prepare { .. }
+                | ast::Statement::Execute { .. }
+                | ast::Statement::Deallocate(_) => {
+                    return Err(Error::Value("Can't EXPLAIN prepared statement commands".into()))
+                }
+                statement => {
+                    Node::Explain { source: Box::new(self.build_statement(statement)?), analyze }
+                }
+            },
+
+            ast::Statement::CreateTable { name, columns } => Node::CreateTable {
+                schema: Table::new(
+                    name,
+                    columns.into_iter().map(|c| self.build_column(c)).collect::<Result<_>>()?,
+                ),
+            },
+            ast::Statement::DropTable(table) => {
+                if let Some(schema) = self.catalog.read_table(&table)? {
+                    if schema.kind != TableKind::Table {
+                        return Err(Error::Value(format!("{} is a view, not a table", table)));
+                    }
+                }
+                Node::DropTable { table }
+            }
+
+            ast::Statement::CreateView { name, query, sql, materialized } => {
+                let (source, _) = self.build_select(*query, None)?;
+                if Self::has_parameters(&source)? {
+                    return Err(Error::Value("Views can't have parameters".into()));
+                }
+                let references = self.references.take();
+                let source = Box::new(source);
+                Node::CreateView { name, query: sql, references, source, materialized }
+            }
+            ast::Statement::DropView(name) => {
+                self.read_view(&name)?;
+                Node::DropView { name }
+            }
+            ast::Statement::RefreshView(name) => match self.read_view(&name)?.kind {
+                TableKind::MaterializedView { query, .. } => {
+                    let (source, _) = self.build_select(Parser::new(&query).parse()?, None)?;
+                    Node::RefreshView { name, source: Box::new(source) }
+                }
+                _ => return Err(Error::Value(format!("{} is not a materialized view", name))),
+            },
+            // Views have no rows of their own, while materialized views are analyzed like tables.
+            ast::Statement::Analyze(table) => {
+                let tables = match table {
+                    Some(table) => match self.catalog.must_read_table(&table)?.kind {
+                        TableKind::View { .. } => {
+                            return Err(Error::Value(format!("Can't analyze view {}", table)))
+                        }
+                        _ => vec![table],
+                    },
+                    None => self
+                        .catalog
+                        .scan_tables()?
+                        .filter(|t| !matches!(t.kind, TableKind::View { .. }))
+                        .map(|t| t.name)
+                        .collect(),
+                };
+                Node::Analyze { tables }
+            }
+            ast::Statement::ShowTables => self.build_show(information::TABLES)?,
+            ast::Statement::ShowTransactions => self.build_show(information::TRANSACTIONS)?,
+            // The statement is rendered during planning, like views are expanded.
+            ast::Statement::ShowCreateTable(table) => {
+                let schema = self.catalog.must_read_table(&table)?;
+                if schema.kind != TableKind::Table {
+                    return Err(Error::Value(format!("{} is a view, not a table", table)));
+                }
+                let ddl = Expression::Constant(Value::String(schema.to_string()));
+                let expressions = vec![(ddl, Some("create_table".into()))];
+                Node::Projection { source: Box::new(Node::Nothing), expressions }
+            }
+
+            ast::Statement::Delete { table, r#where } => {
+                let (source, _) = self.build_table_source(&table, r#where)?;
+                Node::Delete { table, source: Box::new(source) }
+            }
+
+            ast::Statement::Insert { table, columns, source, on_conflict } => {
+                let schema = self.catalog.must_read_table(&table)?;
+                if schema.kind != TableKind::Table {
+                    return Err(Error::Value(format!("Can't modify view {}", table)));
+                }
+                let columns = columns.unwrap_or_default();
+                let mut seen = HashSet::new();
+                for column in &columns {
+                    if !schema.columns.iter().any(|c| &c.name == column) {
+                        return Err(Error::Value(format!(
+                            "Unknown column {} in table {}",
+                            column, table
+                        )));
+                    }
+                    if !seen.insert(column) {
+                        return Err(Error::Value(format!("Column {} given multiple times", column)));
+                    }
+                }
+                let expected = match columns.is_empty() {
+                    true => schema.columns.len(),
+                    false => columns.len(),
+                };
+                let check = |width: usize| match columns.is_empty() && width > expected
+                    || !columns.is_empty() && width != expected
+                {
+                    true => Err(Error::Value(format!(
+                        "Expected {} values for table {}, got {}",
+                        expected, table, width
+                    ))),
+                    false => Ok(()),
+                };
+                let source = match source {
+                    ast::InsertSource::Values(values) => {
+                        let scope = Scope::new();
+                        let rows = values
+                            .into_iter()
+                            .map(|row| {
+                                check(row.len())?;
+                                row.into_iter().map(|e| self.bind(&scope, e)).collect()
+                            })
+                            .collect::<Result<_>>()?;
+                        Node::Values { rows }
+                    }
+                    ast::InsertSource::Select(select) => {
+                        let (node, projected) = self.build_select(*select, None)?;
+                        check(projected.columns.len())?;
+                        node
+                    }
+                };
+                let conflict = match on_conflict {
+                    Some(on_conflict) => self.build_conflict(&schema, on_conflict)?,
+                    None => Conflict::Error,
+                };
+                Node::Insert { table, columns, source: Box::new(source), conflict }
+            }
+
+            ast::Statement::Update { table, set, r#where } => {
+                let (source, scope) = self.build_table_source(&table, r#where)?;
+                let schema = self.catalog.must_read_table(&table)?;
+                let expressions = set
+                    .into_iter()
+                    .map(|(column, expr)| {
+                        let index = schema.get_column_index(&column).map_err(|_| {
+                            Error::Value(format!("Unknown column {} in table {}", column, table))
+                        })?;
+                        Ok((index, column, self.bind(&scope, expr)?))
+                    })
+                    .collect::<Result<_>>()?;
+                Node::Update { table, source: Box::new(source), expressions }
+            }
+
+            select @ (ast::Statement::Select { .. }
+            | ast::Statement::SetOperation { .. }
+            | ast::Statement::With { .. }) => self.build_select(select, None)?.0,
+        })
+    }
+
+    /// Builds a query of all rows in an information schema table.
+    fn build_show(&self, table: &str) -> Result<Node> {
+        let select = ast::Statement::Select {
+            select: Vec::new(),
+            from: vec![ast::FromItem::Table { name: table.into(), alias: None }],
+            r#where: None,
+            group_by: Vec::new(),
+            having: None,
+            order: Vec::new(),
+            limit: None,
+            offset: None,
+        };
+        Ok(self.build_select(select, None)?.0)
+    }
+
+    /// Reads a view or materialized view from the catalog.
+    fn read_view(&self, name: &str) -> Result<Table> {
+        match self.catalog.read_table(name)? {
+            Some(Table { kind: TableKind::Table, .. }) => {
+                Err(Error::Value(format!("{} is a table, not a view", name)))
+            }
+            Some(view) => Ok(view),
+            None => Err(Error::Value(format!("View {} does not exist", name))),
+        }
+    }
+
+    /// Returns true if the plan or its subquery plans contain parameters.
+    fn has_parameters(node: &Node) -> Result<bool> {
+        let found = Cell::new(false);
+        node.clone().transform(&Ok, &|n| {
+            if let Node::Subquery { subquery, .. } = &n {
+                if Self::has_parameters(subquery)? {
+                    found.set(true);
+                }
+            }
+            n.map_expressions(&|e| {
+                if e.contains(&|e| matches!(e, Expression::Parameter(_))) {
+                    found.set(true);
+                }
+                Ok(e)
+            })
+        })?;
+        Ok(found.get())
+    }
+
+    /// Builds a SELECT statement, returning its node and the scope of its output columns. For
+    /// subqueries, the outer query's scope is given, whose columns are bound as
+    /// Expression::Outer references.
+    fn build_select(
+        &self,
+        statement: ast::Statement,
+        outer: Option<&Scope>,
+    ) -> Result<(Node, Scope)> {
+        match statement {
+            ast::Statement::SetOperation { .. } => {
+                return self.build_set_operation(statement, outer)
+            }
+            ast::Statement::With { recursive, ctes, statement } => {
+                return self.build_with(recursive, ctes, *statement, outer)
+            }
+            _ => {}
+        }
+        let ast::Statement::Select {
+            select,
+            from,
+            r#where,
+            group_by,
+            having,
+            order,
+            limit,
+            offset,
+        } = statement
+        else {
+            return Err(Error::Internal(format!("Expected SELECT, got {:?}", statement)));
+        };
+        let mut scope = Scope { outer: outer.cloned().map(Box::new), ..Scope::new() };
+        let mut node = match from.is_empty() {
+            true => Node::Nothing,
+            false => self.build_from_items(from, &mut scope)?,
+        };
+        if let Some(predicate) = r#where {
+            node = self.build_filter(node, &mut scope, predicate)?;
+        }
+
+        // SELECT * selects all columns in scope.
+        let mut select = select;
+        if select.is_empty() {
+            if scope.columns.is_empty() {
+                return Err(Error::Value("SELECT * requires a FROM clause".into()));
+            }
+            select = (0..scope.columns.len())
+                .map(|i| (ast::Expression::Field(i, scope.label(i)), None))
+                .collect();
+        }
+
+        let mut having = having;
+        let mut order = order;
+        if !group_by.is_empty()
+            || select.iter().any(|(e, _)| Self::is_aggregate(e))
+            || having.iter().any(Self::is_aggregate)
+            || order.iter().any(|(e, _, _)| Self::is_aggregate(e))
+        {
+            node = self.build_aggregate(
+                node,
+                &mut scope,
+                group_by,
+                &mut select,
+                &mut having,
+                &mut order,
+            )?;
+        }
+
+        if let Some(predicate) = having {
+            node = self.build_filter(node, &mut scope, predicate)?;
+        }
+
+        // Subqueries and window functions in the select list are evaluated by Subquery and
+        // Window nodes, and labelled by their expression unless aliased.
+        let mut expressions = Vec::new();
+        for (expr, alias) in select {
+            let alias = alias.or_else(|| {
+                (Self::has_subquery(&expr) || Self::has_window(&expr)).then(|| expr.to_string())
+            });
+            let (source, expr) = self.build_subqueries(node, &mut scope, expr)?;
+            let (source, expr) = self.build_windows(source, &mut scope, expr)?;
+            node = source;
+            expressions.push((self.bind(&scope, expr)?, alias));
+        }
+        let projected = scope.project(&expressions);
+        let width = expressions.len();
+
+        // ORDER BY can refer to output columns by label or position, and to source
+        // columns which are projected as hidden columns and removed after sorting.
+        let mut orders = Vec::new();
+        for (expr, direction, nulls) in order {
+            let index = match &expr {
+                ast::Expression::Constant(Value::Integer(i)) => match *i {
+                    i if i >= 1 && i as usize <= width => i as usize - 1,
+                    i => {
+                        return Err(Error::Value(format!(
+                            "ORDER BY position {} is not in select list",
+                            i
+                        )))
+                    }
+                },
+                ast::Expression::Column(table, name)
+                    if projected.resolve(table.as_deref(), name).is_ok() =>
+                {
+                    projected.resolve(table.as_deref(), name)?
+                }
+                _ => {
+                    let (source, expr) = self.build_windows(node, &mut scope, expr)?;
+                    node = source;
+                    let expr = self.bind(&scope, expr)?;
+                    match expressions.iter().position(|(e, _)| e == &expr) {
+                        Some(index) => index,
+                        None => {
+                            expressions.push((expr, None));
+                            expressions.len() - 1
+                        }
+                    }
+                }
+            };
+            orders.push((Expression::Field(index, None), direction, nulls));
+        }
+
+        let offset = match offset {
+            Some(expr) => Some(self.evaluate_count("offset", expr)?),
+            None => None,
+        };
+        let limit = limit.map(|expr| self.evaluate_count("limit", expr)).transpose()?;
+
+        let labels: Vec<_> = expressions.iter().map(|(e, a)| label(e, a)).collect();
+        let hidden = expressions.len() - width;
+        node = Node::Projection { source: Box::new(node), expressions };
+        if !orders.is_empty() {
+            // With a LIMIT, only the first offset + limit sorted rows are needed.
+            let limit = limit.map(|limit| limit.saturating_add(offset.unwrap_or(0)));
+            node = Node::Order { source: Box::new(node), orders, limit };
+        }
+        if hidden > 0 {
+            node = Node::Projection {
+                source: Box::new(node),
+                expressions: labels
+                    .into_iter()
+                    .take(width)
+                    .enumerate()
+                    .map(|(i, label)| (Expression::Field(i, None), Some(label)))
+                    .collect(),
+            };
+        }
+
+        if limit.is_some() || offset.is_some() {
+            node = Node::Limit {
+                source: Box::new(node),
+                offset: offset.unwrap_or(0),
+                limit,
+            };
+        }
+        Ok((node, projected))
+    }
+
+    /// Builds a set operation, returning its node and the scope of its output columns, which
+    /// are named after the left query's columns. The queries must have the same number of
+    /// columns, and each column's datatypes must have a common datatype. ORDER BY can only refer
+    /// to output columns.
+    fn build_set_operation(
+        &self,
+        statement: ast::Statement,
+        outer: Option<&Scope>,
+    ) -> Result<(Node, Scope)> {
+        let ast::Statement::SetOperation { operator, all, left, right, order, limit, offset } =
+            statement
+        else {
+            return Err(Error::Internal(format!("Expected set operation, got {:?}", statement)));
+        };
+        let (left, scope) = self.build_select(*left, outer)?;
+        let (right, _) = self.build_select(*right, outer)?;
+        let datatypes = self.set_datatypes(operator, &left, &right)?;
+        let mut node = Node::SetOperation {
+            left: Box::new(left),
+            right: Box::new(right),
+            operator,
+            all,
+            datatypes,
+        };
+
+        let width = scope.columns.len();
+        let orders = order
+            .into_iter()
+            .map(|(expr, direction, nulls)| match expr {
+                ast::Expression::Constant(Value::Integer(i)) if i >= 1 && i as usize <= width => {
+                    Ok((Expression::Field(i as usize - 1, None), direction, nulls))
+                }
+                ast::Expression::Constant(Value::Integer(i)) => Err(Error::Value(format!(
+                    "ORDER BY position {} is not in select list",
+                    i
+                ))),
+                expr => Ok((self.bind(&scope, expr)?, direction, nulls)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let offset = offset.map(|expr| self.evaluate_count("offset", expr)).transpose()?;
+        let limit = limit.map(|expr| self.evaluate_count("limit", expr)).transpose()?;
+        if !orders.is_empty() {
+            let limit = limit.map(|limit| limit.saturating_add(offset.unwrap_or(0)));
+            node = Node::Order { source: Box::new(node), orders, limit };
+        }
+        if limit.is_some() || offset.is_some() {
+            node = Node::Limit { source: Box::new(node), offset: offset.unwrap_or(0), limit };
+        }
+        Ok((node, scope))
+    }
+
+    /// Returns the common datatypes of the left and right queries' columns for a set operation,
+    /// erroring if they have different numbers of columns or incompatible datatypes.
+    fn set_datatypes(
+        &self,
+        operator: SetOperator,
+        left: &Node,
+        right: &Node,
+    ) -> Result<Vec<Option<Datatype>>> {
+        let (left, right) = (left.datatypes(self.catalog)?, right.datatypes(self.catalog)?);
+        if left.len() != right.len() {
+            return Err(Error::Value(format!(
+                "{} queries must have the same number of columns, got {} and {}",
+                operator,
+                left.len(),
+                right.len()
+            )));
+        }
+        left.into_iter()
+            .zip(right)
+            .enumerate()
+            .map(|(i, types)| match types {
+                (Some(l), Some(r)) => l.coerce(&r).map(Some).ok_or_else(|| {
+                    Error::Value(format!(
+                        "{} column {} has incompatible types {} and {}",
+                        operator,
+                        i + 1,
+                        l,
+                        r
+                    ))
+                }),
+                (l, r) => Ok(l.or(r)),
+            })
+            .collect()
+    }
+
+    /// Builds a query with CTEs, which are in scope while planning the query and are planned
+    /// where they're referenced, like views.
+    fn build_with(
+        &self,
+        recursive: bool,
+        ctes: Vec<ast::Cte>,
+        statement: ast::Statement,
+        outer: Option<&Scope>,
+    ) -> Result<(Node, Scope)> {
+        let depth = self.ctes.borrow().len();
+        let mut names = HashSet::new();
+        for ast::Cte { name, columns, query } in ctes {
+            if !names.insert(name.clone()) {
+                self.ctes.borrow_mut().truncate(depth);
+                return Err(Error::Value(format!("Duplicate CTE name {}", name)));
+            }
+            let query = Box::new(query);
+            self.ctes.borrow_mut().push(Cte::Query { name, columns, query, recursive });
+        }
+        let result = self.build_select(statement, outer);
+        self.ctes.borrow_mut().truncate(depth);
+        result
+    }
+
+    /// Builds a reference to the CTE at the given index in the CTE stack, adding its columns
+    /// to the scope under the given label. The CTE's query is planned with only the CTEs
+    /// preceding it in scope.
+    fn build_cte(&self, index: usize, label: &str, scope: &mut Scope) -> Result<Node> {
+        let (name, columns, query, recursive) = match &self.ctes.borrow()[index] {
+            Cte::Query { name, columns, query, recursive } => {
+                (name.clone(), columns.clone(), *query.clone(), *recursive)
+            }
+            Cte::WorkingTable { name, columns, datatypes } => {
+                scope.add_columns(label, columns.clone())?;
+                let datatypes = datatypes.clone();
+                return Ok(Node::WorkingTable { name: name.clone(), datatypes });
+            }
+        };
+        let hidden = self.ctes.borrow_mut().split_off(index);
+        let result = match (recursive, query) {
+            (
+                true,
+                ast::Statement::SetOperation {
+                    operator: SetOperator::Union,
+                    all,
+                    left,
+                    right,
+                    order,
+                    limit: None,
+                    offset: None,
+                },
+            ) if order.is_empty() => {
+                self.build_recursive(name.clone(), &columns, all, *left, *right)
+            }
+            (_, query) => self.build_select(query, None),
+        };
+        self.ctes.borrow_mut().extend(hidden);
+        let (node, projected) = result?;
+
+        let mut names: Vec<_> = projected.columns.into_iter().map(|(_, name)| name).collect();
+        if !columns.is_empty() {
+            if columns.len() != names.len() {
+                return Err(Error::Value(format!(
+                    "CTE {} has {} columns, but its query returns {}",
+                    name,
+                    columns.len(),
+                    names.len()
+                )));
+            }
+            names = columns.into_iter().map(Some).collect();
+        }
+        scope.add_columns(label, names)?;
+        Ok(node)
+    }
+
+    /// Builds a recursive CTE from its anchor and recursive term, i.e. the left and right
+    /// queries of its UNION. While planning the recursive term, references to the CTE read the
+    /// working table, whose columns have the anchor's datatypes. If there are no such
+    /// references, it's a plain UNION.
+    fn build_recursive(
+        &self,
+        name: String,
+        columns: &[String],
+        all: bool,
+        anchor: ast::Statement,
+        recursive: ast::Statement,
+    ) -> Result<(Node, Scope)> {
+        let (anchor, scope) = self.build_select(anchor, None)?;
+        let columns = match columns.is_empty() {
+            true => scope.columns.iter().map(|(_, name)| name.clone()).collect(),
+            false => columns.iter().cloned().map(Some).collect(),
+        };
+        let datatypes = anchor.datatypes(self.catalog)?;
+        self.ctes.borrow_mut().push(Cte::WorkingTable { name: name.clone(), columns, datatypes });
+        let result = self.build_select(recursive, None);
+        self.ctes.borrow_mut().pop();
+        let (recursive, _) = result?;
+
+        let datatypes = self.set_datatypes(SetOperator::Union, &anchor, &recursive)?;
+        let (anchor, recursive) = (Box::new(anchor), Box::new(recursive));
+        let node = match Self::reads_working_table(&recursive, &name) {
+            true => Node::Recursive { name, anchor, recursive, all, datatypes },
+            false => Node::SetOperation {
+                left: anchor,
+                right: recursive,
+                operator: SetOperator::Union,
+                all,
+                datatypes,
+            },
+        };
+        Ok((node, scope))
+    }
+
+    /// Returns true if the node tree, including subquery plans, reads the named working table.
+    fn reads_working_table(node: &Node, name: &str) -> bool {
+        match node {
+            Node::WorkingTable { name: n, .. } => n == name,
+            node => node.sources().into_iter().any(|n| Self::reads_working_table(n, name)),
+        }
+    }
+
+    /// Builds the conflict action for INSERT ON CONFLICT. The DO UPDATE expressions are bound
+    /// to the existing row's columns followed by the inserted row's columns, which can only be
+    /// referenced as EXCLUDED.column.
+    fn build_conflict(&self, table: &Table, on_conflict: ast::OnConflict) -> Result<Conflict> {
+        if let Some(column) = &on_conflict.column {
+            if table.get_primary_key()?.name != *column {
+                return Err(Error::Value(format!(
+                    "ON CONFLICT column {} is not the primary key of table {}",
+                    column, table.name
+                )));
+            }
+        }
+        let Some(set) = on_conflict.update else {
+            return Ok(Conflict::Nothing);
+        };
+        let mut scope = Scope::new();
+        scope.add_table(table, None)?;
+        scope.add_qualified("excluded", table.columns.iter().map(|c| c.name.clone()))?;
+        let set = set
+            .into_iter()
+            .map(|(column, expr)| {
+                let index = table.get_column_index(&column).map_err(|_| {
+                    Error::Value(format!("Unknown column {} in table {}", column, table.name))
+                })?;
+                Ok((index, self.bind(&scope, expr)?))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Conflict::Update(set))
+    }
+
+    /// Builds a table schema column from a column definition.
+    fn build_column(&self, column: ast::Column) -> Result<Column> {
+        let nullable = column.nullable.unwrap_or(!column.primary_key);
+        let default = match column.default {
+            Some(expr) => Some(self.evaluate_constant(expr)?),
+            None if nullable => Some(Value::Null),
+            None => None,
+        };
+        Ok(Column {
+            name: column.name,
+            datatype: column.datatype,
+            primary_key: column.primary_key,
+            nullalbe: nullable,
+            default,
+            unique: column.unique || column.primary_key,
+            reference: column.references,
+            index: column.index && !column.primary_key,
+        })
+    }
+
+    /// Builds a scan of a single table with an optional filter, for UPDATE and DELETE.
+    fn build_table_source(
+        &self,
+        table: &str,
+        r#where: Option<ast::Expression>,
+    ) -> Result<(Node, Scope)> {
+        let schema = self.catalog.must_read_table(table)?;
+        if schema.kind != TableKind::Table {
+            return Err(Error::Value(format!("Can't modify view {}", table)));
+        }
+        let mut scope = Scope::new();
+        scope.add_table(&schema, None)?;
+        let mut node = Node::Scan { table: table.into(), alias: None, filter: None };
+        if let Some(predicate) = r#where {
+            node = self.build_filter(node, &mut scope, predicate)?;
+        }
+        Ok((node, scope))
+    }
+
+    /// Builds the FROM clause. Comma-separated items are cross joined.
+    fn build_from_items(&self, items: Vec<ast::FromItem>, scope: &mut Scope) -> Result<Node> {
+        let mut node = None;
+        for item in items {
+            let mut item_scope = Scope::new();
+            let right = self.build_from_item(item, &mut item_scope)?;
+            scope.merge(item_scope)?;
+            node = Some(match node {
+                None => right,
+                Some(left) => Node::Join {
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    predicate: None,
+                    r#type: JoinType::Cross,
+                },
+            });
+        }
+        node.ok_or_else(|| Error::Internal("No FROM items".into()))
+    }
+
+    fn build_from_item(&self, item: ast::FromItem, scope: &mut Scope) -> Result<Node> {
+        Ok(match item {
+            ast::FromItem::Table { name, alias } => {
+                let label = alias.clone().unwrap_or_else(|| name.clone());
+                let cte = self.ctes.borrow().iter().rposition(|cte| cte.name() == name);
+                if let Some(index) = cte {
+                    return self.build_cte(index, &label, scope);
+                }
+                let table = self.catalog.must_read_table(&name)?;
+                if !self.references.borrow().contains(&name) {
+                    self.references.borrow_mut().push(name.clone());
+                }
+                match table.kind {
+                    TableKind::Table => {
+                        scope.add_table(&table, alias.as_deref())?;
+                        Node::Scan { table: name, alias, filter: None }
+                    }
+                    // Views are expanded into their query's plan, without CTEs in scope. The
+                    // view's own references aren't the statement's.
+                    TableKind::View { query, .. } => {
+                        let query = Parser::new(&query).parse()?;
+                        let (ctes, references) = (self.ctes.take(), self.references.take());
+                        let result = self.build_select(query, None);
+                        self.ctes.replace(ctes);
+                        self.references.replace(references);
+                        let (node, projected) = result?;
+                        let columns = projected.columns.into_iter().map(|(_, name)| name);
+                        scope.add_columns(&label, columns)?;
+                        node
+                    }
+                    // Materialized views are scanned like tables, without the row ID column.
+                    TableKind::MaterializedView { .. } => {
+                        let width = table.columns.len() - 1;
+                        let columns = table.columns.into_iter().take(width).map(|c| Some(c.name));
+                        scope.add_columns(&label, columns)?;
+                        let scan = Node::Scan { table: name, alias, filter: None };
+                        let expressions =
+                            (0..width).map(|i| (Expression::Field(i, None), None)).collect();
+                        Node::Projection { source: Box::new(scan), expressions }
+                    }
+                }
+            }
+            ast::FromItem::Join { left, right, r#type, predicate } => {
+                let left = self.build_from_item(*left, scope)?;
+                let mut right_scope = Scope::new();
+                let right = self.build_from_item(*right, &mut right_scope)?;
+                scope.merge(right_scope)?;
+                let predicate = predicate.map(|p| self.bind(scope, p)).transpose()?;
+                Node::Join { left: Box::new(left), right: Box::new(right), predicate, r#type }
+            }
+        })
+    }
+
+    /// Builds a filter for a WHERE or HAVING predicate. EXISTS, NOT EXISTS and IN subqueries in
+    /// its top-level conjuncts are decorrelated into semi and anti joins where possible. Other
+    /// subqueries are evaluated by Subquery nodes, whose columns are removed after filtering.
+    fn build_filter(
+        &self,
+        mut node: Node,
+        scope: &mut Scope,
+        predicate: ast::Expression,
+    ) -> Result<Node> {
+        let width = scope.columns.len();
+        let mut predicates = Vec::new();
+        for expr in Self::conjuncts(predicate) {
+            match Self::semi_join_type(&expr) {
+                Some(r#type) => node = self.build_semi_join(node, scope, expr, r#type)?,
+                None => predicates.push(expr),
+            }
+        }
+        let and = |lhs: ast::Expression, rhs: ast::Expression| {
+            ast::Expression::And(lhs.into(), rhs.into())
+        };
+        let Some(predicate) = predicates.into_iter().reduce(and) else {
+            return Ok(node);
+        };
+        let (source, predicate) = self.build_subqueries(node, scope, predicate)?;
+        node = Node::Filter { source: Box::new(source), predicate: self.bind(scope, predicate)? };
+        if scope.columns.len() > width {
+            let expressions = (0..width).map(|i| (scope.field(i), None)).collect();
+            node = Node::Projection { source: Box::new(node), expressions };
+            scope.truncate(width);
+        }
+        Ok(node)
+    }
+
+    /// Splits a predicate into its top-level AND operands.
+    fn conjuncts(expr: ast::Expression) -> Vec<ast::Expression> {
+        match expr {
+            ast::Expression::And(lhs, rhs) => {
+                let mut conjuncts = Self::conjuncts(*lhs);
+                conjuncts.extend(Self::conjuncts(*rhs));
+                conjuncts
+            }
+            expr => vec![expr],
+        }
+    }
+
+    /// Returns the join type for a predicate that can be decorrelated into a semi or anti
+    /// join: EXISTS, NOT EXISTS or IN with a subquery that reads from tables, has a single
+    /// column for IN, and has no aggregates, LIMIT, OFFSET or nested subqueries.
+    fn semi_join_type(expr: &ast::Expression) -> Option<JoinType> {
+        let (r#type, lhs, statement) = match expr {
+            ast::Expression::Exists(statement) => (JoinType::Semi, None, statement),
+            ast::Expression::Not(expr) => match &**expr {
+                ast::Expression::Exists(statement) => (JoinType::Anti, None, statement),
+                _ => return None,
+            },
+            ast::Expression::InSubquery(lhs, statement) => (JoinType::Semi, Some(lhs), statement),
+            _ => return None,
+        };
+        let ast::Statement::Select { select, from, r#where, group_by, having, limit, offset, .. } =
+            &**statement
+        else {
+            return None;
+        };
+        let simple = |e: &ast::Expression| !Self::is_aggregate(e) && !Self::has_subquery(e);
+        let decorrelate = !from.is_empty()
+            && group_by.is_empty()
+            && having.is_none()
+            && limit.is_none()
+            && offset.is_none()
+            && select.iter().all(|(e, _)| simple(e))
+            && r#where.iter().all(simple)
+            && lhs.is_none_or(|lhs| select.len() == 1 && !Self::has_subquery(lhs));
+        decorrelate.then_some(r#type)
+    }
+
+    /// Builds a semi or anti join of the node with an EXISTS, NOT EXISTS or IN subquery, see
+    /// semi_join_type(). The subquery's WHERE predicate becomes the join predicate, with outer
+    /// references bound to the left columns, and IN adds an equality predicate.
+    fn build_semi_join(
+        &self,
+        left: Node,
+        scope: &Scope,
+        expr: ast::Expression,
+        r#type: JoinType,
+    ) -> Result<Node> {
+        let (lhs, statement) = match expr {
+            ast::Expression::Exists(statement) => (None, statement),
+            ast::Expression::Not(expr) => match *expr {
+                ast::Expression::Exists(statement) => (None, statement),
+                expr => return Err(Error::Internal(format!("Unexpected semi join {}", expr))),
+            },
+            ast::Expression::InSubquery(lhs, statement) => (Some(lhs), statement),
+            expr => return Err(Error::Internal(format!("Unexpected semi join {}", expr))),
+        };
+        let ast::Statement::Select { select, from, r#where, .. } = *statement else {
+            return Err(Error::Internal("Expected SELECT subquery".into()));
+        };
+        let mut inner = Scope { outer: Some(Box::new(scope.clone())), ..Scope::new() };
+        let right = self.build_from_items(from, &mut inner)?;
+
+        // Right fields follow the left fields in the joined row.
+        let width = scope.columns.len();
+        let decorrelate = |expr: Expression| {
+            expr.transform(
+                &|e| match e {
+                    Expression::Field(i, label) => Ok(Expression::Field(width + i, label)),
+                    Expression::Outer(i, label) => Ok(Expression::Field(i, label)),
+                    e => Ok(e),
+                },
+                &Ok,
+            )
+        };
+        let mut select = select
+            .into_iter()
+            .map(|(e, _)| decorrelate(self.bind(&inner, e)?))
+            .collect::<Result<Vec<_>>>()?;
+        let mut predicates = Vec::new();
+        if let Some(lhs) = lhs {
+            let lhs = self.bind(scope, *lhs)?;
+            predicates.push(Expression::Equal(lhs.into(), select.remove(0).into()));
+        }
+        if let Some(predicate) = r#where {
+            predicates.push(decorrelate(self.bind(&inner, predicate)?)?);
+        }
+        let predicate = predicates.into_iter().reduce(|l, r| Expression::And(l.into(), r.into()));
+        Ok(Node::Join { left: Box::new(left), right: Box::new(right), predicate, r#type })
+    }
+
+    /// Builds Subquery nodes on top of the node for the subqueries in an expression, which
+    /// append their results to the scope's columns, and replaces the subqueries with
+    /// references to these columns. The rest of the expression is left unbound.
+    fn build_subqueries(
+        &self,
+        node: Node,
+        scope: &mut Scope,
+        expr: ast::Expression,
+    ) -> Result<(Node, ast::Expression)> {
+        let state = RefCell::new((node, scope));
+        let expr = expr.transform(
+            &|e| {
+                let mut state = state.borrow_mut();
+                let (node, scope) = &mut *state;
+                let (statement, kind) = match e {
+                    ast::Expression::Exists(statement) => (statement, SubqueryKind::Exists),
+                    ast::Expression::InSubquery(lhs, statement) => {
+                        let source = std::mem::replace(node, Node::Nothing);
+                        let (source, lhs) = self.build_subqueries(source, scope, *lhs)?;
+                        *node = source;
+                        (statement, SubqueryKind::In(self.bind(scope, lhs)?))
+                    }
+                    ast::Expression::Subquery(statement) => (statement, SubqueryKind::Scalar),
+                    e => return Ok(e),
+                };
+                let (subquery, projected) = self.build_select(*statement, Some(scope))?;
+                let width = projected.columns.len();
+                if width != 1 && kind != SubqueryKind::Exists {
+                    return Err(Error::Value(format!(
+                        "Subquery returns {} columns, expected 1",
+                        width
+                    )));
+                }
+                let source = Box::new(std::mem::replace(node, Node::Nothing));
+                *node = Node::Subquery { source, subquery: Box::new(subquery), kind };
+                scope.add_column(None, None);
+                Ok(ast::Expression::Field(scope.columns.len() - 1, None))
+            },
+            &Ok,
+        )?;
+        Ok((state.into_inner().0, expr))
+    }
+
+    /// Returns true if the expression contains a subquery.
+    fn has_subquery(expr: &ast::Expression) -> bool {
+        expr.contains(&|e| {
+            matches!(
+                e,
+                ast::Expression::Exists(_)
+                    | ast::Expression::InSubquery(_, _)
+                    | ast::Expression::Subquery(_)
+            )
+        })
+    }
+
+    /// Builds Window nodes on top of the node for the window functions in an expression, which
+    /// append their results to the scope's columns, and replaces the window functions with
+    /// references to these columns. Each Window node is preceded by an Order node which sorts
+    /// the rows by the window's partition and order keys.
+    fn build_windows(
+        &self,
+        node: Node,
+        scope: &mut Scope,
+        expr: ast::Expression,
+    ) -> Result<(Node, ast::Expression)> {
+        let state = RefCell::new((node, scope));
+        let expr = expr.transform(
+            &|e| {
+                let ast::Expression::Over(function, window) = e else {
+                    return Ok(e);
+                };
+                let mut state = state.borrow_mut();
+                let (node, scope) = &mut *state;
+                let ast::Expression::Function(name, args, distinct) = *function else {
+                    return Err(Error::Internal(format!("Expected function, got {}", function)));
+                };
+                let function = WindowFunction::lookup(&name).ok_or_else(|| {
+                    Error::Value(format!("Unknown window function {}", name.to_uppercase()))
+                })?;
+                let arity = match function {
+                    WindowFunction::Aggregate(_) => 1..=1,
+                    WindowFunction::Lag | WindowFunction::Lead => 1..=3,
+                    _ => 0..=0,
+                };
+                if !arity.contains(&args.len()) {
+                    let expected = match arity.start() == arity.end() {
+                        true => arity.start().to_string(),
+                        false => format!("{} to {}", arity.start(), arity.end()),
+                    };
+                    return Err(Error::Value(format!(
+                        "Window function {} takes {} arguments, got {}",
+                        function,
+                        expected,
+                        args.len()
+                    )));
+                }
+                if let Some(frame) = window.frame {
+                    use ast::FrameBound::*;
+                    if frame.start.offset() > frame.end.offset()
+                        || frame.start == UnboundedFollowing
+                        || frame.end == UnboundedPreceding
+                    {
+                        return Err(Error::Value(format!("Invalid window frame {}", frame)));
+                    }
+                }
+                if distinct {
+                    return Err(Error::Value(format!("DISTINCT is not supported for {}", function)));
+                }
+                let args =
+                    args.into_iter().map(|arg| self.bind(scope, arg)).collect::<Result<_>>()?;
+                let ast::Window { partition_by, order, frame } = *window;
+                let partition_by = partition_by
+                    .into_iter()
+                    .map(|e| self.bind(scope, e))
+                    .collect::<Result<Vec<_>>>()?;
+                let order = order
+                    .into_iter()
+                    .map(|(e, d, n)| Ok((self.bind(scope, e)?, d, n)))
+                    .collect::<Result<Vec<_>>>()?;
+
+                let mut source = std::mem::replace(node, Node::Nothing);
+                let orders: Vec<_> = partition_by
+                    .iter()
+                    .map(|e| (e.clone(), Direction::Ascending, Nulls::First))
+                    .chain(order.iter().cloned())
+                    .collect();
+                if !orders.is_empty() {
+                    source = Node::Order { source: Box::new(source), orders, limit: None };
+                }
+                *node = Node::Window {
+                    source: Box::new(source),
+                    function,
+                    args,
+                    partition_by,
+                    order,
+                    frame,
+                };
+                scope.add_column(None, None);
+                Ok(ast::Expression::Field(scope.columns.len() - 1, None))
+            },
+            &Ok,
+        )?;
+        Ok((state.into_inner().0, expr))
+    }
+
+    /// Returns true if the expression contains a window function.
+    fn has_window(expr: &ast::Expression) -> bool {
+        expr.contains(&|e| matches!(e, ast::Expression::Over(_, _)))
+    }
+
+    /// Builds an Aggregate node, and rewrites the SELECT, HAVING and ORDER BY expressions to
+    /// refer to its output: the GROUP BY values followed by the aggregate values. The scope is
+    /// replaced by the aggregate output scope.
+    fn build_aggregate(
+        &self,
+        source: Node,
+        scope: &mut Scope,
+        group_by: Vec<ast::Expression>,
+        select: &mut [(ast::Expression, Option<String>)],
+        having: &mut Option<ast::Expression>,
+        order: &mut [(ast::Expression, Direction, Nulls)],
+    ) -> Result<Node> {
+        // GROUP BY may refer to SELECT aliases.
+        let group_by = group_by
+            .into_iter()
+            .map(|expr| {
+                let expr = match &expr {
+                    ast::Expression::Column(None, name) if scope.resolve(None, name).is_err() =>
+                    {
+                        match select.iter().find(|(_, alias)| alias.as_ref() == Some(name)) {
+                            Some((expr, _)) => expr.clone(),
+                            None => expr,
+                        }
+                    }
+                    _ => expr,
+                };
+                if Self::is_aggregate(&expr) {
+                    return Err(Error::Value(
+                        "Aggregate functions are not allowed in GROUP BY".into(),
+                    ));
+                }
+                self.bind(scope, expr)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut output =
+            Scope { tables: scope.tables.clone(), outer: scope.outer.clone(), ..Scope::new() };
+        for expr in &group_by {
+            match expr {
+                Expression::Field(i, _) => {
+                    let (table, name) = scope.columns[*i].clone();
+                    output.add_column(table, name);
+                }
+                _ => output.add_column(None, None),
+            }
+        }
+
+        // Replace aggregates and GROUP BY expressions with references to the aggregate output.
+        // Any remaining column references must be to GROUP BY columns.
+        let aggregates = RefCell::new(Vec::<(Aggregate, Expression)>::new());
+        let rewrite = |expr: ast::Expression| {
+            self.rewrite_aggregates(expr, scope, &output, &group_by, &aggregates)
+        };
+        let check = |expr: &ast::Expression| Self::check_grouped(expr, scope, &output);
+
+        for (expr, _) in select.iter_mut() {
+            *expr = rewrite(std::mem::replace(expr, ast::Expression::Constant(Value::Null)))?;
+            check(expr)?;
+        }
+        if let Some(expr) = having {
+            *expr = rewrite(std::mem::replace(expr, ast::Expression::Constant(Value::Null)))?;
+            check(expr)?;
+        }
+        for (expr, _, _) in order.iter_mut() {
+            *expr = rewrite(std::mem::replace(expr, ast::Expression::Constant(Value::Null)))?;
+            // Bare fields may be SELECT aliases, which are resolved later.
+            if !matches!(expr, ast::Expression::Column(None, name)
+                if select.iter().any(|(_, alias)| alias.as_ref() == Some(name)))
+            {
+                check(expr)?;
+            }
+        }
+
+        let aggregates = aggregates.into_inner();
+        for _ in &aggregates {
+            output.add_column(None, None);
+        }
+        *scope = output;
+        Ok(Node::Aggregate { source: Box::new(source), group_by, aggregates })
+    }
+
+    /// Replaces aggregate function calls and GROUP BY expressions in an expression with
+    /// references to the aggregate output, adding the aggregates to the given list. Window
+    /// functions are evaluated on the aggregate output, so their arguments and window
+    /// specifications are rewritten too.
+    fn rewrite_aggregates(
+        &self,
+        expr: ast::Expression,
+        scope: &Scope,
+        output: &Scope,
+        group_by: &[Expression],
+        aggregates: &RefCell<Vec<(Aggregate, Expression)>>,
+    ) -> Result<ast::Expression> {
+        expr.transform(
+            &|e| {
+                if let ast::Expression::Over(function, window) = e {
+                    return Self::map_window(*function, *window, &|e| {
+                        self.rewrite_aggregates(e, scope, output, group_by, aggregates)
+                    });
+                }
+                if let ast::Expression::Function(name, args, distinct) = &e {
+                    if let Some(aggregate) = Aggregate::lookup(name) {
+                        if args.len() != 1 {
+                            return Err(Error::Value(format!(
+                                "Aggregate function {} takes 1 argument, got {}",
+                                aggregate,
+                                args.len()
+                            )));
+                        }
+                        let aggregate = match (aggregate, distinct) {
+                            (Aggregate::Count { .. }, true) => Aggregate::Count { distinct: true },
+                            (_, true) => {
+                                return Err(Error::Value(format!(
+                                    "DISTINCT is not supported for {}",
+                                    aggregate
+                                )))
+                            }
+                            (aggregate, false) => aggregate,
+                        };
+                        let arg = &args[0];
+                        if Self::is_aggregate(arg) {
+                            return Err(Error::Value("Aggregate functions can't be nested".into()));
+                        }
+                        let arg = self.bind(scope, arg.clone())?;
+                        let mut aggregates = aggregates.borrow_mut();
+                        let index = match aggregates
+                            .iter()
+                            .position(|(a, e)| *a == aggregate && e == &arg)
+                        {
+                            Some(index) => index,
+                            None => {
+                                aggregates.push((aggregate, arg));
+                                aggregates.len() - 1
+                            }
+                        };
+                        // The aggregate output is labelled by its bound argument, so COUNT(*)
+                        // takes its label from the call instead, as it's bound to TRUE.
+                        let label = matches!(args[0], ast::Expression::All)
+                            .then(|| (None, e.to_string()));
+                        return Ok(ast::Expression::Field(group_by.len() + index, label));
+                    }
+                }
+                if matches!(e, ast::Expression::Constant(_)) {
+                    return Ok(e);
+                }
+                match self.bind(scope, e.clone()) {
+                    Ok(bound) => match group_by.iter().position(|g| g == &bound) {
+                        Some(i) => Ok(ast::Expression::Field(i, output.label(i))),
+                        None => Ok(e),
+                    },
+                    Err(_) => Ok(e),
+                }
+            },
+            &Ok,
+        )
+    }
+
+    /// Checks that an expression rewritten by rewrite_aggregates() only refers to columns of
+    /// the aggregate output, i.e. that other source columns are grouped or aggregated.
+    fn check_grouped(expr: &ast::Expression, scope: &Scope, output: &Scope) -> Result<()> {
+        expr.clone()
+            .transform(
+                &|e| match &e {
+                    ast::Expression::Column(table, name)
+                        if output.resolve(table.as_deref(), name).is_err()
+                            && scope.resolve(table.as_deref(), name).is_ok() =>
+                    {
+                        Err(Error::Value(format!(
+                            "Column {} must appear in GROUP BY or be used in an aggregate \
+                             function",
+                            e
+                        )))
+                    }
+                    ast::Expression::Over(function, window) => {
+                        for expr in Self::window_expressions(function, window) {
+                            Self::check_grouped(expr, scope, output)?;
+                        }
+                        Ok(e)
+                    }
+                    _ => Ok(e),
+                },
+                &Ok,
+            )
+            .map(|_| ())
+    }
+
+    /// Returns true if the expression contains an aggregate function call, including in the
+    /// arguments and window specifications of window functions.
+    fn is_aggregate(expr: &ast::Expression) -> bool {
+        expr.contains(&|e| match e {
+            ast::Expression::Function(name, _, _) => Aggregate::lookup(name).is_some(),
+            ast::Expression::Over(function, window) => {
+                Self::window_expressions(function, window).any(Self::is_aggregate)
+            }
+            _ => false,
+        })
+    }
+
+    /// Returns the arguments of a window function, followed by its PARTITION BY and ORDER BY
+    /// expressions.
+    fn window_expressions<'e>(
+        function: &'e ast::Expression,
+        window: &'e ast::Window,
+    ) -> impl Iterator<Item = &'e ast::Expression> {
+        let args = match function {
+            ast::Expression::Function(_, args, _) => args.as_slice(),
+            _ => &[],
+        };
+        args.iter().chain(&window.partition_by).chain(window.order.iter().map(|(e, _, _)| e))
+    }
+
+    /// Maps the expressions of a window function, see window_expressions(), returning the
+    /// rebuilt window function.
+    fn map_window(
+        function: ast::Expression,
+        window: ast::Window,
+        f: &dyn Fn(ast::Expression) -> Result<ast::Expression>,
+    ) -> Result<ast::Expression> {
+        let function = match function {
+            ast::Expression::Function(name, args, distinct) => {
+                let args = args.into_iter().map(f).collect::<Result<_>>()?;
+                ast::Expression::Function(name, args, distinct)
+            }
+            function => function,
+        };
+        let ast::Window { partition_by, order, frame } = window;
+        let window = ast::Window {
+            partition_by: partition_by.into_iter().map(f).collect::<Result<_>>()?,
+            order: order.into_iter().map(|(e, d, n)| Ok((f(e)?, d, n))).collect::<Result<_>>()?,
+            frame,
+        };
+        Ok(ast::Expression::Over(Box::new(function), Box::new(window)))
+    }
+
+    /// Binds column references in the expression to column indexes in the scope, or in the
+    /// outer scope for correlated subqueries, and checks function calls, building the storage
+    /// expression. Aggregate functions, subqueries and window functions must already have been
+    /// replaced.
+    fn bind(&self, scope: &Scope, expr: ast::Expression) -> Result<Expression> {
+        use ast::Expression as Ast;
+        let bind = |expr: Box<Ast>| self.bind(scope, *expr).map(Box::new);
+        Ok(match expr {
+            Ast::Constant(value) => Expression::Constant(value),
+            Ast::Column(table, name) => scope.bind_field(table, name)?,
+            Ast::Field(index, label) => Expression::Field(index, label),
+            Ast::Parameter(index) => Expression::Parameter(index),
+            Ast::All => Expression::Constant(Value::Boolean(true)),
+
+            Ast::And(lhs, rhs) => Expression::And(bind(lhs)?, bind(rhs)?),
+            Ast::Not(expr) => Expression::Not(bind(expr)?),
+            Ast::Or(lhs, rhs) => Expression::Or(bind(lhs)?, bind(rhs)?),
+
+            Ast::Between(expr, low, high) => {
+                Expression::Between(bind(expr)?, bind(low)?, bind(high)?)
+            }
+            Ast::Equal(lhs, rhs) => Expression::Equal(bind(lhs)?, bind(rhs)?),
+            Ast::GreaterThan(lhs, rhs) => Expression::GreaterThan(bind(lhs)?, bind(rhs)?),
+            Ast::In(expr, list) => Expression::In(
+                bind(expr)?,
+                list.into_iter().map(|e| self.bind(scope, e)).collect::<Result<_>>()?,
+            ),
+            Ast::IsDistinctFrom(lhs, rhs) => Expression::IsDistinctFrom(bind(lhs)?, bind(rhs)?),
+            Ast::IsNull(expr) => Expression::IsNull(bind(expr)?),
+            Ast::LessThan(lhs, rhs) => Expression::LessThan(bind(lhs)?, bind(rhs)?),
+
+            Ast::Add(lhs, rhs) => Expression::Add(bind(lhs)?, bind(rhs)?),
+            Ast::Assert(expr) => Expression::Assert(bind(expr)?),
+            Ast::Cast(expr, datatype) => Expression::Cast(bind(expr)?, datatype),
+            Ast::Divide(lhs, rhs) => Expression::Divide(bind(lhs)?, bind(rhs)?),
+            Ast::Exponentiate(lhs, rhs) => Expression::Exponentiate(bind(lhs)?, bind(rhs)?),
+            Ast::Factorial(expr) => Expression::Factorial(bind(expr)?),
+            Ast::Modulo(lhs, rhs) => Expression::Modulo(bind(lhs)?, bind(rhs)?),
+            Ast::Multiply(lhs, rhs) => Expression::Multiply(bind(lhs)?, bind(rhs)?),
+            Ast::Negate(expr) => Expression::Negate(bind(expr)?),
+            Ast::Subtract(lhs, rhs) => Expression::Subtract(bind(lhs)?, bind(rhs)?),
+
+            Ast::ILike(lhs, rhs) => Expression::ILike(bind(lhs)?, bind(rhs)?, Default::default()),
+            Ast::Like(lhs, rhs) => Expression::Like(bind(lhs)?, bind(rhs)?, Default::default()),
+            Ast::Regexp(lhs, rhs) => {
+                Expression::Regexp(bind(lhs)?, bind(rhs)?, Default::default())
+            }
+
+            Ast::Function(name, args, distinct) => {
+                if Aggregate::lookup(&name).is_some() {
+                    return Err(Error::Value(format!(
+                        "Aggregate function {} is not allowed here",
+                        name.to_uppercase()
+                    )));
+                }
+                if distinct {
+                    return Err(Error::Value("DISTINCT is only allowed in COUNT".into()));
+                }
+                Function::lookup(&name)?.check_arity(args.len())?;
+                let args = args.into_iter().map(|e| self.bind(scope, e)).collect::<Result<_>>()?;
+                Expression::Function(name, args)
+            }
+            Ast::Case(operand, whens, default) => Expression::Case(
+                operand.map(bind).transpose()?,
+                whens
+                    .into_iter()
+                    .map(|(when, then)| Ok((self.bind(scope, when)?, self.bind(scope, then)?)))
+                    .collect::<Result<_>>()?,
+                default.map(bind).transpose()?,
+            ),
+
+            Ast::Exists(_) | Ast::InSubquery(_, _) | Ast::Subquery(_) => {
+                return Err(Error::Value("Subqueries are not allowed here".into()))
+            }
+            Ast::Over(_, _) => {
+                return Err(Error::Value("Window functions are not allowed here".into()))
+            }
+        })
+    }
+
+    /// Evaluates a constant expression, which can't refer to any columns.
+    pub fn evaluate_constant(&self, expr: ast::Expression) -> Result<Value> {
+        self.bind(&Scope::new(), expr)?.evaluate(None)
+    }
+
+    /// Evaluates a constant LIMIT or OFFSET expression, which must be a non-negative integer.
+    fn evaluate_count(&self, name: &str, expr: ast::Expression) -> Result<usize> {
+        match self.evaluate_constant(expr)? {
+            Value::Integer(i) if i >= 0 => Ok(i as usize),
+            value => Err(Error::Value(format!("Invalid {} {}", name, value))),
+        }
+    }
+}
+
+/// The names visible to expressions at a point in the plan, mapping table and column names to
+/// column indexes in the rows emitted by the current node.
+#[derive(Clone, Debug)]
+struct Scope {
+    /// The table names and aliases in scope.
+    tables: HashSet<String>,
+    /// The (table, column) labels of each column, if any.
+    columns: Vec<(Option<String>, Option<String>)>,
+    /// Qualified column names.
+    qualified: HashMap<(String, String), usize>,
+    /// Unqualified column names.
+    unqualified: HashMap<String, usize>,
+    /// Unqualified column names that refer to several columns.
+    ambiguous: HashSet<String>,
+    /// The scope of the enclosing query, for subqueries.
+    outer: Option<Box<Scope>>,
+}
+
+impl Scope {
+    fn new() -> Self {
+        Scope {
+            tables: HashSet::new(),
+            columns: Vec::new(),
+            qualified: HashMap::new(),
+            unqualified: HashMap::new(),
+            ambiguous: HashSet::new(),
+            outer: None,
+        }
+    }
+
+    /// Adds a table's columns to the scope, under its alias if given.
+    fn add_table(&mut self, table: &Table, alias: Option<&str>) -> Result<()> {
+        let columns = table.columns.iter().map(|c| Some(c.name.clone()));
+        self.add_columns(alias.unwrap_or(&table.name), columns)
+    }
+
+    /// Adds columns to the scope under the given table label, e.g. for a view.
+    fn add_columns(
+        &mut self,
+        label: &str,
+        columns: impl IntoIterator<Item = Option<String>>,
+    ) -> Result<()> {
+        if !self.tables.insert(label.into()) {
+            return Err(Error::Value(format!("Duplicate table name {}", label)));
+        }
+        for name in columns {
+            self.add_column(Some(label.into()), name);
+        }
+        Ok(())
+    }
+
+    /// Adds columns to the scope that can only be referenced qualified by the table label.
+    fn add_qualified(
+        &mut self,
+        label: &str,
+        columns: impl IntoIterator<Item = String>,
+    ) -> Result<()> {
+        if !self.tables.insert(label.into()) {
+            return Err(Error::Value(format!("Duplicate table name {}", label)));
+        }
+        for name in columns {
+            self.qualified.insert((label.into(), name.clone()), self.columns.len());
+            self.columns.push((Some(label.into()), Some(name)));
+        }
+        Ok(())
+    }
+
+    fn add_column(&mut self, table: Option<String>, name: Option<String>) {
+        let index = self.columns.len();
+        if let Some(name) = &name {
+            if let Some(table) = &table {
+                self.qualified.insert((table.clone(), name.clone()), index);
+            }
+            if self.unqualified.insert(name.clone(), index).is_some() {
+                self.ambiguous.insert(name.clone());
+            }
+        }
+        self.columns.push((table, name));
+    }
+
+    /// Appends the columns of another scope, e.g. the right side of a join.
+    fn merge(&mut self, other: Scope) -> Result<()> {
+        for table in other.tables {
+            if !self.tables.insert(table.clone()) {
+                return Err(Error::Value(format!("Duplicate table name {}", table)));
+            }
+        }
+        for (table, name) in other.columns {
+            self.add_column(table, name);
+        }
+        Ok(())
+    }
+
+    /// Resolves a column reference to a column index.
+    fn resolve(&self, table: Option<&str>, name: &str) -> Result<usize> {
+        match table {
+            Some(table) => {
+                if !self.tables.contains(table) {
+                    return Err(Error::Value(format!("Unknown table {}", table)));
+                }
+                self.qualified
+                    .get(&(table.into(), name.into()))
+                    .copied()
+                    .ok_or_else(|| Error::Value(format!("Unknown column {}.{}", table, name)))
+            }
+            None if self.ambiguous.contains(name) => {
+                Err(Error::Value(format!("Ambiguous column name {}", name)))
+            }
+            None => self
+                .unqualified
+                .get(name)
+                .copied()
+                .ok_or_else(|| Error::Value(format!("Unknown column {}", name))),
+        }
+    }
+
+    /// Binds a column reference to a field in the scope, or else to an outer reference to a
+    /// field in the outer scope.
+    fn bind_field(&self, table: Option<String>, name: String) -> Result<Expression> {
+        let err = match self.resolve(table.as_deref(), &name) {
+            Ok(index) => {
+                let table = table.or_else(|| self.columns[index].0.clone());
+                return Ok(Expression::Field(index, Some((table, name))));
+            }
+            Err(err) => err,
+        };
+        match &self.outer {
+            Some(outer) if table.is_some() || !self.ambiguous.contains(&name) => {
+                match outer.resolve(table.as_deref(), &name) {
+                    Ok(index) => {
+                        let table = table.or_else(|| outer.columns[index].0.clone());
+                        Ok(Expression::Outer(index, Some((table, name))))
+                    }
+                    Err(_) => Err(err),
+                }
+            }
+            _ => Err(err),
+        }
+    }
+
+    /// Removes the columns from the given index onwards.
+    fn truncate(&mut self, width: usize) {
+        let columns = std::mem::take(&mut self.columns);
+        self.qualified.clear();
+        self.unqualified.clear();
+        self.ambiguous.clear();
+        for (table, name) in columns.into_iter().take(width) {
+            self.add_column(table, name);
+        }
+    }
+
+    /// Returns the (table, column) label of a column, if it has a name.
+    fn label(&self, index: usize) -> Option<(Option<String>, String)> {
+        let (table, name) = &self.columns[index];
+        name.clone().map(|name| (table.clone(), name))
+    }
+
+    /// Returns a bound field expression for a column.
+    fn field(&self, index: usize) -> Expression {
+        Expression::Field(index, self.label(index))
+    }
+
+    /// Returns the scope of a projection's output.
+    fn project(&self, expressions: &[(Expression, Option<String>)]) -> Self {
+        let mut scope =
+            Scope { tables: self.tables.clone(), outer: self.outer.clone(), ..Scope::new() };
+        for (expr, alias) in expressions {
+            match (alias, expr) {
+                (Some(alias), _) => scope.add_column(None, Some(alias.clone())),
+                (None, Expression::Field(_, Some((table, name)))) => {
+                    scope.add_column(table.clone(), Some(name.clone()))
+                }
+                (None, _) => scope.add_column(None, None),
+            }
+        }
+        scope
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::super::parser::Parser;
+    use super::super::super::storage::{Datatype, Kv, Memory, Mode, Mvcc, Transaction};
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn setup() -> Result<Kv> {
+        let kv = Kv::new(Mvcc::new(Box::new(Memory::new())));
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        for query in [
+            "CREATE TABLE studios (id INTEGER PRIMARY KEY, name STRING NOT NULL)",
+            "CREATE TABLE movies (
+                id INTEGER PRIMARY KEY,
+                title STRING NOT NULL,
+                studio_id INTEGER REFERENCES studios INDEX,
+                rating FLOAT
+            )",
+        ] {
+            match plan(&txn, query)? {
+                Node::CreateTable { schema } => txn.create_table(schema)?,
+                node => panic!("Unexpected node {:?}", node),
+            }
+        }
+        txn.commit()?;
+        Ok(kv)
+    }
+
+    fn plan<C: Catalog>(catalog: &C, query: &str) -> Result<Node> {
+        Ok(Plan::build(Parser::new(query).parse()?, catalog)?.0)
+    }
+
+    fn field(index: usize, table: &str, name: &str) -> Expression {
+        Expression::Field(index, Some((Some(table.into()), name.into())))
+    }
+
+    fn scan(table: &str, alias: Option<&str>) -> Box<Node> {
+        Box::new(Node::Scan { table: table.into(), alias: alias.map(|a| a.into()), filter: None })
+    }
+
+    #[test]
+    fn create_table() -> Result<()> {
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        let movies = txn.must_read_table("movies")?;
+        let column = |name: &str,
+                      datatype,
+                      primary_key,
+                      nullalbe,
+                      default,
+                      reference: Option<&str>,
+                      index| Column {
+            name: name.into(),
+            datatype,
+            primary_key,
+            nullalbe,
+            default,
+            unique: primary_key,
+            reference: reference.map(|r| r.into()),
+            index,
+        };
+        assert_eq!(
+            movies.columns,
+            vec![
+                column("id", Datatype::Integer, true, false, None, None, false),
+                column("title", Datatype::String, false, false, None, None, false),
+                column(
+                    "studio_id",
+                    Datatype::Integer,
+                    false,
+                    true,
+                    Some(Value::Null),
+                    Some("studios"),
+                    true
+                ),
+                column("rating", Datatype::Float, false, true, Some(Value::Null), None, false),
+            ]
+        );
+        let three = Value::Integer(3);
+        assert_eq!(
+            plan(&txn, "CREATE TABLE t (id INT PRIMARY KEY, n INT DEFAULT 1 + 2)")?,
+            Node::CreateTable {
+                schema: Table::new(
+                    "t".into(),
+                    vec![
+                        column("id", Datatype::Integer, true, false, None, None, false),
+                        column("n", Datatype::Integer, false, true, Some(three), None, false),
+                    ]
+                )
+            }
+        );
+        assert_eq!(
+            plan(&txn, "CREATE TABLE t (id INT PRIMARY KEY DEFAULT x)"),
+            Err(Error::Value("Unknown column x".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn select_join() -> Result<()> {
+        use Expression::*;
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        assert_eq!(
+            plan(
+                &txn,
+                "SELECT m.title, name AS studio FROM movies m LEFT JOIN studios s \
+                 ON m.studio_id = s.id WHERE rating > 3"
+            )?,
+            Node::Projection {
+                source: Box::new(Node::Filter {
+                    source: Box::new(Node::Join {
+                        left: scan("movies", Some("m")),
+                        right: scan("studios", Some("s")),
+                        predicate: Some(Equal(
+                            field(2, "m", "studio_id").into(),
+                            field(4, "s", "id").into()
+                        )),
+                        r#type: JoinType::Left,
+                    }),
+                    predicate: GreaterThan(
+                        field(3, "m", "rating").into(),
+                        Constant(Value::Integer(3)).into()
+                    ),
+                }),
+                expressions: vec![
+                    (field(1, "m", "title"), None),
+                    (field(5, "s", "name"), Some("studio".into())),
+                ],
+            }
+        );
+        assert_eq!(
+            plan(&txn, "SELECT * FROM studios, movies LIMIT 2 OFFSET 1")?,
+            Node::Limit {
+                source: Box::new(Node::Projection {
+                    source: Box::new(Node::Join {
+                        left: scan("studios", None),
+                        right: scan("movies", None),
+                        predicate: None,
+                        r#type: JoinType::Cross,
+                    }),
+                    expressions: vec![
+                        (field(0, "studios", "id"), None),
+                        (field(1, "studios", "name"), None),
+                        (field(2, "movies", "id"), None),
+                        (field(3, "movies", "title"), None),
+                        (field(4, "movies", "studio_id"), None),
+                        (field(5, "movies", "rating"), None),
+                    ],
+                }),
+                offset: 1,
+                limit: Some(2),
+            }
+        );
+        assert_eq!(
+            plan(&txn, "SELECT 1 + 1 AS two")?,
+            Node::Projection {
+                source: Box::new(Node::Nothing),
+                expressions: vec![(
+                    Add(Constant(Value::Integer(1)).into(), Constant(Value::Integer(1)).into()),
+                    Some("two".into())
+                )],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn select_order() -> Result<()> {
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        // Ordering by an unselected column projects it as a hidden column. A LIMIT is pushed
+        // into the sort, including the offset.
+        assert_eq!(
+            plan(
+                &txn,
+                "SELECT title AS t, id FROM movies ORDER BY rating DESC NULLS FIRST, t, 2 \
+                 LIMIT 2 OFFSET 1"
+            )?,
+            Node::Limit {
+                source: Box::new(Node::Projection {
+                    source: Box::new(Node::Order {
+                        source: Box::new(Node::Projection {
+                            source: scan("movies", None),
+                            expressions: vec![
+                                (field(1, "movies", "title"), Some("t".into())),
+                                (field(0, "movies", "id"), None),
+                                (field(3, "movies", "rating"), None),
+                            ],
+                        }),
+                        orders: vec![
+                            (Expression::Field(2, None), Direction::Descending, Nulls::First),
+                            (Expression::Field(0, None), Direction::Ascending, Nulls::First),
+                            (Expression::Field(1, None), Direction::Ascending, Nulls::First),
+                        ],
+                        limit: Some(3),
+                    }),
+                    expressions: vec![
+                        (Expression::Field(0, None), Some("t".into())),
+                        (Expression::Field(1, None), Some("id".into())),
+                    ],
+                }),
+                offset: 1,
+                limit: Some(2),
+            }
+        );
+        assert_eq!(
+            plan(&txn, "SELECT id FROM movies ORDER BY id DESC OFFSET 2")?,
+            Node::Limit {
+                source: Box::new(Node::Order {
+                    source: Box::new(Node::Projection {
+                        source: scan("movies", None),
+                        expressions: vec![(field(0, "movies", "id"), None)],
+                    }),
+                    orders: vec![(Expression::Field(0, None), Direction::Descending, Nulls::Last)],
+                    limit: None,
+                }),
+                offset: 2,
+                limit: None,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn explain() -> Result<()> {
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        assert_eq!(
+            plan(&txn, "EXPLAIN ANALYZE DROP TABLE movies")?,
+            Node::Explain {
+                source: Box::new(Node::DropTable { table: "movies".into() }),
+                analyze: true,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn select_aggregate() -> Result<()> {
+        use Expression::*;
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        let count = Constant(Value::Boolean(true));
+        let count_label = Some((None, "COUNT(*)".to_string()));
+        assert_eq!(
+            plan(
+                &txn,
+                "SELECT studio_id, COUNT(*) AS n, MAX(rating) + 1 FROM movies \
+                 GROUP BY studio_id HAVING COUNT(*) > 1 ORDER BY MAX(rating) DESC"
+            )?,
+            Node::Order {
+                source: Box::new(Node::Projection {
+                    source: Box::new(Node::Filter {
+                        source: Box::new(Node::Aggregate {
+                            source: scan("movies", None),
+                            group_by: vec![field(2, "movies", "studio_id")],
+                            aggregates: vec![
+                                (Aggregate::Count { distinct: false }, count),
+                                (Aggregate::Max, field(3, "movies", "rating")),
+                            ],
+                        }),
+                        predicate: GreaterThan(
+                            Field(1, count_label.clone()).into(),
+                            Constant(Value::Integer(1)).into()
+                        ),
+                    }),
+                    expressions: vec![
+                        (field(0, "movies", "studio_id"), None),
+                        (Field(1, count_label), Some("n".into())),
+                        (Add(Field(2, None).into(), Constant(Value::Integer(1)).into()), None),
+                        (Field(2, None), None),
+                    ],
+                }),
+                orders: vec![(Field(3, None), Direction::Descending, Nulls::Last)],
+                limit: None,
+            }
+            .into_hidden(3)
+        );
+        assert_eq!(
+            plan(&txn, "SELECT id % 2 AS parity, SUM(rating) FROM movies GROUP BY parity")?,
+            Node::Projection {
+                source: Box::new(Node::Aggregate {
+                    source: scan("movies", None),
+                    group_by: vec![Modulo(
+                        field(0, "movies", "id").into(),
+                        Constant(Value::Integer(2)).into()
+                    )],
+                    aggregates: vec![(Aggregate::Sum, field(3, "movies", "rating"))],
+                }),
+                expressions: vec![(Field(0, None), Some("parity".into())), (Field(1, None), None)],
+            }
+        );
+        assert_eq!(
+            plan(&txn, "SELECT COUNT(DISTINCT studio_id), COUNT(studio_id) FROM movies")?,
+            Node::Projection {
+                source: Box::new(Node::Aggregate {
+                    source: scan("movies", None),
+                    group_by: vec![],
+                    aggregates: vec![
+                        (Aggregate::Count { distinct: true }, field(2, "movies", "studio_id")),
+                        (Aggregate::Count { distinct: false }, field(2, "movies", "studio_id")),
+                    ],
+                }),
+                expressions: vec![(Field(0, None), None), (Field(1, None), None)],
+            }
+        );
+        Ok(())
+    }
+
+    impl Node {
+        /// Wraps the node in a projection trimming hidden ORDER BY columns, for tests.
+        fn into_hidden(self, width: usize) -> Node {
+            let labels = match &self {
+                Node::Order { source, .. } => match &**source {
+                    Node::Projection { expressions, .. } => expressions
+                        .iter()
+                        .map(|(e, a)| label(e, a))
+                        .collect::<Vec<_>>(),
+                    _ => panic!("Expected projection"),
+                },
+                _ => panic!("Expected order"),
+            };
+            Node::Projection {
+                source: Box::new(self),
+                expressions: (0..width)
+                    .map(|i| (Expression::Field(i, None), Some(labels[i].clone())))
+                    .collect(),
+            }
+        }
+    }
+
+    #[test]
+    fn mutations() -> Result<()> {
+        use Expression::*;
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        assert_eq!(
+            plan(&txn, "INSERT INTO movies (title, id) VALUES ('a', 1), ('b', 1 + 1)")?,
+            Node::Insert {
+                table: "movies".into(),
+                columns: vec!["title".into(), "id".into()],
+                source: Box::new(Node::Values {
+                    rows: vec![
+                        vec![Constant(Value::String("a".into())), Constant(Value::Integer(1))],
+                        vec![
+                            Constant(Value::String("b".into())),
+                            Add(
+                                Constant(Value::Integer(1)).into(),
+                                Constant(Value::Integer(1)).into()
+                            ),
+                        ],
+                    ],
+                }),
+                conflict: Conflict::Error,
+            }
+        );
+        assert_eq!(
+            plan(
+                &txn,
+                "INSERT INTO movies (id, title) VALUES (1, 'a')
+                 ON CONFLICT (id) DO UPDATE SET title = excluded.title, rating = rating + 1"
+            )?,
+            Node::Insert {
+                table: "movies".into(),
+                columns: vec!["id".into(), "title".into()],
+                source: Box::new(Node::Values {
+                    rows: vec![vec![
+                        Constant(Value::Integer(1)),
+                        Constant(Value::String("a".into())),
+                    ]],
+                }),
+                conflict: Conflict::Update(vec![
+                    (3, Add(
+                        Field(3, Some((Some("movies".into()), "rating".into()))).into(),
+                        Constant(Value::Integer(1)).into(),
+                    )),
+                    (1, Field(5, Some((Some("excluded".into()), "title".into())))),
+                ]),
+            }
+        );
+        assert_eq!(
+            plan(&txn, "INSERT INTO studios SELECT id, title FROM movies ON CONFLICT DO NOTHING")?,
+            Node::Insert {
+                table: "studios".into(),
+                columns: vec![],
+                source: Box::new(Node::Projection {
+                    source: scan("movies", None),
+                    expressions: vec![
+                        (Field(0, Some((Some("movies".into()), "id".into()))), None),
+                        (Field(1, Some((Some("movies".into()), "title".into()))), None),
+                    ],
+                }),
+                conflict: Conflict::Nothing,
+            }
+        );
+        assert_eq!(
+            plan(&txn, "UPDATE movies SET rating = rating * 2 WHERE id = 1")?,
+            Node::Update {
+                table: "movies".into(),
+                source: Box::new(Node::Filter {
+                    source: scan("movies", None),
+                    predicate: Equal(
+                        field(0, "movies", "id").into(),
+                        Constant(Value::Integer(1)).into()
+                    ),
+                }),
+                expressions: vec![(
+                    3,
+                    "rating".into(),
+                    Multiply(
+                        field(3, "movies", "rating").into(),
+                        Constant(Value::Integer(2)).into()
+                    )
+                )],
+            }
+        );
+        assert_eq!(
+            plan(&txn, "DELETE FROM movies")?,
+            Node::Delete { table: "movies".into(), source: scan("movies", None) }
+        );
+        assert_eq!(
+            plan(&txn, "DROP TABLE movies")?,
+            Node::DropTable { table: "movies".into() }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn subqueries() -> Result<()> {
+        use Expression::*;
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        // EXISTS is decorrelated into a semi join, with the outer reference bound to the left.
+        assert_eq!(
+            plan(
+                &txn,
+                "SELECT title FROM movies m WHERE EXISTS \
+                 (SELECT id FROM studios s WHERE s.id = m.studio_id)"
+            )?,
+            Node::Projection {
+                source: Box::new(Node::Join {
+                    left: scan("movies", Some("m")),
+                    right: scan("studios", Some("s")),
+                    predicate: Some(Equal(
+                        field(4, "s", "id").into(),
+                        field(2, "m", "studio_id").into()
+                    )),
+                    r#type: JoinType::Semi,
+                }),
+                expressions: vec![(field(1, "m", "title"), None)],
+            }
+        );
+        // Scalar subqueries are evaluated by Subquery nodes, with outer references.
+        assert_eq!(
+            plan(
+                &txn,
+                "SELECT id, (SELECT name FROM studios WHERE id = m.studio_id) FROM movies m"
+            )?,
+            Node::Projection {
+                source: Box::new(Node::Subquery {
+                    source: scan("movies", Some("m")),
+                    subquery: Box::new(Node::Projection {
+                        source: Box::new(Node::Filter {
+                            source: scan("studios", None),
+                            predicate: Equal(
+                                field(0, "studios", "id").into(),
+                                Outer(2, Some((Some("m".into()), "studio_id".into()))).into()
+                            ),
+                        }),
+                        expressions: vec![(field(1, "studios", "name"), None)],
+                    }),
+                    kind: SubqueryKind::Scalar,
+                }),
+                expressions: vec![
+                    (field(0, "m", "id"), None),
+                    (Field(4, None), Some("(subquery)".into())),
+                ],
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<()> {
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        let error = |query: &str| match plan(&txn, query) {
+            Err(Error::Value(message)) => message,
+            result => panic!("Expected error for {}, got {:?}", query, result),
+        };
+        assert_eq!(error("SELECT * FROM nope"), "Table nope does not exist");
+        assert_eq!(error("SELECT nope FROM movies"), "Unknown column nope");
+        assert_eq!(error("SELECT x.id FROM movies"), "Unknown table x");
+        assert_eq!(error("SELECT movies.nope FROM movies"), "Unknown column movies.nope");
+        assert_eq!(error("SELECT movies.id FROM movies m"), "Unknown table movies");
+        assert_eq!(
+            error("SELECT id FROM movies JOIN studios ON studio_id = studios.id"),
+            "Ambiguous column name id"
+        );
+        assert_eq!(error("SELECT * FROM movies, movies"), "Duplicate table name movies");
+        assert_eq!(error("SELECT *"), "SELECT * requires a FROM clause");
+        assert_eq!(error("EXPLAIN BEGIN"), "Can't EXPLAIN transaction statements");
+        assert_eq!(
+            error("SELECT * FROM movies WHERE COUNT(*) > 1"),
+            "Aggregate function COUNT is not allowed here"
+        );
+        assert_eq!(
+            error("SELECT title, COUNT(*) FROM movies"),
+            "Column title must appear in GROUP BY or be used in an aggregate function"
+        );
+        assert_eq!(
+            error("SELECT MAX(MIN(id)) FROM movies"),
+            "Aggregate functions can't be nested"
+        );
+        assert_eq!(
+            error("SELECT SUM(id, 1) FROM movies"),
+            "Aggregate function SUM takes 1 argument, got 2"
+        );
+        assert_eq!(
+            error("SELECT SUM(DISTINCT id) FROM movies"),
+            "DISTINCT is not supported for SUM"
+        );
+        assert_eq!(
+            error("SELECT UPPER(DISTINCT title) FROM movies"),
+            "DISTINCT is only allowed in COUNT"
+        );
+        assert_eq!(
+            error("SELECT COUNT(\"DISTINCT\"(id)) FROM movies"),
+            "Unknown function DISTINCT"
+        );
+        assert_eq!(
+            error("SELECT (SELECT id, title FROM movies)"),
+            "Subquery returns 2 columns, expected 1"
+        );
+        assert_eq!(
+            error("SELECT id FROM movies GROUP BY (SELECT 1)"),
+            "Subqueries are not allowed here"
+        );
+        assert_eq!(
+            error("SELECT id FROM movies m WHERE EXISTS (SELECT nope FROM studios)"),
+            "Unknown column nope"
+        );
+        assert_eq!(error("SELECT nope(1)"), "Unknown function nope");
+        assert_eq!(error("SELECT * FROM movies LIMIT -1"), "Invalid limit -1");
+        assert_eq!(error("SELECT * FROM movies OFFSET 'a'"), "Invalid offset a");
+        assert_eq!(
+            error("SELECT id FROM movies ORDER BY 2"),
+            "ORDER BY position 2 is not in select list"
+        );
+        assert_eq!(
+            error("INSERT INTO movies (id, nope) VALUES (1, 2)"),
+            "Unknown column nope in table movies"
+        );
+        assert_eq!(
+            error("INSERT INTO movies (id, id) VALUES (1, 2)"),
+            "Column id given multiple times"
+        );
+        assert_eq!(
+            error("INSERT INTO movies (id) VALUES (1, 2)"),
+            "Expected 1 values for table movies, got 2"
+        );
+        assert_eq!(error("INSERT INTO movies VALUES (id)"), "Unknown column id");
+        assert_eq!(
+            error("UPDATE movies SET nope = 1"),
+            "Unknown column nope in table movies"
+        );
+        Ok(())
+    }
+}
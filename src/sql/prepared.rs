@@ -0,0 +1,446 @@
+use super::execution::{Config, ResultSet};
+use super::parser::ast;
+use super::parser::Parser;
+use super::plan::{Node, Plan};
+use super::storage::{
+    self, Catalog, Conflict, Datatype, Table, TableStats, Tables, Transaction, Value,
+};
+use crate::error::{Error, Result};
+
+use std::cell::RefCell;
+
+/// A prepared statement, which is parsed and planned once and then executed with different
+/// parameter values. Parameters are given as positional ? or numbered $n placeholders, and
+/// their datatypes are inferred from the columns and values they're compared with or assigned
+/// to. The plan is cached along with the schemas of the tables it references, and is rebuilt
+/// when any of them change.
+#[derive(Clone, Debug)]
+pub struct Prepared {
+    statement: ast::Statement,
+    /// The inferred datatype of each parameter, or None if it can't be inferred.
+    parameters: Vec<Option<Datatype>>,
+    /// The tables referenced by the statement.
+    tables: Vec<String>,
+    /// The cached plan, and the schemas of the referenced tables it was built with.
+    plan: Option<(Plan, Vec<Option<Table>>)>,
+}
+
+impl Prepared {
+    /// Parses and plans a statement.
+    pub fn new<C: Catalog>(query: &str, catalog: &C) -> Result<Self> {
+        Self::from_statement(Parser::new(query).parse()?, catalog)
+    }
+
+    /// Plans a parsed statement.
+    pub fn from_statement<C: Catalog>(statement: ast::Statement, catalog: &C) -> Result<Self> {
+        match &statement {
+            ast::Statement::Begin { .. } | ast::Statement::Commit | ast::Statement::Rollback => {
+                return Err(Error::Value("Can't prepare transaction statements".into()))
+            }
+            ast::Statement::Prepare { .. }
+            | ast::Statement::Execute { .. }
+            | ast::Statement::Deallocate(_) => {
+                return Err(Error::Value("Can't prepare prepared statement commands".into()))
+            }
+            _ => {}
+        }
+        let mut prepared =
+            Prepared { statement, parameters: Vec::new(), tables: Vec::new(), plan: None };
+        prepared.plan(catalog)?;
+        Ok(prepared)
+    }
+
+    /// Returns the prepared statement.
+    pub fn statement(&self) -> &ast::Statement {
+        &self.statement
+    }
+
+    /// Returns the inferred parameter datatypes, where None accepts any value.
+    pub fn parameters(&self) -> &[Option<Datatype>] {
+        &self.parameters
+    }
+
+    /// Executes the statement with the given parameter values.
+    pub fn execute<T: Transaction>(
+        &mut self,
+        txn: &mut T,
+        values: Vec<Value>,
+    ) -> Result<ResultSet> {
+        self.execute_with(txn, values, &Config::default())
+    }
+
+    /// Executes the statement with the given parameter values and executor configuration.
+    /// The plan is optimized once the values are bound, such that they can be used for key
+    /// and index lookups.
+    pub fn execute_with<T: Transaction>(
+        &mut self,
+        txn: &mut T,
+        values: Vec<Value>,
+        config: &Config,
+    ) -> Result<ResultSet> {
+        let plan = self.plan(txn)?;
+        let values = self.check(values)?;
+        Plan(bind(plan.0, &values)?).optimize(txn)?.execute_with(txn, config)
+    }
+
+    /// Returns the cached plan, rebuilding it if a referenced table's schema has changed.
+    fn plan<C: Catalog>(&mut self, catalog: &C) -> Result<Plan> {
+        let schemas =
+            self.tables.iter().map(|t| catalog.read_table(t)).collect::<Result<Vec<_>>>()?;
+        if let Some((plan, cached)) = &self.plan {
+            if *cached == schemas {
+                return Ok(plan.clone());
+            }
+        }
+        let recorder = Recorder { catalog, tables: RefCell::new(Vec::new()) };
+        let plan = Plan::build(self.statement.clone(), &recorder)?;
+        let mut parameters = Vec::new();
+        infer(&plan.0, catalog, &mut parameters)?;
+        let tables = recorder.tables.into_inner();
+        let schemas = tables.iter().map(|t| catalog.read_table(t)).collect::<Result<_>>()?;
+        self.parameters = parameters;
+        self.tables = tables;
+        self.plan = Some((plan.clone(), schemas));
+        Ok(plan)
+    }
+
+    /// Checks parameter values against the inferred datatypes, casting integers to floats
+    /// where needed.
+    fn check(&self, values: Vec<Value>) -> Result<Vec<Value>> {
+        if values.len() != self.parameters.len() {
+            return Err(Error::Value(format!(
+                "Expected {} parameters, got {}",
+                self.parameters.len(),
+                values.len()
+            )));
+        }
+        values
+            .into_iter()
+            .zip(&self.parameters)
+            .enumerate()
+            .map(|(i, (value, datatype))| match (datatype, value.datatype()) {
+                (Some(expect), Some(actual)) if *expect != actual => {
+                    match expect.coerce(&actual) {
+                        Some(d) if d == *expect => value.cast(expect),
+                        _ => Err(Error::Value(format!(
+                            "Parameter ${} must be {}, got {}",
+                            i + 1,
+                            expect,
+                            actual
+                        ))),
+                    }
+                }
+                _ => Ok(value),
+            })
+            .collect()
+    }
+}
+
+/// Replaces parameters in the plan with their values, including in subquery plans.
+fn bind(node: Node, values: &[Value]) -> Result<Node> {
+    node.transform(&Ok, &|n| {
+        let n = match n {
+            Node::Subquery { source, subquery, kind } => {
+                Node::Subquery { source, subquery: Box::new(bind(*subquery, values)?), kind }
+            }
+            n => n,
+        };
+        n.map_expressions(&|e| {
+            e.transform(
+                &|e| match e {
+                    storage::Expression::Parameter(i) => {
+                        Ok(storage::Expression::Constant(values[i].clone()))
+                    }
+                    e => Ok(e),
+                },
+                &Ok,
+            )
+        })
+    })
+}
+
+/// Infers parameter datatypes from a plan, including subquery plans, from the datatypes of
+/// the rows each node's expressions are evaluated on, see Expression::infer_parameters().
+/// Parameters used as predicates are booleans, and parameters inserted into or assigned to a
+/// column take its datatype.
+fn infer<C: Catalog>(
+    node: &Node,
+    catalog: &C,
+    parameters: &mut Vec<Option<Datatype>>,
+) -> Result<()> {
+    let mut assign = |expr: &storage::Expression, datatype: &Datatype| {
+        if let storage::Expression::Parameter(i) = expr {
+            if parameters.len() <= *i {
+                parameters.resize(i + 1, None);
+            }
+            parameters[*i].get_or_insert(datatype.clone());
+        }
+    };
+    match node {
+        Node::Filter { predicate, .. }
+        | Node::HashJoin { predicate: Some(predicate), .. }
+        | Node::Join { predicate: Some(predicate), .. }
+        | Node::LookupJoin { predicate: Some(predicate), .. }
+        | Node::MergeJoin { predicate: Some(predicate), .. }
+        | Node::Scan { filter: Some(predicate), .. } => assign(predicate, &Datatype::Boolean),
+        Node::Insert { table, columns, source, conflict } => {
+            let table = catalog.must_read_table(table)?;
+            let targets = match columns.is_empty() {
+                true => table.columns.iter().collect(),
+                false => columns
+                    .iter()
+                    .map(|c| table.get_column(c))
+                    .collect::<Result<Vec<_>>>()?,
+            };
+            if let Node::Values { rows } = &**source {
+                for row in rows {
+                    for (expr, column) in row.iter().zip(&targets) {
+                        assign(expr, &column.datatype);
+                    }
+                }
+            }
+            if let Conflict::Update(set) = conflict {
+                for (i, expr) in set {
+                    assign(expr, &table.columns[*i].datatype);
+                }
+            }
+        }
+        Node::Update { table, expressions, .. } => {
+            let table = catalog.must_read_table(table)?;
+            for (i, _, expr) in expressions {
+                assign(expr, &table.columns[*i].datatype);
+            }
+        }
+        _ => {}
+    }
+    // Rows whose datatypes can't be inferred here, e.g. with outer references, are unknown.
+    let columns = node.input_datatypes(catalog).unwrap_or_default();
+    for expr in node.expressions() {
+        expr.infer_parameters(&columns, parameters);
+    }
+    for source in node.sources() {
+        infer(source, catalog, parameters)?;
+    }
+    Ok(())
+}
+
+/// A catalog that records the tables read from it while planning, i.e. the tables the plan
+/// depends on, including the tables referenced by views.
+struct Recorder<'a, C: Catalog> {
+    catalog: &'a C,
+    tables: RefCell<Vec<String>>,
+}
+
+impl<'a, C: Catalog> Catalog for Recorder<'a, C> {
+    fn create_table(&mut self, _: Table) -> Result<()> {
+        Err(Error::Internal("Can't modify the catalog while planning".into()))
+    }
+
+    fn delete_table(&mut self, _: &str) -> Result<()> {
+        Err(Error::Internal("Can't modify the catalog while planning".into()))
+    }
+
+    fn read_table(&self, table: &str) -> Result<Option<Table>> {
+        let mut tables = self.tables.borrow_mut();
+        if !tables.iter().any(|t| t == table) {
+            tables.push(table.to_string());
+        }
+        self.catalog.read_table(table)
+    }
+
+    fn scan_tables(&self) -> Result<Tables> {
+        self.catalog.scan_tables()
+    }
+
+    fn read_stats(&self, table: &str) -> Result<Option<TableStats>> {
+        self.catalog.read_stats(table)
+    }
+
+    fn write_stats(&mut self, _: &str, _: TableStats) -> Result<()> {
+        Err(Error::Internal("Can't modify the catalog while planning".into()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::storage::{Kv, Memory, Mode, Mvcc};
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    use Value::{Float, Integer, Null, String as Str};
+
+    fn setup() -> Result<Kv> {
+        let kv = Kv::new(Mvcc::new(Box::new(Memory::new())));
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        for query in [
+            "CREATE TABLE movies (
+                id INTEGER PRIMARY KEY,
+                title STRING NOT NULL,
+                rating FLOAT,
+                released BOOLEAN
+            )",
+            "INSERT INTO movies VALUES
+                (1, 'Stalker', 8.2, TRUE),
+                (2, 'Sicario', 7.6, TRUE),
+                (3, 'Solaris', 8, FALSE)",
+        ] {
+            Prepared::new(query, &txn)?.execute(&mut txn, vec![])?;
+        }
+        txn.commit()?;
+        Ok(kv)
+    }
+
+    #[test]
+    fn parameters() -> Result<()> {
+        use Datatype::*;
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        let parameters = |query| -> Result<Vec<Option<Datatype>>> {
+            Ok(Prepared::new(query, &txn)?.parameters().to_vec())
+        };
+
+        assert_eq!(
+            parameters("SELECT * FROM movies m WHERE id = ? AND ? < m.rating AND title LIKE ?")?,
+            vec![Some(Integer), Some(Float), Some(String)]
+        );
+        assert_eq!(
+            parameters("SELECT ?, $1 + 1, $2 * rating FROM movies")?,
+            vec![Some(Integer), Some(Float)]
+        );
+        assert_eq!(parameters("SELECT $2 FROM movies")?, vec![None, None]);
+        assert_eq!(
+            parameters("SELECT LAG(title, ?) OVER (ORDER BY id + ?) FROM movies")?,
+            vec![None, Some(Integer)]
+        );
+        assert_eq!(
+            parameters("SELECT id FROM movies WHERE id = ? UNION SELECT ? + 1.5")?,
+            vec![Some(Integer), Some(Float)]
+        );
+        assert_eq!(parameters("SELECT ? IS NULL OR ?")?, vec![None, Some(Boolean)]);
+        assert_eq!(
+            parameters("INSERT INTO movies (title, id) VALUES (?, ?), ('Ran', ? + 1)")?,
+            vec![Some(String), Some(Integer), Some(Integer)]
+        );
+        assert_eq!(
+            parameters("UPDATE movies SET rating = ? WHERE released = ?")?,
+            vec![Some(Float), Some(Boolean)]
+        );
+        assert_eq!(
+            parameters(
+                "SELECT title FROM movies m WHERE id IN (SELECT id FROM movies WHERE rating > ?)
+                 AND EXISTS (SELECT 1 FROM movies WHERE id = ? AND m.released = ?)"
+            )?,
+            vec![Some(Float), Some(Integer), Some(Boolean)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn execute() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+
+        let mut select =
+            Prepared::new("SELECT title FROM movies WHERE rating >= ? ORDER BY id", &txn)?;
+        assert_eq!(
+            select.execute(&mut txn, vec![Float(8.0)])?.into_rows()?,
+            vec![vec![Str("Stalker".into())], vec![Str("Solaris".into())]]
+        );
+        // Integers are cast to floats, and NULL is accepted for any parameter.
+        assert_eq!(
+            select.execute(&mut txn, vec![Integer(7)])?.into_rows()?,
+            vec![
+                vec![Str("Stalker".into())],
+                vec![Str("Sicario".into())],
+                vec![Str("Solaris".into())]
+            ]
+        );
+        assert_eq!(select.execute(&mut txn, vec![Null])?.into_rows()?, Vec::<Vec<Value>>::new());
+
+        let mut insert = Prepared::new("INSERT INTO movies (id, title) VALUES (?, ?)", &txn)?;
+        assert!(matches!(
+            insert.execute(&mut txn, vec![Integer(4), Str("Ran".into())])?,
+            ResultSet::Create { count: 1 }
+        ));
+        let mut update = Prepared::new("UPDATE movies SET rating = $2 WHERE id = $1", &txn)?;
+        assert!(matches!(
+            update.execute(&mut txn, vec![Integer(4), Float(8.3)])?,
+            ResultSet::Update { count: 1 }
+        ));
+        assert_eq!(
+            Prepared::new("SELECT title, rating FROM movies WHERE id = ?", &txn)?
+                .execute(&mut txn, vec![Integer(4)])?
+                .into_rows()?,
+            vec![vec![Str("Ran".into()), Float(8.3)]]
+        );
+
+        assert_eq!(
+            select.execute(&mut txn, vec![Str("8".into())]).err(),
+            Some(Error::Value("Parameter $1 must be FLOAT, got STRING".into()))
+        );
+        assert_eq!(
+            select.execute(&mut txn, vec![]).err(),
+            Some(Error::Value("Expected 1 parameters, got 0".into()))
+        );
+        assert_eq!(
+            Prepared::new("COMMIT", &txn).err(),
+            Some(Error::Value("Can't prepare transaction statements".into()))
+        );
+        assert_eq!(
+            Prepared::new("SELECT * FROM unknown WHERE id = ?", &txn).err(),
+            Some(Error::Value("Table unknown does not exist".into()))
+        );
+        assert_eq!(
+            Prepared::new("SELECT * FROM movies LIMIT ?", &txn).err(),
+            Some(Error::Value("Unbound parameter $1".into()))
+        );
+        txn.rollback()
+    }
+
+    #[test]
+    fn invalidation() -> Result<()> {
+        let kv = setup()?;
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        let mut select = Prepared::new("SELECT * FROM movies WHERE id = ?", &txn)?;
+        let cached = select.plan.clone();
+        assert_eq!(select.execute(&mut txn, vec![Integer(1)])?.into_rows()?.len(), 1);
+        assert_eq!(select.plan, cached);
+        Prepared::new("CREATE VIEW titles AS SELECT title FROM movies", &txn)?
+            .execute(&mut txn, vec![])?;
+        let mut view = Prepared::new("SELECT * FROM titles", &txn)?;
+        assert_eq!(view.execute(&mut txn, vec![])?.into_rows()?.len(), 3);
+        txn.commit()?;
+
+        // Recreating the table with another schema replans the statement, and infers the new
+        // parameter datatypes. The view must be dropped first, since it references the table.
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        txn.delete_table("titles")?;
+        txn.delete_table("movies")?;
+        for query in [
+            "CREATE TABLE movies (id STRING PRIMARY KEY, title STRING)",
+            "INSERT INTO movies VALUES ('a', 'Ran')",
+            "CREATE VIEW titles AS SELECT id FROM movies",
+        ] {
+            Prepared::new(query, &txn)?.execute(&mut txn, vec![])?;
+        }
+        txn.commit()?;
+
+        let mut txn = kv.begin(Mode::ReadOnly)?;
+        match select.execute(&mut txn, vec![Str("a".into())])? {
+            ResultSet::Query { columns, rows } => {
+                assert_eq!(columns, vec!["id".to_string(), "title".to_string()]);
+                assert_eq!(
+                    rows.collect::<Result<Vec<_>>>()?,
+                    vec![vec![Str("a".into()), Str("Ran".into())]]
+                );
+            }
+            _ => panic!("Expected query result"),
+        }
+        assert_ne!(select.plan, cached);
+        assert_eq!(select.parameters(), &[Some(Datatype::String)]);
+
+        // Statements are replanned when the views they reference change.
+        assert_eq!(view.execute(&mut txn, vec![])?.into_rows()?, vec![vec![Str("a".into())]]);
+        txn.commit()
+    }
+}
@@ -0,0 +1,361 @@
+use super::execution::{Config, ResultSet};
+use super::parser::{ast, Parser};
+use super::plan::{self, Plan};
+use super::prepared::Prepared;
+use super::storage::{Engine, Mode, Transaction, Value};
+use crate::error::{Error, Result};
+
+use std::collections::HashMap;
+
+/// A SQL session, which executes SQL statements against an engine. Statements run in the
+/// session's explicit transaction if one was begun with BEGIN, or otherwise in an implicit
+/// transaction that is committed when the statement succeeds. Errors roll back the
+/// transaction either way. The session also holds its named prepared statements.
+pub struct Session<E: Engine> {
+    engine: E,
+    config: Config,
+    /// The explicit transaction, if any.
+    txn: Option<E::Transaction>,
+    prepared: HashMap<String, Prepared>,
+}
+
+impl<E: Engine> Session<E> {
+    pub fn new(engine: E, config: Config) -> Self {
+        Session { engine, config, txn: None, prepared: HashMap::new() }
+    }
+
+    /// Executes a SQL statement. Query results are buffered, such that any errors are
+    /// raised before the statement's transaction is committed.
+    pub fn execute(&mut self, query: &str) -> Result<ResultSet> {
+        match Parser::new(query).parse()? {
+            ast::Statement::Begin { read_only, as_of } => {
+                if let Some(txn) = &self.txn {
+                    return Err(Error::Value(format!("Already in transaction {}", txn.id())));
+                }
+                let mode = match (read_only, as_of) {
+                    (_, Some(version)) => Mode::Snapshot { version },
+                    (true, None) => Mode::ReadOnly,
+                    (false, None) => Mode::ReadWrite,
+                };
+                let txn = self.engine.begin(mode)?;
+                let id = txn.id();
+                self.txn = Some(txn);
+                Ok(ResultSet::Begin { id, mode })
+            }
+            ast::Statement::Commit => {
+                let txn = self.txn.take().ok_or_else(not_in_transaction)?;
+                let id = txn.id();
+                txn.commit()?;
+                Ok(ResultSet::Commit { id })
+            }
+            ast::Statement::Rollback => {
+                let txn = self.txn.take().ok_or_else(not_in_transaction)?;
+                let id = txn.id();
+                txn.rollback()?;
+                Ok(ResultSet::Rollback { id })
+            }
+            ast::Statement::Deallocate(name) => match self.prepared.remove(&name) {
+                Some(_) => Ok(ResultSet::Deallocate { name }),
+                None => Err(Error::Value(format!("Unknown prepared statement {}", name))),
+            },
+            statement => {
+                let read_only = match &statement {
+                    ast::Statement::Execute { name, .. } => {
+                        self.prepared.get(name).is_some_and(|p| read_only(p.statement()))
+                    }
+                    statement => read_only(statement),
+                };
+                let mode = match read_only {
+                    true => Mode::ReadOnly,
+                    false => Mode::ReadWrite,
+                };
+                self.with_txn(mode, |txn, prepared, config| {
+                    execute(txn, statement, prepared, config)
+                })
+            }
+        }
+    }
+
+    /// Prepares a statement for repeated execution via execute_prepared().
+    pub fn prepare(&mut self, query: &str) -> Result<Prepared> {
+        self.with_txn(Mode::ReadOnly, |txn, _, _| Prepared::new(query, txn))
+    }
+
+    /// Executes a prepared statement with the given parameter values.
+    pub fn execute_prepared(
+        &mut self,
+        prepared: &mut Prepared,
+        values: Vec<Value>,
+    ) -> Result<ResultSet> {
+        let mode = match read_only(prepared.statement()) {
+            true => Mode::ReadOnly,
+            false => Mode::ReadWrite,
+        };
+        self.with_txn(mode, |txn, _, config| {
+            buffer(prepared.execute_with(txn, values, config)?)
+        })
+    }
+
+    /// Returns the ID and mode of the explicit transaction, if any.
+    pub fn transaction(&self) -> Option<(u64, Mode)> {
+        self.txn.as_ref().map(|txn| (txn.id(), txn.mode()))
+    }
+
+    /// Runs a closure in the explicit transaction, rolling it back on error, or in an implicit
+    /// transaction with the given mode which is committed on success. The closure's error is
+    /// returned even if the rollback fails.
+    fn with_txn<R, F>(&mut self, mode: Mode, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut E::Transaction, &mut HashMap<String, Prepared>, &Config) -> Result<R>,
+    {
+        if let Some(txn) = self.txn.as_mut() {
+            return match f(txn, &mut self.prepared, &self.config) {
+                Ok(result) => Ok(result),
+                Err(err) => {
+                    if let Some(txn) = self.txn.take() {
+                        txn.rollback().ok();
+                    }
+                    Err(err)
+                }
+            };
+        }
+        let mut txn = self.engine.begin(mode)?;
+        match f(&mut txn, &mut self.prepared, &self.config) {
+            Ok(result) => {
+                txn.commit()?;
+                Ok(result)
+            }
+            Err(err) => {
+                txn.rollback().ok();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl<E: Engine> Drop for Session<E> {
+    /// Rolls back the explicit transaction, if any.
+    fn drop(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            txn.rollback().ok();
+        }
+    }
+}
+
+fn not_in_transaction() -> Error {
+    Error::Value("Not in a transaction".into())
+}
+
+/// Returns true if the statement only reads, and can run in a read-only implicit transaction.
+fn read_only(statement: &ast::Statement) -> bool {
+    match statement {
+        ast::Statement::Select { .. }
+        | ast::Statement::SetOperation { .. }
+        | ast::Statement::With { .. } => true,
+        ast::Statement::Explain { statement, analyze } => !analyze || read_only(statement),
+        _ => false,
+    }
+}
+
+/// Executes a statement in a transaction.
+fn execute<T: Transaction>(
+    txn: &mut T,
+    statement: ast::Statement,
+    prepared: &mut HashMap<String, Prepared>,
+    config: &Config,
+) -> Result<ResultSet> {
+    match statement {
+        ast::Statement::Prepare { name, statement } => {
+            if prepared.contains_key(&name) {
+                return Err(Error::Value(format!("Prepared statement {} already exists", name)));
+            }
+            prepared.insert(name.clone(), Prepared::from_statement(*statement, txn)?);
+            Ok(ResultSet::Prepare { name })
+        }
+        ast::Statement::Execute { name, parameters } => {
+            let values = parameters
+                .into_iter()
+                .map(|p| match p.contains(&|e| matches!(e, ast::Expression::Column(_, _))) {
+                    true => Err(Error::Value(format!("Parameter {} must be constant", p))),
+                    false => plan::evaluate_constant(p, txn),
+                })
+                .collect::<Result<_>>()?;
+            buffer(
+                prepared
+                    .get_mut(&name)
+                    .ok_or_else(|| Error::Value(format!("Unknown prepared statement {}", name)))?
+                    .execute_with(txn, values, config)?,
+            )
+        }
+        statement => buffer(Plan::build(statement, txn)?.optimize(txn)?.execute_with(txn, config)?),
+    }
+}
+
+/// Buffers the rows of a query result.
+fn buffer(result: ResultSet) -> Result<ResultSet> {
+    Ok(match result {
+        ResultSet::Query { columns, rows } => {
+            let rows = rows.collect::<Result<Vec<_>>>()?;
+            ResultSet::Query { columns, rows: Box::new(rows.into_iter().map(Ok)) }
+        }
+        result => result,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::storage::{Kv, Memory, Mvcc};
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    use Value::{Integer, String as Str};
+
+    fn setup() -> Result<Session<Kv>> {
+        let kv = Kv::new(Mvcc::new(Box::new(Memory::new())));
+        let mut session = Session::new(kv, Config::default());
+        session.execute("CREATE TABLE t (id INTEGER PRIMARY KEY, name STRING)")?;
+        Ok(session)
+    }
+
+    fn rows(session: &mut Session<Kv>, query: &str) -> Result<Vec<Vec<Value>>> {
+        session.execute(query)?.into_rows()
+    }
+
+    #[test]
+    fn autocommit() -> Result<()> {
+        let mut session = setup()?;
+        session.execute("INSERT INTO t VALUES (1, 'a')")?;
+        assert_eq!(session.transaction(), None);
+
+        // A failed statement is rolled back as a whole.
+        assert_eq!(
+            session.execute("INSERT INTO t VALUES (2, 'b'), (1, 'c')").err(),
+            Some(Error::Value("Primary key 1 already exists for table t".into()))
+        );
+        assert_eq!(
+            session.execute("SELECT 1 / 0 FROM t").err(),
+            Some(Error::Value("Can't divide by zero".into()))
+        );
+        assert_eq!(rows(&mut session, "SELECT * FROM t")?, vec![vec![Integer(1), Str("a".into())]]);
+        assert_eq!(session.execute("COMMIT").err(), Some(not_in_transaction()));
+        assert_eq!(session.execute("ROLLBACK").err(), Some(not_in_transaction()));
+        Ok(())
+    }
+
+    #[test]
+    fn explicit() -> Result<()> {
+        let mut session = setup()?;
+        let id = match session.execute("BEGIN")? {
+            ResultSet::Begin { id, mode: Mode::ReadWrite } => id,
+            _ => panic!("Expected BEGIN result"),
+        };
+        assert_eq!(session.transaction(), Some((id, Mode::ReadWrite)));
+        assert_eq!(
+            session.execute("BEGIN").err(),
+            Some(Error::Value(format!("Already in transaction {}", id)))
+        );
+        session.execute("INSERT INTO t VALUES (1, 'a')")?;
+        assert!(matches!(session.execute("ROLLBACK")?, ResultSet::Rollback { id: i } if i == id));
+        assert_eq!(rows(&mut session, "SELECT * FROM t")?, Vec::<Vec<Value>>::new());
+
+        session.execute("BEGIN TRANSACTION")?;
+        session.execute("INSERT INTO t VALUES (1, 'a')")?;
+        assert!(matches!(session.execute("COMMIT")?, ResultSet::Commit { .. }));
+        assert_eq!(session.transaction(), None);
+        assert_eq!(rows(&mut session, "SELECT * FROM t")?, vec![vec![Integer(1), Str("a".into())]]);
+
+        // An error rolls back the explicit transaction.
+        session.execute("BEGIN")?;
+        session.execute("INSERT INTO t VALUES (2, 'b')")?;
+        assert!(session.execute("INSERT INTO t VALUES (1, 'c')").is_err());
+        assert_eq!(session.transaction(), None);
+        assert_eq!(session.execute("COMMIT").err(), Some(not_in_transaction()));
+        assert_eq!(rows(&mut session, "SELECT id FROM t")?, vec![vec![Integer(1)]]);
+        Ok(())
+    }
+
+    #[test]
+    fn read_only() -> Result<()> {
+        let mut session = setup()?;
+        session.execute("INSERT INTO t VALUES (1, 'a')")?;
+        let version = match session.execute("BEGIN READ ONLY")? {
+            ResultSet::Begin { id, mode: Mode::ReadOnly } => id,
+            _ => panic!("Expected BEGIN result"),
+        };
+        assert_eq!(
+            session.execute("INSERT INTO t VALUES (2, 'b')").err(),
+            Some(Error::ReadOnly)
+        );
+        assert_eq!(session.transaction(), None);
+        session.execute("INSERT INTO t VALUES (2, 'b')")?;
+
+        // A snapshot transaction sees the data as of the given version.
+        assert!(matches!(
+            session.execute(&format!("BEGIN AS OF SYSTEM TIME {}", version))?,
+            ResultSet::Begin { mode: Mode::Snapshot { version: v }, .. } if v == version
+        ));
+        assert_eq!(rows(&mut session, "SELECT id FROM t")?, vec![vec![Integer(1)]]);
+        session.execute("COMMIT")?;
+        assert_eq!(
+            rows(&mut session, "SELECT id FROM t")?,
+            vec![vec![Integer(1)], vec![Integer(2)]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn prepared() -> Result<()> {
+        let mut session = setup()?;
+        assert!(matches!(
+            session.execute("PREPARE ins AS INSERT INTO t VALUES (?, ?)")?,
+            ResultSet::Prepare { name } if name == "ins"
+        ));
+        session.execute("EXECUTE ins (1, 'a')")?;
+        session.execute("EXECUTE ins (1 + 1, 'bc')")?;
+        session.execute("PREPARE sel AS SELECT name FROM t WHERE id = $1")?;
+        assert_eq!(rows(&mut session, "EXECUTE sel (2)")?, vec![vec![Str("bc".into())]]);
+        assert_eq!(rows(&mut session, "EXECUTE sel (3)")?, Vec::<Vec<Value>>::new());
+
+        assert_eq!(
+            session.execute("PREPARE sel AS SELECT 1").err(),
+            Some(Error::Value("Prepared statement sel already exists".into()))
+        );
+        assert_eq!(
+            session.execute("EXECUTE sel ('a')").err(),
+            Some(Error::Value("Parameter $1 must be INTEGER, got STRING".into()))
+        );
+        assert_eq!(
+            session.execute("EXECUTE sel (id)").err(),
+            Some(Error::Value("Parameter id must be constant".into()))
+        );
+        assert_eq!(
+            session.execute("SELECT * FROM t WHERE id = ?").err(),
+            Some(Error::Value("Unbound parameter $1".into()))
+        );
+        assert!(matches!(
+            session.execute("DEALLOCATE sel")?,
+            ResultSet::Deallocate { name } if name == "sel"
+        ));
+        assert_eq!(
+            session.execute("EXECUTE sel (1)").err(),
+            Some(Error::Value("Unknown prepared statement sel".into()))
+        );
+        assert_eq!(
+            session.execute("PREPARE p AS PREPARE q AS SELECT 1").err(),
+            Some(Error::Value("Can't prepare prepared statement commands".into()))
+        );
+
+        let mut prepared = session.prepare("SELECT id FROM t WHERE name = ?")?;
+        assert_eq!(
+            session.execute_prepared(&mut prepared, vec![Str("a".into())])?.into_rows()?,
+            vec![vec![Integer(1)]]
+        );
+        let mut insert = session.prepare("INSERT INTO t VALUES (?, ?)")?;
+        session.execute_prepared(&mut insert, vec![Integer(3), Str("c".into())])?;
+        assert_eq!(
+            rows(&mut session, "SELECT name FROM t WHERE id = 3")?,
+            vec![vec![Str("c".into())]]
+        );
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
-use super::{Mode, Value, Table, Expression};
+use super::{Mode, Value, Table, TableKind, Expression, TableStats};
 use crate::error::{Result, Error};
+use serde_derive::{Deserialize, Serialize};
 use std::collections::HashSet;
 
 
@@ -12,6 +13,13 @@ pub trait Catalog {
 
     fn scan_tables(&self) -> Result<Tables>;
 
+    /// Reads a table's statistics, or None if it hasn't been analyzed.
+    fn read_stats(&self, table: &str) -> Result<Option<TableStats>>;
+
+    /// Stores a table's statistics, replacing any previous ones. They're removed along with
+    /// the table.
+    fn write_stats(&mut self, table: &str, stats: TableStats) -> Result<()>;
+
     fn must_read_table(&self, table_name: &str) -> Result<Table> {
         self.read_table(table_name)?.ok_or_else(|| Error::Value(format!("Table {} does not exist", table_name)))
     }
@@ -32,6 +40,21 @@ pub trait Catalog {
             .collect()
         )
     }
+
+    /// Returns the names of the views whose queries reference the given table or view.
+    fn view_references(&self, table_name: &str) -> Result<Vec<String>> {
+        Ok(self.scan_tables()?
+            .filter(|t| match &t.kind {
+                TableKind::View { references, .. }
+                | TableKind::MaterializedView { references, .. } => {
+                    references.iter().any(|r| r == table_name)
+                }
+                TableKind::Table => false,
+            })
+            .map(|t| t.name)
+            .collect()
+        )
+    }
 }
 
 pub trait Transaction: Catalog {
@@ -47,6 +70,10 @@ pub trait Transaction: Catalog {
 
     fn create(&mut self, table: &str, row: Row) -> Result<()>;
 
+    /// Inserts rows, handling rows whose primary key already exists as given by the conflict
+    /// action. Returns the number of rows inserted or updated.
+    fn upsert(&mut self, table: &str, rows: Vec<Row>, conflict: Conflict) -> Result<u64>;
+
     fn delete(&mut self, table: &str, id: &Value) -> Result<()>;
 
     fn read(&self, table: &str, id: &Value) -> Result<Option<Row>>;
@@ -59,6 +86,25 @@ pub trait Transaction: Catalog {
 
 }
 
+/// How upsert() handles a row whose primary key already exists.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Conflict {
+    /// Errors, like create().
+    Error,
+    /// Skips the row.
+    Nothing,
+    /// Updates the existing row, setting each column index to the expression's value. The
+    /// expressions are evaluated on the existing row followed by the inserted row.
+    Update(Vec<(usize, Expression)>),
+}
+
+/// A SQL storage engine, which begins transactions, either locally or via Raft.
+pub trait Engine {
+    type Transaction: Transaction;
+
+    fn begin(&self, mode: Mode) -> Result<Self::Transaction>;
+}
+
 pub type Row = Vec<Value>;
 
 pub type Tables = Box<dyn DoubleEndedIterator<Item = Table> + Send>;
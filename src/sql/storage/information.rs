@@ -0,0 +1,164 @@
+use super::schema::literal;
+use super::{Column, Datatype, Mode, Row, Status, Table, TableKind, Value};
+use crate::error::{Error, Result};
+
+/// The information schema, i.e. read-only virtual tables describing the catalog and the active
+/// transactions. Their schemas and rows are generated on demand by the storage engine, and
+/// they're not listed by scan_tables(). Views have no columns, indexes or constraints, and the
+/// hidden row ID column of materialized views is left out.
+pub const SCHEMA: &str = "information_schema";
+
+pub const TABLES: &str = "information_schema.tables";
+pub const COLUMNS: &str = "information_schema.columns";
+pub const INDEXES: &str = "information_schema.indexes";
+pub const CONSTRAINTS: &str = "information_schema.constraints";
+pub const TRANSACTIONS: &str = "information_schema.transactions";
+
+/// Returns true if the table name is in the information schema, whether or not it exists.
+pub fn contains(table: &str) -> bool {
+    table.strip_prefix(SCHEMA).is_some_and(|name| name.starts_with('.'))
+}
+
+/// Errors if the table is in the information schema, which can't be modified.
+pub fn check_writable(table: &str) -> Result<()> {
+    match contains(table) {
+        true => Err(Error::Value(format!("Can't modify system table {}", table))),
+        false => Ok(()),
+    }
+}
+
+/// Returns the schema of an information schema table, if it exists.
+pub fn table(name: &str) -> Option<Table> {
+    let columns = match name {
+        TABLES => vec![
+            column("table_name", Datatype::String, false),
+            column("table_type", Datatype::String, false),
+        ],
+        COLUMNS => vec![
+            column("table_name", Datatype::String, false),
+            column("column_name", Datatype::String, false),
+            column("ordinal_position", Datatype::Integer, false),
+            column("data_type", Datatype::String, false),
+            column("is_nullable", Datatype::Boolean, false),
+            column("column_default", Datatype::String, true),
+        ],
+        INDEXES => vec![
+            column("table_name", Datatype::String, false),
+            column("column_name", Datatype::String, false),
+            column("is_primary", Datatype::Boolean, false),
+            column("is_unique", Datatype::Boolean, false),
+        ],
+        CONSTRAINTS => vec![
+            column("table_name", Datatype::String, false),
+            column("column_name", Datatype::String, false),
+            column("constraint_type", Datatype::String, false),
+            column("referenced_table", Datatype::String, true),
+        ],
+        TRANSACTIONS => vec![
+            column("id", Datatype::Integer, false),
+            column("mode", Datatype::String, false),
+            column("version", Datatype::Integer, true),
+            column("current", Datatype::Boolean, false),
+        ],
+        _ => return None,
+    };
+    let mut table = Table::new(name.into(), columns);
+    // The tables and transactions are keyed and emitted in name and ID order respectively.
+    if matches!(name, TABLES | TRANSACTIONS) {
+        table.columns[0].primary_key = true;
+        table.columns[0].unique = true;
+    }
+    Some(table)
+}
+
+/// Generates the rows of an information schema table, given the catalog's tables, the MVCC
+/// status and the ID of the current transaction.
+pub fn scan(name: &str, tables: Vec<Table>, status: Status, txn_id: u64) -> Result<Vec<Row>> {
+    let string = |s: &str| Value::String(s.into());
+    let mut rows = Vec::new();
+    if name == TRANSACTIONS {
+        for (id, mode) in status.active {
+            let (mode, version) = match mode {
+                Mode::ReadWrite => ("READ WRITE", Value::Null),
+                Mode::ReadOnly => ("READ ONLY", Value::Null),
+                Mode::Snapshot { version } => ("SNAPSHOT", Value::Integer(version as i64)),
+            };
+            let current = Value::Boolean(id == txn_id);
+            rows.push(vec![Value::Integer(id as i64), string(mode), version, current]);
+        }
+        return Ok(rows);
+    }
+    for table in tables {
+        let kind = match &table.kind {
+            TableKind::Table => "TABLE",
+            TableKind::View { .. } => "VIEW",
+            TableKind::MaterializedView { .. } => "MATERIALIZED VIEW",
+        };
+        if name == TABLES {
+            rows.push(vec![string(&table.name), string(kind)]);
+            continue;
+        }
+        let columns = match table.kind {
+            TableKind::Table => table.columns.as_slice(),
+            TableKind::View { .. } => &[],
+            TableKind::MaterializedView { .. } => &table.columns[..table.columns.len() - 1],
+        };
+        for (i, c) in columns.iter().enumerate() {
+            let (table, column) = (string(&table.name), string(&c.name));
+            match name {
+                COLUMNS => rows.push(vec![
+                    table,
+                    column,
+                    Value::Integer(i as i64 + 1),
+                    string(&c.datatype.to_string()),
+                    Value::Boolean(c.nullalbe),
+                    match &c.default {
+                        Some(value) => string(&literal(value)),
+                        None => Value::Null,
+                    },
+                ]),
+                INDEXES if c.primary_key || c.index => rows.push(vec![
+                    table,
+                    column,
+                    Value::Boolean(c.primary_key),
+                    Value::Boolean(c.unique),
+                ]),
+                CONSTRAINTS => {
+                    let mut constraint = |r#type: &str, reference: Value| {
+                        rows.push(vec![table.clone(), column.clone(), string(r#type), reference])
+                    };
+                    if c.primary_key {
+                        constraint("PRIMARY KEY", Value::Null);
+                    }
+                    if !c.nullalbe && !c.primary_key {
+                        constraint("NOT NULL", Value::Null);
+                    }
+                    if c.unique && !c.primary_key {
+                        constraint("UNIQUE", Value::Null);
+                    }
+                    if let Some(reference) = &c.reference {
+                        constraint("FOREIGN KEY", string(reference));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    if name == TABLES {
+        rows.sort_by(|a, b| a[0].partial_cmp(&b[0]).unwrap_or(std::cmp::Ordering::Equal));
+    }
+    Ok(rows)
+}
+
+fn column(name: &str, datatype: Datatype, nullable: bool) -> Column {
+    Column {
+        name: name.into(),
+        datatype,
+        primary_key: false,
+        nullalbe: nullable,
+        default: nullable.then_some(Value::Null),
+        unique: false,
+        reference: None,
+        index: false,
+    }
+}
@@ -3,10 +3,12 @@ use std::{borrow::Cow};
 use super::coding::*;
 use super::{Mvcc, Mode, mvcc, Row};
 use serde::{Deserialize, Serialize};
-use crate::sql::storage::{Expression, engine::Transaction};
+use crate::sql::storage::{Datatype, Expression, engine::{Conflict, Engine, Transaction}};
+use crate::sql::storage::{information, TableKind, TableStats};
 use crate::{error::{Error, Result}, sql::storage::{Catalog, Value}};
 
 
+#[derive(Clone)]
 pub struct Kv {
     pub kv: Mvcc,
 }
@@ -37,6 +39,14 @@ impl Kv {
 
 }
 
+impl Engine for Kv {
+    type Transaction = Txn;
+
+    fn begin(&self, mode: Mode) -> Result<Txn> {
+        Kv::begin(self, mode)
+    }
+}
+
 pub struct Txn {
     txn: mvcc::Transaction,
 }
@@ -54,6 +64,12 @@ impl Txn {
             .unwrap_or_else(HashSet::new))
     }
 
+    /// Generates the rows of an information schema table.
+    fn scan_information(&self, table: &str) -> Result<Vec<Row>> {
+        let tables = self.scan_tables()?.collect();
+        information::scan(table, tables, self.txn.status()?, self.txn.id())
+    }
+
     fn index_store(&mut self, table_name: &str, column: &str, value: &Value, index: HashSet<Value>) -> Result<()> {
         let key = Key::Index(table_name.into(), column.into(), Some(value.into())).encode();
         if index.is_empty() {
@@ -82,6 +98,7 @@ impl Transaction for Txn {
     }
 
     fn update(&mut self, table: &str, id: &Value, row: Row) -> Result<()> {
+        information::check_writable(table)?;
         let table = self.must_read_table(table)?;
         if id != &table.get_row_key(&row)? {
             self.delete(&table.name, id)?;
@@ -111,6 +128,7 @@ impl Transaction for Txn {
     }
 
     fn create(&mut self, table_name: &str, row: Row) -> Result<()> {
+        information::check_writable(table_name)?;
         let table = self.must_read_table(table_name)?;
         table.validate_row(&row, self)?;
         let id = table.get_row_key(&row)?;
@@ -134,7 +152,40 @@ impl Transaction for Txn {
         Ok(())   
     }
 
+    fn upsert(&mut self, table: &str, rows: Vec<Row>, conflict: Conflict) -> Result<u64> {
+        information::check_writable(table)?;
+        let table = self.must_read_table(table)?;
+        let mut count = 0;
+        for row in rows {
+            let id = table.get_row_key(&row)?;
+            let old = match conflict {
+                Conflict::Error => None,
+                Conflict::Nothing | Conflict::Update(_) => self.read(&table.name, &id)?,
+            };
+            let Some(old) = old else {
+                self.create(&table.name, row)?;
+                count += 1;
+                continue;
+            };
+            if let Conflict::Update(set) = &conflict {
+                // The expressions see the existing row followed by the inserted row.
+                let mut new = old.clone();
+                let input: Row = old.into_iter().chain(row).collect();
+                for (i, expr) in set {
+                    new[*i] = match (&table.columns[*i].datatype, expr.evaluate(Some(&input))?) {
+                        (Datatype::Float, Value::Integer(i)) => Value::Float(i as f64),
+                        (_, value) => value,
+                    };
+                }
+                self.update(&table.name, &id, new)?;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
     fn delete(&mut self, table: &str, id: &Value) -> Result<()> {
+        information::check_writable(table)?;
         let table = self.must_read_table(table)?;
         for (t, cs) in self.table_references(&table.name, true)? {
             let t = self.must_read_table(&t)?;
@@ -171,6 +222,14 @@ impl Transaction for Txn {
 
         
     fn read(&self, table: &str, id: &crate::sql::storage::Value) -> Result<Option<Row>> {
+        if information::contains(table) {
+            let table = self.must_read_table(table)?;
+            let rows = self.scan_information(&table.name)?;
+            return rows.into_iter().try_fold(None, |found, row| match found {
+                None if &table.get_row_key(&row)? == id => Ok(Some(row)),
+                found => Ok(found),
+            });
+        }
         self.txn
             .get(&Key::Row(table.into(), Some(id.into())).encode())?
             .map(|val| deserialize(&val))
@@ -187,25 +246,31 @@ impl Transaction for Txn {
 
     fn scan(&self, table: &str, filter: Option<Expression>) -> Result<super::KScan> {
         let table = self.must_read_table(table)?;
+        let filter = filter.map(|f| f.analyze_filter(&table)?.compile(&table)).transpose()?;
+        let rows: super::KScan = match information::contains(&table.name) {
+            true => Box::new(self.scan_information(&table.name)?.into_iter().map(Ok)),
+            false => Box::new(
+                self.txn
+                    .scan_prefix(&Key::Row((&table.name).into(), None).encode())?
+                    .map(|r| r.and_then(|(_, val)| deserialize(&val))),
+            ),
+        };
         Ok(Box::new(
-            self.txn
-                .scan_prefix(&Key::Row((&table.name).into(), None).encode())?
-                .map(|r| r.and_then(|(_, val)| deserialize(&val)))
-                .filter_map(move |r| match r {
-                    Ok(row) => match &filter {
-                        Some(filter) => match filter.evaluate(Some(&row)) {
-                            Ok(Value::Boolean(b)) if b => Some(Ok(row)),
-                            Ok(Value::Boolean(_)) | Ok(Value::Null) => None,
-                            Ok(v) => Some(Err(Error::Value(format!(
-                                "Filter returned {}, expected boolean",
-                                v
-                            )))),
-                            Err(err) => Some(Err(err)),
-                        },
-                        None => Some(Ok(row)),
+            rows.filter_map(move |r| match r {
+                Ok(row) => match &filter {
+                    Some(filter) => match filter.evaluate(&row) {
+                        Ok(Value::Boolean(b)) if b => Some(Ok(row)),
+                        Ok(Value::Boolean(_)) | Ok(Value::Null) => None,
+                        Ok(v) => Some(Err(Error::Value(format!(
+                            "Filter returned {}, expected boolean",
+                            v
+                        )))),
+                        Err(err) => Some(Err(err)),
                     },
-                    err => Some(err),
-                }),
+                    None => Some(Ok(row)),
+                },
+                err => Some(err),
+            }),
         ))
     }
 
@@ -231,6 +296,7 @@ impl Transaction for Txn {
 
 impl super::Catalog for Txn {
     fn create_table(&mut self, table: crate::sql::storage::Table) -> Result<()> {
+        information::check_writable(&table.name)?;
         if self.read_table(&table.name)?.is_some() {
             return Err(Error::Value(format!("Table {} already exists", table.name)));
         }
@@ -239,6 +305,7 @@ impl super::Catalog for Txn {
     }
     
     fn delete_table(&mut self, table_name: &str) -> Result<()> {
+        information::check_writable(table_name)?;
         let table = self.must_read_table(table_name)?;
         if let Some((t, cs)) = self.table_references(&table.name, false)?.first() {
             return Err(Error::Value(format!(
@@ -246,14 +313,25 @@ impl super::Catalog for Txn {
                 table.name, t, cs[0]
             )));
         }
+        if let Some(view) = self.view_references(&table.name)?.first() {
+            let kind = if table.kind == TableKind::Table { "Table" } else { "View" };
+            return Err(Error::Value(format!(
+                "{} {} is referenced by view {}",
+                kind, table.name, view
+            )));
+        }
         let mut scan = self.scan(&table.name, None)?;
         while let Some(row) = scan.next().transpose()? {
             self.delete(&table.name, &table.get_row_key(&row)?)?
         }
+        self.txn.delete(&Key::Stats((&table.name).into()).encode())?;
         self.txn.delete(&Key::Table(Some(table.name.into())).encode())
     }
 
     fn read_table(&self, table: &str) -> Result<Option<crate::sql::storage::Table>> {
+        if information::contains(table) {
+            return Ok(information::table(table));
+        }
         self.txn.get(&Key::Table(Some(table.into())).encode())?.map(|v| deserialize(&v)).transpose()
     }
 
@@ -267,6 +345,16 @@ impl super::Catalog for Txn {
         ))
     }
 
+    fn read_stats(&self, table: &str) -> Result<Option<TableStats>> {
+        self.txn.get(&Key::Stats(table.into()).encode())?.map(|v| deserialize(&v)).transpose()
+    }
+
+    fn write_stats(&mut self, table: &str, stats: TableStats) -> Result<()> {
+        information::check_writable(table)?;
+        self.must_read_table(table)?;
+        self.txn.set(&Key::Stats(table.into()).encode(), serialize(&stats)?)
+    }
+
 }
 
 fn serialize<V: Serialize>(value: &V) -> Result<Vec<u8>> {
@@ -281,6 +369,8 @@ enum Key<'a> {
     Table(Option<Cow<'a, str>>),
     Index(Cow<'a, str>, Cow<'a, str>, Option<Cow<'a, Value>>),
     Row(Cow<'a, str>, Option<Cow<'a, Value>>),
+    /// A table's statistics, computed by ANALYZE.
+    Stats(Cow<'a, str>),
 
 }
 
@@ -297,6 +387,7 @@ impl<'a> Key<'a> {
             Self::Row(table, Some(pk)) => {
                     [&[0x03][..], &encode_string(&table), &encode_value(pk.into_owned())].concat()
                 } 
+            Self::Stats(table) => [&[0x04][..], &encode_string(&table)].concat(),
         }
     }
 
@@ -310,6 +401,7 @@ impl<'a> Key<'a> {
                 Some(take_value(bytes)?.into()),
             ),
             0x03 => Self::Row(take_string(bytes)?.into(), Some(take_value(bytes)?.into())),
+            0x04 => Self::Stats(take_string(bytes)?.into()),
             b => return Err(Error::Internal(format!("Unknown SQL key prefix {:x?}", b))),
         };
         if !bytes.is_empty() {
@@ -319,3 +411,78 @@ impl<'a> Key<'a> {
     }
 }
 
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sql::storage::{kv::Memory, Column, Datatype, Table};
+
+    fn setup() -> Result<Kv> {
+        let kv = Kv::new(Mvcc::new(Box::new(Memory::new())));
+        let mut txn = kv.begin(Mode::ReadWrite)?;
+        txn.create_table(Table::new(
+            "t".into(),
+            vec![
+                Column {
+                    name: "id".into(),
+                    datatype: Datatype::Integer,
+                    primary_key: true,
+                    nullalbe: false,
+                    default: None,
+                    unique: true,
+                    reference: None,
+                    index: false,
+                },
+                Column {
+                    name: "name".into(),
+                    datatype: Datatype::String,
+                    primary_key: false,
+                    nullalbe: true,
+                    default: Some(Value::Null),
+                    unique: false,
+                    reference: None,
+                    index: false,
+                },
+            ],
+        ))?;
+        for (id, name) in [(1, "a"), (2, "b"), (3, "c")] {
+            txn.create("t", vec![Value::Integer(id), Value::String(name.into())])?;
+        }
+        txn.commit()?;
+        Ok(kv)
+    }
+
+    #[test]
+    fn scan_filter() -> Result<()> {
+        let kv = setup()?;
+        let txn = kv.begin(Mode::ReadOnly)?;
+        let filter = Expression::GreaterThan(
+            Expression::Field(0, Some((None, "id".into()))).into(),
+            Expression::Add(
+                Expression::Constant(Value::Integer(0)).into(),
+                Expression::Constant(Value::Integer(1)).into(),
+            )
+            .into(),
+        );
+        assert_eq!(
+            txn.scan("t", Some(filter))?.collect::<Result<Vec<_>>>()?,
+            vec![
+                vec![Value::Integer(2), Value::String("b".into())],
+                vec![Value::Integer(3), Value::String("c".into())],
+            ]
+        );
+
+        // Ill-typed filters and unknown fields are rejected before scanning any rows.
+        let filter = Expression::Equal(
+            Expression::Field(0, Some((None, "name".into()))).into(),
+            Expression::Constant(Value::Integer(1)).into(),
+        );
+        assert_eq!(
+            txn.scan("t", Some(filter)).err(),
+            Some(Error::Value("Can't compare STRING and INTEGER".into()))
+        );
+        let filter = Expression::Field(0, Some((None, "missing".into())));
+        assert!(txn.scan("t", Some(filter)).is_err());
+        Ok(())
+    }
+}
@@ -259,7 +259,7 @@ impl Children {
                     split_key
                 },
                 Ordering::Greater => {
-                    new_rnode.insert(self.capacity() - self.len(), split_child);
+                    new_rnode.insert(insert_at - self.len(), split_child);
                     new_rnode.keys.insert(insert_at - self.len() - 1, split_key);
                     self.keys.pop().unwrap()
                 },
@@ -394,16 +394,14 @@ impl Children {
 
             Node::Inner(child) => {
                 let (key, node) = (child.keys.remove(0), child.nodes.remove(0));
-                self.keys[index - 1] = key.clone();
+                let parent_key = mem::replace(&mut self.keys[index - 1], key);
                 match &mut self[index - 1] {
                     Node::Inner(lchild) => {
                         lchild.nodes.push(node);
-                        lchild.keys.push(key);
-
+                        lchild.keys.push(parent_key);
                     },
                     _ => panic!("error left rotate "),
                 }
-
             }
             Node::Leaf(values) => {
                 let key = values[1].0.clone();
@@ -425,10 +423,11 @@ impl Children {
         match &mut self[index] {
             Node::Inner(child) => {
                 let (key, node) = (child.keys.pop().unwrap(), child.pop().unwrap());
+                let parent_key = mem::replace(&mut self.keys[index], key);
                 match &mut self[index + 1] {
                     Node::Inner(rchild) => {
                         rchild.insert(0, node);
-                        self.keys[index] = key;
+                        rchild.keys.insert(0, parent_key);
                     },
                     _ => panic!("error right rotate"),
                 }
@@ -482,7 +481,8 @@ impl Values {
         for (i, (k, value)) in self.iter_mut().enumerate() {
             match key.cmp(&**k) {
                 Ordering::Equal => {
-                    *value = val.to_vec();
+                    *value = val;
+                    return None;
                 }
                 Ordering::Greater => {
                     {}
@@ -828,10 +828,51 @@ mod test {
         assert!(mem.get(&[0x01])?.is_none());
         Ok(())
     }
-}
-
-
-
-
-
 
+    #[test]
+    fn random() -> Result<()> {
+        use std::collections::BTreeMap;
+
+        // Applies random writes to small nodes, checking gets and scans against a BTreeMap.
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for _ in 0..10 {
+            let mut mem = Memory::new_with_order(4);
+            let mut model = BTreeMap::new();
+            for _ in 0..500 {
+                let key = vec![(random() % 100) as u8];
+                if random() % 3 == 0 {
+                    mem.delete(&key)?;
+                    model.remove(&key);
+                } else {
+                    let value = random().to_be_bytes().to_vec();
+                    mem.set(&key, value.clone())?;
+                    model.insert(key, value);
+                }
+            }
+            for key in 0..100 {
+                assert_eq!(mem.get(&[key])?, model.get(&vec![key]).cloned());
+            }
+            let (start, end) = (vec![(random() % 100) as u8], vec![(random() % 100) as u8]);
+            let (start, end) = (start.clone().min(end.clone()), start.max(end));
+            assert_eq!(
+                mem.scan(Range::from(..)).collect::<Result<Vec<_>>>()?,
+                model.clone().into_iter().collect::<Vec<_>>()
+            );
+            assert_eq!(
+                mem.scan(Range::from(&start..=&end)).rev().collect::<Result<Vec<_>>>()?,
+                model
+                    .range(start..=end)
+                    .rev()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect::<Vec<_>>()
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 mod memory;
 pub use memory::Memory;
 mod mvcc;
-pub use mvcc::{Mode, Mvcc};
+pub use mvcc::{Mode, Mvcc, Status};
 pub mod coding;
 pub use coding::*;
 use std::{ops::{Bound, RangeBounds}, fmt::Display};
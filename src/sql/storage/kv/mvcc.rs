@@ -12,9 +12,38 @@ use serde_derive::{Serialize, Deserialize};
 pub struct Status {
     pub txns: u64,
     pub txns_active: u64,
+    /// The active transactions and their modes, in ID order.
+    pub active: Vec<(u64, Mode)>,
     pub storage: String,
 }
 
+impl Status {
+    /// Reads the status from the store, where active transactions have a TxnActive key.
+    fn read(store: &dyn Store) -> Result<Self> {
+        let active = store
+            .scan(Range::from(
+                Key::TxnActive(0).encode()..Key::TxnActive(std::u64::MAX).encode(),
+            ))
+            .map(|r| {
+                let (key, value) = r?;
+                match Key::decode(&key)? {
+                    Key::TxnActive(id) => Ok((id, deserialize(&value)?)),
+                    key => Err(Error::Internal(format!("Expected TxnActive key, got {:?}", key))),
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Status {
+            txns: match store.get(&Key::TxnNext.encode())? {
+                Some(ref v) => deserialize(v)?,
+                None => 1,
+            } - 1,
+            txns_active: active.len() as u64,
+            active,
+            storage: store.to_string(),
+        })
+    }
+}
+
 
 
 pub struct Mvcc {
@@ -22,6 +51,10 @@ pub struct Mvcc {
 }
 
 impl Mvcc {
+    pub fn new(store: Box<dyn Store>) -> Self {
+        Mvcc { store: Arc::new(RwLock::new(store)) }
+    }
+
     pub fn begin(&self) -> Result<Transaction> {
         Transaction::begin(self.store.clone(), Mode::ReadWrite)
     }
@@ -46,20 +79,7 @@ impl Mvcc {
     }
 
     pub fn status(&self) -> Result<Status> {
-        let store = self.store.read()?;
-        return Ok(
-            Status {
-                txns: match store.get(&Key::TxnNext.encode())? {
-                    Some(ref v) => deserialize(v)?,
-                    None => 1,
-                } - 1,
-                txns_active: store
-                .scan(Range::from(
-                    Key::TxnActive(0).encode()..Key::TxnActive(std::u64::MAX).encode(),
-                ))
-                .try_fold(0, |count, r| r.map(|_| count + 1))?,
-            storage: store.to_string(),     
-            });
+        Status::read(self.store.read()?.as_ref())
     }
 }
 
@@ -254,6 +274,11 @@ impl Transaction {
     pub fn mode(&self) -> Mode {
         self.mode.clone()
     }
+
+    /// Returns the current MVCC status, which isn't subject to the transaction's snapshot.
+    pub fn status(&self) -> Result<Status> {
+        Status::read(self.storage.read()?.as_ref())
+    }
     
 }
 
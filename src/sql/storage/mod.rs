@@ -1,16 +1,20 @@
 
 mod kv;
 pub mod engine;
-pub use engine::{Catalog, Transaction, IndexScan, Row, Tables};
+pub use engine::{Catalog, Conflict, Engine, Transaction, IndexScan, Row, Tables};
 pub mod schema;
 
 pub mod types;
 pub use types::{Value, Datatype, Expression};
-pub use kv::{Mode, Kv, Mvcc};
-pub use schema::{Column, Table};
+pub use kv::{Mode, Kv, Memory, Mvcc, Status};
+pub use schema::{Column, Table, TableKind};
+pub mod information;
+pub mod stats;
+pub use stats::{ColumnStats, TableStats};
 mod raftlog;
-pub use raftlog::{Store, Range};
+pub use raftlog::{LogStore, Range, Store};
 mod raft;
+pub use raft::{Raft, State as RaftState};
 use crate::raft::Client;
 use crate::error::{Error, Result};
 
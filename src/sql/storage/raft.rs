@@ -2,7 +2,7 @@ use super::{engine::Transaction, Value, Row, Table, Expression, kv::kv, Kv, Cata
 use serde::{Serialize, Deserialize, de::value};
 use serde_derive::{Deserialize, Serialize};
 use crate::error::{Error, Result};
-use super::{Mode, Client, Tables};
+use super::{Mode, Client, Tables, Conflict, TableStats};
 
 #[derive(Serialize, Deserialize)]
 enum Mutation {
@@ -10,10 +10,12 @@ enum Mutation {
     Commit(u64),
     Rollback(u64),
     Create{txn_id: u64, table: String, row: Row},
+    Upsert{txn_id: u64, table: String, rows: Vec<Row>, conflict: Conflict},
     Delete{txn_id: u64, table: String, id: Value},
     Update{txn_id: u64, table: String, id: Value, row: Row},
     CreateTable {txn_id: u64, schema: Table},
     DeleteTable {txn_id: u64, table: String},
+    WriteStats { txn_id: u64, table: String, stats: TableStats },
 }
 
 #[derive(Serialize, Deserialize)]
@@ -26,6 +28,27 @@ enum Query {
     ScanIndex {txn_id: u64, table: String, column: String, },
     ScanTables { txn_id: u64 },
     ReadTable { txn_id: u64, table: String },
+    ReadStats { txn_id: u64, table: String },
+}
+
+/// A SQL engine which replicates transactions via Raft.
+#[derive(Clone)]
+pub struct Raft {
+    client: Client,
+}
+
+impl Raft {
+    pub fn new(client: Client) -> Self {
+        Raft { client }
+    }
+}
+
+impl super::Engine for Raft {
+    type Transaction = RaftTxn;
+
+    fn begin(&self, mode: Mode) -> Result<RaftTxn> {
+        RaftTxn::begin(self.client.clone(), mode)
+    }
 }
 
 pub struct RaftTxn {
@@ -78,6 +101,12 @@ impl super::Transaction for RaftTxn {
         )?)
     }
 
+    fn upsert(&mut self, table: &str, rows: Vec<Row>, conflict: Conflict) -> Result<u64> {
+        deserialize(&self.mutate(
+            Mutation::Upsert { txn_id: self.id, table: table.to_string(), rows, conflict }
+        )?)
+    }
+
     fn delete(&mut self, table: &str, id: &Value) -> Result<()> {
         deserialize(&self.mutate(
             Mutation::Delete { txn_id: self.id, table: table.to_string(), id: id.clone(), }
@@ -156,6 +185,20 @@ impl super::Catalog for RaftTxn {
                 .into_iter(),
         ))
     }
+
+    fn read_stats(&self, table: &str) -> Result<Option<TableStats>> {
+        deserialize(
+            &self.query(Query::ReadStats { txn_id: self.id, table: table.to_string() })?,
+        )
+    }
+
+    fn write_stats(&mut self, table: &str, stats: TableStats) -> Result<()> {
+        deserialize(&self.mutate(Mutation::WriteStats {
+            txn_id: self.id,
+            table: table.to_string(),
+            stats,
+        })?)
+    }
 }
 
 fn serialize<V: Serialize>(value: &V) -> Result<Vec<u8>> {
@@ -192,6 +235,9 @@ impl State {
             Mutation::Create { txn_id, table, row } => {
                 serialize(&self.engine.resume(txn_id)?.create(&table, row)?)
             }
+            Mutation::Upsert { txn_id, table, rows, conflict } => {
+                serialize(&self.engine.resume(txn_id)?.upsert(&table, rows, conflict)?)
+            }
             Mutation::Delete { txn_id, table, id } => {
                 serialize(&self.engine.resume(txn_id)?.delete(&table, &id)?)
             }
@@ -205,6 +251,9 @@ impl State {
             Mutation::DeleteTable { txn_id, table } => {
                 serialize(&self.engine.resume(txn_id)?.delete_table(&table)?)
             }
+            Mutation::WriteStats { txn_id, table, stats } => {
+                serialize(&self.engine.resume(txn_id)?.write_stats(&table, stats)?)
+            }
         }
     }
 }
@@ -257,6 +306,9 @@ impl super::State for State {
             Query::ScanTables { txn_id } => {
                 serialize(&self.engine.resume(txn_id)?.scan_tables()?.collect::<Vec<_>>())
             }
+            Query::ReadStats { txn_id, table } => {
+                serialize(&self.engine.resume(txn_id)?.read_stats(&table)?)
+            }
         }
     }
 
@@ -26,7 +26,7 @@ impl Display for LogStore {
 }
 
 impl LogStore {
-    fn new(dir: &Path, sync: bool) -> Result<Self> {
+    pub fn new(dir: &Path, sync: bool) -> Result<Self> {
         create_dir_all(dir)?;
 
         let file = OpenOptions::new()
@@ -137,7 +137,7 @@ impl super::Store for LogStore {
     fn get(&self, index: u64) -> Result<Option<Vec<u8>>> {
         match index {
             0 => Ok(None),
-            i if index < self.index.len() as u64 => {
+            i if index <= self.index.len() as u64 => {
                 let (pos, size) = self.index.get(&index).copied().ok_or_else(
                     || Error::Internal(format!("Indexed position not found for entry {}", i)) 
                 )?;
@@ -190,7 +190,7 @@ impl super::Store for LogStore {
                     self.uncommited
                         .iter()
                         .skip(start as usize - min(start as usize, self.index.len() + 1))
-                        .take(end as usize - max(start as usize, self.index.len()) + 1)
+                        .take(end as usize - max(start as usize, self.index.len() + 1) + 1)
                         .cloned()
                         .map(Ok),
                 ),
@@ -281,4 +281,27 @@ mod test {
 
         Ok(())
     }
+    #[test]
+    fn commited_boundary() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("toydb-logstore-{}", uuid::Uuid::new_v4()));
+        let mut log = LogStore::new(&dir, false)?;
+        for entry in 1..=5 {
+            log.append(vec![entry])?;
+        }
+        log.commit(3)?;
+
+        // The last commited entry is read from the file, and the rest from memory.
+        assert_eq!(log.get(3)?, Some(vec![3]));
+        assert_eq!(log.get(4)?, Some(vec![4]));
+        assert_eq!(log.get(6)?, None);
+
+        // Scans spanning commited and uncommited entries don't overlap or overrun.
+        let scan = |start, end| log.scan(Range::from(start..=end)).collect::<Result<Vec<_>>>();
+        assert_eq!(scan(1, 5)?, vec![vec![1], vec![2], vec![3], vec![4], vec![5]]);
+        assert_eq!(scan(3, 4)?, vec![vec![3], vec![4]]);
+        assert_eq!(scan(4, 4)?, vec![vec![4]]);
+        assert_eq!(scan(5, 5)?, vec![vec![5]]);
+        std::fs::remove_dir_all(dir)?;
+        Ok(())
+    }
 }
@@ -5,17 +5,38 @@ use super::Datatype;
 use super::engine::Row;
 use crate::error::{Error, Result};
 use super::Transaction;
+use crate::sql::parser::Keyword;
 
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
+    pub kind: TableKind,
+}
+
+/// The kind of a catalog entry. Views are stored next to tables, and share their namespace.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum TableKind {
+    Table,
+    /// A view, whose SELECT query is expanded by the planner wherever the view is referenced.
+    /// The query is stored as SQL text, along with the tables and views it references, which
+    /// can't be dropped while the view exists. It has no columns or rows of its own.
+    View { query: String, references: Vec<String> },
+    /// A materialized view, whose SELECT query results are stored as the table's rows when
+    /// created or refreshed. The query is stored like a view's, and the rows are keyed by a
+    /// trailing hidden row ID column.
+    MaterializedView { query: String, references: Vec<String> },
 }
 
 impl Table {
     pub fn new(name: String, columns: Vec<Column>) -> Self {
-        Table { name, columns, }
+        Table { name, columns, kind: TableKind::Table }
+    }
+
+    /// Creates a view with the given query and referenced tables.
+    pub fn view(name: String, query: String, references: Vec<String>) -> Self {
+        Table { name, columns: Vec::new(), kind: TableKind::View { query, references } }
     }
 
     pub fn get_column(&self, col_name: &str) -> Result<&Column> {
@@ -51,6 +72,9 @@ impl Table {
     }
 
     pub fn validate(&self, txn: &mut dyn Transaction) -> Result<()> {
+        if let TableKind::View { .. } = self.kind {
+            return Ok(());
+        }
         if self.columns.is_empty() {
             return Err(Error::Value(format!("Table {} has no columns", self.name)));
         }
@@ -80,7 +104,74 @@ impl Table {
     }
 }
 
-#[derive(Clone, Deserialize, Serialize)]
+/// Formats the table as a CREATE TABLE statement, which creates an identical table. Views
+/// have no columns, and are formatted as empty tables.
+impl std::fmt::Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "CREATE TABLE {} (", ident(&self.name))?;
+        for (i, column) in self.columns.iter().enumerate() {
+            write!(f, "  {}", column)?;
+            writeln!(f, "{}", if i + 1 < self.columns.len() { "," } else { "" })?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Formats the column as a CREATE TABLE column definition. Nullable columns default to NULL,
+/// which is left implicit.
+impl std::fmt::Display for Column {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", ident(&self.name), self.datatype)?;
+        if self.primary_key {
+            write!(f, " PRIMARY KEY")?;
+        } else if !self.nullalbe {
+            write!(f, " NOT NULL")?;
+        }
+        match &self.default {
+            Some(Value::Null) if self.nullalbe => {}
+            Some(value) => write!(f, " DEFAULT {}", literal(value))?,
+            None => {}
+        }
+        if self.unique && !self.primary_key {
+            write!(f, " UNIQUE")?;
+        }
+        if let Some(reference) = &self.reference {
+            write!(f, " REFERENCES {}", ident(reference))?;
+        }
+        if self.index {
+            write!(f, " INDEX")?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats an identifier, quoting it if it isn't a valid unquoted identifier.
+fn ident(name: &str) -> String {
+    let valid = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && name.to_lowercase() == name
+        && Keyword::lookup(name).is_none();
+    match valid {
+        true => name.to_string(),
+        false => format!("\"{}\"", name.replace('"', "\"\"")),
+    }
+}
+
+/// Formats a value as an SQL literal, which parses back into the same value.
+pub fn literal(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("'{}'", s.replace('\'', "''")),
+        Value::Float(f) if f.is_nan() => "NAN".into(),
+        Value::Float(f) if f.is_infinite() && *f > 0.0 => "INFINITY".into(),
+        Value::Float(f) if f.is_infinite() => "-INFINITY".into(),
+        Value::Float(f) => format!("{:?}", f),
+        // The literal 9223372036854775808 overflows, so the minimum can't be negated.
+        Value::Integer(i64::MIN) => format!("({} - 1)", i64::MIN + 1),
+        value => value.to_string(),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 pub struct Column {
     pub name: String,
 
@@ -0,0 +1,271 @@
+use super::{Row, Value};
+use crate::error::Result;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// The number of rows sampled per column for histograms. Smaller tables are sampled in full.
+const SAMPLE_SIZE: usize = 10_000;
+
+/// The number of histogram buckets.
+const HISTOGRAM_BUCKETS: usize = 100;
+
+/// Table statistics, computed by ANALYZE and used by the planner's cost model. They're a
+/// snapshot as of the ANALYZE, and aren't updated by writes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TableStats {
+    pub rows: u64,
+    /// The statistics of each column, in table column order.
+    pub columns: Vec<ColumnStats>,
+}
+
+/// Column statistics.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ColumnStats {
+    /// The estimated number of distinct non-NULL values.
+    pub distinct: u64,
+    /// The fraction of rows that are NULL.
+    pub null_fraction: f64,
+    /// An equi-depth histogram of the non-NULL values, given as the sorted bucket bounds, such
+    /// that each bucket between adjacent bounds holds about the same number of values. The
+    /// first and last bounds are the minimum and maximum values. Empty if there are no values.
+    pub histogram: Vec<Value>,
+}
+
+impl TableStats {
+    /// Computes the statistics of a table's rows, given its number of columns. Distinct values
+    /// are estimated with a HyperLogLog sketch, while histograms are built from a uniform
+    /// sample of the rows. The sample is seeded, so the statistics are deterministic.
+    pub fn compute(columns: usize, rows: impl Iterator<Item = Result<Row>>) -> Result<Self> {
+        let mut sketches = vec![HyperLogLog::new(); columns];
+        let mut nulls = vec![0; columns];
+        let mut samples = vec![Vec::new(); columns];
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut count = 0;
+        for row in rows {
+            let row = row?;
+            // Reservoir sampling, where the nth row replaces a random sample with probability
+            // SAMPLE_SIZE/n.
+            let slot = match count < SAMPLE_SIZE {
+                true => Some(count),
+                false => Some(rng.gen_range(0..=count)).filter(|i| *i < SAMPLE_SIZE),
+            };
+            count += 1;
+            for (i, value) in row.into_iter().enumerate().take(columns) {
+                if value == Value::Null {
+                    nulls[i] += 1;
+                    continue;
+                }
+                sketches[i].add(&value);
+                match slot {
+                    Some(slot) if slot < samples[i].len() => samples[i][slot] = value,
+                    Some(_) => samples[i].push(value),
+                    None => {}
+                }
+            }
+        }
+        let columns = sketches
+            .into_iter()
+            .zip(nulls)
+            .zip(samples)
+            .map(|((sketch, nulls), sample)| ColumnStats {
+                distinct: sketch.estimate(),
+                null_fraction: match count {
+                    0 => 0.0,
+                    count => nulls as f64 / count as f64,
+                },
+                histogram: histogram(sample),
+            })
+            .collect();
+        Ok(Self { rows: count as u64, columns })
+    }
+}
+
+impl ColumnStats {
+    /// Estimates the fraction of non-NULL values that equal the value. Frequent values that
+    /// span several histogram buckets are given the fraction of buckets they span.
+    pub fn equal_fraction(&self, value: &Value) -> f64 {
+        if self.distinct == 0 || *value == Value::Null {
+            return 0.0;
+        }
+        let buckets = self.histogram.len().saturating_sub(1);
+        if let (Some(min), Some(max)) = (self.histogram.first(), self.histogram.last()) {
+            if value.compare(min) == Some(Ordering::Less)
+                || value.compare(max) == Some(Ordering::Greater)
+            {
+                return 0.0;
+            }
+        }
+        let spanned = match buckets {
+            0 => 0.0,
+            buckets => {
+                let equal = |b: &&Value| b.compare(value) == Some(Ordering::Equal);
+                let bounds = self.histogram.iter().filter(equal);
+                bounds.count().saturating_sub(1) as f64 / buckets as f64
+            }
+        };
+        spanned.max(1.0 / self.distinct as f64)
+    }
+
+    /// Estimates the fraction of non-NULL values that are less than the value, by
+    /// interpolating within its histogram bucket. Returns None if there's no histogram or the
+    /// value isn't comparable with the column's values.
+    pub fn less_fraction(&self, value: &Value) -> Option<f64> {
+        let (min, max) = (self.histogram.first()?, self.histogram.last()?);
+        if *value == Value::Null {
+            return None;
+        }
+        match (value.compare(min)?, value.compare(max)?) {
+            (Ordering::Less | Ordering::Equal, _) => return Some(0.0),
+            (_, Ordering::Greater) => return Some(1.0),
+            (_, _) => {}
+        }
+        // The first bound at or above the value, which is past the first.
+        let i = self.histogram.partition_point(|b| b.compare(value) == Some(Ordering::Less));
+        let (lo, hi) = (&self.histogram[i - 1], &self.histogram[i]);
+        let within = match (number(lo), number(hi), number(value)) {
+            (Some(lo), Some(hi), Some(value)) if hi > lo => (value - lo) / (hi - lo),
+            _ => 0.5,
+        };
+        Some(((i - 1) as f64 + within) / (self.histogram.len() - 1) as f64)
+    }
+}
+
+/// Returns the numeric value of integers and floats, for histogram interpolation.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(i) => Some(*i as f64),
+        Value::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+/// Builds an equi-depth histogram of sampled non-NULL values. NaN floats are incomparable,
+/// and are left out.
+fn histogram(mut sample: Vec<Value>) -> Vec<Value> {
+    sample.retain(|v| !matches!(v, Value::Float(f) if f.is_nan()));
+    sample.sort_by(|a, b| a.compare(b).unwrap_or(Ordering::Equal));
+    if sample.is_empty() {
+        return Vec::new();
+    }
+    let buckets = HISTOGRAM_BUCKETS.min(sample.len() - 1).max(1);
+    (0..=buckets).map(|i| sample[i * (sample.len() - 1) / buckets].clone()).collect()
+}
+
+/// A HyperLogLog sketch, which estimates the number of distinct values added to it using
+/// constant memory. Each value's hash selects a register by its first bits, and the register
+/// keeps the maximum number of leading zeros seen in the remaining bits. The estimate is
+/// accurate to within about 2% for 2^12 registers.
+#[derive(Clone)]
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// The number of hash bits used to select a register.
+    const PRECISION: u32 = 12;
+
+    fn new() -> Self {
+        Self { registers: vec![0; 1 << Self::PRECISION] }
+    }
+
+    fn add(&mut self, value: &Value) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let register = (hash >> (64 - Self::PRECISION)) as usize;
+        // A sentinel bit bounds the rank when the remaining bits are all zero.
+        let rest = (hash << Self::PRECISION) | (1 << (Self::PRECISION - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[register] = self.registers[register].max(rank);
+    }
+
+    fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+        // Small cardinalities are estimated more accurately by linear counting of the empty
+        // registers.
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            return (m * (m / zeros as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn hyperloglog() {
+        for n in [0, 1, 10, 100, 1000, 100_000] {
+            let mut sketch = HyperLogLog::new();
+            for i in 0..n {
+                // Duplicates don't affect the estimate.
+                sketch.add(&Value::Integer(i));
+                sketch.add(&Value::Integer(i));
+            }
+            let error = (sketch.estimate() as f64 - n as f64).abs() / (n as f64).max(1.0);
+            assert!(error < 0.03, "estimated {} for {} values", sketch.estimate(), n);
+        }
+    }
+
+    #[test]
+    fn compute() -> Result<()> {
+        let rows = (0..1000).map(|i| match i % 10 {
+            0 => Ok(vec![Value::Integer(i), Value::Null]),
+            _ => Ok(vec![Value::Integer(i), Value::String(format!("{}", i % 4))]),
+        });
+        let stats = TableStats::compute(2, rows)?;
+        assert_eq!(stats.rows, 1000);
+        let (id, name) = (&stats.columns[0], &stats.columns[1]);
+        assert!((990..=1010).contains(&id.distinct), "{}", id.distinct);
+        assert_eq!(id.null_fraction, 0.0);
+        assert_eq!(id.histogram.len(), HISTOGRAM_BUCKETS + 1);
+        assert_eq!(id.histogram.first(), Some(&Value::Integer(0)));
+        assert_eq!(id.histogram.last(), Some(&Value::Integer(999)));
+        assert_eq!(name.distinct, 4);
+        assert_eq!(name.null_fraction, 0.1);
+
+        // Values are estimated by interpolating within histogram buckets.
+        assert_eq!(id.less_fraction(&Value::Integer(-1)), Some(0.0));
+        assert_eq!(id.less_fraction(&Value::Integer(2000)), Some(1.0));
+        let below = id.less_fraction(&Value::Float(250.0)).unwrap();
+        assert!((below - 0.25).abs() < 0.01, "{}", below);
+        assert_eq!(id.less_fraction(&Value::String("a".into())), None);
+        assert!((id.equal_fraction(&Value::Integer(7)) - 0.001).abs() < 0.0001);
+        assert_eq!(id.equal_fraction(&Value::Integer(-7)), 0.0);
+        // "1" is 250 of 900 values, which the histogram picks up.
+        let equal = name.equal_fraction(&Value::String("1".into()));
+        assert!((equal - 0.278).abs() < 0.01, "{}", equal);
+        assert_eq!(name.equal_fraction(&Value::Null), 0.0);
+
+        // Integers and floats are compared numerically, for equality too.
+        let rows = (0..100).map(|i| Ok(vec![Value::Integer(if i < 50 { 5 } else { i })]));
+        let stats = TableStats::compute(1, rows)?;
+        let equal = stats.columns[0].equal_fraction(&Value::Float(5.0));
+        assert!((equal - 0.5).abs() < 0.1, "{}", equal);
+
+        // Tables larger than the sample size are sampled.
+        let stats = TableStats::compute(1, (0..50_000).map(|i| Ok(vec![Value::Integer(i)])))?;
+        let below = stats.columns[0].less_fraction(&Value::Integer(10_000)).unwrap();
+        assert!((below - 0.2).abs() < 0.02, "{}", below);
+
+        let stats = TableStats::compute(1, std::iter::empty())?;
+        assert_eq!(
+            stats,
+            TableStats {
+                rows: 0,
+                columns: vec![ColumnStats { distinct: 0, null_fraction: 0.0, histogram: vec![] }]
+            }
+        );
+        Ok(())
+    }
+}
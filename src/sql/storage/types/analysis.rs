@@ -0,0 +1,600 @@
+use super::{Datatype, Expression, Function, Value};
+use crate::error::{Error, Result};
+use crate::sql::storage::Table;
+
+use std::cell::RefCell;
+
+impl Expression {
+    /// Analyzes the expression against a table schema before it is evaluated: resolves field
+    /// names to column indexes, type-checks all operations, and folds constant subtrees. Returns
+    /// the prepared expression and its result datatype, which is None if it's always NULL.
+    pub fn analyze(self, table: &Table) -> Result<(Self, Option<Datatype>)> {
+        let expr = self.resolve(table)?;
+        let datatype = expr.datatype(table)?;
+        Ok((expr.fold()?, datatype))
+    }
+
+    /// Analyzes a filter expression, which must evaluate to a boolean or NULL.
+    pub fn analyze_filter(self, table: &Table) -> Result<Self> {
+        match self.analyze(table)? {
+            (expr, None) | (expr, Some(Datatype::Boolean)) => Ok(expr),
+            (expr, Some(datatype)) => Err(Error::Value(format!(
+                "Filter {} returns {}, expected BOOLEAN",
+                expr, datatype
+            ))),
+        }
+    }
+
+    /// Resolves named fields to their column index in the given table, and checks that
+    /// unnamed field indexes are valid.
+    pub fn resolve(self, table: &Table) -> Result<Self> {
+        self.transform(
+            &|e| match e {
+                Self::Field(_, Some((Some(t), name))) if t != table.name => Err(Error::Value(
+                    format!("Can't resolve field {}.{} in table {}", t, name, table.name),
+                )),
+                Self::Field(_, Some((t, name))) => {
+                    Ok(Self::Field(table.get_column_index(&name)?, Some((t, name))))
+                }
+                Self::Field(i, None) if i >= table.columns.len() => Err(Error::Value(format!(
+                    "Field #{} out of range for table {}",
+                    i, table.name
+                ))),
+                e => Ok(e),
+            },
+            &Ok,
+        )
+    }
+
+    /// Infers the datatype the expression evaluates to for rows of the given table, returning
+    /// an error if any operation is applied to operands it can't handle. None is the datatype of
+    /// NULL, which is a valid operand for any operation.
+    pub fn datatype(&self, table: &Table) -> Result<Option<Datatype>> {
+        self.infer(&|i| {
+            let column = table.columns.get(i).ok_or_else(|| {
+                Error::Value(format!("Field #{} out of range for table {}", i, table.name))
+            })?;
+            Ok(Some(column.datatype.clone()))
+        })
+    }
+
+    /// Like datatype(), but for rows with the given column datatypes, e.g. the output of a
+    /// plan node. A None column datatype is always NULL or unknown.
+    pub fn row_datatype(&self, columns: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+        self.infer(&|i| {
+            columns
+                .get(i)
+                .cloned()
+                .ok_or_else(|| Error::Value(format!("Field #{} out of range", i)))
+        })
+    }
+
+    /// Casts the results of conditionals (CASE, COALESCE and IF) to their common datatype, for
+    /// rows with the given column datatypes, such that the value has the inferred datatype
+    /// whichever branch is taken, e.g. COALESCE(1, 2.0) evaluates to 1.0. Conditionals whose
+    /// datatype can't be inferred here, e.g. with outer references, are left as is.
+    pub fn coerce_branches(self, columns: &[Option<Datatype>]) -> Result<Self> {
+        self.transform(&Ok, &|e| {
+            let datatype = match e.row_datatype(columns) {
+                Ok(Some(datatype)) => datatype,
+                _ => return Ok(e),
+            };
+            let cast = |e: Self| match e.row_datatype(columns) {
+                Ok(Some(d)) if d != datatype => Self::Cast(Box::new(e), datatype.clone()),
+                _ => e,
+            };
+            Ok(match e {
+                Self::Case(operand, whens, default) => Self::Case(
+                    operand,
+                    whens.into_iter().map(|(when, then)| (when, cast(then))).collect(),
+                    default.map(|d| Box::new(cast(*d))),
+                ),
+                Self::Function(name, args) => match Function::lookup(&name)?.name {
+                    "COALESCE" => Self::Function(name, args.into_iter().map(cast).collect()),
+                    "IF" => Self::Function(
+                        name,
+                        args.into_iter()
+                            .enumerate()
+                            .map(|(i, arg)| if i == 0 { arg } else { cast(arg) })
+                            .collect(),
+                    ),
+                    _ => Self::Function(name, args),
+                },
+                e => e,
+            })
+        })
+    }
+
+    /// Infers the datatypes of prepared statement parameters in the expression, for rows with
+    /// the given column datatypes. A parameter takes the datatype of the first operand with a
+    /// known datatype that it's compared or computed with, or the operand datatype of logical
+    /// and string operations. Each parameter's datatype is set unless already inferred, and
+    /// parameters whose datatype can't be inferred are added as None.
+    pub fn infer_parameters(
+        &self,
+        columns: &[Option<Datatype>],
+        parameters: &mut Vec<Option<Datatype>>,
+    ) {
+        let inferred = RefCell::new(Vec::new());
+        self.walk(&|e| {
+            let (operands, datatype): (Vec<&Self>, _) = match e {
+                Self::Parameter(_) => (vec![e], None),
+                Self::Equal(lhs, rhs)
+                | Self::GreaterThan(lhs, rhs)
+                | Self::LessThan(lhs, rhs)
+                | Self::IsDistinctFrom(lhs, rhs)
+                | Self::Add(lhs, rhs)
+                | Self::Divide(lhs, rhs)
+                | Self::Modulo(lhs, rhs)
+                | Self::Multiply(lhs, rhs)
+                | Self::Subtract(lhs, rhs) => (vec![lhs, rhs], None),
+                Self::Between(expr, low, high) => (vec![expr, low, high], None),
+                Self::In(expr, list) => (std::iter::once(&**expr).chain(list).collect(), None),
+                Self::And(lhs, rhs) | Self::Or(lhs, rhs) => {
+                    (vec![lhs, rhs], Some(Datatype::Boolean))
+                }
+                Self::Not(expr) => (vec![expr], Some(Datatype::Boolean)),
+                Self::ILike(lhs, rhs, _) | Self::Like(lhs, rhs, _) | Self::Regexp(lhs, rhs, _) => {
+                    (vec![lhs, rhs], Some(Datatype::String))
+                }
+                _ => return true,
+            };
+            // Operands whose datatype can't be inferred here, e.g. outer references, are unknown.
+            let datatype = datatype.or_else(|| {
+                operands.iter().find_map(|o| o.row_datatype(columns).ok().flatten())
+            });
+            for operand in operands {
+                if let Self::Parameter(i) = operand {
+                    inferred.borrow_mut().push((*i, datatype.clone()));
+                }
+            }
+            true
+        });
+        for (i, datatype) in inferred.into_inner() {
+            if parameters.len() <= i {
+                parameters.resize(i + 1, None);
+            }
+            if let Some(datatype) = datatype {
+                parameters[i].get_or_insert(datatype);
+            }
+        }
+    }
+
+    /// Infers the expression's datatype, given the datatype of each field index.
+    fn infer(
+        &self,
+        field: &dyn Fn(usize) -> Result<Option<Datatype>>,
+    ) -> Result<Option<Datatype>> {
+        use Datatype::*;
+        let comparable = |lhs: &Option<Datatype>, rhs: &Option<Datatype>| match (lhs, rhs) {
+            (Some(lhs), Some(rhs)) => lhs.coerce(rhs).is_some(),
+            _ => true,
+        };
+        let numeric = |d: &Option<Datatype>| matches!(d, None | Some(Integer) | Some(Float));
+
+        Ok(match self {
+            Self::Constant(value) => value.datatype(),
+            Self::Field(i, _) => field(*i)?,
+
+            // Logical operations
+            Self::And(lhs, rhs) | Self::Or(lhs, rhs) => {
+                match (lhs.infer(field)?, rhs.infer(field)?) {
+                    (None | Some(Boolean), None | Some(Boolean)) => Some(Boolean),
+                    (l, r) => {
+                        let op = if matches!(self, Self::And(..)) { "and" } else { "or" };
+                        return Err(invalid(op, &[l, r]));
+                    }
+                }
+            }
+            Self::Not(expr) => match expr.infer(field)? {
+                None | Some(Boolean) => Some(Boolean),
+                d => return Err(invalid("negate", &[d])),
+            },
+
+            // Comparison operations
+            Self::Equal(lhs, rhs)
+            | Self::GreaterThan(lhs, rhs)
+            | Self::LessThan(lhs, rhs)
+            | Self::IsDistinctFrom(lhs, rhs) => {
+                let (l, r) = (lhs.infer(field)?, rhs.infer(field)?);
+                if !comparable(&l, &r) {
+                    return Err(invalid("compare", &[l, r]));
+                }
+                Some(Boolean)
+            }
+            Self::Between(expr, low, high) => {
+                let d = expr.infer(field)?;
+                for bound in [low.infer(field)?, high.infer(field)?] {
+                    if !comparable(&d, &bound) {
+                        return Err(invalid("compare", &[d, bound]));
+                    }
+                }
+                Some(Boolean)
+            }
+            Self::In(expr, list) => {
+                let d = expr.infer(field)?;
+                for item in list {
+                    let item = item.infer(field)?;
+                    if !comparable(&d, &item) {
+                        return Err(invalid("compare", &[d, item]));
+                    }
+                }
+                Some(Boolean)
+            }
+            Self::IsNull(expr) => {
+                expr.infer(field)?;
+                Some(Boolean)
+            }
+
+            // Mathematical operations
+            Self::Add(lhs, rhs)
+            | Self::Divide(lhs, rhs)
+            | Self::Exponentiate(lhs, rhs)
+            | Self::Modulo(lhs, rhs)
+            | Self::Multiply(lhs, rhs)
+            | Self::Subtract(lhs, rhs) => match (lhs.infer(field)?, rhs.infer(field)?) {
+                (l, r) if numeric(&l) && numeric(&r) => match (l, r) {
+                    (Some(l), Some(r)) => l.coerce(&r),
+                    (l, r) => l.or(r),
+                },
+                (l, r) => {
+                    let op = match self {
+                        Self::Add(..) => "add",
+                        Self::Divide(..) => "divide",
+                        Self::Exponentiate(..) => "exponentiate",
+                        Self::Modulo(..) => "take modulo of",
+                        Self::Multiply(..) => "multiply",
+                        _ => "subtract",
+                    };
+                    return Err(invalid(op, &[l, r]));
+                }
+            },
+            Self::Assert(expr) | Self::Negate(expr) => match expr.infer(field)? {
+                d if numeric(&d) => d,
+                d if matches!(self, Self::Assert(_)) => {
+                    return Err(invalid("take the positive of", &[d]))
+                }
+                d => return Err(invalid("negate", &[d])),
+            },
+            Self::Cast(expr, datatype) => {
+                expr.infer(field)?;
+                Some(datatype.clone())
+            }
+            Self::Factorial(expr) => match expr.infer(field)? {
+                None | Some(Integer) => Some(Integer),
+                d => return Err(invalid("take factorial of", &[d])),
+            },
+
+            // String operations
+            Self::ILike(lhs, rhs, _) | Self::Like(lhs, rhs, _) | Self::Regexp(lhs, rhs, _) => {
+                match (lhs.infer(field)?, rhs.infer(field)?) {
+                    (None | Some(String), None | Some(String)) => Some(Boolean),
+                    (l, r) => {
+                        let op = match self {
+                            Self::ILike(..) => "ILIKE",
+                            Self::Like(..) => "LIKE",
+                            _ => "REGEXP",
+                        };
+                        return Err(invalid(op, &[l, r]));
+                    }
+                }
+            }
+
+            // Function calls and conditionals
+            Self::Function(name, args) => Function::lookup(name)?
+                .datatype(&args.iter().map(|a| a.infer(field)).collect::<Result<Vec<_>>>()?)?,
+            Self::Case(operand, whens, default) => {
+                let operand = operand.as_ref().map(|o| o.infer(field)).transpose()?;
+                let mut result = None;
+                for (when, then) in whens {
+                    let when = when.infer(field)?;
+                    match &operand {
+                        Some(d) if !comparable(d, &when) => {
+                            return Err(invalid("compare", &[d.clone(), when]))
+                        }
+                        None if !matches!(when, None | Some(Boolean)) => {
+                            return Err(invalid("use as CASE condition", &[when]))
+                        }
+                        _ => {}
+                    }
+                    result = common(result, then.infer(field)?)?;
+                }
+                match default {
+                    Some(default) => common(result, default.infer(field)?)?,
+                    None => result,
+                }
+            }
+
+            // Parameters are checked against their inferred datatype when they're bound, and
+            // can be NULL, so they're valid operands for any operation.
+            Self::Parameter(_) => None,
+
+            Self::Outer(..) => {
+                return Err(Error::Value(format!("Can't use {} in this context", self)))
+            }
+        })
+    }
+
+    /// Folds constant subtrees into constant values, and simplifies logical operations with a
+    /// constant boolean operand. Subtrees that fail to evaluate are left as is, so that errors
+    /// are only raised if they're actually evaluated.
+    pub fn fold(self) -> Result<Self> {
+        use Value::*;
+        self.transform(&Ok, &|e| {
+            if matches!(e, Self::Constant(_) | Self::Field(_, _) | Self::Outer(_, _)) {
+                return Ok(e);
+            }
+            if !e.contains(&|e| matches!(e, Self::Field(_, _) | Self::Outer(_, _))) {
+                return match e.evaluate(None) {
+                    Ok(value) => Ok(Self::Constant(value)),
+                    Err(_) => Ok(e),
+                };
+            }
+            Ok(match e {
+                Self::And(lhs, rhs) => match (*lhs, *rhs) {
+                    (Self::Constant(Boolean(false)), _) | (_, Self::Constant(Boolean(false))) => {
+                        Self::Constant(Boolean(false))
+                    }
+                    (Self::Constant(Boolean(true)), e) | (e, Self::Constant(Boolean(true))) => e,
+                    (lhs, rhs) => Self::And(lhs.into(), rhs.into()),
+                },
+                Self::Or(lhs, rhs) => match (*lhs, *rhs) {
+                    (Self::Constant(Boolean(true)), _) | (_, Self::Constant(Boolean(true))) => {
+                        Self::Constant(Boolean(true))
+                    }
+                    (Self::Constant(Boolean(false)), e) | (e, Self::Constant(Boolean(false))) => e,
+                    (lhs, rhs) => Self::Or(lhs.into(), rhs.into()),
+                },
+                e => e,
+            })
+        })
+    }
+}
+
+/// Returns an error for an operation applied to operands of invalid datatypes.
+fn invalid(op: &str, datatypes: &[Option<Datatype>]) -> Error {
+    Error::Value(format!(
+        "Can't {} {}",
+        op,
+        datatypes
+            .iter()
+            .map(|d| d.as_ref().map_or("NULL".to_string(), |d| d.to_string()))
+            .collect::<Vec<_>>()
+            .join(" and ")
+    ))
+}
+
+/// Returns the common datatype of two CASE branches.
+fn common(lhs: Option<Datatype>, rhs: Option<Datatype>) -> Result<Option<Datatype>> {
+    match (lhs, rhs) {
+        (Some(l), Some(r)) => match l.coerce(&r) {
+            Some(d) => Ok(Some(d)),
+            None => Err(invalid("mix CASE results", &[Some(l), Some(r)])),
+        },
+        (l, r) => Ok(l.or(r)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sql::storage::Column;
+    use pretty_assertions::assert_eq;
+
+    fn column(name: &str, datatype: Datatype) -> Column {
+        Column {
+            name: name.into(),
+            datatype,
+            primary_key: false,
+            nullalbe: true,
+            default: Some(Value::Null),
+            unique: false,
+            reference: None,
+            index: false,
+        }
+    }
+
+    fn table() -> Table {
+        Table::new(
+            "t".into(),
+            vec![
+                column("id", Datatype::Integer),
+                column("name", Datatype::String),
+                column("score", Datatype::Float),
+                column("active", Datatype::Boolean),
+            ],
+        )
+    }
+
+    fn field(name: &str) -> Box<Expression> {
+        Box::new(Expression::Field(0, Some((None, name.into()))))
+    }
+
+    fn constant(value: Value) -> Box<Expression> {
+        Box::new(Expression::Constant(value))
+    }
+
+    #[test]
+    fn resolve() -> Result<()> {
+        let table = table();
+        assert_eq!(
+            Expression::Field(0, Some((None, "score".into()))).resolve(&table)?,
+            Expression::Field(2, Some((None, "score".into())))
+        );
+        assert_eq!(
+            Expression::Field(0, Some((Some("t".into()), "active".into()))).resolve(&table)?,
+            Expression::Field(3, Some((Some("t".into()), "active".into())))
+        );
+        assert!(Expression::Field(0, Some((None, "nope".into()))).resolve(&table).is_err());
+        assert!(Expression::Field(0, Some((Some("u".into()), "id".into()))).resolve(&table).is_err());
+        assert!(Expression::Field(4, None).resolve(&table).is_err());
+        assert_eq!(Expression::Field(3, None).resolve(&table)?, Expression::Field(3, None));
+        Ok(())
+    }
+
+    #[test]
+    fn datatype() -> Result<()> {
+        use Datatype::*;
+        use Expression::*;
+        let table = table();
+        let datatype = |e: Expression| e.resolve(&table)?.datatype(&table);
+
+        assert_eq!(datatype(*field("name"))?, Some(String));
+        assert_eq!(datatype(Constant(Value::Null))?, None);
+        assert_eq!(datatype(Add(field("id"), constant(Value::Integer(1))))?, Some(Integer));
+        assert_eq!(datatype(Add(field("id"), field("score")))?, Some(Float));
+        assert_eq!(datatype(Multiply(field("id"), constant(Value::Null)))?, Some(Integer));
+        assert_eq!(datatype(Negate(field("score")))?, Some(Float));
+        assert_eq!(datatype(Equal(field("id"), field("score")))?, Some(Boolean));
+        assert_eq!(datatype(And(field("active"), constant(Value::Null)))?, Some(Boolean));
+        assert_eq!(datatype(Cast(field("id"), String))?, Some(String));
+        assert_eq!(
+            datatype(In(field("id"), vec![Constant(Value::Integer(1)), Constant(Value::Null)]))?,
+            Some(Boolean)
+        );
+        assert_eq!(
+            datatype(Between(field("score"), constant(Value::Integer(1)), field("id")))?,
+            Some(Boolean)
+        );
+        assert_eq!(
+            datatype(Function("length".into(), vec![*field("name")]))?,
+            Some(Integer)
+        );
+        assert_eq!(
+            datatype(Function("coalesce".into(), vec![*field("id"), *field("score")]))?,
+            Some(Float)
+        );
+        assert_eq!(
+            datatype(Case(
+                None,
+                vec![(*field("active"), *field("id"))],
+                Some(constant(Value::Float(0.5)))
+            ))?,
+            Some(Float)
+        );
+
+        // Row datatypes may be unknown, which is valid for any operation.
+        let columns = [Some(Integer), None];
+        let add = Add(Field(0, None).into(), Field(1, None).into());
+        assert_eq!(add.row_datatype(&columns)?, Some(Integer));
+        assert_eq!(Not(Field(1, None).into()).row_datatype(&columns)?, Some(Boolean));
+        assert_eq!(
+            Field(2, None).row_datatype(&columns),
+            Err(Error::Value("Field #2 out of range".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn datatype_errors() {
+        use Expression::*;
+        let table = table();
+        let datatype = |e: Expression| e.resolve(&table)?.datatype(&table);
+
+        assert_eq!(
+            datatype(Add(field("id"), field("name"))),
+            Err(Error::Value("Can't add INTEGER and STRING".into()))
+        );
+        assert_eq!(
+            datatype(Equal(field("active"), field("id"))),
+            Err(Error::Value("Can't compare BOOLEAN and INTEGER".into()))
+        );
+        assert_eq!(
+            datatype(And(field("active"), field("score"))),
+            Err(Error::Value("Can't and BOOLEAN and FLOAT".into()))
+        );
+        assert_eq!(
+            datatype(Not(field("id"))),
+            Err(Error::Value("Can't negate INTEGER".into()))
+        );
+        assert_eq!(
+            datatype(Like(field("id"), constant(Value::String("1%".into())), Default::default())),
+            Err(Error::Value("Can't LIKE INTEGER and STRING".into()))
+        );
+        assert_eq!(
+            datatype(Function("upper".into(), vec![*field("id")])),
+            Err(Error::Value("Can't call UPPER(INTEGER)".into()))
+        );
+        assert!(datatype(Function("upper".into(), vec![])).is_err());
+        assert!(datatype(Function("nope".into(), vec![])).is_err());
+        assert!(datatype(In(field("id"), vec![*field("name")])).is_err());
+        assert!(datatype(Factorial(field("score"))).is_err());
+        assert!(datatype(Case(None, vec![(*field("id"), *field("id"))], None)).is_err());
+        assert!(datatype(Case(
+            None,
+            vec![(*field("active"), *field("id")), (*field("active"), *field("name"))],
+            None
+        ))
+        .is_err());
+        // Type errors are found even in subtrees that might never be evaluated.
+        assert!(datatype(Function(
+            "if".into(),
+            vec![Constant(Value::Boolean(true)), *field("id"), *field("name")]
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn fold() -> Result<()> {
+        use Expression::*;
+        use Value::*;
+
+        assert_eq!(Add(constant(Integer(1)), constant(Integer(2))).fold()?, Constant(Integer(3)));
+        assert_eq!(
+            Equal(field("id"), Add(constant(Integer(1)), constant(Integer(2))).into()).fold()?,
+            Equal(field("id"), constant(Integer(3)))
+        );
+        assert_eq!(
+            Function("upper".into(), vec![Constant(String("a".into()))]).fold()?,
+            Constant(String("A".into()))
+        );
+        assert_eq!(
+            And(Equal(constant(Integer(1)), constant(Integer(1))).into(), field("active")).fold()?,
+            *field("active")
+        );
+        assert_eq!(
+            And(field("active"), constant(Boolean(false))).fold()?,
+            Constant(Boolean(false))
+        );
+        assert_eq!(Or(constant(Boolean(true)), field("active")).fold()?, Constant(Boolean(true)));
+        assert_eq!(Or(field("active"), constant(Boolean(false))).fold()?, *field("active"));
+        assert_eq!(
+            Or(field("active"), constant(Null)).fold()?,
+            Or(field("active"), constant(Null))
+        );
+
+        // Failing subtrees are left for evaluation, where they may not be reached.
+        let divide = Divide(constant(Integer(1)), constant(Integer(0)));
+        assert_eq!(divide.clone().fold()?, divide);
+        assert_eq!(
+            Function("coalesce".into(), vec![*field("id"), divide.clone()]).fold()?,
+            Function("coalesce".into(), vec![*field("id"), divide])
+        );
+        let negate = Negate(constant(Integer(i64::MIN)));
+        assert_eq!(negate.clone().fold()?, negate);
+        Ok(())
+    }
+
+    #[test]
+    fn analyze_filter() -> Result<()> {
+        use Expression::*;
+        use Value::*;
+        let table = table();
+
+        assert_eq!(
+            GreaterThan(field("score"), Add(constant(Integer(1)), constant(Integer(1))).into())
+                .analyze_filter(&table)?,
+            GreaterThan(
+                Field(2, Some((None, "score".into()))).into(),
+                constant(Integer(2))
+            )
+        );
+        assert_eq!(Constant(Null).analyze_filter(&table)?, Constant(Null));
+        assert_eq!(
+            Add(field("id"), constant(Integer(1))).analyze_filter(&table),
+            Err(Error::Value("Filter id + 1 returns INTEGER, expected BOOLEAN".into()))
+        );
+        assert!(Equal(field("id"), field("name")).analyze_filter(&table).is_err());
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
-use super::{Row, Value};
+use super::{Datatype, Function, Row, Value};
 use crate::error::{Error, Result};
 
 use regex::Regex;
 use serde_derive::{Deserialize, Serialize};
 use std::fmt::{self, Display};
 use std::mem::replace;
+use std::sync::{Arc, Mutex};
 
 /// An expression, made up of constants and operations
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -12,6 +13,11 @@ pub enum Expression {
     // Values
     Constant(Value),
     Field(usize, Option<(Option<String>, String)>),
+    /// A field of the enclosing query's row, referenced from a correlated subquery.
+    Outer(usize, Option<(Option<String>, String)>),
+    /// A prepared statement parameter, by 0-based position. Bound to a constant before
+    /// execution.
+    Parameter(usize),
 
     // Logical operations
     And(Box<Expression>, Box<Expression>),
@@ -19,14 +25,18 @@ pub enum Expression {
     Or(Box<Expression>, Box<Expression>),
 
     // Comparisons operations (GTE, LTE, and NEQ are composite operations)
+    Between(Box<Expression>, Box<Expression>, Box<Expression>),
     Equal(Box<Expression>, Box<Expression>),
     GreaterThan(Box<Expression>, Box<Expression>),
+    In(Box<Expression>, Vec<Expression>),
+    IsDistinctFrom(Box<Expression>, Box<Expression>),
     IsNull(Box<Expression>),
     LessThan(Box<Expression>, Box<Expression>),
 
     // Mathematical operations
     Add(Box<Expression>, Box<Expression>),
     Assert(Box<Expression>),
+    Cast(Box<Expression>, Datatype),
     Divide(Box<Expression>, Box<Expression>),
     Exponentiate(Box<Expression>, Box<Expression>),
     Factorial(Box<Expression>),
@@ -36,7 +46,13 @@ pub enum Expression {
     Subtract(Box<Expression>, Box<Expression>),
 
     // String operations
-    Like(Box<Expression>, Box<Expression>),
+    ILike(Box<Expression>, Box<Expression>, #[serde(skip)] PatternCache),
+    Like(Box<Expression>, Box<Expression>, #[serde(skip)] PatternCache),
+    Regexp(Box<Expression>, Box<Expression>, #[serde(skip)] PatternCache),
+
+    // Function calls and conditionals
+    Function(String, Vec<Expression>),
+    Case(Option<Box<Expression>>, Vec<(Expression, Expression)>, Option<Box<Expression>>),
 }
 
 impl Expression {
@@ -47,218 +63,360 @@ impl Expression {
             // Constant values
             Self::Constant(c) => c.clone(),
             Self::Field(i, _) => row.and_then(|row| row.get(*i).cloned()).unwrap_or(Null),
+            Self::Outer(..) => {
+                return Err(Error::Internal(format!("Unbound outer reference {}", self)))
+            }
+            Self::Parameter(_) => return Err(Error::Value(format!("Unbound parameter {}", self))),
 
             // Logical operations
-            Self::And(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Boolean(lhs), Boolean(rhs)) => Boolean(lhs && rhs),
-                (Boolean(lhs), Null) if !lhs => Boolean(false),
-                (Boolean(_), Null) => Null,
-                (Null, Boolean(rhs)) if !rhs => Boolean(false),
-                (Null, Boolean(_)) => Null,
-                (Null, Null) => Null,
-                (lhs, rhs) => return Err(Error::Value(format!("Can't and {} and {}", lhs, rhs))),
-            },
-            Self::Not(expr) => match expr.evaluate(row)? {
-                Boolean(b) => Boolean(!b),
-                Null => Null,
-                value => return Err(Error::Value(format!("Can't negate {}", value))),
-            },
-            Self::Or(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Boolean(lhs), Boolean(rhs)) => Boolean(lhs || rhs),
-                (Boolean(lhs), Null) if lhs => Boolean(true),
-                (Boolean(_), Null) => Null,
-                (Null, Boolean(rhs)) if rhs => Boolean(true),
-                (Null, Boolean(_)) => Null,
-                (Null, Null) => Null,
-                (lhs, rhs) => return Err(Error::Value(format!("Can't or {} and {}", lhs, rhs))),
-            },
+            Self::And(lhs, rhs) => Self::and(lhs.evaluate(row)?, rhs.evaluate(row)?)?,
+            Self::Not(expr) => Self::not(expr.evaluate(row)?)?,
+            Self::Or(lhs, rhs) => Self::or(lhs.evaluate(row)?, rhs.evaluate(row)?)?,
 
             // Comparison operations
-            #[allow(clippy::float_cmp)] // Up to the user if they want to compare or not
-            Self::Equal(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Boolean(lhs), Boolean(rhs)) => Boolean(lhs == rhs),
-                (Integer(lhs), Integer(rhs)) => Boolean(lhs == rhs),
-                (Integer(lhs), Float(rhs)) => Boolean(lhs as f64 == rhs),
-                (Float(lhs), Integer(rhs)) => Boolean(lhs == rhs as f64),
-                (Float(lhs), Float(rhs)) => Boolean(lhs == rhs),
-                (String(lhs), String(rhs)) => Boolean(lhs == rhs),
-                (Null, _) | (_, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
+            Self::Between(expr, low, high) => {
+                let value = expr.evaluate(row)?;
+                let below = Self::less_than(value.clone(), low.evaluate(row)?)?;
+                let above = Self::greater_than(value, high.evaluate(row)?)?;
+                match (below, above) {
+                    (Boolean(true), _) | (_, Boolean(true)) => Boolean(false),
+                    (Boolean(false), Boolean(false)) => Boolean(true),
+                    _ => Null,
                 }
-            },
-            Self::GreaterThan(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                #[allow(clippy::bool_comparison)]
-                (Boolean(lhs), Boolean(rhs)) => Boolean(lhs > rhs),
-                (Integer(lhs), Integer(rhs)) => Boolean(lhs > rhs),
-                (Integer(lhs), Float(rhs)) => Boolean(lhs as f64 > rhs),
-                (Float(lhs), Integer(rhs)) => Boolean(lhs > rhs as f64),
-                (Float(lhs), Float(rhs)) => Boolean(lhs > rhs),
-                (String(lhs), String(rhs)) => Boolean(lhs > rhs),
-                (Null, _) | (_, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
-                }
-            },
-            Self::LessThan(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                #[allow(clippy::bool_comparison)]
-                (Boolean(lhs), Boolean(rhs)) => Boolean(lhs < rhs),
-                (Integer(lhs), Integer(rhs)) => Boolean(lhs < rhs),
-                (Integer(lhs), Float(rhs)) => Boolean((lhs as f64) < rhs),
-                (Float(lhs), Integer(rhs)) => Boolean(lhs < rhs as f64),
-                (Float(lhs), Float(rhs)) => Boolean(lhs < rhs),
-                (String(lhs), String(rhs)) => Boolean(lhs < rhs),
-                (Null, _) | (_, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs)))
+            }
+            Self::Equal(lhs, rhs) => Self::equal(lhs.evaluate(row)?, rhs.evaluate(row)?)?,
+            Self::GreaterThan(lhs, rhs) => {
+                Self::greater_than(lhs.evaluate(row)?, rhs.evaluate(row)?)?
+            }
+            Self::In(expr, list) => {
+                let value = expr.evaluate(row)?;
+                let mut result = Boolean(false);
+                for item in list {
+                    match Self::equal(value.clone(), item.evaluate(row)?)? {
+                        Boolean(true) => return Ok(Boolean(true)),
+                        Boolean(false) => {}
+                        _ => result = Null,
+                    }
                 }
+                result
+            }
+            Self::IsDistinctFrom(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                (Null, Null) => Boolean(false),
+                (Null, _) | (_, Null) => Boolean(true),
+                (lhs, rhs) => match Self::equal(lhs, rhs)? {
+                    Boolean(equal) => Boolean(!equal),
+                    value => return Err(Error::Internal(format!("Unexpected equality {}", value))),
+                },
             },
             Self::IsNull(expr) => match expr.evaluate(row)? {
                 Null => Boolean(true),
                 _ => Boolean(false),
             },
+            Self::LessThan(lhs, rhs) => Self::less_than(lhs.evaluate(row)?, rhs.evaluate(row)?)?,
 
             // Mathematical operations
-            Self::Add(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Integer(lhs), Integer(rhs)) => Integer(
-                    lhs.checked_add(rhs).ok_or_else(|| Error::Value("Integer overflow".into()))?,
-                ),
-                (Integer(lhs), Float(rhs)) => Float(lhs as f64 + rhs),
-                (Integer(_), Null) => Null,
-                (Float(lhs), Float(rhs)) => Float(lhs + rhs),
-                (Float(lhs), Integer(rhs)) => Float(lhs + rhs as f64),
-                (Float(_), Null) => Null,
-                (Null, Float(_)) => Null,
-                (Null, Integer(_)) => Null,
-                (Null, Null) => Null,
-                (lhs, rhs) => return Err(Error::Value(format!("Can't add {} and {}", lhs, rhs))),
-            },
-            Self::Assert(expr) => match expr.evaluate(row)? {
-                Float(f) => Float(f),
-                Integer(i) => Integer(i),
-                Null => Null,
-                expr => return Err(Error::Value(format!("Can't take the positive of {}", expr))),
-            },
-            Self::Divide(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Integer(_), Integer(rhs)) if rhs == 0 => {
-                    return Err(Error::Value("Can't divide by zero".into()))
-                }
-                (Integer(lhs), Integer(rhs)) => Integer(lhs / rhs),
-                (Integer(lhs), Float(rhs)) => Float(lhs as f64 / rhs),
-                (Integer(_), Null) => Null,
-                (Float(lhs), Integer(rhs)) => Float(lhs / rhs as f64),
-                (Float(lhs), Float(rhs)) => Float(lhs / rhs),
-                (Float(_), Null) => Null,
-                (Null, Float(_)) => Null,
-                (Null, Integer(_)) => Null,
-                (Null, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't divide {} and {}", lhs, rhs)))
-                }
-            },
-            Self::Exponentiate(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Integer(lhs), Integer(rhs)) if rhs >= 0 => Integer(
-                    lhs.checked_pow(rhs as u32)
-                        .ok_or_else(|| Error::Value("Integer overflow".into()))?,
-                ),
-                (Integer(lhs), Integer(rhs)) => Float((lhs as f64).powf(rhs as f64)),
-                (Integer(lhs), Float(rhs)) => Float((lhs as f64).powf(rhs)),
-                (Integer(_), Null) => Null,
-                (Float(lhs), Integer(rhs)) => Float((lhs).powi(rhs as i32)),
-                (Float(lhs), Float(rhs)) => Float((lhs).powf(rhs)),
-                (Float(_), Null) => Null,
-                (Null, Float(_)) => Null,
-                (Null, Integer(_)) => Null,
-                (Null, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't exponentiate {} and {}", lhs, rhs)))
+            Self::Add(lhs, rhs) => Self::add(lhs.evaluate(row)?, rhs.evaluate(row)?)?,
+            Self::Assert(expr) => Self::assert(expr.evaluate(row)?)?,
+            Self::Cast(expr, datatype) => expr.evaluate(row)?.cast(datatype)?,
+            Self::Divide(lhs, rhs) => Self::divide(lhs.evaluate(row)?, rhs.evaluate(row)?)?,
+            Self::Exponentiate(lhs, rhs) => {
+                Self::exponentiate(lhs.evaluate(row)?, rhs.evaluate(row)?)?
+            }
+            Self::Factorial(expr) => Self::factorial(expr.evaluate(row)?)?,
+            Self::Modulo(lhs, rhs) => Self::modulo(lhs.evaluate(row)?, rhs.evaluate(row)?)?,
+            Self::Multiply(lhs, rhs) => Self::multiply(lhs.evaluate(row)?, rhs.evaluate(row)?)?,
+            Self::Negate(expr) => Self::negate(expr.evaluate(row)?)?,
+            Self::Subtract(lhs, rhs) => Self::subtract(lhs.evaluate(row)?, rhs.evaluate(row)?)?,
+
+            // String operations
+            Self::ILike(lhs, rhs, cache) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                (String(lhs), String(rhs)) => {
+                    Boolean(cache.compile(&rhs, |p| Self::like_regex(p, true))?.is_match(&lhs))
                 }
+                (String(_), Null) => Null,
+                (Null, String(_)) => Null,
+                (lhs, rhs) => return Err(Error::Value(format!("Can't ILIKE {} and {}", lhs, rhs))),
             },
-            Self::Factorial(expr) => match expr.evaluate(row)? {
-                Integer(i) if i < 0 => {
-                    return Err(Error::Value("Can't take factorial of negative number".into()))
+            Self::Like(lhs, rhs, cache) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                (String(lhs), String(rhs)) => {
+                    Boolean(cache.compile(&rhs, |p| Self::like_regex(p, false))?.is_match(&lhs))
                 }
-                Integer(i) => Integer((1..=i).fold(1, |a, b| a * b as i64)),
-                Null => Null,
-                value => return Err(Error::Value(format!("Can't take factorial of {}", value))),
+                (String(_), Null) => Null,
+                (Null, String(_)) => Null,
+                (lhs, rhs) => return Err(Error::Value(format!("Can't LIKE {} and {}", lhs, rhs))),
             },
-            Self::Modulo(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                // This uses remainder semantics, like Postgres.
-                (Integer(_), Integer(rhs)) if rhs == 0 => {
-                    return Err(Error::Value("Can't divide by zero".into()))
+            Self::Regexp(lhs, rhs, cache) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
+                (String(lhs), String(rhs)) => {
+                    Boolean(cache.compile(&rhs, |p| Ok(Regex::new(p)?))?.is_match(&lhs))
                 }
-                (Integer(lhs), Integer(rhs)) => Integer(lhs % rhs),
-                (Integer(lhs), Float(rhs)) => Float(lhs as f64 % rhs),
-                (Integer(_), Null) => Null,
-                (Float(lhs), Integer(rhs)) => Float(lhs % rhs as f64),
-                (Float(lhs), Float(rhs)) => Float(lhs % rhs),
-                (Float(_), Null) => Null,
-                (Null, Float(_)) => Null,
-                (Null, Integer(_)) => Null,
-                (Null, Null) => Null,
+                (String(_), Null) => Null,
+                (Null, String(_)) => Null,
                 (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't take modulo of {} and {}", lhs, rhs)))
+                    return Err(Error::Value(format!("Can't REGEXP {} and {}", lhs, rhs)))
                 }
             },
-            Self::Multiply(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Integer(lhs), Integer(rhs)) => Integer(
-                    lhs.checked_mul(rhs).ok_or_else(|| Error::Value("Integer overflow".into()))?,
-                ),
-                (Integer(lhs), Float(rhs)) => Float(lhs as f64 * rhs),
-                (Integer(_), Null) => Null,
-                (Float(lhs), Integer(rhs)) => Float(lhs * rhs as f64),
-                (Float(lhs), Float(rhs)) => Float(lhs * rhs),
-                (Float(_), Null) => Null,
-                (Null, Float(_)) => Null,
-                (Null, Integer(_)) => Null,
-                (Null, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't multiply {} and {}", lhs, rhs)))
+
+            // Function calls and conditionals
+            Self::Function(name, args) => Function::lookup(name)?.call(args, row)?,
+            Self::Case(operand, whens, default) => {
+                let operand = operand.as_ref().map(|o| o.evaluate(row)).transpose()?;
+                for (when, then) in whens {
+                    let matched = match &operand {
+                        Some(operand) => Self::equal(operand.clone(), when.evaluate(row)?)?,
+                        None => when.evaluate(row)?,
+                    };
+                    match matched {
+                        Boolean(true) => return then.evaluate(row),
+                        Boolean(false) | Null => {}
+                        value => {
+                            return Err(Error::Value(format!(
+                                "Can't use {} as CASE condition",
+                                value
+                            )))
+                        }
+                    }
                 }
-            },
-            Self::Negate(expr) => match expr.evaluate(row)? {
-                Integer(i) => Integer(-i),
-                Float(f) => Float(-f),
-                Null => Null,
-                value => return Err(Error::Value(format!("Can't negate {}", value))),
-            },
-            Self::Subtract(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (Integer(lhs), Integer(rhs)) => Integer(
-                    lhs.checked_sub(rhs).ok_or_else(|| Error::Value("Integer overflow".into()))?,
-                ),
-                (Integer(lhs), Float(rhs)) => Float(lhs as f64 - rhs),
-                (Integer(_), Null) => Null,
-                (Float(lhs), Integer(rhs)) => Float(lhs - rhs as f64),
-                (Float(lhs), Float(rhs)) => Float(lhs - rhs),
-                (Float(_), Null) => Null,
-                (Null, Float(_)) => Null,
-                (Null, Integer(_)) => Null,
-                (Null, Null) => Null,
-                (lhs, rhs) => {
-                    return Err(Error::Value(format!("Can't subtract {} and {}", lhs, rhs)))
+                match default {
+                    Some(default) => default.evaluate(row)?,
+                    None => Null,
                 }
-            },
+            }
+        })
+    }
 
-            // String operations
-            Self::Like(lhs, rhs) => match (lhs.evaluate(row)?, rhs.evaluate(row)?) {
-                (String(lhs), String(rhs)) => Boolean(
-                    Regex::new(&format!(
-                        "^{}$",
-                        regex::escape(&rhs)
-                            .replace("%", ".*")
-                            .replace(".*.*", "%")
-                            .replace("_", ".")
-                            .replace("..", "_")
-                    ))?
-                    .is_match(&lhs),
-                ),
-                (String(_), Null) => Null,
-                (Null, String(_)) => Null,
-                (lhs, rhs) => return Err(Error::Value(format!("Can't LIKE {} and {}", lhs, rhs))),
-            },
+    /// Evaluates lhs AND rhs using three-valued logic.
+    pub(super) fn and(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match (lhs, rhs) {
+            (Boolean(lhs), Boolean(rhs)) => Boolean(lhs && rhs),
+            (Boolean(lhs), Null) if !lhs => Boolean(false),
+            (Boolean(_), Null) => Null,
+            (Null, Boolean(rhs)) if !rhs => Boolean(false),
+            (Null, Boolean(_)) => Null,
+            (Null, Null) => Null,
+            (lhs, rhs) => return Err(Error::Value(format!("Can't and {} and {}", lhs, rhs))),
+        })
+    }
+
+    /// Evaluates lhs OR rhs using three-valued logic.
+    pub(super) fn or(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match (lhs, rhs) {
+            (Boolean(lhs), Boolean(rhs)) => Boolean(lhs || rhs),
+            (Boolean(lhs), Null) if lhs => Boolean(true),
+            (Boolean(_), Null) => Null,
+            (Null, Boolean(rhs)) if rhs => Boolean(true),
+            (Null, Boolean(_)) => Null,
+            (Null, Null) => Null,
+            (lhs, rhs) => return Err(Error::Value(format!("Can't or {} and {}", lhs, rhs))),
+        })
+    }
+
+    /// Evaluates NOT value using three-valued logic.
+    pub(super) fn not(value: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match value {
+            Boolean(b) => Boolean(!b),
+            Null => Null,
+            value => return Err(Error::Value(format!("Can't negate {}", value))),
+        })
+    }
+
+    /// Adds two numbers, after coercing them to a common datatype.
+    pub(super) fn add(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match Value::coerce(lhs, rhs)? {
+            (Integer(lhs), Integer(rhs)) => Integer(
+                lhs.checked_add(rhs).ok_or_else(|| Error::Value("Integer overflow".into()))?,
+            ),
+            (Float(lhs), Float(rhs)) => Float(lhs + rhs),
+            (Integer(_), Null) | (Float(_), Null) => Null,
+            (Null, Integer(_)) | (Null, Float(_)) | (Null, Null) => Null,
+            (lhs, rhs) => return Err(Error::Value(format!("Can't add {} and {}", lhs, rhs))),
         })
     }
 
+    /// Takes the positive of a number.
+    pub(super) fn assert(value: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match value {
+            Float(f) => Float(f),
+            Integer(i) => Integer(i),
+            Null => Null,
+            expr => return Err(Error::Value(format!("Can't take the positive of {}", expr))),
+        })
+    }
+
+    /// Divides two numbers, after coercing them to a common datatype.
+    pub(super) fn divide(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match Value::coerce(lhs, rhs)? {
+            (Integer(_), Integer(rhs)) if rhs == 0 => {
+                return Err(Error::Value("Can't divide by zero".into()))
+            }
+            (Integer(lhs), Integer(rhs)) => Integer(lhs / rhs),
+            (Float(lhs), Float(rhs)) => Float(lhs / rhs),
+            (Integer(_), Null) | (Float(_), Null) => Null,
+            (Null, Integer(_)) | (Null, Float(_)) | (Null, Null) => Null,
+            (lhs, rhs) => {
+                return Err(Error::Value(format!("Can't divide {} and {}", lhs, rhs)))
+            }
+        })
+    }
+
+    /// Exponentiates two numbers, after coercing them to a common datatype.
+    pub(super) fn exponentiate(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match Value::coerce(lhs, rhs)? {
+            (Integer(lhs), Integer(rhs)) if rhs >= 0 => Integer(
+                lhs.checked_pow(rhs as u32)
+                    .ok_or_else(|| Error::Value("Integer overflow".into()))?,
+            ),
+            (Integer(lhs), Integer(rhs)) => Float((lhs as f64).powf(rhs as f64)),
+            (Float(lhs), Float(rhs)) => Float((lhs).powf(rhs)),
+            (Integer(_), Null) | (Float(_), Null) => Null,
+            (Null, Integer(_)) | (Null, Float(_)) | (Null, Null) => Null,
+            (lhs, rhs) => {
+                return Err(Error::Value(format!("Can't exponentiate {} and {}", lhs, rhs)))
+            }
+        })
+    }
+
+    /// Takes the factorial of an integer.
+    pub(super) fn factorial(value: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match value {
+            Integer(i) if i < 0 => {
+                return Err(Error::Value("Can't take factorial of negative number".into()))
+            }
+            Integer(i) => Integer(
+                (1..=i)
+                    .try_fold(1_i64, |a, b| a.checked_mul(b))
+                    .ok_or_else(|| Error::Value("Integer overflow".into()))?,
+            ),
+            Null => Null,
+            value => return Err(Error::Value(format!("Can't take factorial of {}", value))),
+        })
+    }
+
+    /// Takes the remainder of two numbers, after coercing them to a common datatype.
+    pub(super) fn modulo(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match Value::coerce(lhs, rhs)? {
+            // This uses remainder semantics, like Postgres.
+            (Integer(_), Integer(rhs)) if rhs == 0 => {
+                return Err(Error::Value("Can't divide by zero".into()))
+            }
+            (Integer(lhs), Integer(rhs)) => Integer(lhs % rhs),
+            (Float(lhs), Float(rhs)) => Float(lhs % rhs),
+            (Integer(_), Null) | (Float(_), Null) => Null,
+            (Null, Integer(_)) | (Null, Float(_)) | (Null, Null) => Null,
+            (lhs, rhs) => {
+                return Err(Error::Value(format!("Can't take modulo of {} and {}", lhs, rhs)))
+            }
+        })
+    }
+
+    /// Multiplies two numbers, after coercing them to a common datatype.
+    pub(super) fn multiply(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match Value::coerce(lhs, rhs)? {
+            (Integer(lhs), Integer(rhs)) => Integer(
+                lhs.checked_mul(rhs).ok_or_else(|| Error::Value("Integer overflow".into()))?,
+            ),
+            (Float(lhs), Float(rhs)) => Float(lhs * rhs),
+            (Integer(_), Null) | (Float(_), Null) => Null,
+            (Null, Integer(_)) | (Null, Float(_)) | (Null, Null) => Null,
+            (lhs, rhs) => {
+                return Err(Error::Value(format!("Can't multiply {} and {}", lhs, rhs)))
+            }
+        })
+    }
+
+    /// Negates a number.
+    pub(super) fn negate(value: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match value {
+            Integer(i) => {
+                Integer(i.checked_neg().ok_or_else(|| Error::Value("Integer overflow".into()))?)
+            }
+            Float(f) => Float(-f),
+            Null => Null,
+            value => return Err(Error::Value(format!("Can't negate {}", value))),
+        })
+    }
+
+    /// Subtracts two numbers, after coercing them to a common datatype.
+    pub(super) fn subtract(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match Value::coerce(lhs, rhs)? {
+            (Integer(lhs), Integer(rhs)) => Integer(
+                lhs.checked_sub(rhs).ok_or_else(|| Error::Value("Integer overflow".into()))?,
+            ),
+            (Float(lhs), Float(rhs)) => Float(lhs - rhs),
+            (Integer(_), Null) | (Float(_), Null) => Null,
+            (Null, Integer(_)) | (Null, Float(_)) | (Null, Null) => Null,
+            (lhs, rhs) => {
+                return Err(Error::Value(format!("Can't subtract {} and {}", lhs, rhs)))
+            }
+        })
+    }
+
+    /// Compares two values for equality, after coercing them to a common datatype.
+    #[allow(clippy::float_cmp)] // Up to the user if they want to compare or not
+    pub(super) fn equal(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match Value::coerce(lhs, rhs)? {
+            (Boolean(lhs), Boolean(rhs)) => Boolean(lhs == rhs),
+            (Integer(lhs), Integer(rhs)) => Boolean(lhs == rhs),
+            (Float(lhs), Float(rhs)) => Boolean(lhs == rhs),
+            (String(lhs), String(rhs)) => Boolean(lhs == rhs),
+            (Null, _) | (_, Null) => Null,
+            (lhs, rhs) => return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs))),
+        })
+    }
+
+    /// Checks whether lhs > rhs, after coercing them to a common datatype.
+    pub(super) fn greater_than(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match Value::coerce(lhs, rhs)? {
+            #[allow(clippy::bool_comparison)]
+            (Boolean(lhs), Boolean(rhs)) => Boolean(lhs > rhs),
+            (Integer(lhs), Integer(rhs)) => Boolean(lhs > rhs),
+            (Float(lhs), Float(rhs)) => Boolean(lhs > rhs),
+            (String(lhs), String(rhs)) => Boolean(lhs > rhs),
+            (Null, _) | (_, Null) => Null,
+            (lhs, rhs) => return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs))),
+        })
+    }
+
+    /// Checks whether lhs < rhs, after coercing them to a common datatype.
+    pub(super) fn less_than(lhs: Value, rhs: Value) -> Result<Value> {
+        use Value::*;
+        Ok(match Value::coerce(lhs, rhs)? {
+            #[allow(clippy::bool_comparison)]
+            (Boolean(lhs), Boolean(rhs)) => Boolean(lhs < rhs),
+            (Integer(lhs), Integer(rhs)) => Boolean(lhs < rhs),
+            (Float(lhs), Float(rhs)) => Boolean(lhs < rhs),
+            (String(lhs), String(rhs)) => Boolean(lhs < rhs),
+            (Null, _) | (_, Null) => Null,
+            (lhs, rhs) => return Err(Error::Value(format!("Can't compare {} and {}", lhs, rhs))),
+        })
+    }
+
+    /// Compiles a LIKE pattern into an anchored regular expression.
+    fn like_regex(pattern: &str, case_insensitive: bool) -> Result<Regex> {
+        Ok(Regex::new(&format!(
+            "{}^{}$",
+            if case_insensitive { "(?i)" } else { "" },
+            regex::escape(pattern)
+                .replace("%", ".*")
+                .replace(".*.*", "%")
+                .replace("_", ".")
+                .replace("..", "_")
+        ))?)
+    }
+
     /// Walks the expression tree while calling a closure. Returns true as soon as the closure
     /// returns true. This is the inverse of walk().
     pub fn contains<F: Fn(&Expression) -> bool>(&self, visitor: &F) -> bool {
@@ -288,23 +446,60 @@ impl Expression {
             | Self::Equal(lhs, rhs)
             | Self::Exponentiate(lhs, rhs)
             | Self::GreaterThan(lhs, rhs)
+            | Self::ILike(lhs, rhs, _)
+            | Self::IsDistinctFrom(lhs, rhs)
             | Self::LessThan(lhs, rhs)
-            | Self::Like(lhs, rhs)
+            | Self::Like(lhs, rhs, _)
             | Self::Modulo(lhs, rhs)
             | Self::Multiply(lhs, rhs)
             | Self::Or(lhs, rhs)
+            | Self::Regexp(lhs, rhs, _)
             | Self::Subtract(lhs, rhs) => {
                 Self::replace_with(lhs, |e| e.transform(before, after))?;
                 Self::replace_with(rhs, |e| e.transform(before, after))?;
             }
 
+            Self::Between(expr, low, high) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                Self::replace_with(low, |e| e.transform(before, after))?;
+                Self::replace_with(high, |e| e.transform(before, after))?;
+            }
+            Self::In(expr, list) => {
+                Self::replace_with(expr, |e| e.transform(before, after))?;
+                for item in list {
+                    Self::replace_with(item, |e| e.transform(before, after))?;
+                }
+            }
+
             Self::Assert(expr)
+            | Self::Cast(expr, _)
             | Self::Factorial(expr)
             | Self::IsNull(expr)
             | Self::Negate(expr)
             | Self::Not(expr) => Self::replace_with(expr, |e| e.transform(before, after))?,
 
-            Self::Constant(_) | Self::Field(_, _) => {}
+            Self::Function(_, args) => {
+                for arg in args {
+                    Self::replace_with(arg, |e| e.transform(before, after))?;
+                }
+            }
+            Self::Case(operand, whens, default) => {
+                if let Some(operand) = operand {
+                    Self::replace_with(operand, |e| e.transform(before, after))?;
+                }
+                for (when, then) in whens {
+                    Self::replace_with(when, |e| e.transform(before, after))?;
+                    Self::replace_with(then, |e| e.transform(before, after))?;
+                }
+                if let Some(default) = default {
+                    Self::replace_with(default, |e| e.transform(before, after))?;
+                }
+            }
+
+            Self::Constant(_)
+            | Self::Field(_, _)
+            | Self::Outer(_, _)
+            | Self::Parameter(_) => {}
         };
         after(self)
     }
@@ -319,20 +514,39 @@ impl Expression {
                 | Self::Equal(lhs, rhs)
                 | Self::Exponentiate(lhs, rhs)
                 | Self::GreaterThan(lhs, rhs)
+                | Self::ILike(lhs, rhs, _)
+                | Self::IsDistinctFrom(lhs, rhs)
                 | Self::LessThan(lhs, rhs)
-                | Self::Like(lhs, rhs)
+                | Self::Like(lhs, rhs, _)
                 | Self::Modulo(lhs, rhs)
                 | Self::Multiply(lhs, rhs)
                 | Self::Or(lhs, rhs)
+                | Self::Regexp(lhs, rhs, _)
                 | Self::Subtract(lhs, rhs) => lhs.walk(visitor) && rhs.walk(visitor),
 
+                Self::Between(expr, low, high) => {
+                    expr.walk(visitor) && low.walk(visitor) && high.walk(visitor)
+                }
+                Self::In(expr, list) => expr.walk(visitor) && list.iter().all(|i| i.walk(visitor)),
+
                 Self::Assert(expr)
+                | Self::Cast(expr, _)
                 | Self::Factorial(expr)
                 | Self::IsNull(expr)
                 | Self::Negate(expr)
                 | Self::Not(expr) => expr.walk(visitor),
 
-                Self::Constant(_) | Self::Field(_, _) => true,
+                Self::Function(_, args) => args.iter().all(|arg| arg.walk(visitor)),
+                Self::Case(operand, whens, default) => {
+                    operand.as_ref().is_none_or(|o| o.walk(visitor))
+                        && whens.iter().all(|(w, t)| w.walk(visitor) && t.walk(visitor))
+                        && default.as_ref().is_none_or(|d| d.walk(visitor))
+                }
+
+                Self::Constant(_)
+                | Self::Field(_, _)
+                | Self::Outer(_, _)
+                | Self::Parameter(_) => true,
             }
     }
 
@@ -464,17 +678,30 @@ impl Expression {
     }
 
     // Checks if the expression is a field lookup, and returns the list of values looked up.
-    // Expressions must be a combination of =, IS NULL, OR to be converted.
+    // Expressions must be a combination of =, IN, IS NULL, OR to be converted. Since = NULL
+    // never matches, NULL constants are omitted from the values.
     pub fn as_lookup(&self, field: usize) -> Option<Vec<Value>> {
         use Expression::*;
+        let constant = |v: &Value| if v == &Value::Null { vec![] } else { vec![v.clone()] };
         // FIXME This should use a single match level, but since the child expressions are boxed
         // that would require box patterns, which are unstable.
         match &*self {
             Equal(lhs, rhs) => match (&**lhs, &**rhs) {
-                (Field(i, _), Constant(v)) if i == &field => Some(vec![v.clone()]),
-                (Constant(v), Field(i, _)) if i == &field => Some(vec![v.clone()]),
+                (Field(i, _), Constant(v)) if i == &field => Some(constant(v)),
+                (Constant(v), Field(i, _)) if i == &field => Some(constant(v)),
                 (_, _) => None,
             },
+            In(expr, list) => match &**expr {
+                Field(i, _) if i == &field => list
+                    .iter()
+                    .map(|e| match e {
+                        Constant(v) => Some(constant(v)),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .map(|values| values.concat()),
+                _ => None,
+            },
             IsNull(e) => match &**e {
                 Field(i, _) if i == &field => Some(vec![Value::Null]),
                 _ => None,
@@ -517,25 +744,73 @@ impl Expression {
     }
 }
 
+/// A cache of the most recently compiled pattern of a pattern-matching expression, to avoid
+/// recompiling the regex for every row when the pattern is constant. Clones share the cache.
+/// It is ignored when comparing or serializing expressions.
+#[derive(Clone, Default)]
+pub struct PatternCache(Arc<Mutex<Option<(String, Regex)>>>);
+
+impl PatternCache {
+    /// Returns the compiled regex for the given pattern, compiling and caching it if needed.
+    fn compile<F>(&self, pattern: &str, compile: F) -> Result<Regex>
+    where
+        F: FnOnce(&str) -> Result<Regex>,
+    {
+        let mut cache = self.0.lock()?;
+        match &*cache {
+            Some((cached, regex)) if cached == pattern => Ok(regex.clone()),
+            _ => {
+                let regex = compile(pattern)?;
+                *cache = Some((pattern.to_string(), regex.clone()));
+                Ok(regex)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for PatternCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PatternCache")
+    }
+}
+
+impl PartialEq for PatternCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
 impl Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             Self::Constant(v) => v.to_string(),
-            Self::Field(i, None) => format!("#{}", i),
-            Self::Field(_, Some((None, name))) => name.to_string(),
-            Self::Field(_, Some((Some(table), name))) => format!("{}.{}", table, name),
+            Self::Field(i, None) | Self::Outer(i, None) => format!("#{}", i),
+            Self::Field(_, Some((None, name))) | Self::Outer(_, Some((None, name))) => {
+                name.to_string()
+            }
+            Self::Field(_, Some((Some(table), name)))
+            | Self::Outer(_, Some((Some(table), name))) => format!("{}.{}", table, name),
+            Self::Parameter(i) => format!("${}", i + 1),
 
             Self::And(lhs, rhs) => format!("{} AND {}", lhs, rhs),
             Self::Or(lhs, rhs) => format!("{} OR {}", lhs, rhs),
             Self::Not(expr) => format!("NOT {}", expr),
 
+            Self::Between(expr, low, high) => format!("{} BETWEEN {} AND {}", expr, low, high),
             Self::Equal(lhs, rhs) => format!("{} = {}", lhs, rhs),
             Self::GreaterThan(lhs, rhs) => format!("{} > {}", lhs, rhs),
+            Self::In(expr, list) => format!(
+                "{} IN ({})",
+                expr,
+                list.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+            Self::IsDistinctFrom(lhs, rhs) => format!("{} IS DISTINCT FROM {}", lhs, rhs),
             Self::LessThan(lhs, rhs) => format!("{} < {}", lhs, rhs),
             Self::IsNull(expr) => format!("{} IS NULL", expr),
 
             Self::Add(lhs, rhs) => format!("{} + {}", lhs, rhs),
             Self::Assert(expr) => expr.to_string(),
+            Self::Cast(expr, datatype) => format!("CAST({} AS {})", expr, datatype),
             Self::Divide(lhs, rhs) => format!("{} / {}", lhs, rhs),
             Self::Exponentiate(lhs, rhs) => format!("{} ^ {}", lhs, rhs),
             Self::Factorial(expr) => format!("!{}", expr),
@@ -544,8 +819,364 @@ impl Display for Expression {
             Self::Negate(expr) => format!("-{}", expr),
             Self::Subtract(lhs, rhs) => format!("{} - {}", lhs, rhs),
 
-            Self::Like(lhs, rhs) => format!("{} LIKE {}", lhs, rhs),
+            Self::ILike(lhs, rhs, _) => format!("{} ILIKE {}", lhs, rhs),
+            Self::Like(lhs, rhs, _) => format!("{} LIKE {}", lhs, rhs),
+            Self::Regexp(lhs, rhs, _) => format!("{} REGEXP {}", lhs, rhs),
+
+            Self::Function(name, args) => format!(
+                "{}({})",
+                name.to_uppercase(),
+                args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
+            ),
+            Self::Case(operand, whens, default) => {
+                let mut s = "CASE".to_string();
+                if let Some(operand) = operand {
+                    s += &format!(" {}", operand);
+                }
+                for (when, then) in whens {
+                    s += &format!(" WHEN {} THEN {}", when, then);
+                }
+                if let Some(default) = default {
+                    s += &format!(" ELSE {}", default);
+                }
+                s + " END"
+            }
         };
         write!(f, "{}", s)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn constant(value: Value) -> Box<Expression> {
+        Box::new(Expression::Constant(value))
+    }
+
+    fn cast(value: Value, datatype: Datatype) -> Result<Value> {
+        Expression::Cast(constant(value), datatype).evaluate(None)
+    }
+
+    #[test]
+    fn cast_null() -> Result<()> {
+        for datatype in [Datatype::Boolean, Datatype::Integer, Datatype::Float, Datatype::String] {
+            assert_eq!(cast(Value::Null, datatype)?, Value::Null);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn cast_boolean() -> Result<()> {
+        use Value::*;
+        assert_eq!(cast(Boolean(true), Datatype::Boolean)?, Boolean(true));
+        assert_eq!(cast(Boolean(true), Datatype::Integer)?, Integer(1));
+        assert_eq!(cast(Boolean(false), Datatype::Integer)?, Integer(0));
+        assert_eq!(cast(Boolean(true), Datatype::Float)?, Float(1.0));
+        assert_eq!(cast(Boolean(false), Datatype::Float)?, Float(0.0));
+        assert_eq!(cast(Boolean(true), Datatype::String)?, String("TRUE".into()));
+        assert_eq!(cast(Boolean(false), Datatype::String)?, String("FALSE".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn cast_integer() -> Result<()> {
+        use Value::*;
+        assert_eq!(cast(Integer(0), Datatype::Boolean)?, Boolean(false));
+        assert_eq!(cast(Integer(-3), Datatype::Boolean)?, Boolean(true));
+        assert_eq!(cast(Integer(7), Datatype::Integer)?, Integer(7));
+        assert_eq!(cast(Integer(-7), Datatype::Float)?, Float(-7.0));
+        assert_eq!(cast(Integer(42), Datatype::String)?, String("42".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn cast_float() -> Result<()> {
+        use Value::*;
+        assert_eq!(cast(Float(0.0), Datatype::Boolean)?, Boolean(false));
+        assert_eq!(cast(Float(0.1), Datatype::Boolean)?, Boolean(true));
+        assert!(cast(Float(f64::NAN), Datatype::Boolean).is_err());
+        assert_eq!(cast(Float(2.4), Datatype::Integer)?, Integer(2));
+        assert_eq!(cast(Float(2.5), Datatype::Integer)?, Integer(3));
+        assert_eq!(cast(Float(-2.5), Datatype::Integer)?, Integer(-3));
+        assert!(cast(Float(f64::NAN), Datatype::Integer).is_err());
+        assert!(cast(Float(f64::INFINITY), Datatype::Integer).is_err());
+        assert!(cast(Float(1e20), Datatype::Integer).is_err());
+        assert_eq!(cast(Float(1.5), Datatype::Float)?, Float(1.5));
+        assert_eq!(cast(Float(1.5), Datatype::String)?, String("1.5".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn cast_string() -> Result<()> {
+        use Value::*;
+        for s in ["true", "TRUE", " t ", "1"] {
+            assert_eq!(cast(String(s.into()), Datatype::Boolean)?, Boolean(true));
+        }
+        for s in ["false", "False", "f", "0"] {
+            assert_eq!(cast(String(s.into()), Datatype::Boolean)?, Boolean(false));
+        }
+        assert!(cast(String("yes please".into()), Datatype::Boolean).is_err());
+        assert_eq!(cast(String(" 42 ".into()), Datatype::Integer)?, Integer(42));
+        assert_eq!(cast(String("-1".into()), Datatype::Integer)?, Integer(-1));
+        assert!(cast(String("4.2".into()), Datatype::Integer).is_err());
+        assert!(cast(String("abc".into()), Datatype::Integer).is_err());
+        assert_eq!(cast(String("4.2".into()), Datatype::Float)?, Float(4.2));
+        assert_eq!(cast(String("1e3".into()), Datatype::Float)?, Float(1000.0));
+        assert!(cast(String("abc".into()), Datatype::Float).is_err());
+        assert_eq!(cast(String("abc".into()), Datatype::String)?, String("abc".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn coerce_table() {
+        use Datatype::*;
+        let datatypes = [Boolean, Integer, Float, String];
+        for lhs in &datatypes {
+            for rhs in &datatypes {
+                let expect = match (lhs, rhs) {
+                    (l, r) if l == r => Some(l.clone()),
+                    (Integer, Float) | (Float, Integer) => Some(Float),
+                    _ => None,
+                };
+                assert_eq!(lhs.coerce(rhs), expect, "coercing {} and {}", lhs, rhs);
+                assert_eq!(rhs.coerce(lhs), expect, "coercing {} and {}", rhs, lhs);
+            }
+        }
+    }
+
+    #[test]
+    fn coerce_comparison() -> Result<()> {
+        use Value::*;
+        let eq = |l, r| Expression::Equal(constant(l), constant(r)).evaluate(None);
+        let gt = |l, r| Expression::GreaterThan(constant(l), constant(r)).evaluate(None);
+        let lt = |l, r| Expression::LessThan(constant(l), constant(r)).evaluate(None);
+
+        assert_eq!(eq(Integer(1), Float(1.0))?, Boolean(true));
+        assert_eq!(eq(Float(1.0), Integer(1))?, Boolean(true));
+        assert_eq!(gt(Integer(2), Float(1.5))?, Boolean(true));
+        assert_eq!(lt(Float(1.5), Integer(2))?, Boolean(true));
+        assert_eq!(eq(Integer(1), Null)?, Null);
+        assert_eq!(gt(Null, String("a".into()))?, Null);
+        assert_eq!(lt(Boolean(false), Boolean(true))?, Boolean(true));
+        assert_eq!(lt(String("a".into()), String("b".into()))?, Boolean(true));
+
+        assert_eq!(
+            eq(Integer(1), String("1".into())),
+            Err(Error::Value("Can't compare 1 and 1".into()))
+        );
+        assert!(gt(Boolean(true), Integer(1)).is_err());
+        assert!(lt(String("1.0".into()), Float(1.0)).is_err());
+
+        // Explicit casts make mixed comparisons possible.
+        assert_eq!(
+            Expression::Equal(
+                constant(Integer(1)),
+                Expression::Cast(constant(String("1".into())), Datatype::Integer).into(),
+            )
+            .evaluate(None)?,
+            Boolean(true)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn coerce_arithmetic() -> Result<()> {
+        use Value::*;
+        let add = |l, r| Expression::Add(constant(l), constant(r)).evaluate(None);
+        let sub = |l, r| Expression::Subtract(constant(l), constant(r)).evaluate(None);
+        let mul = |l, r| Expression::Multiply(constant(l), constant(r)).evaluate(None);
+        let div = |l, r| Expression::Divide(constant(l), constant(r)).evaluate(None);
+        let rem = |l, r| Expression::Modulo(constant(l), constant(r)).evaluate(None);
+        let pow = |l, r| Expression::Exponentiate(constant(l), constant(r)).evaluate(None);
+
+        assert_eq!(add(Integer(1), Integer(2))?, Integer(3));
+        assert_eq!(add(Integer(1), Float(0.5))?, Float(1.5));
+        assert_eq!(sub(Float(2.5), Integer(1))?, Float(1.5));
+        assert_eq!(mul(Integer(2), Float(1.5))?, Float(3.0));
+        assert_eq!(div(Integer(7), Integer(2))?, Integer(3));
+        assert_eq!(div(Integer(7), Float(2.0))?, Float(3.5));
+        assert_eq!(rem(Integer(7), Float(2.0))?, Float(1.0));
+        assert_eq!(pow(Integer(2), Integer(3))?, Integer(8));
+        assert_eq!(pow(Integer(2), Integer(-1))?, Float(0.5));
+        assert_eq!(pow(Float(2.0), Integer(2))?, Float(4.0));
+
+        for op in [add, sub, mul, div, rem, pow] {
+            assert_eq!(op(Integer(1), Null)?, Null);
+            assert_eq!(op(Null, Float(1.0))?, Null);
+            assert_eq!(op(Null, Null)?, Null);
+            assert!(op(Integer(1), String("1".into())).is_err());
+            assert!(op(Boolean(true), Integer(1)).is_err());
+            assert!(op(Boolean(true), Null).is_err());
+        }
+
+        assert!(div(Integer(1), Integer(0)).is_err());
+        assert!(add(Integer(i64::MAX), Integer(1)).is_err());
+        assert_eq!(
+            Expression::Negate(constant(Integer(i64::MIN))).evaluate(None),
+            Err(Error::Value("Integer overflow".into()))
+        );
+        assert_eq!(
+            Expression::Negate(constant(Integer(i64::MAX))).evaluate(None)?,
+            Integer(-i64::MAX)
+        );
+        assert_eq!(
+            add(String("a".into()), Integer(1)),
+            Err(Error::Value("Can't add a and 1".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn in_truth_table() -> Result<()> {
+        use Value::*;
+        let in_list = |value: Value, list: Vec<Value>| {
+            Expression::In(constant(value), list.into_iter().map(Expression::Constant).collect())
+                .evaluate(None)
+        };
+        assert_eq!(in_list(Integer(1), vec![Integer(1), Integer(2)])?, Boolean(true));
+        assert_eq!(in_list(Integer(3), vec![Integer(1), Integer(2)])?, Boolean(false));
+        assert_eq!(in_list(Integer(1), vec![Null, Integer(1)])?, Boolean(true));
+        assert_eq!(in_list(Integer(3), vec![Integer(1), Null])?, Null);
+        assert_eq!(in_list(Null, vec![Integer(1), Integer(2)])?, Null);
+        assert_eq!(in_list(Null, vec![])?, Boolean(false));
+        assert_eq!(in_list(Integer(1), vec![])?, Boolean(false));
+        assert_eq!(in_list(Integer(2), vec![Float(2.0)])?, Boolean(true));
+        assert!(in_list(Integer(1), vec![String("1".into())]).is_err());
+
+        // NOT IN follows from NOT over the three-valued result.
+        let list = vec![Expression::Constant(Integer(1)), Expression::Constant(Null)];
+        let not_in = Expression::Not(Expression::In(constant(Integer(3)), list).into());
+        assert_eq!(not_in.evaluate(None)?, Null);
+        Ok(())
+    }
+
+    #[test]
+    fn between_truth_table() -> Result<()> {
+        use Value::*;
+        let between = |value, low, high| {
+            Expression::Between(constant(value), constant(low), constant(high)).evaluate(None)
+        };
+        assert_eq!(between(Integer(2), Integer(1), Integer(3))?, Boolean(true));
+        assert_eq!(between(Integer(1), Integer(1), Integer(3))?, Boolean(true));
+        assert_eq!(between(Integer(3), Integer(1), Integer(3))?, Boolean(true));
+        assert_eq!(between(Integer(0), Integer(1), Integer(3))?, Boolean(false));
+        assert_eq!(between(Integer(4), Integer(1), Integer(3))?, Boolean(false));
+        assert_eq!(between(Integer(2), Integer(3), Integer(1))?, Boolean(false));
+        assert_eq!(between(Float(1.5), Integer(1), Integer(2))?, Boolean(true));
+        assert_eq!(
+            between(String("b".into()), String("a".into()), String("c".into()))?,
+            Boolean(true)
+        );
+
+        // NULL bounds only yield NULL if the other bound doesn't already rule the value out.
+        assert_eq!(between(Null, Integer(1), Integer(3))?, Null);
+        assert_eq!(between(Integer(2), Null, Integer(3))?, Null);
+        assert_eq!(between(Integer(2), Integer(1), Null)?, Null);
+        assert_eq!(between(Integer(0), Integer(1), Null)?, Boolean(false));
+        assert_eq!(between(Integer(4), Null, Integer(3))?, Boolean(false));
+        assert!(between(Integer(1), String("a".into()), Integer(3)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn is_distinct_from_truth_table() -> Result<()> {
+        use Value::*;
+        let distinct =
+            |lhs, rhs| Expression::IsDistinctFrom(constant(lhs), constant(rhs)).evaluate(None);
+        assert_eq!(distinct(Integer(1), Integer(1))?, Boolean(false));
+        assert_eq!(distinct(Integer(1), Integer(2))?, Boolean(true));
+        assert_eq!(distinct(Integer(1), Float(1.0))?, Boolean(false));
+        assert_eq!(distinct(Integer(1), Null)?, Boolean(true));
+        assert_eq!(distinct(Null, Integer(1))?, Boolean(true));
+        assert_eq!(distinct(Null, Null)?, Boolean(false));
+        assert_eq!(distinct(Null, String("a".into()))?, Boolean(true));
+        assert!(distinct(Integer(1), String("1".into())).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn pattern_truth_table() -> Result<()> {
+        use Value::*;
+        let s = |s: &str| String(s.into());
+        let like = |lhs, rhs| {
+            Expression::Like(constant(lhs), constant(rhs), PatternCache::default()).evaluate(None)
+        };
+        let ilike = |lhs, rhs| {
+            Expression::ILike(constant(lhs), constant(rhs), PatternCache::default()).evaluate(None)
+        };
+        let regexp = |lhs, rhs| {
+            Expression::Regexp(constant(lhs), constant(rhs), PatternCache::default()).evaluate(None)
+        };
+
+        assert_eq!(like(s("abc"), s("a%"))?, Boolean(true));
+        assert_eq!(like(s("abc"), s("A%"))?, Boolean(false));
+        assert_eq!(like(s("abc"), s("a_c"))?, Boolean(true));
+        assert_eq!(like(s("a.c"), s("a.c"))?, Boolean(true));
+        assert_eq!(like(s("abc"), s("a.c"))?, Boolean(false));
+        assert_eq!(ilike(s("abc"), s("A%"))?, Boolean(true));
+        assert_eq!(ilike(s("ABC"), s("a_c"))?, Boolean(true));
+        assert_eq!(ilike(s("abd"), s("a_c"))?, Boolean(false));
+        assert_eq!(regexp(s("abc123"), s("[0-9]+"))?, Boolean(true));
+        assert_eq!(regexp(s("abc"), s("^b"))?, Boolean(false));
+        assert!(regexp(s("abc"), s("(")).is_err());
+
+        for op in [like, ilike, regexp] {
+            assert_eq!(op(s("a"), Null)?, Null);
+            assert_eq!(op(Null, s("a"))?, Null);
+            assert!(op(Integer(1), s("1")).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn pattern_cache() -> Result<()> {
+        use Value::*;
+        let row = |s: &str, p: &str| vec![String(s.into()), String(p.into())];
+        let expr = Expression::Regexp(
+            Expression::Field(0, None).into(),
+            Expression::Field(1, None).into(),
+            PatternCache::default(),
+        );
+        assert_eq!(expr.evaluate(Some(&row("abc", "^a")))?, Boolean(true));
+        assert_eq!(expr.evaluate(Some(&row("bbc", "^a")))?, Boolean(false));
+        assert_eq!(expr.evaluate(Some(&row("bbc", "^b")))?, Boolean(true));
+
+        // The cache is ignored by equality and serialization.
+        let other = Expression::Regexp(
+            Expression::Field(0, None).into(),
+            Expression::Field(1, None).into(),
+            PatternCache::default(),
+        );
+        assert_eq!(expr, other);
+        let decoded: Expression = bincode::deserialize(&bincode::serialize(&expr)?)?;
+        assert_eq!(decoded, expr);
+        assert_eq!(decoded.evaluate(Some(&row("abc", "^a")))?, Boolean(true));
+        Ok(())
+    }
+
+    #[test]
+    fn three_valued_display() {
+        use Value::*;
+        let field = || Box::new(Expression::Field(0, Some((None, "a".into()))));
+        let list = vec![Expression::Constant(Integer(1)), Expression::Constant(Integer(2))];
+        assert_eq!(Expression::In(field(), list).to_string(), "a IN (1, 2)");
+        assert_eq!(
+            Expression::Between(field(), constant(Integer(1)), constant(Integer(2))).to_string(),
+            "a BETWEEN 1 AND 2"
+        );
+        assert_eq!(
+            Expression::IsDistinctFrom(field(), constant(Null)).to_string(),
+            "a IS DISTINCT FROM NULL"
+        );
+    }
+
+    #[test]
+    fn cast_display() {
+        assert_eq!(
+            Expression::Cast(constant(Value::Integer(1)), Datatype::String).to_string(),
+            "CAST(1 AS STRING)"
+        );
+    }
+}
@@ -0,0 +1,611 @@
+use super::{Datatype, Expression, Row, Value};
+use crate::error::{Error, Result};
+
+/// A built-in scalar function, called via Expression::Function.
+pub struct Function {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: Option<usize>,
+    body: Body,
+    signature: Signature,
+}
+
+/// Infers a function's result datatype from its argument datatypes, or errors if the arguments
+/// have invalid types. None is the datatype of NULL, which is valid for any argument.
+type Signature = fn(&str, &[Option<Datatype>]) -> Result<Option<Datatype>>;
+
+/// How a function's arguments are evaluated before it is called.
+enum Body {
+    /// Called with evaluated arguments. Returns NULL without calling if any argument is NULL,
+    /// like the operators do.
+    Strict(fn(&str, Vec<Value>) -> Result<Value>),
+    /// Called with evaluated arguments, including NULLs.
+    Nullable(fn(&str, Vec<Value>) -> Result<Value>),
+    /// Called with unevaluated arguments, for conditionals that only evaluate some of them.
+    Lazy(fn(&str, &[Expression], Option<&Row>) -> Result<Value>),
+}
+
+/// The function registry. Names are matched case-insensitively.
+const FUNCTIONS: &[Function] = &[
+    // String functions
+    Function::new("UPPER", 1, Some(1), Body::Strict(upper), strings_to_string),
+    Function::new("LOWER", 1, Some(1), Body::Strict(lower), strings_to_string),
+    Function::new("LENGTH", 1, Some(1), Body::Strict(length), string_to_integer),
+    Function::new("SUBSTR", 2, Some(3), Body::Strict(substr), substr_signature),
+    Function::new("TRIM", 1, Some(1), Body::Strict(trim), strings_to_string),
+    Function::new("CONCAT", 1, None, Body::Strict(concat), any_to_string),
+    Function::new("REPLACE", 3, Some(3), Body::Strict(replace), strings_to_string),
+    // Math functions
+    Function::new("ABS", 1, Some(1), Body::Strict(abs), numeric_to_same),
+    Function::new("ROUND", 1, Some(2), Body::Strict(round), round_signature),
+    Function::new("FLOOR", 1, Some(1), Body::Strict(floor), numeric_to_same),
+    Function::new("CEIL", 1, Some(1), Body::Strict(ceil), numeric_to_same),
+    Function::new("SQRT", 1, Some(1), Body::Strict(sqrt), numeric_to_float),
+    Function::new("POW", 2, Some(2), Body::Strict(pow), common_numeric),
+    // NULL handling
+    Function::new("COALESCE", 1, None, Body::Lazy(coalesce), common),
+    Function::new("NULLIF", 2, Some(2), Body::Nullable(nullif), nullif_signature),
+    // Conditionals
+    Function::new("IF", 3, Some(3), Body::Lazy(iff), if_signature),
+];
+
+impl Function {
+    const fn new(
+        name: &'static str,
+        min_args: usize,
+        max_args: Option<usize>,
+        body: Body,
+        signature: Signature,
+    ) -> Self {
+        Self { name, min_args, max_args, body, signature }
+    }
+
+    /// Looks up a built-in function by name.
+    pub fn lookup(name: &str) -> Result<&'static Function> {
+        FUNCTIONS
+            .iter()
+            .find(|f| f.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| Error::Value(format!("Unknown function {}", name)))
+    }
+
+    /// Checks that the function can be called with the given number of arguments.
+    pub fn check_arity(&self, args: usize) -> Result<()> {
+        if args < self.min_args || self.max_args.is_some_and(|max| args > max) {
+            return Err(Error::Value(format!(
+                "Function {} takes {} arguments, got {}",
+                self.name,
+                match self.max_args {
+                    Some(max) if max == self.min_args => max.to_string(),
+                    Some(max) => format!("{} to {}", self.min_args, max),
+                    None => format!("at least {}", self.min_args),
+                },
+                args
+            )));
+        }
+        Ok(())
+    }
+
+    /// Infers the function's result datatype for the given argument datatypes.
+    pub fn datatype(&self, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+        self.check_arity(args.len())?;
+        (self.signature)(self.name, args)
+    }
+
+    /// Calls the function with the given arguments, evaluating them as needed.
+    pub fn call(&self, args: &[Expression], row: Option<&Row>) -> Result<Value> {
+        self.check_arity(args.len())?;
+        match self.body {
+            Body::Strict(f) => {
+                let args = args.iter().map(|a| a.evaluate(row)).collect::<Result<Vec<_>>>()?;
+                if args.contains(&Value::Null) {
+                    return Ok(Value::Null);
+                }
+                f(self.name, args)
+            }
+            Body::Nullable(f) => {
+                f(self.name, args.iter().map(|a| a.evaluate(row)).collect::<Result<_>>()?)
+            }
+            Body::Lazy(f) => f(self.name, args, row),
+        }
+    }
+}
+
+/// Returns an error for a function called with invalid arguments.
+fn invalid(name: &str, args: &[Value]) -> Error {
+    Error::Value(format!(
+        "Can't call {}({})",
+        name,
+        args.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")
+    ))
+}
+
+/// Returns an error for a function called with arguments of invalid datatypes.
+fn invalid_types(name: &str, args: &[Option<Datatype>]) -> Error {
+    Error::Value(format!(
+        "Can't call {}({})",
+        name,
+        args.iter()
+            .map(|a| a.as_ref().map_or("NULL".to_string(), |a| a.to_string()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))
+}
+
+fn is_numeric(datatype: &Option<Datatype>) -> bool {
+    matches!(datatype, None | Some(Datatype::Integer) | Some(Datatype::Float))
+}
+
+fn strings_to_string(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    match args.iter().all(|a| matches!(a, None | Some(Datatype::String))) {
+        true => Ok(Some(Datatype::String)),
+        false => Err(invalid_types(name, args)),
+    }
+}
+
+fn string_to_integer(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    strings_to_string(name, args).map(|_| Some(Datatype::Integer))
+}
+
+fn substr_signature(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    match args {
+        [None | Some(Datatype::String), rest @ ..]
+            if rest.iter().all(|a| matches!(a, None | Some(Datatype::Integer))) =>
+        {
+            Ok(Some(Datatype::String))
+        }
+        _ => Err(invalid_types(name, args)),
+    }
+}
+
+fn any_to_string(_: &str, _: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    Ok(Some(Datatype::String))
+}
+
+fn numeric_to_same(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    match args {
+        [arg] if is_numeric(arg) => Ok(arg.clone()),
+        _ => Err(invalid_types(name, args)),
+    }
+}
+
+fn round_signature(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    match args {
+        [arg] | [arg, None | Some(Datatype::Integer)] if is_numeric(arg) => Ok(arg.clone()),
+        _ => Err(invalid_types(name, args)),
+    }
+}
+
+fn numeric_to_float(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    match args.iter().all(is_numeric) {
+        true => Ok(Some(Datatype::Float)),
+        false => Err(invalid_types(name, args)),
+    }
+}
+
+fn common_numeric(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    match args.iter().all(is_numeric) {
+        true => common(name, args),
+        false => Err(invalid_types(name, args)),
+    }
+}
+
+/// The common datatype that all arguments can be coerced to.
+fn common(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    args.iter().try_fold(None, |common: Option<Datatype>, arg| match (common, arg) {
+        (None, arg) => Ok(arg.clone()),
+        (common, None) => Ok(common),
+        (Some(common), Some(arg)) => {
+            common.coerce(arg).map(Some).ok_or_else(|| invalid_types(name, args))
+        }
+    })
+}
+
+fn nullif_signature(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    common(name, args)?;
+    Ok(args[0].clone())
+}
+
+fn if_signature(name: &str, args: &[Option<Datatype>]) -> Result<Option<Datatype>> {
+    match args {
+        [None | Some(Datatype::Boolean), branches @ ..] => common(name, branches),
+        _ => Err(invalid_types(name, args)),
+    }
+}
+
+fn upper(name: &str, args: Vec<Value>) -> Result<Value> {
+    match &args[..] {
+        [Value::String(s)] => Ok(Value::String(s.to_uppercase())),
+        args => Err(invalid(name, args)),
+    }
+}
+
+fn lower(name: &str, args: Vec<Value>) -> Result<Value> {
+    match &args[..] {
+        [Value::String(s)] => Ok(Value::String(s.to_lowercase())),
+        args => Err(invalid(name, args)),
+    }
+}
+
+fn length(name: &str, args: Vec<Value>) -> Result<Value> {
+    match &args[..] {
+        [Value::String(s)] => Ok(Value::Integer(s.chars().count() as i64)),
+        args => Err(invalid(name, args)),
+    }
+}
+
+/// SUBSTR(string, start [, length]) with a 1-based start, like Postgres. Positions before the
+/// start of the string count towards the length but yield no characters.
+fn substr(name: &str, args: Vec<Value>) -> Result<Value> {
+    let (s, start, len) = match &args[..] {
+        [Value::String(s), Value::Integer(start)] => (s, *start, None),
+        [Value::String(s), Value::Integer(start), Value::Integer(len)] if *len >= 0 => {
+            (s, *start, Some(*len))
+        }
+        args => return Err(invalid(name, args)),
+    };
+    let end = len.map(|len| start.saturating_add(len));
+    Ok(Value::String(
+        s.chars()
+            .zip(1..)
+            .filter(|(_, i)| *i >= start && end.is_none_or(|end| *i < end))
+            .map(|(c, _)| c)
+            .collect(),
+    ))
+}
+
+fn trim(name: &str, args: Vec<Value>) -> Result<Value> {
+    match &args[..] {
+        [Value::String(s)] => Ok(Value::String(s.trim().to_string())),
+        args => Err(invalid(name, args)),
+    }
+}
+
+/// CONCAT(value, ...) converts all arguments to strings.
+fn concat(_: &str, args: Vec<Value>) -> Result<Value> {
+    Ok(Value::String(args.into_iter().map(|a| a.to_string()).collect()))
+}
+
+fn replace(name: &str, args: Vec<Value>) -> Result<Value> {
+    match &args[..] {
+        [Value::String(s), Value::String(from), Value::String(to)] => {
+            Ok(Value::String(s.replace(from.as_str(), to)))
+        }
+        args => Err(invalid(name, args)),
+    }
+}
+
+fn abs(name: &str, args: Vec<Value>) -> Result<Value> {
+    match &args[..] {
+        [Value::Integer(i)] => Ok(Value::Integer(
+            i.checked_abs().ok_or_else(|| Error::Value("Integer overflow".into()))?,
+        )),
+        [Value::Float(f)] => Ok(Value::Float(f.abs())),
+        args => Err(invalid(name, args)),
+    }
+}
+
+/// ROUND(number [, digits]) rounds half away from zero. Negative digits round to the left of
+/// the decimal point.
+fn round(name: &str, args: Vec<Value>) -> Result<Value> {
+    match &args[..] {
+        [Value::Integer(i)] => Ok(Value::Integer(*i)),
+        [Value::Integer(i), Value::Integer(d)] if *d >= 0 => Ok(Value::Integer(*i)),
+        [Value::Integer(i), Value::Integer(d)] => {
+            let scale = 10f64.powi(-digits(*d));
+            Value::Float((*i as f64 / scale).round() * scale).cast(&super::Datatype::Integer)
+        }
+        [Value::Float(f)] => Ok(Value::Float(f.round())),
+        [Value::Float(f), Value::Integer(d)] => {
+            let scale = 10f64.powi(digits(*d));
+            match f * scale {
+                // Floats have no digits this far to the right of the decimal point.
+                scaled if scaled.is_infinite() => Ok(Value::Float(*f)),
+                scaled => Ok(Value::Float(scaled.round() / scale)),
+            }
+        }
+        args => Err(invalid(name, args)),
+    }
+}
+
+/// Clamps ROUND digits to the range of float exponents, beyond which the result is the same.
+fn digits(d: i64) -> i32 {
+    d.clamp(-308, 308) as i32
+}
+
+fn floor(name: &str, args: Vec<Value>) -> Result<Value> {
+    match &args[..] {
+        [Value::Integer(i)] => Ok(Value::Integer(*i)),
+        [Value::Float(f)] => Ok(Value::Float(f.floor())),
+        args => Err(invalid(name, args)),
+    }
+}
+
+fn ceil(name: &str, args: Vec<Value>) -> Result<Value> {
+    match &args[..] {
+        [Value::Integer(i)] => Ok(Value::Integer(*i)),
+        [Value::Float(f)] => Ok(Value::Float(f.ceil())),
+        args => Err(invalid(name, args)),
+    }
+}
+
+fn sqrt(name: &str, args: Vec<Value>) -> Result<Value> {
+    let f = match &args[..] {
+        [Value::Integer(i)] => *i as f64,
+        [Value::Float(f)] => *f,
+        args => return Err(invalid(name, args)),
+    };
+    if f < 0.0 {
+        return Err(Error::Value(format!("Can't take square root of negative number {}", f)));
+    }
+    Ok(Value::Float(f.sqrt()))
+}
+
+/// POW(base, exponent) is equivalent to the ^ operator.
+fn pow(_: &str, mut args: Vec<Value>) -> Result<Value> {
+    let exponent = args.pop().unwrap();
+    let base = args.pop().unwrap();
+    Expression::Exponentiate(
+        Expression::Constant(base).into(),
+        Expression::Constant(exponent).into(),
+    )
+    .evaluate(None)
+}
+
+/// COALESCE(value, ...) returns the first non-NULL argument, evaluating no further ones.
+fn coalesce(_: &str, args: &[Expression], row: Option<&Row>) -> Result<Value> {
+    for arg in args {
+        match arg.evaluate(row)? {
+            Value::Null => continue,
+            value => return Ok(value),
+        }
+    }
+    Ok(Value::Null)
+}
+
+/// NULLIF(value, other) returns NULL if value = other, otherwise value.
+fn nullif(_: &str, mut args: Vec<Value>) -> Result<Value> {
+    let other = args.pop().unwrap();
+    let value = args.pop().unwrap();
+    match Expression::equal(value.clone(), other)? {
+        Value::Boolean(true) => Ok(Value::Null),
+        _ => Ok(value),
+    }
+}
+
+/// IF(condition, then, else) only evaluates the chosen branch. A NULL condition takes the
+/// else branch, like CASE WHEN.
+fn iff(name: &str, args: &[Expression], row: Option<&Row>) -> Result<Value> {
+    match args[0].evaluate(row)? {
+        Value::Boolean(true) => args[1].evaluate(row),
+        Value::Boolean(false) | Value::Null => args[2].evaluate(row),
+        value => Err(Error::Value(format!("Can't use {} as {} condition", value, name))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn call(name: &str, args: Vec<Value>) -> Result<Value> {
+        Expression::Function(name.into(), args.into_iter().map(Expression::Constant).collect())
+            .evaluate(None)
+    }
+
+    fn string(s: &str) -> Value {
+        Value::String(s.into())
+    }
+
+    #[test]
+    fn lookup() -> Result<()> {
+        assert_eq!(Function::lookup("upper")?.name, "UPPER");
+        assert_eq!(Function::lookup("Coalesce")?.name, "COALESCE");
+        assert_eq!(
+            Function::lookup("nope").err(),
+            Some(Error::Value("Unknown function nope".into()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn arity() {
+        assert_eq!(
+            call("upper", vec![]),
+            Err(Error::Value("Function UPPER takes 1 arguments, got 0".into()))
+        );
+        assert_eq!(
+            call("substr", vec![string("a")]),
+            Err(Error::Value("Function SUBSTR takes 2 to 3 arguments, got 1".into()))
+        );
+        assert_eq!(
+            call("coalesce", vec![]),
+            Err(Error::Value("Function COALESCE takes at least 1 arguments, got 0".into()))
+        );
+        assert!(call("pow", vec![Value::Integer(1); 3]).is_err());
+    }
+
+    #[test]
+    fn string_functions() -> Result<()> {
+        use Value::*;
+        assert_eq!(call("upper", vec![string("aBc")])?, string("ABC"));
+        assert_eq!(call("lower", vec![string("aBc")])?, string("abc"));
+        assert_eq!(call("length", vec![string("héllo")])?, Integer(5));
+        assert_eq!(call("substr", vec![string("hello"), Integer(2)])?, string("ello"));
+        assert_eq!(call("substr", vec![string("hello"), Integer(2), Integer(3)])?, string("ell"));
+        assert_eq!(call("substr", vec![string("hello"), Integer(0), Integer(3)])?, string("he"));
+        assert_eq!(call("substr", vec![string("hello"), Integer(9)])?, string(""));
+        assert!(call("substr", vec![string("hello"), Integer(1), Integer(-1)]).is_err());
+        assert_eq!(call("trim", vec![string("  a b  ")])?, string("a b"));
+        assert_eq!(
+            call("concat", vec![string("a"), Integer(1), Boolean(true), Float(1.5)])?,
+            string("a1TRUE1.5")
+        );
+        assert_eq!(
+            call("replace", vec![string("a-b-c"), string("-"), string("+")])?,
+            string("a+b+c")
+        );
+        assert_eq!(
+            call("upper", vec![Integer(1)]),
+            Err(Error::Value("Can't call UPPER(1)".into()))
+        );
+        assert!(call("length", vec![Boolean(true)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn math_functions() -> Result<()> {
+        use Value::*;
+        assert_eq!(call("abs", vec![Integer(-3)])?, Integer(3));
+        assert_eq!(call("abs", vec![Float(-1.5)])?, Float(1.5));
+        assert!(call("abs", vec![Integer(i64::MIN)]).is_err());
+        assert_eq!(call("round", vec![Float(2.5)])?, Float(3.0));
+        assert_eq!(call("round", vec![Float(-2.5)])?, Float(-3.0));
+        assert_eq!(call("round", vec![Float(1.23456), Integer(2)])?, Float(1.23));
+        assert_eq!(call("round", vec![Float(1.5), Integer(400)])?, Float(1.5));
+        assert_eq!(call("round", vec![Float(1.5), Integer(4294967296)])?, Float(1.5));
+        assert_eq!(call("round", vec![Float(1e300), Integer(300)])?, Float(1e300));
+        assert_eq!(call("round", vec![Integer(1), Integer(i64::MIN)])?, Integer(0));
+        assert_eq!(call("round", vec![Integer(7)])?, Integer(7));
+        assert_eq!(call("round", vec![Integer(1250), Integer(-2)])?, Integer(1300));
+        assert_eq!(call("floor", vec![Float(-1.5)])?, Float(-2.0));
+        assert_eq!(call("floor", vec![Integer(4)])?, Integer(4));
+        assert_eq!(call("ceil", vec![Float(1.2)])?, Float(2.0));
+        assert_eq!(call("ceil", vec![Integer(4)])?, Integer(4));
+        assert_eq!(call("sqrt", vec![Integer(9)])?, Float(3.0));
+        assert_eq!(call("sqrt", vec![Float(2.25)])?, Float(1.5));
+        assert!(call("sqrt", vec![Integer(-1)]).is_err());
+        assert_eq!(call("pow", vec![Integer(2), Integer(10)])?, Integer(1024));
+        assert_eq!(call("pow", vec![Integer(2), Float(0.5)])?, Float(2f64.powf(0.5)));
+        assert!(call("pow", vec![Integer(2), string("a")]).is_err());
+        assert!(call("floor", vec![string("1")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn null_propagation() -> Result<()> {
+        use Value::*;
+        for (name, args) in [
+            ("upper", vec![Null]),
+            ("length", vec![Null]),
+            ("substr", vec![string("a"), Null]),
+            ("concat", vec![string("a"), Null]),
+            ("replace", vec![string("a"), Null, string("b")]),
+            ("abs", vec![Null]),
+            ("round", vec![Float(1.5), Null]),
+            ("sqrt", vec![Null]),
+            ("pow", vec![Null, Integer(2)]),
+        ] {
+            assert_eq!(call(name, args)?, Null, "{} with NULL", name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn null_functions() -> Result<()> {
+        use Value::*;
+        assert_eq!(call("coalesce", vec![Null, Null, Integer(1), Integer(2)])?, Integer(1));
+        assert_eq!(call("coalesce", vec![Null, Null])?, Null);
+        assert_eq!(call("nullif", vec![Integer(1), Integer(1)])?, Null);
+        assert_eq!(call("nullif", vec![Integer(1), Float(1.0)])?, Null);
+        assert_eq!(call("nullif", vec![Integer(1), Integer(2)])?, Integer(1));
+        assert_eq!(call("nullif", vec![Integer(1), Null])?, Integer(1));
+        assert_eq!(call("nullif", vec![Null, Integer(1)])?, Null);
+        assert!(call("nullif", vec![Integer(1), string("1")]).is_err());
+
+        // COALESCE doesn't evaluate arguments after the first non-NULL one.
+        let divide_by_zero = Expression::Divide(
+            Expression::Constant(Integer(1)).into(),
+            Expression::Constant(Integer(0)).into(),
+        );
+        assert_eq!(
+            Expression::Function(
+                "coalesce".into(),
+                vec![Expression::Constant(Integer(1)), divide_by_zero.clone()]
+            )
+            .evaluate(None)?,
+            Integer(1)
+        );
+        assert!(Expression::Function(
+            "coalesce".into(),
+            vec![Expression::Constant(Null), divide_by_zero]
+        )
+        .evaluate(None)
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn conditional_functions() -> Result<()> {
+        use Value::*;
+        assert_eq!(call("if", vec![Boolean(true), Integer(1), Integer(2)])?, Integer(1));
+        assert_eq!(call("if", vec![Boolean(false), Integer(1), Integer(2)])?, Integer(2));
+        assert_eq!(call("if", vec![Null, Integer(1), Integer(2)])?, Integer(2));
+        assert!(call("if", vec![Integer(1), Integer(1), Integer(2)]).is_err());
+
+        // IF only evaluates the chosen branch.
+        let divide_by_zero = Expression::Divide(
+            Expression::Constant(Integer(1)).into(),
+            Expression::Constant(Integer(0)).into(),
+        );
+        assert_eq!(
+            Expression::Function(
+                "if".into(),
+                vec![
+                    Expression::Constant(Boolean(true)),
+                    Expression::Constant(Integer(1)),
+                    divide_by_zero
+                ]
+            )
+            .evaluate(None)?,
+            Integer(1)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn case() -> Result<()> {
+        use Value::*;
+        let c = |v: Value| Expression::Constant(v);
+        let row = vec![Integer(2), Null];
+
+        // Simple CASE compares the operand with each WHEN value.
+        let simple = |field: usize| {
+            Expression::Case(
+                Some(Expression::Field(field, None).into()),
+                vec![(c(Integer(1)), c(string("one"))), (c(Float(2.0)), c(string("two")))],
+                Some(c(string("other")).into()),
+            )
+        };
+        assert_eq!(simple(0).evaluate(Some(&row))?, string("two"));
+        assert_eq!(simple(1).evaluate(Some(&row))?, string("other"));
+
+        // Searched CASE evaluates each condition, treating NULL as false.
+        let searched = Expression::Case(
+            None,
+            vec![
+                (Expression::IsNull(Expression::Field(0, None).into()), c(Integer(1))),
+                (Expression::Field(1, None), c(Integer(2))),
+                (
+                    Expression::GreaterThan(
+                        Expression::Field(0, None).into(),
+                        c(Integer(1)).into(),
+                    ),
+                    c(Integer(3)),
+                ),
+            ],
+            None,
+        );
+        assert_eq!(searched.evaluate(Some(&row))?, Integer(3));
+        assert_eq!(searched.evaluate(Some(&vec![Integer(0), Null]))?, Null);
+        assert!(Expression::Case(None, vec![(c(Integer(1)), c(Integer(1)))], None)
+            .evaluate(None)
+            .is_err());
+
+        assert_eq!(
+            searched.to_string(),
+            "CASE WHEN #0 IS NULL THEN 1 WHEN #1 THEN 2 WHEN #0 > 1 THEN 3 END"
+        );
+        assert_eq!(simple(0).to_string(), "CASE #0 WHEN 1 THEN one WHEN 2 THEN two ELSE other END");
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
 pub mod sqltype;
+mod analysis;
+pub mod compile;
 pub mod expression;
+pub mod function;
 pub use expression::Expression;
+pub use function::Function;
 pub use sqltype::{Datatype, Value};
 pub use super::Row;
@@ -3,6 +3,8 @@ use std::hash::{Hash, Hasher};
 
 use serde_derive::{Deserialize, Serialize};
 
+use crate::error::{Error, Result};
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub enum Value {
     Null,
@@ -22,6 +24,64 @@ impl Value {
             _ => None 
         }
     }
+
+    /// Explicitly converts the value to the given datatype, as done by CAST. NULL casts to NULL
+    /// for every datatype, floats are rounded when cast to integers, and strings are trimmed
+    /// before being parsed.
+    pub fn cast(self, datatype: &Datatype) -> Result<Value> {
+        use Datatype::*;
+        Ok(match (self, datatype) {
+            (Self::Null, _) => Self::Null,
+
+            (Self::Boolean(b), Boolean) => Self::Boolean(b),
+            (Self::Boolean(b), Integer) => Self::Integer(b as i64),
+            (Self::Boolean(b), Float) => Self::Float(if b { 1.0 } else { 0.0 }),
+
+            (Self::Integer(i), Boolean) => Self::Boolean(i != 0),
+            (Self::Integer(i), Integer) => Self::Integer(i),
+            (Self::Integer(i), Float) => Self::Float(i as f64),
+
+            (Self::Float(f), Boolean) if !f.is_nan() => Self::Boolean(f != 0.0),
+            (Self::Float(f), Integer)
+                if f.is_finite() && f.round() >= i64::MIN as f64 && f.round() < i64::MAX as f64 =>
+            {
+                Self::Integer(f.round() as i64)
+            }
+            (Self::Float(f), Float) => Self::Float(f),
+
+            (Self::String(s), Boolean) => match s.trim().to_lowercase().as_str() {
+                "true" | "t" | "1" => Self::Boolean(true),
+                "false" | "f" | "0" => Self::Boolean(false),
+                _ => return Err(Error::Value(format!("Can't cast {} to {}", s, datatype))),
+            },
+            (Self::String(s), Integer) => match s.trim().parse() {
+                Ok(i) => Self::Integer(i),
+                Err(_) => return Err(Error::Value(format!("Can't cast {} to {}", s, datatype))),
+            },
+            (Self::String(s), Float) => match s.trim().parse() {
+                Ok(f) => Self::Float(f),
+                Err(_) => return Err(Error::Value(format!("Can't cast {} to {}", s, datatype))),
+            },
+
+            (value, String) => Self::String(value.to_string()),
+            (value, datatype) => {
+                return Err(Error::Value(format!("Can't cast {} to {}", value, datatype)))
+            }
+        })
+    }
+
+    /// Implicitly coerces a pair of values to their common datatype (see Datatype::coerce), as
+    /// done for the operands of comparison and arithmetic operators. Pairs without a common
+    /// datatype, including any pair with a NULL, are returned unchanged.
+    pub fn coerce(lhs: Value, rhs: Value) -> Result<(Value, Value)> {
+        match (lhs.datatype(), rhs.datatype()) {
+            (Some(l), Some(r)) => match l.coerce(&r) {
+                Some(datatype) => Ok((lhs.cast(&datatype)?, rhs.cast(&datatype)?)),
+                None => Ok((lhs, rhs)),
+            },
+            _ => Ok((lhs, rhs)),
+        }
+    }
 }
 
 #[allow(clippy::derive_hash_xor_eq)]
@@ -29,7 +89,7 @@ impl Hash for Value {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.datatype().hash(state);
         match self {
-            Value::Null => self.hash(state),
+            Value::Null => {}
             Value::Boolean(v) => v.hash(state),
             Value::Integer(v) => v.hash(state),
             Value::Float(v) => v.to_be_bytes().hash(state),
@@ -41,6 +101,36 @@ impl Hash for Value {
 impl std::cmp::Eq for Value {
 }
 
+/// Orders NULL before all other values. Consistent with equality, values of different
+/// datatypes are incomparable, as are NaN floats. See Value::compare() for numeric ordering.
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Self::Integer(_), Self::Float(_)) | (Self::Float(_), Self::Integer(_)) => None,
+            (a, b) => a.compare(b),
+        }
+    }
+}
+
+impl Value {
+    /// Compares values like partial_cmp(), except that integers and floats are compared
+    /// numerically, such that e.g. Integer(1) and Float(1.0) are equal.
+    pub fn compare(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        use std::cmp::Ordering::*;
+        match (self, other) {
+            (Self::Null, Self::Null) => Some(Equal),
+            (Self::Null, _) => Some(Less),
+            (_, Self::Null) => Some(Greater),
+            (Self::Boolean(a), Self::Boolean(b)) => a.partial_cmp(b),
+            (Self::Integer(a), Self::Integer(b)) => a.partial_cmp(b),
+            (Self::Integer(a), Self::Float(b)) => (*a as f64).partial_cmp(b),
+            (Self::Float(a), Self::Integer(b)) => a.partial_cmp(&(*b as f64)),
+            (Self::Float(a), Self::Float(b)) => a.partial_cmp(b),
+            (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            (_, _) => None,
+        }
+    }
+}
 
 impl<'a> From<Value> for Cow<'a, Value> {
     fn from(v: Value) -> Self {
@@ -80,6 +170,20 @@ pub enum Datatype{
     Float,
 }
 
+impl Datatype {
+    /// Returns the datatype that operands of the two datatypes are implicitly coerced to by
+    /// comparison and arithmetic operators, or None if they can only be mixed via an explicit
+    /// CAST. This is the single coercion table used by expression evaluation.
+    pub fn coerce(&self, other: &Datatype) -> Option<Datatype> {
+        use Datatype::*;
+        match (self, other) {
+            (lhs, rhs) if lhs == rhs => Some(lhs.clone()),
+            (Integer, Float) | (Float, Integer) => Some(Float),
+            _ => None,
+        }
+    }
+}
+
 impl std::fmt::Display for Datatype {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         f.write_str(match self {